@@ -0,0 +1,584 @@
+use crate::{
+    AtbBacktestError, AtbBacktestResult, BacktestReport, BotSettings, Broker, Context, EquityPoint,
+    FeeSchedule, History, IntrabarPath, MarketRule, SimBroker, SlippageModel, Strategy,
+};
+use atb_db::{AtbDB, AtbDbResult, OhlcvBar, OhlcvQuery};
+use std::sync::Arc;
+
+// 初期資金の既定値
+pub const DEFAULT_INITIAL_CASH: f64 = 1_000_000.0;
+
+// 保存済みのローソク足を古い順に再生してストラテジーを実行する
+// ローソク足1本ごとに
+//   1. それまでに出された注文をこの足で約定させ(SimBroker参照)、約定ごとにon_fillを呼ぶ
+//   2. 確定した足を履歴に加えてon_barを呼ぶ(出した注文は次の足で約定する)
+//   3. 終値で評価額を記録する
+// 時計や乱数を使わないので、同じ入力からは常に同じ結果になる
+#[derive(Clone, Debug)]
+pub struct Backtest {
+    exchange: String,
+    pair: String,
+    period: i64,
+    from: Option<i64>,
+    to: Option<i64>,
+    initial_cash: f64,
+    intrabar_path: IntrabarPath,
+    // 指定しなければrunではデータベースの市場の手数料体系、run_barsでは手数料なし
+    fee_schedule: Option<FeeSchedule>,
+    slippage: Vec<Arc<dyn SlippageModel>>,
+    // 評価額に対して持てる建玉の代金の倍率
+    leverage: f64,
+    // 指定しなければrunではデータベースの市場の呼値・最小注文数量、run_barsでは制限なし
+    market_rule: Option<MarketRule>,
+}
+
+impl Backtest {
+    pub fn new(exchange: &str, pair: &str, period: i64) -> Backtest {
+        Backtest {
+            exchange: exchange.to_string(),
+            pair: pair.to_string(),
+            period,
+            from: None,
+            to: None,
+            initial_cash: DEFAULT_INITIAL_CASH,
+            intrabar_path: IntrabarPath::default(),
+            fee_schedule: None,
+            slippage: vec![],
+            leverage: 1.0,
+            market_rule: None,
+        }
+    }
+
+    // 指定したunixtime以降(指定時刻を含む)の足で実行する
+    pub fn from_time(mut self, unixtime: i64) -> Backtest {
+        self.from = Some(unixtime);
+        self
+    }
+
+    // 指定したunixtime以前(指定時刻を含む)の足で実行する
+    pub fn to_time(mut self, unixtime: i64) -> Backtest {
+        self.to = Some(unixtime);
+        self
+    }
+
+    pub fn initial_cash(mut self, cash: f64) -> Backtest {
+        self.initial_cash = cash;
+        self
+    }
+
+    // 指値・逆指値注文の約定を判定する足の中の値動き
+    pub fn intrabar_path(mut self, path: IntrabarPath) -> Backtest {
+        self.intrabar_path = path;
+        self
+    }
+
+    // 市場の手数料体系の代わりに使う手数料体系
+    pub fn fee_schedule(mut self, fee_schedule: FeeSchedule) -> Backtest {
+        self.fee_schedule = Some(fee_schedule);
+        self
+    }
+
+    // テイカーの約定に加えるスリッページ(複数指定すると合計する)
+    pub fn slippage<M: SlippageModel + 'static>(mut self, model: M) -> Backtest {
+        self.slippage.push(Arc::new(model));
+        self
+    }
+
+    // 評価額に対して持てる建玉の代金の倍率(既定値は1.0で、資金の範囲内でしか建玉を持てない)
+    pub fn leverage(mut self, leverage: f64) -> Backtest {
+        self.leverage = leverage;
+        self
+    }
+
+    // 市場の呼値・最小注文数量の代わりに使う値
+    pub fn market_rule(mut self, market_rule: MarketRule) -> Backtest {
+        self.market_rule = Some(market_rule);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    #[allow(dead_code)]
+    pub fn get_pair(&self) -> &str {
+        &self.pair
+    }
+
+    #[allow(dead_code)]
+    pub fn get_period(&self) -> i64 {
+        self.period
+    }
+
+    #[allow(dead_code)]
+    pub fn get_initial_cash(&self) -> f64 {
+        self.initial_cash
+    }
+
+    #[allow(dead_code)]
+    pub fn get_intrabar_path(&self) -> IntrabarPath {
+        self.intrabar_path
+    }
+
+    #[allow(dead_code)]
+    pub fn get_fee_schedule(&self) -> Option<&FeeSchedule> {
+        self.fee_schedule.as_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_slippage(&self) -> &[Arc<dyn SlippageModel>] {
+        &self.slippage
+    }
+
+    #[allow(dead_code)]
+    pub fn get_leverage(&self) -> f64 {
+        self.leverage
+    }
+
+    #[allow(dead_code)]
+    pub fn get_market_rule(&self) -> Option<&MarketRule> {
+        self.market_rule.as_ref()
+    }
+
+    // データベースのローソク足で実行する
+    // ストラテジーのbot_idで登録したボットの設定・市場の手数料体系(market_fee)・呼値・最小注文数量に従う
+    pub fn run<S: Strategy>(
+        &self,
+        atbdb: &AtbDB,
+        strategy: &mut S,
+    ) -> AtbBacktestResult<BacktestReport> {
+        let bot = atbdb.get_bot(strategy.bot_id())?;
+        if bot.is_archived() {
+            return Err(AtbBacktestError::Validation(format!(
+                "アーカイブ済みのボットです: {}",
+                bot.get_id()
+            )));
+        }
+        atbdb.validate_market_period(&self.exchange, &self.pair, self.period)?;
+        let fee_schedule = match &self.fee_schedule {
+            Some(fee_schedule) => fee_schedule.clone(),
+            None => FeeSchedule::from(&atbdb.get_market_fee_list(&self.exchange, &self.pair)?[..]),
+        };
+        let market_rule = match &self.market_rule {
+            Some(market_rule) => *market_rule,
+            None => MarketRule::from(&atbdb.get_market(&self.exchange, &self.pair)?),
+        };
+
+        let mut query = OhlcvQuery::new(&self.exchange, &self.pair, self.period);
+        if let Some(from) = self.from {
+            query = query.from_time(from);
+        }
+        if let Some(to) = self.to {
+            query = query.to_time(to);
+        }
+        self.replay(
+            &BotSettings::from(&bot),
+            &fee_schedule,
+            &market_rule,
+            atbdb.iter_ohlcv(&query)?,
+            strategy,
+        )
+    }
+
+    // 渡したボットの設定・ローソク足で実行する(テストなど)
+    // 期間の指定は無視せず、範囲外の足は読み飛ばす
+    pub fn run_bars<S: Strategy>(
+        &self,
+        bot: &BotSettings,
+        bars: &[OhlcvBar],
+        strategy: &mut S,
+    ) -> AtbBacktestResult<BacktestReport> {
+        if bot.get_id() != strategy.bot_id() {
+            return Err(AtbBacktestError::Validation(format!(
+                "ボットのIDが一致しません: {} != {}",
+                bot.get_id(),
+                strategy.bot_id()
+            )));
+        }
+        let bars = bars.iter().filter(|bar| {
+            let secs = bar.get_unixtime().get_secs();
+            self.from.is_none_or(|from| from <= secs) && self.to.is_none_or(|to| secs <= to)
+        });
+        let fee_schedule = self.fee_schedule.clone().unwrap_or_default();
+        let market_rule = self.market_rule.unwrap_or_default();
+        self.replay(
+            bot,
+            &fee_schedule,
+            &market_rule,
+            bars.cloned().map(Ok),
+            strategy,
+        )
+    }
+
+    fn replay<I, S>(
+        &self,
+        bot: &BotSettings,
+        fee_schedule: &FeeSchedule,
+        market_rule: &MarketRule,
+        bars: I,
+        strategy: &mut S,
+    ) -> AtbBacktestResult<BacktestReport>
+    where
+        I: Iterator<Item = AtbDbResult<OhlcvBar>>,
+        S: Strategy,
+    {
+        if !self.initial_cash.is_finite() || self.initial_cash < 0.0 {
+            return Err(AtbBacktestError::Validation(format!(
+                "初期資金には0以上の値を指定してください: {}",
+                self.initial_cash
+            )));
+        }
+
+        if !self.leverage.is_finite() || self.leverage <= 0.0 {
+            return Err(AtbBacktestError::Validation(format!(
+                "レバレッジには0より大きい値を指定してください: {}",
+                self.leverage
+            )));
+        }
+
+        let mut broker = SimBroker::new(self.initial_cash, bot)
+            .intrabar_path(self.intrabar_path)
+            .fee_schedule(fee_schedule)
+            .slippage(&self.slippage)
+            .leverage(self.leverage)
+            .market_rule(market_rule);
+        let mut history = History::default();
+        let mut equity = vec![];
+        strategy.on_start(&mut Context::new(bot, &history, &mut broker));
+        for bar in bars {
+            let bar = bar?;
+            let unixtime = bar.get_unixtime();
+            history.check_next(&bar)?;
+
+            let count = broker.open_bar(&bar);
+            let fills = broker.get_fills()[broker.get_fills().len() - count..].to_vec();
+            for fill in &fills {
+                strategy.on_fill(fill, &mut Context::new(bot, &history, &mut broker));
+            }
+
+            broker.close_bar(&bar);
+            history.push(bar)?;
+            strategy.on_bar(&mut Context::new(bot, &history, &mut broker));
+            equity.push(EquityPoint::new(
+                unixtime,
+                broker.get_cash(),
+                broker.get_position().get_size(),
+                broker.get_equity(),
+            ));
+        }
+        strategy.on_end(&mut Context::new(bot, &history, &mut broker));
+
+        // 最後の足・on_endで出された注文は約定させずに終える
+        Ok(BacktestReport::new(
+            bot.get_id(),
+            self,
+            fee_schedule,
+            market_rule,
+            broker.finish(),
+            equity,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn bar(unixtime: i64, open: i64, close: i64) -> atb_db::OhlcvBar {
+        use atb_db::Decimal;
+        atb_db::OhlcvBar::new(
+            atb_db::UnixTime::new(unixtime),
+            Decimal::new(open, 0),
+            Decimal::new(open.max(close), 0),
+            Decimal::new(open.min(close), 0),
+            Decimal::new(close, 0),
+            Decimal::new(1, 0),
+        )
+        .unwrap()
+    }
+
+    // 終値が前の足より上がったら買い、下がったら決済する
+    struct Momentum {
+        bot_id: i64,
+        events: Vec<String>,
+    }
+
+    impl Momentum {
+        fn new(bot_id: i64) -> Momentum {
+            Momentum {
+                bot_id,
+                events: vec![],
+            }
+        }
+    }
+
+    impl crate::Strategy for Momentum {
+        fn bot_id(&self) -> i64 {
+            self.bot_id
+        }
+
+        fn on_start(&mut self, ctx: &mut crate::Context) {
+            self.events
+                .push(format!("start {}", ctx.get_history().len()));
+        }
+
+        fn on_bar(&mut self, ctx: &mut crate::Context) {
+            let bar = ctx.get_bar().unwrap().get_unixtime().get_secs();
+            self.events.push(format!("bar {}", bar));
+            let history = ctx.get_history();
+            if let (Some(close), Some(last)) = (history.ago(0), history.ago(1)) {
+                let (close, last) = (close.get_close(), last.get_close());
+                if last < close && ctx.get_position().is_flat() {
+                    ctx.buy(1.0).unwrap();
+                } else if close < last {
+                    ctx.close_position().unwrap();
+                }
+            }
+        }
+
+        fn on_fill(&mut self, fill: &crate::Fill, ctx: &mut crate::Context) {
+            // 約定した足はまだ履歴にない
+            let last = ctx.get_bar().unwrap().get_unixtime().get_secs();
+            self.events.push(format!(
+                "fill {} {} after {}",
+                fill.get_side().as_str(),
+                fill.get_unixtime().get_secs(),
+                last
+            ));
+        }
+
+        fn on_end(&mut self, ctx: &mut crate::Context) {
+            self.events.push(format!("end {}", ctx.get_history().len()));
+        }
+    }
+
+    fn bars() -> Vec<atb_db::OhlcvBar> {
+        vec![
+            bar(60, 100, 100),
+            bar(120, 100, 110),
+            bar(180, 112, 120),
+            bar(240, 121, 115),
+            bar(300, 114, 118),
+            bar(360, 119, 119),
+        ]
+    }
+
+    #[test]
+    fn run_bars() {
+        use super::*;
+        let backtest = Backtest::new("bitflyer", "btcjpy", 60).initial_cash(1000.0);
+        let bot = BotSettings::new(1);
+        let mut strategy = Momentum::new(1);
+        let report = backtest.run_bars(&bot, &bars(), &mut strategy).unwrap();
+        assert_eq!(report.get_bot_id(), 1);
+
+        // 120の足で買い注文 -> 180の足の始値112で約定
+        // 240の足で決済注文 -> 300の足の始値114で約定
+        // 300の足で買い注文 -> 360の足の始値119で約定
+        assert_eq!(report.get_fills().len(), 3);
+        assert_eq!(report.get_fills()[0].get_price(), 112.0);
+        assert_eq!(report.get_trades().len(), 1);
+        let trade = &report.get_trades()[0];
+        assert_eq!(trade.get_pnl(), 2.0);
+        assert_eq!(trade.get_entry_time().get_secs(), 180);
+        assert_eq!(trade.get_exit_time().get_secs(), 300);
+
+        assert_eq!(report.get_equity().len(), 6);
+        assert_eq!(report.get_equity()[2].get_equity(), 1008.0);
+        assert_eq!(report.get_equity()[3].get_equity(), 1003.0);
+        assert_eq!(report.get_final_cash(), 883.0);
+        assert_eq!(report.get_final_position().get_size(), 1.0);
+        assert_eq!(report.get_final_equity(), 1002.0);
+
+        // 成績: 1008から1002まで下落し、終了まで回復しない
+        let metrics = report.get_metrics();
+        assert!((metrics.get_total_return().unwrap() - 0.002).abs() < 1e-9);
+        assert!((metrics.get_max_drawdown() - 6.0 / 1008.0).abs() < 1e-9);
+        assert_eq!(metrics.get_max_drawdown_secs(), 180);
+        assert_eq!(metrics.get_long().get_trade_count(), 1);
+        assert_eq!(metrics.get_short().get_trade_count(), 0);
+        assert_eq!(metrics.get_all().get_exposure(), 0.5);
+
+        // フックが呼ばれる順序
+        assert_eq!(
+            strategy.events,
+            vec![
+                "start 0",
+                "bar 60",
+                "bar 120",
+                "fill buy 180 after 120",
+                "bar 180",
+                "bar 240",
+                "fill sell 300 after 240",
+                "bar 300",
+                "fill buy 360 after 300",
+                "bar 360",
+                "end 6",
+            ]
+        );
+
+        // 同じ入力からは同じ結果になる
+        let again = backtest
+            .run_bars(&bot, &bars(), &mut Momentum::new(1))
+            .unwrap();
+        assert_eq!(report, again);
+        assert_eq!(
+            serde_json::to_string(&report).unwrap(),
+            serde_json::to_string(&again).unwrap()
+        );
+
+        // 期間で絞り込む
+        let report = backtest
+            .clone()
+            .from_time(120)
+            .to_time(240)
+            .run_bars(&bot, &bars(), &mut Momentum::new(1))
+            .unwrap();
+        assert_eq!(report.get_equity().len(), 3);
+        assert_eq!(report.get_fills().len(), 1);
+    }
+
+    #[test]
+    fn run_bars_error() {
+        use super::*;
+        let backtest = Backtest::new("bitflyer", "btcjpy", 60);
+        let bot = BotSettings::new(1);
+        let mut bars = bars();
+        bars.swap(1, 2);
+        let result = backtest.run_bars(&bot, &bars, &mut Momentum::new(1));
+        assert!(matches!(result, Err(AtbBacktestError::Validation(_))));
+
+        let result = backtest
+            .clone()
+            .initial_cash(-1.0)
+            .run_bars(&bot, &[], &mut Momentum::new(1));
+        assert!(matches!(result, Err(AtbBacktestError::Validation(_))));
+
+        let result = backtest
+            .clone()
+            .leverage(0.0)
+            .run_bars(&bot, &[], &mut Momentum::new(1));
+        assert!(matches!(result, Err(AtbBacktestError::Validation(_))));
+
+        let result = backtest.run_bars(&bot, &[], &mut Momentum::new(2));
+        assert!(matches!(result, Err(AtbBacktestError::Validation(_))));
+
+        // ローソク足がなければ初期資金のまま
+        let report = backtest.run_bars(&bot, &[], &mut Momentum::new(1)).unwrap();
+        assert_eq!(report.get_final_equity(), DEFAULT_INITIAL_CASH);
+    }
+
+    #[test]
+    fn run_db() {
+        use super::*;
+        use atb_db::{Actor, BotCreate};
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars())
+            .unwrap();
+        let actor = Actor::cli("test");
+        let bot = atbdb
+            .insert_bot(&BotCreate::new("momentum", "momentum"), &actor)
+            .unwrap();
+
+        // 市場の手数料体系(bitflyer/btcjpyはテイカー0.15%、スプレッド0.02%)・呼値(1円)で約定する
+        let backtest = Backtest::new("bitflyer", "btcjpy", 60).initial_cash(1000.0);
+        let report = backtest
+            .run(&atbdb, &mut Momentum::new(bot.get_id()))
+            .unwrap();
+        let fee_schedule =
+            FeeSchedule::from(&atbdb.get_market_fee_list("bitflyer", "btcjpy").unwrap()[..]);
+        let market_rule = MarketRule::from(&atbdb.get_market("bitflyer", "btcjpy").unwrap());
+        let expected = backtest
+            .clone()
+            .fee_schedule(fee_schedule)
+            .market_rule(market_rule)
+            .run_bars(
+                &BotSettings::from(&bot),
+                &bars(),
+                &mut Momentum::new(bot.get_id()),
+            )
+            .unwrap();
+        assert_eq!(report, expected);
+        assert_eq!(report.get_bot_id(), bot.get_id());
+        // 112 × 1.0001を呼値に切り上げる
+        assert_eq!(report.get_fills()[0].get_price(), 113.0);
+        assert!(0.0 < report.get_total_fee());
+        let trade = &report.get_trades()[0];
+        assert!(trade.get_pnl() < 2.0);
+
+        // 手数料体系を指定すると市場の手数料体系の代わりに使う
+        let free = backtest
+            .clone()
+            .fee_schedule(FeeSchedule::default())
+            .run(&atbdb, &mut Momentum::new(bot.get_id()))
+            .unwrap();
+        assert_eq!(free.get_total_fee(), 0.0);
+        assert_eq!(free.get_trades()[0].get_pnl(), 2.0);
+
+        // 最小注文数量(0.001BTC)の倍数でない注文は出せない
+        struct BuyOnce(i64, Option<bool>);
+        impl Strategy for BuyOnce {
+            fn bot_id(&self) -> i64 {
+                self.0
+            }
+
+            fn on_bar(&mut self, ctx: &mut Context) {
+                if self.1.is_none() {
+                    self.1 = Some(ctx.buy(0.0005).is_ok());
+                }
+            }
+        }
+        let mut strategy = BuyOnce(bot.get_id(), None);
+        let report = backtest.run(&atbdb, &mut strategy).unwrap();
+        assert_eq!(strategy.1, Some(false));
+        assert!(report.get_fills().is_empty());
+
+        // 未登録のボット
+        let result = backtest.run(&atbdb, &mut Momentum::new(bot.get_id() + 1));
+        assert!(matches!(
+            result,
+            Err(AtbBacktestError::Db(atb_db::AtbDbError::NotFound))
+        ));
+
+        // アーカイブ済みのボット
+        atbdb.archive_bot(bot.get_id(), &actor).unwrap();
+        let result = backtest.run(&atbdb, &mut Momentum::new(bot.get_id()));
+        assert!(matches!(result, Err(AtbBacktestError::Validation(_))));
+
+        // 未登録の市場・足の期間
+        let bot = atbdb
+            .insert_bot(&BotCreate::new("other", "other"), &actor)
+            .unwrap();
+        let result =
+            Backtest::new("bitflyer", "ethjpy", 60).run(&atbdb, &mut Momentum::new(bot.get_id()));
+        assert!(matches!(
+            result,
+            Err(AtbBacktestError::Db(atb_db::AtbDbError::Validation(_)))
+        ));
+    }
+
+    #[test]
+    fn run_bot_settings() {
+        use super::*;
+        // ロングが許可されていないボットでは買い注文が出せない
+        struct BuyOnce(Option<bool>);
+        impl Strategy for BuyOnce {
+            fn bot_id(&self) -> i64 {
+                1
+            }
+
+            fn on_bar(&mut self, ctx: &mut Context) {
+                if self.0.is_none() {
+                    self.0 = Some(ctx.buy(1.0).is_ok());
+                }
+            }
+        }
+
+        let backtest = Backtest::new("bitflyer", "btcjpy", 60);
+        let mut strategy = BuyOnce(None);
+        let bot = BotSettings::new(1).long_order(false);
+        let report = backtest.run_bars(&bot, &bars(), &mut strategy).unwrap();
+        assert_eq!(strategy.0, Some(false));
+        assert!(report.get_fills().is_empty());
+    }
+}
@@ -0,0 +1,532 @@
+use crate::fill_model::{execute, Execution};
+use crate::{
+    AtbBacktestResult, BotSettings, FeeSchedule, Fill, IntrabarPath, Liquidity, MarketRule, Order,
+    OrderId, OrderRequest, Position, SlippageModel, Trade,
+};
+use atb_db::{OhlcvBar, UnixTime};
+use std::sync::Arc;
+
+// 注文の受付・建玉・資金の問い合わせ先
+// バックテストではSimBroker、フォワードテスト・実運用では取引所につなぐ実装を使う
+pub trait Broker {
+    fn get_cash(&self) -> f64;
+
+    fn get_position(&self) -> Position;
+
+    // 直近の価格での評価額(資金 + 建玉の時価)
+    fn get_equity(&self) -> f64;
+
+    // 出した順に並んだ未約定の注文
+    fn get_pending_orders(&self) -> &[Order];
+
+    // 注文を出す
+    fn submit(&mut self, request: &OrderRequest) -> AtbBacktestResult<OrderId>;
+
+    // 未約定の注文を取り消す(取り消せたらtrue)
+    fn cancel(&mut self, id: OrderId) -> bool;
+}
+
+// バックテスト用にローソク足から約定を推定するBroker
+// 注文はストラテジーが見ているローソク足の次の足から約定の対象になる(先読みしない)
+//   - 成行注文は次の足の始値で約定する
+//   - 指値・逆指値注文は足の中の値動き(IntrabarPath)をたどり、最初に条件を満たした価格で約定する
+//     始値の時点で条件を満たしていれば始値で約定する(窓を開けて飛び越えた場合を含む)
+//   - 同じ足で約定する注文は値動きの中で約定した順(同じ位置なら出した順)に反映する
+//   - ボットで許可されていない方向の建玉が増える約定は、約定させずに注文を取り消す
+//   - テイカーの約定はスプレッドの半分とスリッページの分だけ不利な価格で約定し、呼値に丸める
+//   - 約定代金に約定した時点の手数料(メイカー・テイカー)をかけた額を資金から差し引く
+//   - 建玉が増える約定で、約定後の建玉の代金が評価額 × レバレッジを超える場合は注文を取り消す
+//   - 最小注文数量の倍数でない注文は受け付けない
+pub struct SimBroker {
+    cash: f64,
+    position: Position,
+    bot: BotSettings,
+    path: IntrabarPath,
+    fee_schedule: FeeSchedule,
+    slippage: Vec<Arc<dyn SlippageModel>>,
+    // 評価額に対して持てる建玉の代金の倍率(1.0なら現物と同じく資金の範囲内)
+    leverage: f64,
+    market_rule: MarketRule,
+    next_order_id: OrderId,
+    pending: Vec<Order>,
+    // 取り消した・期限切れになった注文
+    canceled: Vec<Order>,
+    fills: Vec<Fill>,
+    trades: Vec<Trade>,
+    // 確定したローソク足の時刻・終値
+    unixtime: UnixTime,
+    price: f64,
+}
+
+impl SimBroker {
+    pub(crate) fn new(cash: f64, bot: &BotSettings) -> SimBroker {
+        SimBroker {
+            cash,
+            position: Position::default(),
+            bot: bot.clone(),
+            path: IntrabarPath::default(),
+            fee_schedule: FeeSchedule::default(),
+            slippage: vec![],
+            leverage: 1.0,
+            market_rule: MarketRule::default(),
+            next_order_id: 1,
+            pending: vec![],
+            canceled: vec![],
+            fills: vec![],
+            trades: vec![],
+            unixtime: UnixTime::new(0),
+            price: 0.0,
+        }
+    }
+
+    pub(crate) fn intrabar_path(mut self, path: IntrabarPath) -> SimBroker {
+        self.path = path;
+        self
+    }
+
+    pub(crate) fn fee_schedule(mut self, fee_schedule: &FeeSchedule) -> SimBroker {
+        self.fee_schedule = fee_schedule.clone();
+        self
+    }
+
+    pub(crate) fn slippage(mut self, slippage: &[Arc<dyn SlippageModel>]) -> SimBroker {
+        self.slippage = slippage.to_vec();
+        self
+    }
+
+    pub(crate) fn leverage(mut self, leverage: f64) -> SimBroker {
+        self.leverage = leverage;
+        self
+    }
+
+    pub(crate) fn market_rule(mut self, market_rule: &MarketRule) -> SimBroker {
+        self.market_rule = *market_rule;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_canceled_orders(&self) -> &[Order] {
+        &self.canceled
+    }
+
+    #[allow(dead_code)]
+    pub fn get_fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    #[allow(dead_code)]
+    pub fn get_trades(&self) -> &[Trade] {
+        &self.trades
+    }
+
+    // 新しいローソク足で未約定の注文を約定させ、約定した件数を返す
+    pub(crate) fn open_bar(&mut self, bar: &OhlcvBar) -> usize {
+        let points = self.path.points(bar, &self.position);
+
+        let mut executions: Vec<(Execution, Order)> = vec![];
+        for mut order in std::mem::take(&mut self.pending) {
+            if order.is_expired(bar.get_unixtime()) {
+                self.canceled.push(order);
+                continue;
+            }
+            match execute(&mut order, &points) {
+                Some(execution) => executions.push((execution, order)),
+                None if order.get_time_in_force() == crate::TimeInForce::Ioc => {
+                    self.canceled.push(order)
+                }
+                None => self.pending.push(order),
+            }
+        }
+        executions.sort_by(|(a, a_order), (b, b_order)| {
+            a.progress
+                .partial_cmp(&b.progress)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a_order.get_id().cmp(&b_order.get_id()))
+        });
+
+        let mut count = 0;
+        for (execution, order) in executions {
+            if !self.is_allowed(&order) {
+                self.canceled.push(order);
+                continue;
+            }
+            let fill = self.fill(&order, &execution, bar);
+            if !self.is_affordable(&fill) {
+                self.canceled.push(order);
+                continue;
+            }
+            self.cash -= fill.get_side().sign() * fill.get_notional() + fill.get_fee();
+            if let Some(trade) = self.position.apply(&fill) {
+                self.trades.push(trade);
+            }
+            self.fills.push(fill);
+            count += 1;
+        }
+        count
+    }
+
+    // 約定価格に手数料・スプレッド・スリッページを反映する
+    fn fill(&self, order: &Order, execution: &Execution, bar: &OhlcvBar) -> Fill {
+        let rate = self.fee_schedule.get_rate(bar.get_unixtime());
+        let price = match execution.liquidity {
+            Liquidity::Maker => execution.price,
+            Liquidity::Taker => {
+                let slippage: f64 = self
+                    .slippage
+                    .iter()
+                    .map(|model| model.ratio(bar, order.get_size()))
+                    .sum();
+                // 売りの約定価格が負にならないよう比率は0〜1にする
+                let ratio = (rate.get_spread() / 2.0 + slippage).clamp(0.0, 1.0);
+                let price = execution.price * (1.0 + order.get_side().sign() * ratio);
+                self.market_rule.round_price(price, order.get_side())
+            }
+        };
+        let slippage = (price - execution.price).abs() * order.get_size();
+        let fee = price * order.get_size() * rate.get_fee(execution.liquidity);
+        Fill::new(order, price, bar.get_unixtime()).costs(execution.liquidity, fee, slippage)
+    }
+
+    // 約定後の建玉がボットで許可されている範囲か
+    fn is_allowed(&self, order: &Order) -> bool {
+        let current = self.position.get_size();
+        let next = current + order.get_side().sign() * order.get_size();
+        let long_grows = 0.0 < next && current.max(0.0) < next;
+        let short_grows = next < 0.0 && next < current.min(0.0);
+        (self.bot.is_long_order() || !long_grows) && (self.bot.is_short_order() || !short_grows)
+    }
+
+    // 建玉が増える約定で、約定後の建玉の代金が評価額 × レバレッジ以内か
+    // 評価額は約定後の資金(手数料を差し引いた額)と約定価格で評価した建玉の合計
+    fn is_affordable(&self, fill: &Fill) -> bool {
+        let current = self.position.get_size();
+        let next = current + fill.get_side().sign() * fill.get_size();
+        if next.abs() <= current.abs() {
+            return true;
+        }
+        let cash = self.cash - fill.get_side().sign() * fill.get_notional() - fill.get_fee();
+        let equity = cash + next * fill.get_price();
+        next.abs() * fill.get_price() <= equity * self.leverage
+    }
+
+    // ローソク足が確定した(ストラテジーに渡す前に呼ぶ)
+    pub(crate) fn close_bar(&mut self, bar: &OhlcvBar) {
+        self.unixtime = bar.get_unixtime();
+        self.price = bar.get_close().to_f64();
+    }
+
+    // 結果に含める内容を取り出す
+    pub(crate) fn finish(self) -> SimBrokerResult {
+        SimBrokerResult {
+            cash: self.cash,
+            position: self.position,
+            open_orders: self.pending,
+            canceled_orders: self.canceled,
+            fills: self.fills,
+            trades: self.trades,
+        }
+    }
+}
+
+// 終了時のSimBrokerの状態
+pub(crate) struct SimBrokerResult {
+    pub(crate) cash: f64,
+    pub(crate) position: Position,
+    pub(crate) open_orders: Vec<Order>,
+    pub(crate) canceled_orders: Vec<Order>,
+    pub(crate) fills: Vec<Fill>,
+    pub(crate) trades: Vec<Trade>,
+}
+
+impl Broker for SimBroker {
+    fn get_cash(&self) -> f64 {
+        self.cash
+    }
+
+    fn get_position(&self) -> Position {
+        self.position
+    }
+
+    fn get_equity(&self) -> f64 {
+        self.cash + self.position.get_size() * self.price
+    }
+
+    fn get_pending_orders(&self) -> &[Order] {
+        &self.pending
+    }
+
+    fn submit(&mut self, request: &OrderRequest) -> AtbBacktestResult<OrderId> {
+        request.validate()?;
+        self.market_rule.validate_size(request.get_size())?;
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+        self.pending.push(Order::new(id, request, self.unixtime));
+        Ok(id)
+    }
+
+    fn cancel(&mut self, id: OrderId) -> bool {
+        match self.pending.iter().position(|order| order.get_id() == id) {
+            Some(index) => {
+                let order = self.pending.remove(index);
+                self.canceled.push(order);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn bar(unixtime: i64, open: i64, high: i64, low: i64, close: i64) -> atb_db::OhlcvBar {
+        use atb_db::Decimal;
+        atb_db::OhlcvBar::new(
+            atb_db::UnixTime::new(unixtime),
+            Decimal::new(open, 0),
+            Decimal::new(high, 0),
+            Decimal::new(low, 0),
+            Decimal::new(close, 0),
+            Decimal::new(1, 0),
+        )
+        .unwrap()
+    }
+
+    fn broker() -> super::SimBroker {
+        super::SimBroker::new(1000.0, &crate::BotSettings::new(1))
+    }
+
+    #[test]
+    fn market_order() {
+        use super::*;
+        use crate::Side;
+        let mut broker = broker();
+        broker.close_bar(&bar(60, 100, 105, 100, 105));
+        let id = broker
+            .submit(&OrderRequest::market(Side::Buy, 2.0))
+            .unwrap();
+        assert_eq!(broker.get_pending_orders().len(), 1);
+        assert!(broker
+            .submit(&OrderRequest::market(Side::Buy, 0.0))
+            .is_err());
+
+        // 次の足の始値で約定する
+        assert_eq!(broker.open_bar(&bar(120, 110, 120, 110, 120)), 1);
+        broker.close_bar(&bar(120, 110, 120, 110, 120));
+        assert!(broker.get_pending_orders().is_empty());
+        assert_eq!(broker.get_fills()[0].get_order_id(), id);
+        assert_eq!(broker.get_fills()[0].get_price(), 110.0);
+        assert_eq!(broker.get_cash(), 780.0);
+        assert_eq!(broker.get_equity(), 1020.0);
+
+        broker
+            .submit(&OrderRequest::market(Side::Sell, 2.0))
+            .unwrap();
+        broker.open_bar(&bar(180, 130, 130, 130, 130));
+        assert_eq!(broker.get_cash(), 1040.0);
+        assert_eq!(broker.get_trades()[0].get_pnl(), 40.0);
+        assert!(broker.get_position().is_flat());
+    }
+
+    #[test]
+    fn bracket_orders() {
+        use super::*;
+        use crate::Side;
+        // ロング1に利確の指値と損切りの逆指値を出す
+        let mut broker = broker();
+        broker
+            .submit(&OrderRequest::market(Side::Buy, 1.0))
+            .unwrap();
+        broker.open_bar(&bar(60, 100, 100, 100, 100));
+        broker
+            .submit(&OrderRequest::limit(Side::Sell, 1.0, 110.0))
+            .unwrap();
+        broker
+            .submit(&OrderRequest::stop(Side::Sell, 1.0, 90.0))
+            .unwrap();
+
+        // 両方に届く足: 始値→高値→安値→終値なら利確が先に約定する
+        // 損切りは約定するとショートになるが、ロングのみ許可なので取り消す
+        let mut long_only = SimBroker::new(1000.0, &crate::BotSettings::new(1).short_order(false));
+        long_only
+            .submit(&OrderRequest::market(Side::Buy, 1.0))
+            .unwrap();
+        long_only.open_bar(&bar(60, 100, 100, 100, 100));
+        long_only
+            .submit(&OrderRequest::limit(Side::Sell, 1.0, 110.0))
+            .unwrap();
+        long_only
+            .submit(&OrderRequest::stop(Side::Sell, 1.0, 90.0))
+            .unwrap();
+        assert_eq!(long_only.open_bar(&bar(120, 100, 115, 85, 95)), 1);
+        assert_eq!(long_only.get_trades()[0].get_pnl(), 10.0);
+        assert!(long_only.get_position().is_flat());
+        assert_eq!(long_only.get_canceled_orders().len(), 1);
+
+        // 不利な方向を先にたどると損切りが先に約定する
+        let mut broker = broker.intrabar_path(IntrabarPath::WorstCase);
+        assert_eq!(broker.open_bar(&bar(120, 100, 115, 85, 95)), 2);
+        assert_eq!(broker.get_fills()[1].get_price(), 90.0);
+        assert_eq!(broker.get_fills()[2].get_price(), 110.0);
+        assert_eq!(broker.get_trades()[0].get_pnl(), -10.0);
+        assert_eq!(broker.get_position().get_size(), -1.0);
+    }
+
+    #[test]
+    fn fee_and_slippage() {
+        use super::*;
+        use crate::{FixedBps, Side};
+        let slippage: Vec<Arc<dyn SlippageModel>> = vec![Arc::new(FixedBps::new(10.0))];
+        let mut broker = broker()
+            .fee_schedule(&FeeSchedule::flat(-0.001, 0.002, 0.004))
+            .slippage(&slippage);
+
+        // テイカー: スプレッドの半分(0.2%)とスリッページ(0.1%)だけ高く買い、0.2%の手数料を払う
+        broker
+            .submit(&OrderRequest::market(Side::Buy, 2.0))
+            .unwrap();
+        broker.open_bar(&bar(60, 100, 100, 100, 100));
+        let fill = &broker.get_fills()[0];
+        assert_eq!(fill.get_liquidity(), Liquidity::Taker);
+        assert!((fill.get_price() - 100.3).abs() < 1e-9);
+        assert!((fill.get_slippage() - 0.6).abs() < 1e-9);
+        assert!((fill.get_fee() - 0.4012).abs() < 1e-9);
+        assert!((broker.get_cash() - (1000.0 - 200.6 - 0.4012)).abs() < 1e-9);
+
+        // メイカー: 指値で約定し、リベートを受け取る
+        broker
+            .submit(&OrderRequest::limit(Side::Sell, 2.0, 110.0))
+            .unwrap();
+        broker.open_bar(&bar(120, 100, 115, 100, 110));
+        let fill = &broker.get_fills()[1];
+        assert_eq!(fill.get_liquidity(), Liquidity::Maker);
+        assert_eq!(fill.get_price(), 110.0);
+        assert_eq!(fill.get_slippage(), 0.0);
+        assert!((fill.get_fee() + 0.22).abs() < 1e-9);
+
+        // 取引の損益は手数料を差し引いた額
+        let trade = &broker.get_trades()[0];
+        assert!((trade.get_fee() - (0.4012 - 0.22)).abs() < 1e-9);
+        assert!((trade.get_pnl() - (19.4 - 0.1812)).abs() < 1e-9);
+        assert!((broker.get_cash() - (1000.0 + trade.get_pnl())).abs() < 1e-9);
+
+        // 窓を開けて指値を飛び越えた場合は始値のテイカーとして、スプレッド・スリッページと手数料を払う
+        broker
+            .submit(&OrderRequest::limit(Side::Buy, 2.0, 90.0))
+            .unwrap();
+        broker.open_bar(&bar(180, 80, 85, 75, 82));
+        let fill = &broker.get_fills()[2];
+        assert_eq!(fill.get_liquidity(), Liquidity::Taker);
+        assert!((fill.get_price() - 80.24).abs() < 1e-9);
+        assert!((fill.get_slippage() - 0.48).abs() < 1e-9);
+        assert!((fill.get_fee() - 0.32096).abs() < 1e-9);
+        broker
+            .submit(&OrderRequest::limit(Side::Sell, 2.0, 110.0))
+            .unwrap();
+        broker.open_bar(&bar(240, 120, 125, 115, 118));
+        let fill = &broker.get_fills()[3];
+        assert_eq!(fill.get_liquidity(), Liquidity::Taker);
+        assert!((fill.get_price() - 119.64).abs() < 1e-9);
+        assert!((fill.get_slippage() - 0.72).abs() < 1e-9);
+        assert!((fill.get_fee() - 0.47856).abs() < 1e-9);
+    }
+
+    #[test]
+    fn buying_power() {
+        use super::*;
+        use crate::Side;
+        let fee_schedule = FeeSchedule::flat(0.0, 0.01, 0.0);
+
+        // 代金と手数料が資金を超える注文は約定させずに取り消す
+        let mut spot = broker().fee_schedule(&fee_schedule);
+        spot.submit(&OrderRequest::market(Side::Buy, 10.0)).unwrap();
+        assert_eq!(spot.open_bar(&bar(60, 100, 100, 100, 100)), 0);
+        assert_eq!(spot.get_canceled_orders().len(), 1);
+        assert_eq!(spot.get_cash(), 1000.0);
+        spot.submit(&OrderRequest::market(Side::Buy, 9.0)).unwrap();
+        assert_eq!(spot.open_bar(&bar(120, 100, 100, 100, 100)), 1);
+        assert_eq!(spot.get_cash(), 91.0);
+
+        // 建玉を減らす約定は常に約定する
+        spot.submit(&OrderRequest::market(Side::Sell, 9.0)).unwrap();
+        assert_eq!(spot.open_bar(&bar(180, 50, 50, 50, 50)), 1);
+        assert!(spot.get_position().is_flat());
+
+        // ショートも評価額の範囲内
+        let mut short = broker();
+        short
+            .submit(&OrderRequest::market(Side::Sell, 11.0))
+            .unwrap();
+        assert_eq!(short.open_bar(&bar(60, 100, 100, 100, 100)), 0);
+
+        // レバレッジを指定すると評価額の倍率まで建玉を持てる
+        let mut leveraged = broker().leverage(2.0);
+        leveraged
+            .submit(&OrderRequest::market(Side::Buy, 20.0))
+            .unwrap();
+        leveraged
+            .submit(&OrderRequest::market(Side::Buy, 1.0))
+            .unwrap();
+        assert_eq!(leveraged.open_bar(&bar(60, 100, 100, 100, 100)), 1);
+        assert_eq!(leveraged.get_position().get_size(), 20.0);
+        assert_eq!(leveraged.get_cash(), -1000.0);
+        assert_eq!(leveraged.get_canceled_orders().len(), 1);
+    }
+
+    #[test]
+    fn market_rule() {
+        use super::*;
+        use crate::{FixedBps, Side};
+        let slippage: Vec<Arc<dyn SlippageModel>> = vec![Arc::new(FixedBps::new(10.0))];
+        let mut broker = broker()
+            .slippage(&slippage)
+            .market_rule(&MarketRule::new(1.0, 0.01));
+
+        // 最小注文数量の倍数でない注文は受け付けない
+        assert!(broker
+            .submit(&OrderRequest::market(Side::Buy, 0.015))
+            .is_err());
+        assert!(broker.get_pending_orders().is_empty());
+
+        // スリッページ後の価格は不利な方向に呼値へ丸める(100.1 -> 101、99.9 -> 99)
+        broker
+            .submit(&OrderRequest::market(Side::Buy, 2.0))
+            .unwrap();
+        broker.open_bar(&bar(60, 100, 100, 100, 100));
+        assert_eq!(broker.get_fills()[0].get_price(), 101.0);
+        assert_eq!(broker.get_fills()[0].get_slippage(), 2.0);
+        broker
+            .submit(&OrderRequest::market(Side::Sell, 2.0))
+            .unwrap();
+        broker.open_bar(&bar(120, 100, 100, 100, 100));
+        assert_eq!(broker.get_fills()[1].get_price(), 99.0);
+    }
+
+    #[test]
+    fn cancel_and_expire() {
+        use super::*;
+        use crate::{Side, TimeInForce};
+        let mut broker = broker();
+        let id = broker
+            .submit(&OrderRequest::market(Side::Sell, 1.0))
+            .unwrap();
+        assert!(broker.cancel(id));
+        assert!(!broker.cancel(id));
+        assert_eq!(broker.open_bar(&bar(60, 100, 100, 100, 100)), 0);
+        assert!(broker.get_fills().is_empty());
+
+        // 期限切れ・IOCで約定しなかった注文は取り消す
+        let gtd = TimeInForce::Gtd(UnixTime::new(120));
+        broker
+            .submit(&OrderRequest::limit(Side::Buy, 1.0, 50.0).time_in_force(gtd))
+            .unwrap();
+        broker
+            .submit(&OrderRequest::limit(Side::Buy, 1.0, 90.0).time_in_force(TimeInForce::Ioc))
+            .unwrap();
+        broker.open_bar(&bar(120, 100, 100, 80, 80));
+        assert_eq!(broker.get_pending_orders().len(), 1);
+        broker.open_bar(&bar(180, 100, 100, 100, 100));
+        assert!(broker.get_pending_orders().is_empty());
+        assert_eq!(broker.get_canceled_orders().len(), 3);
+        assert!(broker.get_fills().is_empty());
+    }
+}
@@ -0,0 +1,232 @@
+use crate::{
+    indicator, AtbBacktestError, AtbBacktestResult, Broker, History, Order, OrderId, OrderRequest,
+    OrderType, Position, Side,
+};
+use atb_db::{Bot, OhlcvBar};
+
+// ストラテジーが従うボットの設定(atb-dbに登録したボットから作る)
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct BotSettings {
+    id: i64,
+    // ロング・ショートの建玉を持ってよいか
+    long_order: bool,
+    short_order: bool,
+}
+
+impl BotSettings {
+    // ロング・ショートとも許可した設定(テストなど)
+    pub fn new(id: i64) -> BotSettings {
+        BotSettings {
+            id,
+            long_order: true,
+            short_order: true,
+        }
+    }
+
+    pub fn long_order(mut self, long_order: bool) -> BotSettings {
+        self.long_order = long_order;
+        self
+    }
+
+    pub fn short_order(mut self, short_order: bool) -> BotSettings {
+        self.short_order = short_order;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_id(&self) -> i64 {
+        self.id
+    }
+
+    #[allow(dead_code)]
+    pub fn is_long_order(&self) -> bool {
+        self.long_order
+    }
+
+    #[allow(dead_code)]
+    pub fn is_short_order(&self) -> bool {
+        self.short_order
+    }
+}
+
+impl From<&Bot> for BotSettings {
+    fn from(bot: &Bot) -> BotSettings {
+        BotSettings {
+            id: bot.get_id(),
+            long_order: bot.is_long_order(),
+            short_order: bot.is_short_order(),
+        }
+    }
+}
+
+// ストラテジーに渡す実行中の状態
+// 確定した足・指標・建玉を参照し、Broker経由で注文を出す
+pub struct Context<'a> {
+    bot: &'a BotSettings,
+    history: &'a History,
+    broker: &'a mut dyn Broker,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(
+        bot: &'a BotSettings,
+        history: &'a History,
+        broker: &'a mut dyn Broker,
+    ) -> Context<'a> {
+        Context {
+            bot,
+            history,
+            broker,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_bot(&self) -> &BotSettings {
+        self.bot
+    }
+
+    pub fn get_bot_id(&self) -> i64 {
+        self.bot.id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_history(&self) -> &History {
+        self.history
+    }
+
+    // 最新の確定した足
+    pub fn get_bar(&self) -> Option<&OhlcvBar> {
+        self.history.last()
+    }
+
+    pub fn get_cash(&self) -> f64 {
+        self.broker.get_cash()
+    }
+
+    pub fn get_position(&self) -> Position {
+        self.broker.get_position()
+    }
+
+    pub fn get_equity(&self) -> f64 {
+        self.broker.get_equity()
+    }
+
+    pub fn get_pending_orders(&self) -> &[Order] {
+        self.broker.get_pending_orders()
+    }
+
+    // 注文を出す
+    // ボットで許可されていない方向の建玉が増える注文はエラーにする
+    // (指値・逆指値注文は約定する時点でもSimBrokerが確認する)
+    // 最小注文数量・資金の範囲内かはBrokerが確認する(SimBrokerは資金が足りない約定を取り消す)
+    pub fn submit(&mut self, request: OrderRequest) -> AtbBacktestResult<OrderId> {
+        // 未約定の成行注文がすべて約定した後の建玉で判断する
+        let current = self.get_position().get_size()
+            + self
+                .get_pending_orders()
+                .iter()
+                .filter(|order| order.get_order_type() == OrderType::Market)
+                .map(|order| order.get_side().sign() * order.get_size())
+                .sum::<f64>();
+        let next = current + request.get_side().sign() * request.get_size();
+        if !self.bot.long_order && 0.0 < next && current.max(0.0) < next {
+            return Err(AtbBacktestError::Validation(format!(
+                "ボット{}はロングの注文が許可されていません",
+                self.bot.id
+            )));
+        }
+        if !self.bot.short_order && next < 0.0 && next < current.min(0.0) {
+            return Err(AtbBacktestError::Validation(format!(
+                "ボット{}はショートの注文が許可されていません",
+                self.bot.id
+            )));
+        }
+        self.broker.submit(&request)
+    }
+
+    // 成行で買う
+    pub fn buy(&mut self, size: f64) -> AtbBacktestResult<OrderId> {
+        self.submit(OrderRequest::market(Side::Buy, size))
+    }
+
+    // 成行で売る
+    pub fn sell(&mut self, size: f64) -> AtbBacktestResult<OrderId> {
+        self.submit(OrderRequest::market(Side::Sell, size))
+    }
+
+    // 建玉をすべて決済する成行注文を出す(建玉がなければNone)
+    pub fn close_position(&mut self) -> AtbBacktestResult<Option<OrderId>> {
+        match self.get_position().closing_order() {
+            Some((side, size)) => self.submit(OrderRequest::market(side, size)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // 未約定の注文を取り消す(取り消せたらtrue)
+    pub fn cancel(&mut self, id: OrderId) -> bool {
+        self.broker.cancel(id)
+    }
+
+    // 終値の単純移動平均
+    pub fn sma(&self, period: usize) -> Option<f64> {
+        indicator::sma(self.history.get_closes(), period)
+    }
+
+    // 終値の指数移動平均
+    pub fn ema(&self, period: usize) -> Option<f64> {
+        indicator::ema(self.history.get_closes(), period)
+    }
+
+    // 終値の標準偏差
+    pub fn stddev(&self, period: usize) -> Option<f64> {
+        indicator::stddev(self.history.get_closes(), period)
+    }
+
+    // 終値のRSI
+    pub fn rsi(&self, period: usize) -> Option<f64> {
+        indicator::rsi(self.history.get_closes(), period)
+    }
+
+    // ATR
+    pub fn atr(&self, period: usize) -> Option<f64> {
+        indicator::atr(self.history.get_bars(), period)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn order_direction() {
+        use super::*;
+        let history = History::default();
+        // ロングのみ許可
+        let bot = BotSettings::new(1).short_order(false);
+        let mut broker = crate::SimBroker::new(1000.0, &bot);
+        let mut ctx = Context::new(&bot, &history, &mut broker);
+        assert_eq!(ctx.get_bot_id(), 1);
+        assert!(ctx.sell(1.0).is_err());
+        ctx.buy(2.0).unwrap();
+        // 未約定の買いを返済する売りは許可する
+        ctx.sell(2.0).unwrap();
+        assert!(ctx.sell(0.5).is_err());
+        // 約定するか分からない指値・逆指値注文は数えない
+        ctx.buy(1.0).unwrap();
+        ctx.submit(OrderRequest::limit(Side::Sell, 1.0, 110.0))
+            .unwrap();
+        ctx.submit(OrderRequest::stop(Side::Sell, 1.0, 90.0))
+            .unwrap();
+        assert!(ctx
+            .submit(OrderRequest::stop(Side::Sell, 2.0, 90.0))
+            .is_err());
+
+        // ショートのみ許可
+        let bot = BotSettings::new(2).long_order(false);
+        let mut broker = crate::SimBroker::new(1000.0, &bot);
+        let mut ctx = Context::new(&bot, &history, &mut broker);
+        assert!(ctx.buy(1.0).is_err());
+        ctx.sell(1.0).unwrap();
+        ctx.buy(1.0).unwrap();
+        assert_eq!(ctx.get_pending_orders().len(), 2);
+        assert_eq!(ctx.close_position().unwrap(), None);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::Liquidity;
+use atb_db::{MarketFee, UnixTime};
+
+// ある時点から適用する手数料・スプレッド(約定代金に対する比率)
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct FeeRate {
+    valid_from: i64,
+    // 負ならリベート
+    maker_fee: f64,
+    taker_fee: f64,
+    spread: f64,
+}
+
+impl FeeRate {
+    pub fn new(valid_from: i64, maker_fee: f64, taker_fee: f64, spread: f64) -> FeeRate {
+        FeeRate {
+            valid_from,
+            maker_fee,
+            taker_fee,
+            spread,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_valid_from(&self) -> i64 {
+        self.valid_from
+    }
+
+    #[allow(dead_code)]
+    pub fn get_maker_fee(&self) -> f64 {
+        self.maker_fee
+    }
+
+    #[allow(dead_code)]
+    pub fn get_taker_fee(&self) -> f64 {
+        self.taker_fee
+    }
+
+    #[allow(dead_code)]
+    pub fn get_spread(&self) -> f64 {
+        self.spread
+    }
+
+    // 約定の種類に応じた手数料
+    pub fn get_fee(&self, liquidity: Liquidity) -> f64 {
+        match liquidity {
+            Liquidity::Maker => self.maker_fee,
+            Liquidity::Taker => self.taker_fee,
+        }
+    }
+}
+
+impl From<&MarketFee> for FeeRate {
+    fn from(fee: &MarketFee) -> FeeRate {
+        FeeRate::new(
+            fee.get_valid_from(),
+            fee.get_maker_fee().to_f64(),
+            fee.get_taker_fee().to_f64(),
+            fee.get_spread().to_f64(),
+        )
+    }
+}
+
+// 市場の手数料体系(約定した足の時刻で適用する手数料を選ぶ)
+// 既定値は手数料・スプレッドなし
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct FeeSchedule {
+    // 適用開始日時の順
+    rates: Vec<FeeRate>,
+}
+
+impl FeeSchedule {
+    pub fn new(mut rates: Vec<FeeRate>) -> FeeSchedule {
+        rates.sort_by_key(|rate| rate.valid_from);
+        FeeSchedule { rates }
+    }
+
+    // 期間を通して同じ手数料体系
+    pub fn flat(maker_fee: f64, taker_fee: f64, spread: f64) -> FeeSchedule {
+        FeeSchedule::new(vec![FeeRate::new(0, maker_fee, taker_fee, spread)])
+    }
+
+    #[allow(dead_code)]
+    pub fn get_rates(&self) -> &[FeeRate] {
+        &self.rates
+    }
+
+    // 指定した時刻に適用する手数料(適用開始前なら手数料なし)
+    pub fn get_rate(&self, unixtime: UnixTime) -> FeeRate {
+        self.rates
+            .iter()
+            .rev()
+            .find(|rate| rate.valid_from <= unixtime.get_secs())
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl From<&[MarketFee]> for FeeSchedule {
+    fn from(fees: &[MarketFee]) -> FeeSchedule {
+        FeeSchedule::new(fees.iter().map(FeeRate::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn fee_schedule() {
+        use super::*;
+        use atb_db::Decimal;
+        let fees = [
+            MarketFee::new(
+                "bitflyer",
+                "btcjpy",
+                120,
+                Decimal::new(-1, 4),
+                Decimal::new(5, 4),
+                Decimal::zero(0),
+            ),
+            MarketFee::new(
+                "bitflyer",
+                "btcjpy",
+                0,
+                Decimal::new(15, 4),
+                Decimal::new(15, 4),
+                Decimal::new(2, 4),
+            ),
+        ];
+        let schedule = FeeSchedule::from(&fees[..]);
+        assert_eq!(schedule.get_rates()[0].get_valid_from(), 0);
+
+        let rate = schedule.get_rate(UnixTime::new(60));
+        assert_eq!(rate.get_fee(Liquidity::Maker), 0.0015);
+        assert_eq!(rate.get_spread(), 0.0002);
+        let rate = schedule.get_rate(UnixTime::new(120));
+        assert_eq!(rate.get_fee(Liquidity::Maker), -0.0001);
+        assert_eq!(rate.get_fee(Liquidity::Taker), 0.0005);
+
+        // 適用開始前・手数料体系なし
+        let schedule = FeeSchedule::new(vec![FeeRate::new(120, 0.1, 0.1, 0.1)]);
+        assert_eq!(schedule.get_rate(UnixTime::new(60)), FeeRate::default());
+        assert_eq!(
+            FeeSchedule::default().get_rate(UnixTime::new(60)),
+            FeeRate::default()
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use crate::{Order, OrderType, Position, Side, TimeInForce};
+use atb_db::OhlcvBar;
+
+// ローソク足の中での値動きの仮定
+// 足の中の値動きは分からないので、始値から高値・安値を経由して終値まで直線的に動いたとみなす
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum IntrabarPath {
+    // 始値→高値→安値→終値
+    #[default]
+    OpenHighLowClose,
+    // 始値→安値→高値→終値
+    OpenLowHighClose,
+    // 足の始めの建玉に不利な方向へ先に動く
+    // ロングなら始値→安値→高値→終値、ショート・建玉なしなら始値→高値→安値→終値
+    WorstCase,
+}
+
+impl IntrabarPath {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntrabarPath::OpenHighLowClose => "open_high_low_close",
+            IntrabarPath::OpenLowHighClose => "open_low_high_close",
+            IntrabarPath::WorstCase => "worst_case",
+        }
+    }
+
+    // 足の値動きを折れ線の頂点で返す
+    pub(crate) fn points(&self, bar: &OhlcvBar, position: &Position) -> [f64; 4] {
+        let open = bar.get_open().to_f64();
+        let high = bar.get_high().to_f64();
+        let low = bar.get_low().to_f64();
+        let close = bar.get_close().to_f64();
+        let low_first = match self {
+            IntrabarPath::OpenHighLowClose => false,
+            IntrabarPath::OpenLowHighClose => true,
+            IntrabarPath::WorstCase => position.is_long(),
+        };
+        if low_first {
+            [open, low, high, close]
+        } else {
+            [open, high, low, close]
+        }
+    }
+}
+
+// 約定の種類(手数料・スリッページの扱いが変わる)
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Liquidity {
+    // 板に並んでいた注文の約定(指値注文)
+    Maker,
+    // 板の注文を取る約定(成行・逆指値・IOC注文)
+    Taker,
+}
+
+impl Liquidity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Liquidity::Maker => "maker",
+            Liquidity::Taker => "taker",
+        }
+    }
+}
+
+// 値動きの中で注文が約定する位置と価格
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Execution {
+    // 折れ線の先頭からの進み具合(区間の番号 + 区間内の割合)、同じ足の約定の順序に使う
+    pub(crate) progress: f64,
+    pub(crate) price: f64,
+    pub(crate) liquidity: Liquidity,
+}
+
+impl Execution {
+    fn taker(mut self) -> Execution {
+        self.liquidity = Liquidity::Taker;
+        self
+    }
+}
+
+// 価格が指定した水準に達したか
+#[derive(Clone, Copy, Debug)]
+enum Touch {
+    AtOrBelow(f64),
+    AtOrAbove(f64),
+}
+
+impl Touch {
+    fn hit(&self, price: f64) -> bool {
+        match *self {
+            Touch::AtOrBelow(level) => price <= level,
+            Touch::AtOrAbove(level) => level <= price,
+        }
+    }
+
+    fn level(&self) -> f64 {
+        match *self {
+            Touch::AtOrBelow(level) | Touch::AtOrAbove(level) => level,
+        }
+    }
+
+    // 指値: 買いは指値以下、売りは指値以上
+    fn limit(side: Side, price: f64) -> Touch {
+        match side {
+            Side::Buy => Touch::AtOrBelow(price),
+            Side::Sell => Touch::AtOrAbove(price),
+        }
+    }
+
+    // 逆指値: 買いは逆指値以上、売りは逆指値以下
+    fn stop(side: Side, trigger: f64) -> Touch {
+        match side {
+            Side::Buy => Touch::AtOrAbove(trigger),
+            Side::Sell => Touch::AtOrBelow(trigger),
+        }
+    }
+}
+
+// startの位置から値動きをたどり、最初にtouchを満たす位置と価格を返す
+// startの時点で満たしていればその価格(窓を開けて飛び越えた場合など)でテイカーとする
+// (板に並ぶ前に約定できる注文なので、有利な価格とメイカーの手数料を両方は得られない)
+// 途中で満たした場合は水準ちょうどの価格になる
+fn find(points: &[f64; 4], start: Execution, touch: Touch) -> Option<Execution> {
+    if touch.hit(start.price) {
+        return Some(start.taker());
+    }
+    let segment = start.progress.floor() as usize;
+    for i in segment..points.len() - 1 {
+        let (from, to) = (points[i], points[i + 1]);
+        if touch.hit(to) {
+            let level = touch.level();
+            return Some(Execution {
+                progress: i as f64 + (level - from) / (to - from),
+                price: level,
+                liquidity: start.liquidity,
+            });
+        }
+    }
+    None
+}
+
+// 注文を足の値動きに当てはめ、約定する位置と価格を返す
+// IOCは始値でしか約定しない。ストップ・リミット注文が約定せずにtriggerに達した場合はorderに記録する
+// 指値注文はメイカー、それ以外(始値で約定する指値注文、triggerに達したその場で約定したストップ・リミット注文を含む)はテイカーとする
+pub(crate) fn execute(order: &mut Order, points: &[f64; 4]) -> Option<Execution> {
+    let ioc = order.get_time_in_force() == TimeInForce::Ioc;
+    let open = Execution {
+        progress: 0.0,
+        price: points[0],
+        liquidity: if ioc {
+            Liquidity::Taker
+        } else {
+            Liquidity::Maker
+        },
+    };
+    let path: &[f64; 4] = if ioc {
+        // 始値から動かない値動きとして扱う
+        &[points[0]; 4]
+    } else {
+        points
+    };
+
+    let side = order.get_side();
+    match order.get_order_type() {
+        OrderType::Market => Some(open.taker()),
+        OrderType::Limit { price } => find(path, open, Touch::limit(side, price)),
+        OrderType::Stop { trigger } => find(path, open.taker(), Touch::stop(side, trigger)),
+        OrderType::StopLimit { trigger, limit } => {
+            let start = if order.is_triggered() {
+                open
+            } else {
+                let triggered = find(path, open.taker(), Touch::stop(side, trigger))?;
+                order.trigger();
+                triggered
+            };
+            let execution = find(path, start, Touch::limit(side, limit))?;
+            if start.progress < execution.progress && !ioc {
+                Some(Execution {
+                    liquidity: Liquidity::Maker,
+                    ..execution
+                })
+            } else {
+                Some(execution)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn bar(open: i64, high: i64, low: i64, close: i64) -> atb_db::OhlcvBar {
+        use atb_db::Decimal;
+        atb_db::OhlcvBar::new(
+            atb_db::UnixTime::new(60),
+            Decimal::new(open, 0),
+            Decimal::new(high, 0),
+            Decimal::new(low, 0),
+            Decimal::new(close, 0),
+            Decimal::new(1, 0),
+        )
+        .unwrap()
+    }
+
+    fn order(request: crate::OrderRequest) -> crate::Order {
+        crate::Order::new(1, &request, atb_db::UnixTime::new(0))
+    }
+
+    fn price(request: crate::OrderRequest, points: &[f64; 4]) -> Option<f64> {
+        super::execute(&mut order(request), points).map(|e| e.price)
+    }
+
+    #[test]
+    fn intrabar_path() {
+        use super::*;
+        let bar = bar(100, 110, 90, 105);
+        let flat = Position::default();
+        assert_eq!(
+            IntrabarPath::OpenHighLowClose.points(&bar, &flat),
+            [100.0, 110.0, 90.0, 105.0]
+        );
+        assert_eq!(
+            IntrabarPath::OpenLowHighClose.points(&bar, &flat),
+            [100.0, 90.0, 110.0, 105.0]
+        );
+        assert_eq!(
+            IntrabarPath::WorstCase.points(&bar, &flat),
+            [100.0, 110.0, 90.0, 105.0]
+        );
+    }
+
+    #[test]
+    fn limit_and_stop() {
+        use super::*;
+        use crate::OrderRequest;
+        let points = [100.0, 110.0, 90.0, 105.0];
+
+        // 成行は始値
+        assert_eq!(
+            price(OrderRequest::market(Side::Buy, 1.0), &points),
+            Some(100.0)
+        );
+        // 足の中で触れた指値・逆指値はその価格
+        assert_eq!(
+            price(OrderRequest::limit(Side::Buy, 1.0, 95.0), &points),
+            Some(95.0)
+        );
+        assert_eq!(
+            price(OrderRequest::limit(Side::Sell, 1.0, 108.0), &points),
+            Some(108.0)
+        );
+        assert_eq!(
+            price(OrderRequest::stop(Side::Buy, 1.0, 108.0), &points),
+            Some(108.0)
+        );
+        assert_eq!(
+            price(OrderRequest::stop(Side::Sell, 1.0, 95.0), &points),
+            Some(95.0)
+        );
+        // 届かない
+        assert_eq!(
+            price(OrderRequest::limit(Side::Buy, 1.0, 85.0), &points),
+            None
+        );
+        assert_eq!(
+            price(OrderRequest::stop(Side::Buy, 1.0, 111.0), &points),
+            None
+        );
+
+        // 窓を開けて飛び越えた場合は始値(指値は有利、逆指値は不利な価格になる)
+        let gap_up = [120.0, 125.0, 115.0, 118.0];
+        assert_eq!(
+            price(OrderRequest::limit(Side::Sell, 1.0, 110.0), &gap_up),
+            Some(120.0)
+        );
+        assert_eq!(
+            price(OrderRequest::stop(Side::Buy, 1.0, 110.0), &gap_up),
+            Some(120.0)
+        );
+        let gap_down = [80.0, 85.0, 75.0, 82.0];
+        assert_eq!(
+            price(OrderRequest::limit(Side::Buy, 1.0, 90.0), &gap_down),
+            Some(80.0)
+        );
+        assert_eq!(
+            price(OrderRequest::stop(Side::Sell, 1.0, 90.0), &gap_down),
+            Some(80.0)
+        );
+        // 始値で約定する指値注文はテイカー
+        let mut limit = order(OrderRequest::limit(Side::Sell, 1.0, 110.0));
+        let execution = execute(&mut limit, &gap_up).unwrap();
+        assert_eq!(execution.liquidity, Liquidity::Taker);
+        let mut limit = order(OrderRequest::limit(Side::Buy, 1.0, 90.0));
+        let execution = execute(&mut limit, &gap_down).unwrap();
+        assert_eq!(execution.liquidity, Liquidity::Taker);
+        let mut limit = order(OrderRequest::limit(Side::Buy, 1.0, 101.0));
+        let execution = execute(&mut limit, &points).unwrap();
+        assert_eq!(execution.liquidity, Liquidity::Taker);
+
+        // 指値はメイカー、成行・逆指値はテイカー
+        let liquidity =
+            |request: OrderRequest| execute(&mut order(request), &points).unwrap().liquidity;
+        assert_eq!(
+            liquidity(OrderRequest::limit(Side::Buy, 1.0, 95.0)),
+            Liquidity::Maker
+        );
+        assert_eq!(
+            liquidity(OrderRequest::market(Side::Buy, 1.0)),
+            Liquidity::Taker
+        );
+        assert_eq!(
+            liquidity(OrderRequest::stop(Side::Buy, 1.0, 108.0)),
+            Liquidity::Taker
+        );
+
+        // 同じ足の中では値動きの順に約定する
+        let mut stop = order(OrderRequest::stop(Side::Sell, 1.0, 95.0));
+        let mut limit = order(OrderRequest::limit(Side::Sell, 1.0, 108.0));
+        let stop = execute(&mut stop, &points).unwrap();
+        let limit = execute(&mut limit, &points).unwrap();
+        assert!(limit.progress < stop.progress);
+        let reversed = [100.0, 90.0, 110.0, 105.0];
+        let mut stop = order(OrderRequest::stop(Side::Sell, 1.0, 95.0));
+        let mut limit = order(OrderRequest::limit(Side::Sell, 1.0, 108.0));
+        let stop = execute(&mut stop, &reversed).unwrap();
+        let limit = execute(&mut limit, &reversed).unwrap();
+        assert!(stop.progress < limit.progress);
+    }
+
+    #[test]
+    fn stop_limit() {
+        use super::*;
+        use crate::OrderRequest;
+        let points = [100.0, 110.0, 90.0, 105.0];
+
+        // 108で指値108以下の買いになり、その場で約定する
+        let request = OrderRequest::stop_limit(Side::Buy, 1.0, 108.0, 109.0);
+        assert_eq!(price(request, &points), Some(108.0));
+
+        // 108で指値95以下の買いになり、下落して95で約定する
+        let request = OrderRequest::stop_limit(Side::Buy, 1.0, 108.0, 95.0);
+        assert_eq!(price(request, &points), Some(95.0));
+
+        // triggerに達したその場で約定した場合はテイカー、その後に約定した場合はメイカー
+        let mut taker = order(OrderRequest::stop_limit(Side::Buy, 1.0, 108.0, 109.0));
+        let execution = execute(&mut taker, &points).unwrap();
+        assert_eq!(execution.liquidity, Liquidity::Taker);
+        let mut maker = order(OrderRequest::stop_limit(Side::Buy, 1.0, 108.0, 95.0));
+        let execution = execute(&mut maker, &points).unwrap();
+        assert_eq!(execution.liquidity, Liquidity::Maker);
+
+        // 安値が先の値動きでは、triggerに達した後に95まで下がらない
+        let mut order = order(OrderRequest::stop_limit(Side::Buy, 1.0, 108.0, 95.0));
+        assert_eq!(execute(&mut order, &[100.0, 90.0, 110.0, 105.0]), None);
+        assert!(order.is_triggered());
+        // 次の足では指値注文として扱う
+        let next = execute(&mut order, &[100.0, 101.0, 94.0, 96.0]).unwrap();
+        assert_eq!(next.price, 95.0);
+        assert_eq!(next.liquidity, Liquidity::Maker);
+
+        // 窓を開けてtriggerと指値を飛び越えた場合は約定しない
+        let request = OrderRequest::stop_limit(Side::Buy, 1.0, 108.0, 109.0);
+        assert_eq!(price(request, &[120.0, 125.0, 115.0, 118.0]), None);
+    }
+
+    #[test]
+    fn immediate_or_cancel() {
+        use super::*;
+        use crate::{OrderRequest, TimeInForce};
+        let points = [100.0, 110.0, 90.0, 105.0];
+        let ioc = |request: OrderRequest| request.time_in_force(TimeInForce::Ioc);
+
+        assert_eq!(
+            price(ioc(OrderRequest::market(Side::Sell, 1.0)), &points),
+            Some(100.0)
+        );
+        assert_eq!(
+            price(ioc(OrderRequest::limit(Side::Buy, 1.0, 95.0)), &points),
+            None
+        );
+        assert_eq!(
+            price(ioc(OrderRequest::limit(Side::Buy, 1.0, 101.0)), &points),
+            Some(100.0)
+        );
+        let mut limit = order(ioc(OrderRequest::limit(Side::Buy, 1.0, 101.0)));
+        assert_eq!(
+            execute(&mut limit, &points).unwrap().liquidity,
+            Liquidity::Taker
+        );
+        assert_eq!(
+            price(ioc(OrderRequest::stop(Side::Buy, 1.0, 99.0)), &points),
+            Some(100.0)
+        );
+        assert_eq!(
+            price(ioc(OrderRequest::stop(Side::Buy, 1.0, 105.0)), &points),
+            None
+        );
+    }
+}
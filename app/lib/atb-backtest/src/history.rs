@@ -0,0 +1,100 @@
+use crate::{AtbBacktestError, AtbBacktestResult};
+use atb_db::OhlcvBar;
+
+// 確定したローソク足(古い順)
+// フォワードテスト・実運用のエンジンは、足が確定するたびにpushしてContextに渡す
+#[derive(Clone, Debug, Default)]
+pub struct History {
+    bars: Vec<OhlcvBar>,
+    // 指標の計算用に終値を保持する
+    closes: Vec<f64>,
+}
+
+impl History {
+    // 確定した足を追加する(最新の足より新しい足のみ)
+    pub fn push(&mut self, bar: OhlcvBar) -> AtbBacktestResult<()> {
+        self.check_next(&bar)?;
+        self.closes.push(bar.get_close().to_f64());
+        self.bars.push(bar);
+        Ok(())
+    }
+
+    // 次に追加する足が最新の足より新しいか確認する
+    pub(crate) fn check_next(&self, bar: &OhlcvBar) -> AtbBacktestResult<()> {
+        if let Some(last) = self.last() {
+            let (last, next) = (
+                last.get_unixtime().get_secs(),
+                bar.get_unixtime().get_secs(),
+            );
+            if next <= last {
+                return Err(AtbBacktestError::Validation(format!(
+                    "ローソク足が時刻順に並んでいません: {} -> {}",
+                    last, next
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.bars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bars.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_bars(&self) -> &[OhlcvBar] {
+        &self.bars
+    }
+
+    #[allow(dead_code)]
+    pub fn get_closes(&self) -> &[f64] {
+        &self.closes
+    }
+
+    // 最新の足
+    pub fn last(&self) -> Option<&OhlcvBar> {
+        self.bars.last()
+    }
+
+    // n本前の足(0なら最新の足)
+    pub fn ago(&self, n: usize) -> Option<&OhlcvBar> {
+        self.bars.len().checked_sub(n + 1).map(|i| &self.bars[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn history() {
+        use super::*;
+        use atb_db::{Decimal, UnixTime};
+        let bar = |t: i64, close: i64| {
+            let price = Decimal::new(close, 0);
+            OhlcvBar::new(
+                UnixTime::new(t),
+                price,
+                price,
+                price,
+                price,
+                Decimal::zero(0),
+            )
+            .unwrap()
+        };
+
+        let mut history = History::default();
+        assert!(history.is_empty());
+        assert!(history.last().is_none());
+        history.push(bar(60, 100)).unwrap();
+        history.push(bar(120, 101)).unwrap();
+        assert!(history.push(bar(120, 102)).is_err());
+        assert!(history.push(bar(60, 102)).is_err());
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get_closes(), &[100.0, 101.0]);
+        assert_eq!(history.ago(0).unwrap().get_unixtime().get_secs(), 120);
+        assert_eq!(history.ago(1).unwrap().get_unixtime().get_secs(), 60);
+        assert!(history.ago(2).is_none());
+    }
+}
@@ -0,0 +1,130 @@
+use atb_db::OhlcvBar;
+
+// EMA・RSIは直近の期間 × この倍率の本数から計算する
+// 切り捨てた部分の重みは(1 - 2 / (期間 + 1))^(期間 × 倍率)程度で無視できる
+const SMOOTHING_WINDOW: usize = 10;
+
+// 単純移動平均(本数が足りなければNone)
+pub fn sma(values: &[f64], period: usize) -> Option<f64> {
+    let window = last(values, period)?;
+    Some(window.iter().sum::<f64>() / period as f64)
+}
+
+// 指数移動平均(最初の期間の単純移動平均から始める)
+pub fn ema(values: &[f64], period: usize) -> Option<f64> {
+    last(values, period)?;
+    let values = &values[values.len().saturating_sub(period * SMOOTHING_WINDOW)..];
+    let alpha = 2.0 / (period as f64 + 1.0);
+    let mut ema = values[..period].iter().sum::<f64>() / period as f64;
+    for value in &values[period..] {
+        ema += alpha * (value - ema);
+    }
+    Some(ema)
+}
+
+// 標準偏差(母標準偏差)
+pub fn stddev(values: &[f64], period: usize) -> Option<f64> {
+    let window = last(values, period)?;
+    let mean = window.iter().sum::<f64>() / period as f64;
+    let variance = window.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / period as f64;
+    Some(variance.sqrt())
+}
+
+// RSI(Wilderの平滑化、0〜100)
+// 期間 + 1本の値が必要。値動きがなければ50を返す
+pub fn rsi(values: &[f64], period: usize) -> Option<f64> {
+    last(values, period + 1).filter(|_| 0 < period)?;
+    let values = &values[values.len().saturating_sub(period * SMOOTHING_WINDOW + 1)..];
+    let changes: Vec<f64> = values.windows(2).map(|w| w[1] - w[0]).collect();
+
+    let mut gain = changes[..period].iter().map(|c| c.max(0.0)).sum::<f64>() / period as f64;
+    let mut loss = changes[..period].iter().map(|c| (-c).max(0.0)).sum::<f64>() / period as f64;
+    for change in &changes[period..] {
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+    }
+
+    if gain + loss == 0.0 {
+        Some(50.0)
+    } else {
+        Some(100.0 * gain / (gain + loss))
+    }
+}
+
+// ATR(真の値幅の単純移動平均)
+// 最初の足は前の終値がないので期間 + 1本が必要
+pub fn atr(bars: &[OhlcvBar], period: usize) -> Option<f64> {
+    last(bars, period + 1).filter(|_| 0 < period)?;
+    let ranges: Vec<f64> = bars[bars.len() - period - 1..]
+        .windows(2)
+        .map(|w| {
+            let prev_close = w[0].get_close().to_f64();
+            let high = w[1].get_high().to_f64().max(prev_close);
+            let low = w[1].get_low().to_f64().min(prev_close);
+            high - low
+        })
+        .collect();
+    sma(&ranges, period)
+}
+
+// 末尾のn件(足りない・nが0ならNone)
+fn last<T>(values: &[T], n: usize) -> Option<&[T]> {
+    if n == 0 || values.len() < n {
+        None
+    } else {
+        Some(&values[values.len() - n..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn moving_average() {
+        use super::*;
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(sma(&values, 3), Some(4.0));
+        assert_eq!(sma(&values, 6), None);
+        assert_eq!(sma(&values, 0), None);
+
+        // 2, (4 - 2) * 0.5 + 2 = 3, (5 - 3) * 0.5 + 3 = 4
+        assert_eq!(ema(&values, 3), Some(4.0));
+        assert_eq!(ema(&values, 5), Some(3.0));
+        assert_eq!(ema(&values, 0), None);
+
+        assert_eq!(
+            stddev(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0], 8),
+            Some(2.0)
+        );
+    }
+
+    #[test]
+    fn oscillator() {
+        use super::*;
+        assert_eq!(rsi(&[1.0, 2.0, 3.0, 4.0], 3), Some(100.0));
+        assert_eq!(rsi(&[4.0, 3.0, 2.0, 1.0], 3), Some(0.0));
+        assert_eq!(rsi(&[1.0, 1.0, 1.0, 1.0], 3), Some(50.0));
+        assert_eq!(rsi(&[1.0, 2.0, 1.0], 2), Some(50.0));
+        assert_eq!(rsi(&[1.0, 2.0, 3.0], 3), None);
+
+        use atb_db::{Decimal, UnixTime};
+        let bar = |t: i64, high: i64, low: i64, close: i64| {
+            OhlcvBar::new(
+                UnixTime::new(t),
+                Decimal::new(close, 0),
+                Decimal::new(high, 0),
+                Decimal::new(low, 0),
+                Decimal::new(close, 0),
+                Decimal::new(1, 0),
+            )
+            .unwrap()
+        };
+        // 真の値幅: max(12, 100) - min(8, 100) = 92、max(20, 10) - min(15, 10) = 10
+        let bars = [
+            bar(60, 100, 100, 100),
+            bar(120, 12, 8, 10),
+            bar(180, 20, 15, 15),
+        ];
+        assert_eq!(atr(&bars, 2), Some(51.0));
+        assert_eq!(atr(&bars, 3), None);
+    }
+}
@@ -0,0 +1,66 @@
+extern crate atb_db;
+extern crate serde;
+
+mod backtest;
+mod broker;
+mod context;
+mod fee;
+mod fill_model;
+mod history;
+pub mod indicator;
+mod market_rule;
+mod metrics;
+mod order;
+mod position;
+mod report;
+mod slippage;
+mod strategy;
+
+pub use backtest::{Backtest, DEFAULT_INITIAL_CASH};
+pub use broker::{Broker, SimBroker};
+pub use context::{BotSettings, Context};
+pub use fee::{FeeRate, FeeSchedule};
+pub use fill_model::{IntrabarPath, Liquidity};
+pub use history::History;
+pub use market_rule::MarketRule;
+pub use metrics::{Metrics, TradeMetrics};
+pub use order::{Fill, Order, OrderId, OrderRequest, OrderType, Side, TimeInForce};
+pub use position::{Position, Trade, TradeSide};
+pub use report::{BacktestReport, EquityPoint};
+pub use slippage::{FixedBps, SlippageModel, VolatilityScaled, VolumeParticipation};
+pub use strategy::Strategy;
+
+// atb-backtestの処理で発生するエラー
+#[derive(Debug)]
+pub enum AtbBacktestError {
+    // ローソク足の読み込みに失敗した
+    Db(atb_db::AtbDbError),
+    // 設定・注文の値が不正
+    Validation(String),
+}
+
+pub type AtbBacktestResult<T> = Result<T, AtbBacktestError>;
+
+impl std::fmt::Display for AtbBacktestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AtbBacktestError::Db(err) => write!(f, "{}", err),
+            AtbBacktestError::Validation(msg) => write!(f, "入力値エラー: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AtbBacktestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AtbBacktestError::Db(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<atb_db::AtbDbError> for AtbBacktestError {
+    fn from(err: atb_db::AtbDbError) -> AtbBacktestError {
+        AtbBacktestError::Db(err)
+    }
+}
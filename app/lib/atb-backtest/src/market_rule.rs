@@ -0,0 +1,96 @@
+use crate::{AtbBacktestError, AtbBacktestResult, Side};
+use atb_db::Market;
+
+// 浮動小数点数の誤差として許容する比率
+const RELATIVE_EPSILON: f64 = 1e-9;
+
+// 市場の呼値・最小注文数量
+// 既定値は制限なし(run_barsなど市場の登録がない場合)
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct MarketRule {
+    tick_size: Option<f64>,
+    lot_size: Option<f64>,
+}
+
+impl MarketRule {
+    pub fn new(tick_size: f64, lot_size: f64) -> MarketRule {
+        MarketRule {
+            tick_size: Some(tick_size),
+            lot_size: Some(lot_size),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_tick_size(&self) -> Option<f64> {
+        self.tick_size
+    }
+
+    #[allow(dead_code)]
+    pub fn get_lot_size(&self) -> Option<f64> {
+        self.lot_size
+    }
+
+    // 注文数量が最小注文数量の倍数か確認する
+    pub fn validate_size(&self, size: f64) -> AtbBacktestResult<()> {
+        let lot_size = match self.lot_size {
+            Some(lot_size) => lot_size,
+            None => return Ok(()),
+        };
+        let lots = size / lot_size;
+        if lots.round() < 1.0 || RELATIVE_EPSILON * lots.max(1.0) < (lots - lots.round()).abs() {
+            return Err(AtbBacktestError::Validation(format!(
+                "注文数量は最小注文数量({})の倍数で指定してください: {}",
+                lot_size, size
+            )));
+        }
+        Ok(())
+    }
+
+    // 価格を呼値に合わせる(買いは切り上げ、売りは切り捨てで不利な方向に丸める)
+    pub fn round_price(&self, price: f64, side: Side) -> f64 {
+        let tick_size = match self.tick_size {
+            Some(tick_size) => tick_size,
+            None => return price,
+        };
+        let ticks = price / tick_size;
+        // 呼値ちょうどの価格が誤差で1呼値ずれないようにする
+        let ticks = if (ticks - ticks.round()).abs() < RELATIVE_EPSILON * ticks.abs().max(1.0) {
+            ticks.round()
+        } else {
+            match side {
+                Side::Buy => ticks.ceil(),
+                Side::Sell => ticks.floor(),
+            }
+        };
+        ticks * tick_size
+    }
+}
+
+impl From<&Market> for MarketRule {
+    fn from(market: &Market) -> MarketRule {
+        MarketRule::new(
+            market.get_tick_size().to_f64(),
+            market.get_lot_size().to_f64(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn market_rule() {
+        use super::*;
+        let rule = MarketRule::new(0.5, 0.01);
+        assert!(rule.validate_size(0.01).is_ok());
+        assert!(rule.validate_size(0.07).is_ok());
+        assert!(rule.validate_size(1.23).is_ok());
+        assert!(rule.validate_size(0.005).is_err());
+        assert!(rule.validate_size(0.015).is_err());
+        assert!(MarketRule::default().validate_size(0.005).is_ok());
+
+        assert_eq!(rule.round_price(100.2, Side::Buy), 100.5);
+        assert_eq!(rule.round_price(100.2, Side::Sell), 100.0);
+        assert_eq!(rule.round_price(100.5, Side::Buy), 100.5);
+        assert_eq!(MarketRule::default().round_price(100.2, Side::Buy), 100.2);
+    }
+}
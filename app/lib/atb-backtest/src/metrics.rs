@@ -0,0 +1,400 @@
+use crate::{EquityPoint, Trade, TradeSide};
+
+// 年率換算に使う1年の秒数
+const YEAR_SECS: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+
+// バックテストの成績
+// 比率は1 = 100%。値が決まらない指標(取引がない、損失がないなど)はNone
+// リスクフリーレートは0とし、シャープレシオ・ソルティノレシオは足ごとの評価額の変化率を年率換算する
+// 年率換算には最初の足の開始から最後の足の終了までの経過時間を使う(欠損した足があっても期間を短く見積もらない)
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Metrics {
+    // 初期資金に対する最終評価額の損益率
+    total_return: Option<f64>,
+    // 年率換算した損益率(複利)
+    annualized_return: Option<f64>,
+    sharpe_ratio: Option<f64>,
+    sortino_ratio: Option<f64>,
+    // 年率換算した損益率 / 最大ドローダウン
+    calmar_ratio: Option<f64>,
+    // 評価額の最大値からの最大下落率
+    max_drawdown: f64,
+    // 評価額が最大値を下回っていた最長の期間(秒、終了時点で回復していなければ終了まで)
+    max_drawdown_secs: i64,
+    // 全取引・ロング・ショートの取引ごとの成績
+    all: TradeMetrics,
+    long: TradeMetrics,
+    short: TradeMetrics,
+}
+
+// 決済済みの取引の成績
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct TradeMetrics {
+    trade_count: usize,
+    // 損益が正の取引の割合
+    win_rate: Option<f64>,
+    // 利益の合計 / 損失の合計
+    profit_factor: Option<f64>,
+    // 1取引あたりの平均損益
+    expectancy: Option<f64>,
+    // 平均保有期間(秒)
+    average_holding_secs: Option<f64>,
+    // 損益の合計(手数料を差し引いた額)
+    total_pnl: f64,
+    // 建玉を持っていた足の割合
+    exposure: f64,
+}
+
+impl Metrics {
+    // 初期資金・足の期間・評価額の推移・決済済みの取引から計算する
+    pub(crate) fn new(
+        initial_cash: f64,
+        period: i64,
+        equity: &[EquityPoint],
+        trades: &[Trade],
+    ) -> Metrics {
+        let final_equity = equity
+            .last()
+            .map_or(initial_cash, |point| point.get_equity());
+        let total_return = ratio(final_equity - initial_cash, initial_cash);
+        let duration_secs = elapsed_secs(period, equity) as f64;
+        let annualized_return = total_return
+            .filter(|r| 0.0 < duration_secs && -1.0 < *r)
+            .map(|r| (1.0 + r).powf(YEAR_SECS / duration_secs) - 1.0);
+
+        // 足ごとの評価額の変化率(最初の足は初期資金からの変化率)
+        let values: Vec<f64> = std::iter::once(initial_cash)
+            .chain(equity.iter().map(|point| point.get_equity()))
+            .collect();
+        let returns: Vec<f64> = values
+            .windows(2)
+            .filter(|w| 0.0 < w[0])
+            .map(|w| w[1] / w[0] - 1.0)
+            .collect();
+        // 1年あたりの変化率の数(欠損した足の分は少なくなる)
+        let bars_per_year = returns.len() as f64 * YEAR_SECS / duration_secs;
+        let (sharpe_ratio, sortino_ratio) = if returns.len() < 2 {
+            (None, None)
+        } else {
+            let n = returns.len() as f64;
+            let mean = returns.iter().sum::<f64>() / n;
+            // 標本標準偏差
+            let stddev =
+                (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+            // 下方偏差(0を下回った分だけの二乗平均平方根)
+            let downside = (returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / n).sqrt();
+            (
+                ratio(mean, stddev).map(|r| r * bars_per_year.sqrt()),
+                ratio(mean, downside).map(|r| r * bars_per_year.sqrt()),
+            )
+        };
+
+        let (max_drawdown, max_drawdown_secs) = drawdown(initial_cash, period, equity);
+        let calmar_ratio = annualized_return.and_then(|r| ratio(r, max_drawdown));
+
+        Metrics {
+            total_return,
+            annualized_return,
+            sharpe_ratio,
+            sortino_ratio,
+            calmar_ratio,
+            max_drawdown,
+            max_drawdown_secs,
+            all: TradeMetrics::new(trades.iter(), equity, |position| position != 0.0),
+            long: TradeMetrics::new(
+                trades.iter().filter(|t| t.get_side() == TradeSide::Long),
+                equity,
+                |position| 0.0 < position,
+            ),
+            short: TradeMetrics::new(
+                trades.iter().filter(|t| t.get_side() == TradeSide::Short),
+                equity,
+                |position| position < 0.0,
+            ),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_total_return(&self) -> Option<f64> {
+        self.total_return
+    }
+
+    #[allow(dead_code)]
+    pub fn get_annualized_return(&self) -> Option<f64> {
+        self.annualized_return
+    }
+
+    #[allow(dead_code)]
+    pub fn get_sharpe_ratio(&self) -> Option<f64> {
+        self.sharpe_ratio
+    }
+
+    #[allow(dead_code)]
+    pub fn get_sortino_ratio(&self) -> Option<f64> {
+        self.sortino_ratio
+    }
+
+    #[allow(dead_code)]
+    pub fn get_calmar_ratio(&self) -> Option<f64> {
+        self.calmar_ratio
+    }
+
+    #[allow(dead_code)]
+    pub fn get_max_drawdown(&self) -> f64 {
+        self.max_drawdown
+    }
+
+    #[allow(dead_code)]
+    pub fn get_max_drawdown_secs(&self) -> i64 {
+        self.max_drawdown_secs
+    }
+
+    #[allow(dead_code)]
+    pub fn get_all(&self) -> &TradeMetrics {
+        &self.all
+    }
+
+    #[allow(dead_code)]
+    pub fn get_long(&self) -> &TradeMetrics {
+        &self.long
+    }
+
+    #[allow(dead_code)]
+    pub fn get_short(&self) -> &TradeMetrics {
+        &self.short
+    }
+}
+
+impl TradeMetrics {
+    fn new<'a, I, F>(trades: I, equity: &[EquityPoint], holding: F) -> TradeMetrics
+    where
+        I: Iterator<Item = &'a Trade>,
+        F: Fn(f64) -> bool,
+    {
+        let trades: Vec<&Trade> = trades.collect();
+        let count = trades.len() as f64;
+        let wins = trades.iter().filter(|t| 0.0 < t.get_pnl()).count() as f64;
+        let profit: f64 = trades.iter().map(|t| t.get_pnl().max(0.0)).sum();
+        let loss: f64 = trades.iter().map(|t| (-t.get_pnl()).max(0.0)).sum();
+        let holding_secs: i64 = trades.iter().map(|t| t.get_holding_secs()).sum();
+        let holding_bars = equity
+            .iter()
+            .filter(|point| holding(point.get_position()))
+            .count() as f64;
+
+        TradeMetrics {
+            trade_count: trades.len(),
+            win_rate: ratio(wins, count),
+            profit_factor: ratio(profit, loss),
+            expectancy: ratio(profit - loss, count),
+            average_holding_secs: ratio(holding_secs as f64, count),
+            total_pnl: profit - loss,
+            exposure: ratio(holding_bars, equity.len() as f64).unwrap_or(0.0),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_trade_count(&self) -> usize {
+        self.trade_count
+    }
+
+    #[allow(dead_code)]
+    pub fn get_win_rate(&self) -> Option<f64> {
+        self.win_rate
+    }
+
+    #[allow(dead_code)]
+    pub fn get_profit_factor(&self) -> Option<f64> {
+        self.profit_factor
+    }
+
+    #[allow(dead_code)]
+    pub fn get_expectancy(&self) -> Option<f64> {
+        self.expectancy
+    }
+
+    #[allow(dead_code)]
+    pub fn get_average_holding_secs(&self) -> Option<f64> {
+        self.average_holding_secs
+    }
+
+    #[allow(dead_code)]
+    pub fn get_total_pnl(&self) -> f64 {
+        self.total_pnl
+    }
+
+    #[allow(dead_code)]
+    pub fn get_exposure(&self) -> f64 {
+        self.exposure
+    }
+}
+
+// 割り算(分母が0以下ならNone)
+fn ratio(numerator: f64, denominator: f64) -> Option<f64> {
+    if denominator <= 0.0 {
+        None
+    } else {
+        Some(numerator / denominator)
+    }
+}
+
+// 最初の足の開始から最後の足の終了までの秒数(unixtimeは足の終了時刻)
+fn elapsed_secs(period: i64, equity: &[EquityPoint]) -> i64 {
+    match (equity.first(), equity.last()) {
+        (Some(first), Some(last)) => {
+            last.get_unixtime().get_secs() - first.get_unixtime().get_secs() + period
+        }
+        _ => 0,
+    }
+}
+
+// 最大ドローダウンと、最大値を下回っていた最長の期間(秒)
+// 初期資金は最初の足の1期間前の評価額として扱う
+fn drawdown(initial_cash: f64, period: i64, equity: &[EquityPoint]) -> (f64, i64) {
+    let start = match equity.first() {
+        Some(point) => point.get_unixtime().get_secs() - period,
+        None => return (0.0, 0),
+    };
+    let (mut peak, mut peak_secs) = (initial_cash, start);
+    let (mut max_drawdown, mut max_secs) = (0.0_f64, 0);
+    for point in equity {
+        let secs = point.get_unixtime().get_secs();
+        if peak <= point.get_equity() {
+            peak = point.get_equity();
+            peak_secs = secs;
+        } else {
+            if let Some(drawdown) = ratio(peak - point.get_equity(), peak) {
+                max_drawdown = max_drawdown.max(drawdown);
+            }
+            max_secs = max_secs.max(secs - peak_secs);
+        }
+    }
+    (max_drawdown, max_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    fn point(unixtime: i64, position: f64, equity: f64) -> crate::EquityPoint {
+        crate::EquityPoint::new(atb_db::UnixTime::new(unixtime), 0.0, position, equity)
+    }
+
+    #[test]
+    fn metrics() {
+        use super::*;
+        // 1日足: 100 → 110 → 88 → 99 → 121
+        let day = 86400;
+        let equity = [
+            point(day, 1.0, 110.0),
+            point(day * 2, 1.0, 88.0),
+            point(day * 3, 0.0, 99.0),
+            point(day * 4, -1.0, 121.0),
+        ];
+        let metrics = Metrics::new(100.0, day, &equity, &[]);
+        assert!((metrics.get_total_return().unwrap() - 0.21).abs() < 1e-9);
+        let annualized = 1.21_f64.powf(365.0 / 4.0) - 1.0;
+        assert!((metrics.get_annualized_return().unwrap() - annualized).abs() < 1e-6);
+        // 110 → 88で20%下落し、121で回復するまで2日
+        assert!((metrics.get_max_drawdown() - 0.2).abs() < 1e-9);
+        assert_eq!(metrics.get_max_drawdown_secs(), day * 2);
+        assert!((metrics.get_calmar_ratio().unwrap() - annualized / 0.2).abs() < 1e-6);
+
+        // 変化率: 0.1, -0.2, 0.125, 0.2222...
+        let returns = [0.1, -0.2, 0.125, 22.0 / 99.0];
+        let mean = returns.iter().sum::<f64>() / 4.0;
+        let stddev = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / 3.0).sqrt();
+        let sharpe = mean / stddev * 365.0_f64.sqrt();
+        assert!((metrics.get_sharpe_ratio().unwrap() - sharpe).abs() < 1e-9);
+        let sortino = mean / (0.04_f64 / 4.0).sqrt() * 365.0_f64.sqrt();
+        assert!((metrics.get_sortino_ratio().unwrap() - sortino).abs() < 1e-9);
+
+        // 建玉を持っていた足の割合
+        assert_eq!(metrics.get_all().get_exposure(), 0.75);
+        assert_eq!(metrics.get_long().get_exposure(), 0.5);
+        assert_eq!(metrics.get_short().get_exposure(), 0.25);
+        assert_eq!(metrics.get_all().get_trade_count(), 0);
+        assert_eq!(metrics.get_all().get_win_rate(), None);
+
+        // 回復しないまま終わった場合は終了までの期間
+        let metrics = Metrics::new(100.0, day, &equity[..3], &[]);
+        assert_eq!(metrics.get_max_drawdown_secs(), day * 2);
+        let metrics = Metrics::new(100.0, day, &[point(day, 0.0, 90.0)], &[]);
+        assert!((metrics.get_max_drawdown() - 0.1).abs() < 1e-9);
+        assert_eq!(metrics.get_max_drawdown_secs(), day);
+        assert_eq!(metrics.get_sharpe_ratio(), None);
+
+        // 欠損した足があっても経過時間で年率換算する(3日目の足がない)
+        let gapped = [equity[0].clone(), equity[1].clone(), equity[3].clone()];
+        let metrics = Metrics::new(100.0, day, &gapped, &[]);
+        let annualized = 1.21_f64.powf(365.0 / 4.0) - 1.0;
+        assert!((metrics.get_annualized_return().unwrap() - annualized).abs() < 1e-6);
+        // 変化率: 0.1, -0.2, 0.375 (4日間に3つ)
+        let returns = [0.1, -0.2, 0.375];
+        let mean = returns.iter().sum::<f64>() / 3.0;
+        let stddev = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / 2.0).sqrt();
+        let sharpe = mean / stddev * (3.0 * 365.0 / 4.0_f64).sqrt();
+        assert!((metrics.get_sharpe_ratio().unwrap() - sharpe).abs() < 1e-9);
+
+        // ローソク足がない
+        let metrics = Metrics::new(100.0, day, &[], &[]);
+        assert_eq!(metrics.get_total_return(), Some(0.0));
+        assert_eq!(metrics.get_annualized_return(), None);
+        assert_eq!(metrics.get_max_drawdown(), 0.0);
+        assert_eq!(metrics.get_calmar_ratio(), None);
+    }
+
+    #[test]
+    fn trade_metrics() {
+        use super::*;
+        use crate::{Fill, Order, OrderRequest, Position, Side};
+        use atb_db::UnixTime;
+        // 取引を作る(建ててから決済するまで)
+        let trade = |side: Side, entry: f64, exit: f64, entry_time: i64, exit_time: i64| {
+            let mut position = Position::default();
+            let open = Order::new(0, &OrderRequest::market(side, 1.0), UnixTime::new(0));
+            let close = Order::new(
+                0,
+                &OrderRequest::market(side.opposite(), 1.0),
+                UnixTime::new(0),
+            );
+            position.apply(&Fill::new(&open, entry, UnixTime::new(entry_time)));
+            position
+                .apply(&Fill::new(&close, exit, UnixTime::new(exit_time)))
+                .unwrap()
+        };
+        let trades = [
+            trade(Side::Buy, 100.0, 130.0, 0, 60),
+            trade(Side::Buy, 100.0, 90.0, 60, 180),
+            trade(Side::Sell, 100.0, 80.0, 180, 360),
+            trade(Side::Buy, 100.0, 100.0, 360, 420),
+        ];
+        let metrics = Metrics::new(1000.0, 60, &[], &trades);
+
+        let all = metrics.get_all();
+        assert_eq!(all.get_trade_count(), 4);
+        assert_eq!(all.get_win_rate(), Some(0.5));
+        assert_eq!(all.get_profit_factor(), Some(5.0));
+        assert_eq!(all.get_expectancy(), Some(10.0));
+        assert_eq!(all.get_average_holding_secs(), Some(105.0));
+        assert_eq!(all.get_total_pnl(), 40.0);
+
+        let long = metrics.get_long();
+        assert_eq!(long.get_trade_count(), 3);
+        assert_eq!(long.get_win_rate(), Some(1.0 / 3.0));
+        assert_eq!(long.get_profit_factor(), Some(3.0));
+        assert_eq!(long.get_total_pnl(), 20.0);
+
+        // 損失がなければプロフィットファクターは決まらない
+        let short = metrics.get_short();
+        assert_eq!(short.get_trade_count(), 1);
+        assert_eq!(short.get_win_rate(), Some(1.0));
+        assert_eq!(short.get_profit_factor(), None);
+        assert_eq!(short.get_average_holding_secs(), Some(180.0));
+
+        // JSON・YAMLに変換できる(決まらない指標はnull)
+        let json = serde_json::to_value(&metrics).unwrap();
+        assert_eq!(json["short"]["profit_factor"], serde_json::Value::Null);
+        assert_eq!(json["long"]["trade_count"], 3);
+        let yaml = serde_yaml::to_string(&metrics).unwrap();
+        assert!(yaml.contains("trade_count: 4"));
+    }
+}
@@ -0,0 +1,350 @@
+use crate::{AtbBacktestError, AtbBacktestResult, Liquidity};
+use atb_db::UnixTime;
+
+// 注文ID(出した順の連番)
+pub type OrderId = u64;
+
+// 売買の方向
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+
+    // 建玉の増減の向き(買いは1、売りは-1)
+    pub fn sign(&self) -> f64 {
+        match self {
+            Side::Buy => 1.0,
+            Side::Sell => -1.0,
+        }
+    }
+
+    pub fn opposite(&self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+// 注文の種類
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OrderType {
+    // 成行
+    Market,
+    // 指値(買いはprice以下、売りはprice以上で約定する)
+    Limit { price: f64 },
+    // 逆指値(買いはtrigger以上、売りはtrigger以下になったら成行で約定する)
+    Stop { trigger: f64 },
+    // ストップ・リミット(triggerに達したらlimitの指値注文になる)
+    StopLimit { trigger: f64, limit: f64 },
+}
+
+impl OrderType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderType::Market => "market",
+            OrderType::Limit { .. } => "limit",
+            OrderType::Stop { .. } => "stop",
+            OrderType::StopLimit { .. } => "stop_limit",
+        }
+    }
+}
+
+// 注文の有効期限
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TimeInForce {
+    // 取り消すまで有効
+    #[default]
+    Gtc,
+    // 次の足の始値で約定しなければ取り消す
+    Ioc,
+    // 指定した時刻(を含む)までの足で約定しなければ取り消す
+    Gtd(UnixTime),
+}
+
+// 出す注文の内容
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct OrderRequest {
+    side: Side,
+    size: f64,
+    order_type: OrderType,
+    #[serde(default)]
+    time_in_force: TimeInForce,
+}
+
+impl OrderRequest {
+    pub fn market(side: Side, size: f64) -> OrderRequest {
+        OrderRequest::new(side, size, OrderType::Market)
+    }
+
+    pub fn limit(side: Side, size: f64, price: f64) -> OrderRequest {
+        OrderRequest::new(side, size, OrderType::Limit { price })
+    }
+
+    pub fn stop(side: Side, size: f64, trigger: f64) -> OrderRequest {
+        OrderRequest::new(side, size, OrderType::Stop { trigger })
+    }
+
+    pub fn stop_limit(side: Side, size: f64, trigger: f64, limit: f64) -> OrderRequest {
+        OrderRequest::new(side, size, OrderType::StopLimit { trigger, limit })
+    }
+
+    fn new(side: Side, size: f64, order_type: OrderType) -> OrderRequest {
+        OrderRequest {
+            side,
+            size,
+            order_type,
+            time_in_force: TimeInForce::Gtc,
+        }
+    }
+
+    pub fn time_in_force(mut self, time_in_force: TimeInForce) -> OrderRequest {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_side(&self) -> Side {
+        self.side
+    }
+
+    #[allow(dead_code)]
+    pub fn get_size(&self) -> f64 {
+        self.size
+    }
+
+    #[allow(dead_code)]
+    pub fn get_order_type(&self) -> OrderType {
+        self.order_type
+    }
+
+    #[allow(dead_code)]
+    pub fn get_time_in_force(&self) -> TimeInForce {
+        self.time_in_force
+    }
+
+    // 入力値を確認する
+    pub fn validate(&self) -> AtbBacktestResult<()> {
+        validate_positive("注文数量", self.size)?;
+        match self.order_type {
+            OrderType::Market => Ok(()),
+            OrderType::Limit { price } => validate_positive("指値", price),
+            OrderType::Stop { trigger } => validate_positive("逆指値", trigger),
+            OrderType::StopLimit { trigger, limit } => {
+                validate_positive("逆指値", trigger)?;
+                validate_positive("指値", limit)
+            }
+        }
+    }
+}
+
+fn validate_positive(name: &str, value: f64) -> AtbBacktestResult<()> {
+    if !value.is_finite() || value <= 0.0 {
+        return Err(AtbBacktestError::Validation(format!(
+            "{}には正の値を指定してください: {}",
+            name, value
+        )));
+    }
+    Ok(())
+}
+
+// 受け付けた注文
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Order {
+    id: OrderId,
+    side: Side,
+    size: f64,
+    order_type: OrderType,
+    time_in_force: TimeInForce,
+    // 注文を出した時点のローソク足の時刻
+    submitted: UnixTime,
+    // ストップ・リミット注文がtriggerに達して指値注文になったか
+    triggered: bool,
+}
+
+impl Order {
+    // 受け付けた注文(Brokerの実装が注文IDを振る)
+    pub fn new(id: OrderId, request: &OrderRequest, submitted: UnixTime) -> Order {
+        Order {
+            id,
+            side: request.side,
+            size: request.size,
+            order_type: request.order_type,
+            time_in_force: request.time_in_force,
+            submitted,
+            triggered: false,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_id(&self) -> OrderId {
+        self.id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_side(&self) -> Side {
+        self.side
+    }
+
+    #[allow(dead_code)]
+    pub fn get_size(&self) -> f64 {
+        self.size
+    }
+
+    #[allow(dead_code)]
+    pub fn get_order_type(&self) -> OrderType {
+        self.order_type
+    }
+
+    #[allow(dead_code)]
+    pub fn get_time_in_force(&self) -> TimeInForce {
+        self.time_in_force
+    }
+
+    #[allow(dead_code)]
+    pub fn get_submitted(&self) -> UnixTime {
+        self.submitted
+    }
+
+    #[allow(dead_code)]
+    pub fn is_triggered(&self) -> bool {
+        self.triggered
+    }
+
+    pub(crate) fn trigger(&mut self) {
+        self.triggered = true;
+    }
+
+    // 指定した時刻の足で有効期限が切れているか
+    pub(crate) fn is_expired(&self, unixtime: UnixTime) -> bool {
+        match self.time_in_force {
+            TimeInForce::Gtd(expire) => expire.get_secs() < unixtime.get_secs(),
+            _ => false,
+        }
+    }
+}
+
+// 約定
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Fill {
+    order_id: OrderId,
+    side: Side,
+    price: f64,
+    size: f64,
+    // 約定したローソク足の時刻
+    unixtime: UnixTime,
+    liquidity: Liquidity,
+    // 支払った手数料(負ならリベート)
+    fee: f64,
+    // スリッページ・スプレッドで不利になった金額(priceに含む)
+    slippage: f64,
+}
+
+impl Fill {
+    // 手数料・スリッページのないテイカーの約定
+    pub(crate) fn new(order: &Order, price: f64, unixtime: UnixTime) -> Fill {
+        Fill {
+            order_id: order.id,
+            side: order.side,
+            price,
+            size: order.size,
+            unixtime,
+            liquidity: Liquidity::Taker,
+            fee: 0.0,
+            slippage: 0.0,
+        }
+    }
+
+    pub(crate) fn costs(mut self, liquidity: Liquidity, fee: f64, slippage: f64) -> Fill {
+        self.liquidity = liquidity;
+        self.fee = fee;
+        self.slippage = slippage;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_order_id(&self) -> OrderId {
+        self.order_id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_side(&self) -> Side {
+        self.side
+    }
+
+    #[allow(dead_code)]
+    pub fn get_price(&self) -> f64 {
+        self.price
+    }
+
+    #[allow(dead_code)]
+    pub fn get_size(&self) -> f64 {
+        self.size
+    }
+
+    #[allow(dead_code)]
+    pub fn get_unixtime(&self) -> UnixTime {
+        self.unixtime
+    }
+
+    #[allow(dead_code)]
+    pub fn get_liquidity(&self) -> Liquidity {
+        self.liquidity
+    }
+
+    #[allow(dead_code)]
+    pub fn get_fee(&self) -> f64 {
+        self.fee
+    }
+
+    #[allow(dead_code)]
+    pub fn get_slippage(&self) -> f64 {
+        self.slippage
+    }
+
+    // 約定代金(買いは支払い、売りは受け取り、手数料を含まない)
+    pub fn get_notional(&self) -> f64 {
+        self.price * self.size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn order_request() {
+        use super::*;
+        assert!(OrderRequest::market(Side::Buy, 1.0).validate().is_ok());
+        assert!(OrderRequest::market(Side::Buy, 0.0).validate().is_err());
+        assert!(OrderRequest::limit(Side::Buy, 1.0, -1.0)
+            .validate()
+            .is_err());
+        assert!(OrderRequest::stop(Side::Sell, 1.0, f64::NAN)
+            .validate()
+            .is_err());
+        assert!(OrderRequest::stop_limit(Side::Sell, 1.0, 100.0, 0.0)
+            .validate()
+            .is_err());
+
+        let request = OrderRequest::stop_limit(Side::Sell, 1.0, 100.0, 99.0)
+            .time_in_force(TimeInForce::Gtd(UnixTime::new(120)));
+        let order = Order::new(1, &request, UnixTime::new(60));
+        assert!(!order.is_expired(UnixTime::new(120)));
+        assert!(order.is_expired(UnixTime::new(180)));
+        assert_eq!(
+            serde_json::to_value(&order).unwrap()["order_type"],
+            serde_json::json!({"type": "stop_limit", "trigger": 100.0, "limit": 99.0})
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{Fill, Side};
+use atb_db::UnixTime;
+
+// これより小さい数量は0とみなす(浮動小数点数の誤差対策)
+const SIZE_EPSILON: f64 = 1e-9;
+
+// 取引の方向
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Long,
+    Short,
+}
+
+impl TradeSide {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeSide::Long => "long",
+            TradeSide::Short => "short",
+        }
+    }
+}
+
+// 建玉(sizeはロングなら正、ショートなら負)
+#[derive(serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Position {
+    size: f64,
+    // 平均建値
+    entry_price: f64,
+    // 建玉を持ち始めた時刻
+    entry_time: Option<UnixTime>,
+    // 建てた時の手数料のうち、まだ決済していない分
+    entry_fee: f64,
+}
+
+impl Position {
+    // 取引所の建玉などから作る(sizeはロングなら正、ショートなら負)
+    pub fn new(size: f64, entry_price: f64, entry_time: Option<UnixTime>) -> Position {
+        if size == 0.0 {
+            return Position::default();
+        }
+        Position {
+            size,
+            entry_price,
+            entry_time,
+            entry_fee: 0.0,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_size(&self) -> f64 {
+        self.size
+    }
+
+    #[allow(dead_code)]
+    pub fn get_entry_price(&self) -> f64 {
+        self.entry_price
+    }
+
+    #[allow(dead_code)]
+    pub fn get_entry_time(&self) -> Option<UnixTime> {
+        self.entry_time
+    }
+
+    #[allow(dead_code)]
+    pub fn get_entry_fee(&self) -> f64 {
+        self.entry_fee
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.size == 0.0
+    }
+
+    pub fn is_long(&self) -> bool {
+        0.0 < self.size
+    }
+
+    pub fn is_short(&self) -> bool {
+        self.size < 0.0
+    }
+
+    // 指定した価格での評価損益
+    pub fn unrealized_pnl(&self, price: f64) -> f64 {
+        self.size * (price - self.entry_price)
+    }
+
+    // 約定を建玉に反映し、決済した分があれば取引として返す
+    // 建玉より多く反対売買した場合は、残りで反対方向の建玉を持つ
+    // 手数料は数量で按分し、決済した分の建てた時・決済した時の手数料を取引の損益から差し引く
+    pub(crate) fn apply(&mut self, fill: &Fill) -> Option<Trade> {
+        let quantity = fill.get_side().sign() * fill.get_size();
+
+        // 新規・買い増し・売り増しは平均建値を更新する
+        if self.is_flat() || self.size.signum() == quantity.signum() {
+            if self.is_flat() {
+                self.entry_time = Some(fill.get_unixtime());
+            }
+            let size = self.size + quantity;
+            self.entry_price = (self.size * self.entry_price + quantity * fill.get_price()) / size;
+            self.size = size;
+            self.entry_fee += fill.get_fee();
+            return None;
+        }
+
+        // 決済した分の損益を確定する
+        let closed = quantity.abs().min(self.size.abs());
+        let entry_fee = self.entry_fee * closed / self.size.abs();
+        let fee = entry_fee + fill.get_fee() * closed / fill.get_size();
+        let trade = Trade {
+            side: if self.is_long() {
+                TradeSide::Long
+            } else {
+                TradeSide::Short
+            },
+            size: closed,
+            entry_time: self.entry_time.unwrap_or_else(|| fill.get_unixtime()),
+            entry_price: self.entry_price,
+            exit_time: fill.get_unixtime(),
+            exit_price: fill.get_price(),
+            fee,
+            pnl: closed * (fill.get_price() - self.entry_price) * self.size.signum() - fee,
+        };
+
+        let size = self.size + quantity;
+        if size.abs() < SIZE_EPSILON {
+            *self = Position::default();
+        } else if size.signum() != self.size.signum() {
+            // ドテン: 残りは約定価格で新規に建てる
+            self.size = size;
+            self.entry_price = fill.get_price();
+            self.entry_time = Some(fill.get_unixtime());
+            self.entry_fee = fill.get_fee() * size.abs() / fill.get_size();
+        } else {
+            self.size = size;
+            self.entry_fee -= entry_fee;
+        }
+        Some(trade)
+    }
+
+    // 建玉を決済する注文の方向と数量
+    pub(crate) fn closing_order(&self) -> Option<(Side, f64)> {
+        if self.is_long() {
+            Some((Side::Sell, self.size))
+        } else if self.is_short() {
+            Some((Side::Buy, -self.size))
+        } else {
+            None
+        }
+    }
+}
+
+// 決済済みの取引(建ててから決済するまで)
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct Trade {
+    side: TradeSide,
+    size: f64,
+    entry_time: UnixTime,
+    entry_price: f64,
+    exit_time: UnixTime,
+    exit_price: f64,
+    // 建てた時・決済した時の手数料(決済した数量の分)
+    fee: f64,
+    // 実現損益(手数料を差し引いた額)
+    pnl: f64,
+}
+
+impl Trade {
+    #[allow(dead_code)]
+    pub fn get_side(&self) -> TradeSide {
+        self.side
+    }
+
+    #[allow(dead_code)]
+    pub fn get_size(&self) -> f64 {
+        self.size
+    }
+
+    #[allow(dead_code)]
+    pub fn get_entry_time(&self) -> UnixTime {
+        self.entry_time
+    }
+
+    #[allow(dead_code)]
+    pub fn get_entry_price(&self) -> f64 {
+        self.entry_price
+    }
+
+    #[allow(dead_code)]
+    pub fn get_exit_time(&self) -> UnixTime {
+        self.exit_time
+    }
+
+    #[allow(dead_code)]
+    pub fn get_exit_price(&self) -> f64 {
+        self.exit_price
+    }
+
+    #[allow(dead_code)]
+    pub fn get_fee(&self) -> f64 {
+        self.fee
+    }
+
+    #[allow(dead_code)]
+    pub fn get_pnl(&self) -> f64 {
+        self.pnl
+    }
+
+    // 建値に対する損益の比率
+    pub fn get_return(&self) -> f64 {
+        self.pnl / (self.entry_price * self.size)
+    }
+
+    // 保有期間(秒)
+    pub fn get_holding_secs(&self) -> i64 {
+        self.exit_time.get_secs() - self.entry_time.get_secs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn fill(side: crate::Side, price: f64, size: f64, unixtime: i64) -> crate::Fill {
+        let request = crate::OrderRequest::market(side, size);
+        let order = crate::Order::new(0, &request, atb_db::UnixTime::new(unixtime));
+        crate::Fill::new(&order, price, atb_db::UnixTime::new(unixtime))
+    }
+
+    #[test]
+    fn long_position() {
+        use super::*;
+        let mut position = Position::default();
+        assert!(position.apply(&fill(Side::Buy, 100.0, 1.0, 60)).is_none());
+        assert!(position.apply(&fill(Side::Buy, 130.0, 2.0, 120)).is_none());
+        assert_eq!(position.get_size(), 3.0);
+        assert_eq!(position.get_entry_price(), 120.0);
+        assert_eq!(position.unrealized_pnl(110.0), -30.0);
+
+        // 一部決済
+        let trade = position.apply(&fill(Side::Sell, 150.0, 1.0, 180)).unwrap();
+        assert_eq!(trade.get_side(), TradeSide::Long);
+        assert_eq!(trade.get_pnl(), 30.0);
+        assert_eq!(trade.get_return(), 0.25);
+        assert_eq!(trade.get_holding_secs(), 120);
+        assert_eq!(position.get_size(), 2.0);
+        assert_eq!(position.get_entry_price(), 120.0);
+
+        // 全部決済
+        let trade = position.apply(&fill(Side::Sell, 90.0, 2.0, 240)).unwrap();
+        assert_eq!(trade.get_pnl(), -60.0);
+        assert!(position.is_flat());
+        assert_eq!(position, Position::default());
+    }
+
+    #[test]
+    fn short_and_reverse() {
+        use super::*;
+        let mut position = Position::default();
+        position.apply(&fill(Side::Sell, 100.0, 2.0, 60));
+        assert!(position.is_short());
+        assert_eq!(position.closing_order(), Some((Side::Buy, 2.0)));
+
+        // ドテン: ショートを決済して残りでロングを持つ
+        let trade = position.apply(&fill(Side::Buy, 80.0, 3.0, 120)).unwrap();
+        assert_eq!(trade.get_side(), TradeSide::Short);
+        assert_eq!(trade.get_size(), 2.0);
+        assert_eq!(trade.get_pnl(), 40.0);
+        assert!(position.is_long());
+        assert_eq!(position.get_size(), 1.0);
+        assert_eq!(position.get_entry_price(), 80.0);
+        assert_eq!(position.get_entry_time(), Some(UnixTime::new(120)));
+    }
+
+    #[test]
+    fn fee() {
+        use super::*;
+        use crate::Liquidity;
+        let fee = |fill: crate::Fill, fee: f64| fill.costs(Liquidity::Taker, fee, 0.0);
+        let mut position = Position::default();
+        position.apply(&fee(fill(Side::Buy, 100.0, 2.0, 60), 4.0));
+        assert_eq!(position.get_entry_fee(), 4.0);
+
+        // 一部決済: 建てた時の手数料の半分と決済した時の手数料
+        let trade = position
+            .apply(&fee(fill(Side::Sell, 110.0, 1.0, 120), 1.0))
+            .unwrap();
+        assert_eq!(trade.get_fee(), 3.0);
+        assert_eq!(trade.get_pnl(), 7.0);
+        assert_eq!(position.get_entry_fee(), 2.0);
+
+        // ドテン: 決済しなかった分の手数料は新しい建玉に残す
+        let trade = position
+            .apply(&fee(fill(Side::Sell, 90.0, 3.0, 180), 3.0))
+            .unwrap();
+        assert_eq!(trade.get_fee(), 3.0);
+        assert_eq!(trade.get_pnl(), -13.0);
+        assert_eq!(position.get_entry_fee(), 2.0);
+        let trade = position
+            .apply(&fee(fill(Side::Buy, 80.0, 2.0, 240), -1.0))
+            .unwrap();
+        assert_eq!(trade.get_fee(), 1.0);
+        assert_eq!(trade.get_pnl(), 19.0);
+        assert_eq!(position, Position::default());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::broker::SimBrokerResult;
+use crate::{
+    Backtest, FeeSchedule, Fill, IntrabarPath, MarketRule, Metrics, Order, Position, Trade,
+};
+use atb_db::UnixTime;
+
+// ローソク足ごとの評価額(終値で評価する)
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct EquityPoint {
+    unixtime: UnixTime,
+    cash: f64,
+    // 建玉の数量(ショートは負)
+    position: f64,
+    equity: f64,
+}
+
+impl EquityPoint {
+    pub(crate) fn new(unixtime: UnixTime, cash: f64, position: f64, equity: f64) -> EquityPoint {
+        EquityPoint {
+            unixtime,
+            cash,
+            position,
+            equity,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_unixtime(&self) -> UnixTime {
+        self.unixtime
+    }
+
+    #[allow(dead_code)]
+    pub fn get_cash(&self) -> f64 {
+        self.cash
+    }
+
+    #[allow(dead_code)]
+    pub fn get_position(&self) -> f64 {
+        self.position
+    }
+
+    #[allow(dead_code)]
+    pub fn get_equity(&self) -> f64 {
+        self.equity
+    }
+}
+
+// バックテストの結果
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct BacktestReport {
+    bot_id: i64,
+    exchange: String,
+    pair: String,
+    period: i64,
+    intrabar_path: IntrabarPath,
+    // 約定に適用した手数料体系
+    fee_schedule: FeeSchedule,
+    leverage: f64,
+    market_rule: MarketRule,
+    initial_cash: f64,
+    final_cash: f64,
+    // 支払った手数料の合計(リベートを差し引いた額)
+    total_fee: f64,
+    // スリッページ・スプレッドで不利になった金額の合計
+    total_slippage: f64,
+    // 終了時点の建玉(決済していない分)
+    final_position: Position,
+    // 終了時点で約定していない注文
+    open_orders: Vec<Order>,
+    // 取り消した・期限切れになった注文
+    canceled_orders: Vec<Order>,
+    fills: Vec<Fill>,
+    trades: Vec<Trade>,
+    equity: Vec<EquityPoint>,
+    metrics: Metrics,
+}
+
+impl BacktestReport {
+    pub(crate) fn new(
+        bot_id: i64,
+        backtest: &Backtest,
+        fee_schedule: &FeeSchedule,
+        market_rule: &MarketRule,
+        result: SimBrokerResult,
+        equity: Vec<EquityPoint>,
+    ) -> BacktestReport {
+        BacktestReport {
+            bot_id,
+            exchange: backtest.get_exchange().to_string(),
+            pair: backtest.get_pair().to_string(),
+            period: backtest.get_period(),
+            intrabar_path: backtest.get_intrabar_path(),
+            fee_schedule: fee_schedule.clone(),
+            leverage: backtest.get_leverage(),
+            market_rule: *market_rule,
+            initial_cash: backtest.get_initial_cash(),
+            final_cash: result.cash,
+            total_fee: result.fills.iter().map(|fill| fill.get_fee()).sum(),
+            total_slippage: result.fills.iter().map(|fill| fill.get_slippage()).sum(),
+            final_position: result.position,
+            open_orders: result.open_orders,
+            canceled_orders: result.canceled_orders,
+            metrics: Metrics::new(
+                backtest.get_initial_cash(),
+                backtest.get_period(),
+                &equity,
+                &result.trades,
+            ),
+            fills: result.fills,
+            trades: result.trades,
+            equity,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_bot_id(&self) -> i64 {
+        self.bot_id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    #[allow(dead_code)]
+    pub fn get_pair(&self) -> &str {
+        &self.pair
+    }
+
+    #[allow(dead_code)]
+    pub fn get_period(&self) -> i64 {
+        self.period
+    }
+
+    #[allow(dead_code)]
+    pub fn get_intrabar_path(&self) -> IntrabarPath {
+        self.intrabar_path
+    }
+
+    #[allow(dead_code)]
+    pub fn get_fee_schedule(&self) -> &FeeSchedule {
+        &self.fee_schedule
+    }
+
+    #[allow(dead_code)]
+    pub fn get_leverage(&self) -> f64 {
+        self.leverage
+    }
+
+    #[allow(dead_code)]
+    pub fn get_market_rule(&self) -> &MarketRule {
+        &self.market_rule
+    }
+
+    #[allow(dead_code)]
+    pub fn get_initial_cash(&self) -> f64 {
+        self.initial_cash
+    }
+
+    #[allow(dead_code)]
+    pub fn get_final_cash(&self) -> f64 {
+        self.final_cash
+    }
+
+    #[allow(dead_code)]
+    pub fn get_total_fee(&self) -> f64 {
+        self.total_fee
+    }
+
+    #[allow(dead_code)]
+    pub fn get_total_slippage(&self) -> f64 {
+        self.total_slippage
+    }
+
+    #[allow(dead_code)]
+    pub fn get_final_position(&self) -> &Position {
+        &self.final_position
+    }
+
+    #[allow(dead_code)]
+    pub fn get_open_orders(&self) -> &[Order] {
+        &self.open_orders
+    }
+
+    #[allow(dead_code)]
+    pub fn get_canceled_orders(&self) -> &[Order] {
+        &self.canceled_orders
+    }
+
+    #[allow(dead_code)]
+    pub fn get_fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    #[allow(dead_code)]
+    pub fn get_trades(&self) -> &[Trade] {
+        &self.trades
+    }
+
+    #[allow(dead_code)]
+    pub fn get_equity(&self) -> &[EquityPoint] {
+        &self.equity
+    }
+
+    #[allow(dead_code)]
+    pub fn get_metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    // 最後のローソク足の終値での評価額(ローソク足がなければ初期資金)
+    pub fn get_final_equity(&self) -> f64 {
+        self.equity
+            .last()
+            .map(|point| point.equity)
+            .unwrap_or(self.initial_cash)
+    }
+}
@@ -0,0 +1,113 @@
+use atb_db::OhlcvBar;
+
+// テイカー約定のスリッページの見積もり
+// 約定価格に対する比率(0.001 = 0.1%)を返し、SimBrokerが不利な方向に価格をずらす
+// 複数のモデルを指定した場合は比率を合計する
+pub trait SlippageModel: std::fmt::Debug + Send + Sync {
+    // 約定した足と注文数量からスリッページの比率を返す
+    fn ratio(&self, bar: &OhlcvBar, size: f64) -> f64;
+}
+
+// 固定のスリッページ(bps = 0.01%)
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct FixedBps {
+    bps: f64,
+}
+
+impl FixedBps {
+    pub fn new(bps: f64) -> FixedBps {
+        FixedBps { bps }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_bps(&self) -> f64 {
+        self.bps
+    }
+}
+
+impl SlippageModel for FixedBps {
+    fn ratio(&self, _bar: &OhlcvBar, _size: f64) -> f64 {
+        self.bps / 10_000.0
+    }
+}
+
+// 足の値幅に比例するスリッページ(factor × (高値 - 安値) / 始値)
+// 値動きの荒い足ほど板が薄く、不利な価格で約定しやすいとみなす
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct VolatilityScaled {
+    factor: f64,
+}
+
+impl VolatilityScaled {
+    pub fn new(factor: f64) -> VolatilityScaled {
+        VolatilityScaled { factor }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_factor(&self) -> f64 {
+        self.factor
+    }
+}
+
+impl SlippageModel for VolatilityScaled {
+    fn ratio(&self, bar: &OhlcvBar, _size: f64) -> f64 {
+        let open = bar.get_open().to_f64();
+        if open <= 0.0 {
+            return 0.0;
+        }
+        self.factor * (bar.get_high().to_f64() - bar.get_low().to_f64()) / open
+    }
+}
+
+// 足の出来高に占める注文数量の割合に応じたスリッページ(coefficient × √(数量 / 出来高))
+// 出来高のない足では出来高と同じ数量を注文したとみなす
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct VolumeParticipation {
+    coefficient: f64,
+}
+
+impl VolumeParticipation {
+    pub fn new(coefficient: f64) -> VolumeParticipation {
+        VolumeParticipation { coefficient }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_coefficient(&self) -> f64 {
+        self.coefficient
+    }
+}
+
+impl SlippageModel for VolumeParticipation {
+    fn ratio(&self, bar: &OhlcvBar, size: f64) -> f64 {
+        let volume = bar.get_volume().to_f64();
+        let participation = if volume <= 0.0 { 1.0 } else { size / volume };
+        self.coefficient * participation.sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn slippage_model() {
+        use super::*;
+        use atb_db::{Decimal, UnixTime};
+        let bar = |volume: i64| {
+            OhlcvBar::new(
+                UnixTime::new(60),
+                Decimal::new(100, 0),
+                Decimal::new(110, 0),
+                Decimal::new(90, 0),
+                Decimal::new(105, 0),
+                Decimal::new(volume, 0),
+            )
+            .unwrap()
+        };
+
+        assert_eq!(FixedBps::new(5.0).ratio(&bar(100), 1.0), 0.0005);
+        // 0.1 × 20 / 100
+        assert_eq!(VolatilityScaled::new(0.1).ratio(&bar(100), 1.0), 0.02);
+        // 0.01 × √(4 / 100)
+        assert_eq!(VolumeParticipation::new(0.01).ratio(&bar(100), 4.0), 0.002);
+        assert_eq!(VolumeParticipation::new(0.01).ratio(&bar(0), 4.0), 0.01);
+    }
+}
@@ -0,0 +1,20 @@
+use crate::{Context, Fill};
+
+// 売買ルール
+// 注文・参照はすべてContextを通すので、バックテスト・フォワードテスト・実運用で同じ実装を使える
+// bot_idはatb-dbに登録したボットのIDで、設定の読み込みや結果の記録に使う
+pub trait Strategy {
+    fn bot_id(&self) -> i64;
+
+    // 最初の足の前に1回呼ばれる
+    fn on_start(&mut self, _ctx: &mut Context) {}
+
+    // 足が確定するたびに呼ばれる
+    fn on_bar(&mut self, ctx: &mut Context);
+
+    // 注文が約定するたびに呼ばれる(約定した足はまだ確定していない)
+    fn on_fill(&mut self, _fill: &Fill, _ctx: &mut Context) {}
+
+    // 最後の足の後に1回呼ばれる(ここで出した注文は約定しない)
+    fn on_end(&mut self, _ctx: &mut Context) {}
+}
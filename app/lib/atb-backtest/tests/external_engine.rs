@@ -0,0 +1,129 @@
+extern crate atb_backtest;
+extern crate atb_db;
+
+use atb_backtest::{
+    AtbBacktestResult, BotSettings, Broker, Context, History, Order, OrderId, OrderRequest,
+    Position, Side, Strategy,
+};
+use atb_db::{Decimal, OhlcvBar, UnixTime};
+
+// クレートの外でBrokerを実装するエンジン(フォワードテスト・実運用の代わり)
+// 注文を受け付けるだけで、約定は取引所からの通知を想定してテストから反映する
+struct PaperBroker {
+    cash: f64,
+    position: Position,
+    price: f64,
+    unixtime: UnixTime,
+    next_order_id: OrderId,
+    pending: Vec<Order>,
+}
+
+impl Broker for PaperBroker {
+    fn get_cash(&self) -> f64 {
+        self.cash
+    }
+
+    fn get_position(&self) -> Position {
+        self.position
+    }
+
+    fn get_equity(&self) -> f64 {
+        self.cash + self.position.get_size() * self.price
+    }
+
+    fn get_pending_orders(&self) -> &[Order] {
+        &self.pending
+    }
+
+    fn submit(&mut self, request: &OrderRequest) -> AtbBacktestResult<OrderId> {
+        request.validate()?;
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+        self.pending.push(Order::new(id, request, self.unixtime));
+        Ok(id)
+    }
+
+    fn cancel(&mut self, id: OrderId) -> bool {
+        let count = self.pending.len();
+        self.pending.retain(|order| order.get_id() != id);
+        self.pending.len() < count
+    }
+}
+
+// 終値が2本の移動平均を上回ったら買い、下回ったら決済する
+struct AboveAverage;
+
+impl Strategy for AboveAverage {
+    fn bot_id(&self) -> i64 {
+        1
+    }
+
+    fn on_bar(&mut self, ctx: &mut Context) {
+        let close = ctx.get_bar().unwrap().get_close().to_f64();
+        let average = match ctx.sma(2) {
+            Some(average) => average,
+            None => return,
+        };
+        if average < close && ctx.get_position().is_flat() && ctx.get_pending_orders().is_empty() {
+            ctx.buy(1.0).unwrap();
+        } else if close < average {
+            ctx.close_position().unwrap();
+        }
+    }
+}
+
+fn bar(unixtime: i64, close: i64) -> OhlcvBar {
+    let price = Decimal::new(close, 0);
+    OhlcvBar::new(
+        UnixTime::new(unixtime),
+        price,
+        price,
+        price,
+        price,
+        Decimal::new(1, 0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn external_engine() {
+    let bot = BotSettings::new(1);
+    let mut strategy = AboveAverage;
+    let mut history = History::default();
+    let mut broker = PaperBroker {
+        cash: 1000.0,
+        position: Position::default(),
+        price: 0.0,
+        unixtime: UnixTime::new(0),
+        next_order_id: 1,
+        pending: vec![],
+    };
+
+    // 足が確定するたびに履歴へ追加してon_barを呼ぶ
+    for (unixtime, close) in &[(60, 100), (120, 110)] {
+        let bar = bar(*unixtime, *close);
+        broker.unixtime = bar.get_unixtime();
+        broker.price = bar.get_close().to_f64();
+        history.push(bar).unwrap();
+        strategy.on_bar(&mut Context::new(&bot, &history, &mut broker));
+    }
+    assert_eq!(history.len(), 2);
+    assert_eq!(broker.pending.len(), 1);
+    assert_eq!(broker.pending[0].get_side(), Side::Buy);
+    assert_eq!(broker.pending[0].get_submitted(), UnixTime::new(120));
+
+    // 取引所で約定した建玉を反映すると、ストラテジーは決済する
+    broker.pending.clear();
+    broker.position = Position::new(1.0, 110.0, Some(UnixTime::new(180)));
+    broker.cash -= 110.0;
+    history.push(bar(180, 90)).unwrap();
+    broker.price = 90.0;
+    let mut ctx = Context::new(&bot, &history, &mut broker);
+    assert_eq!(ctx.get_equity(), 980.0);
+    strategy.on_bar(&mut ctx);
+    assert_eq!(broker.pending[0].get_side(), Side::Sell);
+    assert_eq!(broker.pending[0].get_size(), 1.0);
+
+    // 古い足は追加できない
+    assert!(history.push(bar(120, 100)).is_err());
+}
@@ -0,0 +1,433 @@
+// AtbDBの非同期API(featureの`async`で有効になる)
+// 専用のワーカースレッドでクエリを実行し、結果をFutureで返す
+// 同時に実行するクエリはワーカー数まで、待機できるクエリはキューの長さまでに制限する
+// 結果を待つ側がタイムアウト・破棄された処理は、実行中のクエリを中断(sqlite3_interrupt)する
+
+use crate::{AtbDB, AtbDbError, AtbDbResult};
+
+// ワーカー1つあたりに待機できるクエリの数
+const QUEUE_PER_WORKER: usize = 16;
+
+// ワーカースレッドで実行する処理
+type Job = Box<dyn FnOnce(&AtbDB) + Send>;
+
+// パニックの内容をエラーにする(ワーカーは終了させない)
+fn panic_error(payload: Box<dyn std::any::Any + Send>) -> AtbDbError {
+    let msg = if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "不明なパニック".to_string()
+    };
+    AtbDbError::Panic(msg)
+}
+
+thread_local! {
+    // ワーカーで実行中の処理の中断用ハンドル
+    static CURRENT: std::cell::RefCell<Option<std::sync::Arc<Interrupter>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+// ワーカーで実行中の処理が取得したコネクションを中断する
+#[derive(Default)]
+struct Interrupter {
+    state: std::sync::Mutex<InterruptState>,
+}
+
+#[derive(Default)]
+struct InterruptState {
+    canceled: bool,
+    finished: bool,
+    // 処理中に取得したコネクションの中断用ハンドル
+    handles: Vec<rusqlite::InterruptHandle>,
+}
+
+impl Interrupter {
+    // ワーカースレッドで処理を実行する間、取得したコネクションを登録する
+    fn run<T>(self: &std::sync::Arc<Interrupter>, f: impl FnOnce() -> T) -> T {
+        CURRENT.with(|current| *current.borrow_mut() = Some(self.clone()));
+        let result = f();
+        CURRENT.with(|current| *current.borrow_mut() = None);
+
+        // 終了後のコネクションはプールに戻り別の処理で使われるので中断しない
+        let mut state = self.lock();
+        state.finished = true;
+        state.handles.clear();
+        result
+    }
+
+    // 実行中のクエリを中断する(終了した処理には何もしない)
+    fn cancel(&self) {
+        let mut state = self.lock();
+        if state.finished {
+            return;
+        }
+        state.canceled = true;
+        for handle in &state.handles {
+            handle.interrupt();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, InterruptState> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+// 結果を受け取る前にタイムアウト・破棄されたら処理を中断する
+struct CancelOnDrop(std::sync::Arc<Interrupter>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+// ワーカーで実行中の処理なら、取得したコネクションを中断できるよう登録する
+// 中断済みの処理では新しいクエリを実行させない
+pub(crate) fn register_interrupt(conn: &rusqlite::Connection) -> AtbDbResult<()> {
+    CURRENT.with(|current| match &*current.borrow() {
+        Some(interrupter) => {
+            let mut state = interrupter.lock();
+            if state.canceled {
+                return Err(AtbDbError::Busy);
+            }
+            state.handles.push(conn.get_interrupt_handle());
+            Ok(())
+        }
+        None => Ok(()),
+    })
+}
+
+pub struct AsyncAtbDB {
+    atbdb: std::sync::Arc<AtbDB>,
+    // ワーカーへの送信口(破棄時にNoneにしてワーカーを終了させる)
+    sender: Option<std::sync::mpsc::SyncSender<Job>>,
+    workers: Vec<std::thread::JoinHandle<()>>,
+    // クエリのタイムアウト(Noneなら待ち続ける)
+    timeout: Option<std::time::Duration>,
+}
+
+impl AsyncAtbDB {
+    // 指定した数のワーカースレッドでクエリを実行する
+    pub fn new(atbdb: AtbDB, workers: usize) -> AtbDbResult<AsyncAtbDB> {
+        if workers == 0 {
+            return Err(AtbDbError::Config(
+                "ワーカー数には1以上を指定してください".to_string(),
+            ));
+        }
+
+        let queue_len = workers
+            .checked_mul(QUEUE_PER_WORKER)
+            .ok_or_else(|| AtbDbError::Config(format!("ワーカー数が大きすぎます: {}", workers)))?;
+
+        let atbdb = std::sync::Arc::new(atbdb);
+        let (sender, receiver) = std::sync::mpsc::sync_channel::<Job>(queue_len);
+        let receiver = std::sync::Arc::new(std::sync::Mutex::new(receiver));
+
+        let mut handles = Vec::with_capacity(workers);
+        for i in 0..workers {
+            let atbdb = atbdb.clone();
+            let receiver = receiver.clone();
+            let handle = std::thread::Builder::new()
+                .name(format!("atb-db-worker-{}", i))
+                .spawn(move || loop {
+                    // 送信口が破棄されたら終了する
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+                    // 処理がパニックしてもワーカーは次の処理を受け付ける
+                    match job {
+                        Ok(job) => {
+                            let job = std::panic::AssertUnwindSafe(|| job(&atbdb));
+                            let _ = std::panic::catch_unwind(job);
+                        }
+                        Err(_) => return,
+                    }
+                })
+                .map_err(|err| AtbDbError::Config(format!("ワーカーを起動できません: {}", err)))?;
+            handles.push(handle);
+        }
+
+        Ok(AsyncAtbDB {
+            atbdb,
+            sender: Some(sender),
+            workers: handles,
+            timeout: None,
+        })
+    }
+
+    // すべてのクエリに適用するタイムアウトを設定する
+    pub fn timeout(mut self, timeout: std::time::Duration) -> AsyncAtbDB {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_timeout(&self) -> Option<std::time::Duration> {
+        self.timeout
+    }
+
+    #[allow(dead_code)]
+    pub fn get_workers_len(&self) -> usize {
+        self.workers.len()
+    }
+
+    // 同期APIを直接使う(ワーカーを経由しない)
+    #[allow(dead_code)]
+    pub fn get_ref(&self) -> &AtbDB {
+        &self.atbdb
+    }
+
+    // ワーカーで処理を実行し、設定したタイムアウトまで結果を待つ
+    pub async fn run<F, T>(&self, f: F) -> AtbDbResult<T>
+    where
+        F: FnOnce(&AtbDB) -> AtbDbResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.run_inner(f, self.timeout).await
+    }
+
+    // ワーカーで処理を実行し、指定したタイムアウトまで結果を待つ(リクエストごとの指定用)
+    pub async fn run_with_timeout<F, T>(&self, timeout: std::time::Duration, f: F) -> AtbDbResult<T>
+    where
+        F: FnOnce(&AtbDB) -> AtbDbResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.run_inner(f, Some(timeout)).await
+    }
+
+    async fn run_inner<F, T>(&self, f: F, timeout: Option<std::time::Duration>) -> AtbDbResult<T>
+    where
+        F: FnOnce(&AtbDB) -> AtbDbResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = futures_channel::oneshot::channel();
+        let interrupter = std::sync::Arc::new(Interrupter::default());
+        let job_interrupter = interrupter.clone();
+        let job: Job = Box::new(move |atbdb| {
+            // 待つ側がタイムアウト・破棄されていれば実行しない
+            if tx.is_canceled() {
+                return;
+            }
+            let result = job_interrupter.run(|| {
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(atbdb)))
+                    .unwrap_or_else(|payload| Err(panic_error(payload)))
+            });
+            let _ = tx.send(result);
+        });
+
+        // キューがいっぱいなら待たずにエラーを返す
+        let sender = self.sender.as_ref().ok_or(AtbDbError::Busy)?;
+        sender.try_send(job).map_err(|_| AtbDbError::Busy)?;
+        let _cancel = CancelOnDrop(interrupter);
+
+        // ワーカーが処理を終えずに終了した場合はBusyとする
+        let result = async { rx.await.unwrap_or(Err(AtbDbError::Busy)) };
+        match timeout {
+            None => result.await,
+            Some(timeout) => {
+                use futures_util::future::Either;
+                let delay = futures_timer::Delay::new(timeout);
+                futures_util::pin_mut!(result);
+                match futures_util::future::select(result, delay).await {
+                    Either::Left((result, _)) => result,
+                    Either::Right(_) => Err(AtbDbError::Timeout(timeout)),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for AsyncAtbDB {
+    // 送信口を破棄してワーカーを終了させる
+    // 結果を待つFutureはすでに破棄され、実行中のクエリは中断・待機中の処理は実行しないので、
+    // ワーカーの終了は待たない(非同期ランタイムのスレッドを止めない)
+    fn drop(&mut self) {
+        self.sender = None;
+        self.workers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn run_query() {
+        use super::*;
+        use crate::{Actor, BotCreate};
+        let atbdb = AsyncAtbDB::new(AtbDB::open_in_memory().unwrap(), 2).unwrap();
+        assert_eq!(atbdb.get_workers_len(), 2);
+
+        let bot = futures_executor::block_on(atbdb.run(|atbdb| {
+            atbdb.insert_bot(&BotCreate::new("test", "test bot"), &Actor::cli("test"))
+        }))
+        .unwrap();
+        assert_eq!(bot.get_id(), 1);
+
+        // エラーもそのまま返す
+        let result = futures_executor::block_on(atbdb.run(|atbdb| atbdb.get_bot(2)));
+        assert!(matches!(result, Err(AtbDbError::NotFound)));
+        assert_eq!(atbdb.get_ref().get_bot_list().unwrap().get_list_len(), 1);
+
+        assert!(matches!(
+            AsyncAtbDB::new(AtbDB::open_in_memory().unwrap(), 0),
+            Err(AtbDbError::Config(_))
+        ));
+        assert!(matches!(
+            AsyncAtbDB::new(AtbDB::open_in_memory().unwrap(), usize::MAX),
+            Err(AtbDbError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn bounded_concurrency() {
+        use super::*;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let atbdb = AsyncAtbDB::new(AtbDB::open_in_memory().unwrap(), 2).unwrap();
+        let running = std::sync::Arc::new(AtomicUsize::new(0));
+        let max_running = std::sync::Arc::new(AtomicUsize::new(0));
+
+        // 同時に実行されるのはワーカー数まで
+        let futures: Vec<_> = (0..8)
+            .map(|_| {
+                let running = running.clone();
+                let max_running = max_running.clone();
+                atbdb.run(move |_| {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(std::time::Duration::from_millis(20));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                    Ok(())
+                })
+            })
+            .collect();
+        for result in futures_executor::block_on(futures_util::future::join_all(futures)) {
+            result.unwrap();
+        }
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn timeout_and_busy() {
+        use super::*;
+        use std::future::Future;
+        let atbdb = AsyncAtbDB::new(AtbDB::open_in_memory().unwrap(), 1)
+            .unwrap()
+            .timeout(std::time::Duration::from_secs(10));
+        let sleep = |millis| {
+            move |_: &AtbDB| {
+                std::thread::sleep(std::time::Duration::from_millis(millis));
+                Ok(())
+            }
+        };
+
+        // リクエストごとのタイムアウトを優先する
+        let result = futures_executor::block_on(
+            atbdb.run_with_timeout(std::time::Duration::from_millis(10), sleep(200)),
+        );
+        assert!(matches!(result, Err(AtbDbError::Timeout(_))));
+
+        // キューがいっぱいなら待たずにBusyを返す(Futureは最初のpollでキューに入る)
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let rx = std::sync::Mutex::new(rx);
+        let waker = futures_util::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let blocker = atbdb.run(move |_| {
+            let _ = rx.lock().unwrap().recv();
+            Ok(())
+        });
+        futures_util::pin_mut!(blocker);
+        assert!(blocker.as_mut().poll(&mut cx).is_pending());
+
+        // タイムアウトした処理が終わり、ワーカーが取り出すまで待つ
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        let mut queued: Vec<_> = (0..QUEUE_PER_WORKER)
+            .map(|_| Box::pin(atbdb.run(sleep(0))))
+            .collect();
+        for future in queued.iter_mut() {
+            assert!(future.as_mut().poll(&mut cx).is_pending());
+        }
+        let overflow = futures_executor::block_on(atbdb.run(sleep(0)));
+        assert!(matches!(overflow, Err(AtbDbError::Busy)));
+
+        tx.send(()).unwrap();
+        futures_executor::block_on(blocker).unwrap();
+        for result in futures_executor::block_on(futures_util::future::join_all(queued)) {
+            result.unwrap();
+        }
+    }
+
+    #[test]
+    fn interrupt_on_timeout() {
+        use super::*;
+        let atbdb = AsyncAtbDB::new(AtbDB::open_in_memory().unwrap(), 1).unwrap();
+
+        // 終わらないクエリはタイムアウトすると中断され、ワーカーは次の処理を実行できる
+        let (tx, rx) = std::sync::mpsc::channel();
+        let result = futures_executor::block_on(atbdb.run_with_timeout(
+            std::time::Duration::from_millis(50),
+            move |atbdb| {
+                let conn = atbdb.get_conn()?;
+                let result = conn.query_row(
+                    "with recursive c(x) as (select 1 union all select x + 1 from c) select count(*) from c",
+                    rusqlite::NO_PARAMS,
+                    |row| row.get::<_, i64>(0),
+                );
+                let _ = tx.send(result.is_err());
+                Ok(())
+            },
+        ));
+        assert!(matches!(result, Err(AtbDbError::Timeout(_))));
+        assert!(rx.recv_timeout(std::time::Duration::from_secs(10)).unwrap());
+        assert_eq!(
+            futures_executor::block_on(atbdb.run(|atbdb| atbdb.get_bot_list()))
+                .unwrap()
+                .get_list_len(),
+            0
+        );
+    }
+
+    #[test]
+    fn panic_in_job() {
+        use super::*;
+        let atbdb = AsyncAtbDB::new(AtbDB::open_in_memory().unwrap(), 1).unwrap();
+
+        // パニックはエラーとして返し、ワーカーは次の処理を実行できる
+        for _ in 0..3 {
+            let result: AtbDbResult<()> =
+                futures_executor::block_on(atbdb.run(|_| panic!("overflow")));
+            match result {
+                Err(AtbDbError::Panic(msg)) => assert_eq!(msg, "overflow"),
+                other => panic!("{:?}", other),
+            }
+        }
+        assert_eq!(
+            futures_executor::block_on(atbdb.run(|atbdb| atbdb.get_bot_list()))
+                .unwrap()
+                .get_list_len(),
+            0
+        );
+    }
+
+    #[test]
+    fn drop_without_join() {
+        use super::*;
+        use std::future::Future;
+        let atbdb = AsyncAtbDB::new(AtbDB::open_in_memory().unwrap(), 1).unwrap();
+        let waker = futures_util::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+
+        // 実行中の処理が終わるのを待たずに破棄する
+        let mut running = Box::pin(atbdb.run(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            Ok(())
+        }));
+        assert!(running.as_mut().poll(&mut cx).is_pending());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        let started = std::time::Instant::now();
+        drop(running);
+        drop(atbdb);
+        assert!(started.elapsed() < std::time::Duration::from_millis(400));
+    }
+}
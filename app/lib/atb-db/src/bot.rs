@@ -0,0 +1,836 @@
+use crate::bot_history::{record_bot_history, Actor, BotAction};
+use crate::token::{generate_token, token_prefix, verify_token, HashedToken};
+use crate::{AtbDB, AtbDbError, AtbDbResult};
+
+// botの運用段階(宣言順に段階が上がる)
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum OperateType {
+    // バックテスト
+    Backtest,
+    // フォワードテスト
+    Forwardtest,
+    // 実運用
+    Product,
+}
+
+impl OperateType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OperateType::Backtest => "backtest",
+            OperateType::Forwardtest => "forwardtest",
+            OperateType::Product => "product",
+        }
+    }
+
+    // 1つ上の運用段階(実運用ならNone)
+    pub fn next(&self) -> Option<OperateType> {
+        match self {
+            OperateType::Backtest => Some(OperateType::Forwardtest),
+            OperateType::Forwardtest => Some(OperateType::Product),
+            OperateType::Product => None,
+        }
+    }
+}
+
+impl std::str::FromStr for OperateType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OperateType, String> {
+        match s {
+            "backtest" => Ok(OperateType::Backtest),
+            "forwardtest" => Ok(OperateType::Forwardtest),
+            "product" => Ok(OperateType::Product),
+            _ => Err(format!("不明な運用段階です: {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for OperateType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct Bot {
+    id: i64,
+    name: String,
+    description: String,
+    enable: bool,
+    registered: i64,
+    // 検索用のトークンの先頭部分(未発行ならNone)
+    token_prefix: Option<String>,
+    // 発行したトークンの平文(追加・再発行した時のみ保持する)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    long_order: bool,
+    short_order: bool,
+    operate_type: OperateType,
+    // アーカイブした日時(アーカイブしていなければNone)
+    archived_at: Option<i64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct BotForGet {
+    name: String,
+    description: String,
+    enable: bool,
+    registered: i64,
+    long_order: bool,
+    short_order: bool,
+    operate_type: OperateType,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct BotList {
+    bot: Vec<Bot>,
+}
+
+// 追加するbotの設定(指定しない項目はテーブルのデフォルト値と同じ)
+// 運用段階はバックテストから始まり、promote_botでのみ上げられる
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BotCreate {
+    name: String,
+    description: String,
+    #[serde(default = "default_enable")]
+    enable: bool,
+    #[serde(default = "default_long_order")]
+    long_order: bool,
+    #[serde(default)]
+    short_order: bool,
+}
+
+fn default_enable() -> bool {
+    true
+}
+
+fn default_long_order() -> bool {
+    true
+}
+
+impl BotCreate {
+    pub fn new(name: &str, description: &str) -> BotCreate {
+        BotCreate {
+            name: name.to_string(),
+            description: description.to_string(),
+            enable: default_enable(),
+            long_order: default_long_order(),
+            short_order: false,
+        }
+    }
+
+    pub fn enable(mut self, enable: bool) -> BotCreate {
+        self.enable = enable;
+        self
+    }
+
+    pub fn long_order(mut self, long_order: bool) -> BotCreate {
+        self.long_order = long_order;
+        self
+    }
+
+    pub fn short_order(mut self, short_order: bool) -> BotCreate {
+        self.short_order = short_order;
+        self
+    }
+
+    // 入力値を確認する
+    pub fn validate(&self) -> AtbDbResult<()> {
+        validate_text("name", &self.name)?;
+        validate_text("description", &self.description)
+    }
+}
+
+// 更新するbotの設定(Noneの項目は更新しない)
+// 運用段階はpromote_bot・demote_botで変更する
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BotPatch {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    enable: Option<bool>,
+    #[serde(default)]
+    long_order: Option<bool>,
+    #[serde(default)]
+    short_order: Option<bool>,
+}
+
+impl BotPatch {
+    pub fn new() -> BotPatch {
+        BotPatch::default()
+    }
+
+    pub fn name(mut self, name: &str) -> BotPatch {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> BotPatch {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn enable(mut self, enable: bool) -> BotPatch {
+        self.enable = Some(enable);
+        self
+    }
+
+    pub fn long_order(mut self, long_order: bool) -> BotPatch {
+        self.long_order = Some(long_order);
+        self
+    }
+
+    pub fn short_order(mut self, short_order: bool) -> BotPatch {
+        self.short_order = Some(short_order);
+        self
+    }
+
+    // 更新する項目がなければtrue
+    pub fn is_empty(&self) -> bool {
+        *self == BotPatch::default()
+    }
+
+    // 入力値を確認する
+    pub fn validate(&self) -> AtbDbResult<()> {
+        if self.is_empty() {
+            return Err(AtbDbError::Validation(
+                "更新する項目が指定されていません".to_string(),
+            ));
+        }
+        if let Some(name) = &self.name {
+            validate_text("name", name)?;
+        }
+        if let Some(description) = &self.description {
+            validate_text("description", description)?;
+        }
+        Ok(())
+    }
+
+    // 更新するカラムと値の一覧
+    fn to_columns(&self) -> Vec<(&'static str, rusqlite::types::Value)> {
+        use rusqlite::types::Value;
+
+        let mut columns = vec![];
+        if let Some(name) = &self.name {
+            columns.push(("name", Value::Text(name.clone())));
+        }
+        if let Some(description) = &self.description {
+            columns.push(("description", Value::Text(description.clone())));
+        }
+        if let Some(enable) = self.enable {
+            columns.push(("enable", Value::Integer(enable as i64)));
+        }
+        if let Some(long_order) = self.long_order {
+            columns.push(("long_order", Value::Integer(long_order as i64)));
+        }
+        if let Some(short_order) = self.short_order {
+            columns.push(("short_order", Value::Integer(short_order as i64)));
+        }
+        columns
+    }
+}
+
+// 空の文字列は登録できない
+fn validate_text(key: &str, value: &str) -> AtbDbResult<()> {
+    if value.trim().is_empty() {
+        return Err(AtbDbError::Validation(format!("{}が空です", key)));
+    }
+    Ok(())
+}
+
+const SQL_SELECT_BOT: &str = "select id, name, description, enable, registered, token_prefix, long_order, short_order, operate_type, archived_at from bot";
+
+// botを完全削除する時に合わせて削除するデータ(テーブル名, 条件)
+// 変更履歴(bot_history)・運用段階の変更履歴(bot_promotion)と、その根拠にした評価結果は監査用に残す
+const BOT_PURGED_TABLES: &[(&str, &str)] = &[(
+    "bot_evaluation",
+    "bot_id = ?1 and id not in (select evaluation_id from bot_promotion where evaluation_id is not null)",
+)];
+
+// トークンの先頭部分が他のbotと重複した場合に発行し直す回数
+const TOKEN_RETRY: usize = 3;
+
+impl AtbDB {
+    // botデータを追加し、追加したbotを返す
+    // 返したbotには発行したトークンの平文が含まれる(再取得はできない)
+    pub fn insert_bot(&self, bot: &BotCreate, actor: &Actor) -> AtbDbResult<Bot> {
+        bot.validate()?;
+        let mut conn = self.get_conn()?;
+
+        // 完全削除したbotのIDは変更履歴に残るので、履歴のあるIDは使わない
+        let tx = conn.transaction()?;
+        tx.execute(
+            "insert into bot (id, name, description, enable, long_order, short_order) \
+             values ((select coalesce(max(id), 0) + 1 from (select max(id) as id from bot union all select max(bot_id) from bot_history union all select max(bot_id) from bot_promotion)), ?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                bot.name,
+                bot.description,
+                bot.enable,
+                bot.long_order,
+                bot.short_order
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        let token = issue_token(&tx, id)?;
+        let mut bot = select_bot(&tx, id)?;
+        record_bot_history(&tx, id, BotAction::Insert, None, Some(&bot), actor)?;
+        tx.commit()?;
+
+        bot.token = Some(token);
+        Ok(bot)
+    }
+
+    // botのトークンを再発行し、新しいトークンの平文を含むbotを返す
+    // それまでのトークンは使用できなくなる
+    pub fn rotate_bot_token(&self, id: i64, actor: &Actor) -> AtbDbResult<Bot> {
+        let mut conn = self.get_conn()?;
+
+        let tx = conn.transaction()?;
+        let old = select_active_bot(&tx, id)?;
+        let token = issue_token(&tx, id)?;
+        let mut bot = select_bot(&tx, id)?;
+        record_bot_history(
+            &tx,
+            id,
+            BotAction::RotateToken,
+            Some(&old),
+            Some(&bot),
+            actor,
+        )?;
+        tx.commit()?;
+
+        bot.token = Some(token);
+        Ok(bot)
+    }
+
+    // botデータを更新し、更新後のbotを返す
+    pub fn update_bot(&self, id: i64, patch: &BotPatch, actor: &Actor) -> AtbDbResult<Bot> {
+        patch.validate()?;
+        let mut conn = self.get_conn()?;
+
+        // 更新するカラム名は固定の一覧から作成する
+        let columns = patch.to_columns();
+        let sql_set = columns
+            .iter()
+            .enumerate()
+            .map(|(i, (column, _))| format!("{} = ?{}", column, i + 2))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut params = vec![rusqlite::types::Value::Integer(id)];
+        params.extend(columns.into_iter().map(|(_, value)| value));
+        let sql_update = format!("update bot set {} where id = ?1", sql_set);
+
+        let tx = conn.transaction()?;
+        let old = select_active_bot(&tx, id)?;
+        tx.execute(&sql_update, params)?;
+        let bot = select_bot(&tx, id)?;
+        record_bot_history(&tx, id, BotAction::Update, Some(&old), Some(&bot), actor)?;
+        tx.commit()?;
+        Ok(bot)
+    }
+
+    // botをアーカイブし、アーカイブしたbotを返す
+    // アーカイブしたbotは一覧・APIの認証から除外される(restore_botで復元できる)
+    pub fn archive_bot(&self, id: i64, actor: &Actor) -> AtbDbResult<Bot> {
+        let mut conn = self.get_conn()?;
+
+        let tx = conn.transaction()?;
+        let old = select_active_bot(&tx, id)?;
+        tx.execute(
+            "update bot set archived_at = strftime('%s', 'now') where id = ?1",
+            rusqlite::params![id],
+        )?;
+        let bot = select_bot(&tx, id)?;
+        record_bot_history(&tx, id, BotAction::Archive, Some(&old), Some(&bot), actor)?;
+        tx.commit()?;
+        Ok(bot)
+    }
+
+    // アーカイブしたbotを復元し、復元したbotを返す
+    pub fn restore_bot(&self, id: i64, actor: &Actor) -> AtbDbResult<Bot> {
+        let mut conn = self.get_conn()?;
+
+        let tx = conn.transaction()?;
+        let old = select_archived_bot(&tx, id)?;
+        tx.execute(
+            "update bot set archived_at = null where id = ?1",
+            rusqlite::params![id],
+        )?;
+        let bot = select_bot(&tx, id)?;
+        record_bot_history(&tx, id, BotAction::Restore, Some(&old), Some(&bot), actor)?;
+        tx.commit()?;
+        Ok(bot)
+    }
+
+    // アーカイブしたbotと評価結果を完全に削除する(元に戻せない)
+    // 削除したテーブルごとの件数を返す
+    // 変更履歴・運用段階の変更履歴はbotのIDのまま残し、完全削除したことも履歴に残す
+    pub fn purge_bot(&self, id: i64, actor: &Actor) -> AtbDbResult<Vec<(String, usize)>> {
+        let mut conn = self.get_conn()?;
+
+        let tx = conn.transaction()?;
+        let old = select_archived_bot(&tx, id)?;
+        let mut deleted = vec![];
+        for (table, condition) in BOT_PURGED_TABLES {
+            let count = tx.execute(
+                &format!("delete from {} where {}", table, condition),
+                rusqlite::params![id],
+            )?;
+            deleted.push((table.to_string(), count));
+        }
+        let count = tx.execute("delete from bot where id = ?1", rusqlite::params![id])?;
+        deleted.push(("bot".to_string(), count));
+        record_bot_history(&tx, id, BotAction::Purge, Some(&old), None, actor)?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    // botデータを取得する
+    pub fn get_bot(&self, id: i64) -> AtbDbResult<Bot> {
+        let conn = self.get_conn()?;
+        select_bot(&conn, id)
+    }
+
+    // (API用)トークンを確認してbotデータを取得する
+    // idが存在しない場合もトークンが一致しない場合もNotFoundを返す
+    pub fn get_bot_for_api(&self, id: i64, token: &str) -> AtbDbResult<BotForGet> {
+        let conn = self.get_conn()?;
+        verify_bot_token(&conn, id, token)?;
+
+        let bot = select_bot(&conn, id)?;
+        Ok(BotForGet {
+            name: bot.name,
+            description: bot.description,
+            enable: bot.enable,
+            registered: bot.registered,
+            long_order: bot.long_order,
+            short_order: bot.short_order,
+            operate_type: bot.operate_type,
+        })
+    }
+
+    // アーカイブしていないbotデータの一覧を取得する
+    pub fn get_bot_list(&self) -> AtbDbResult<BotList> {
+        self.select_bot_list(&format!("{} where archived_at is null", SQL_SELECT_BOT))
+    }
+
+    // アーカイブしたbotを含むbotデータの一覧を取得する
+    pub fn get_all_bot_list(&self) -> AtbDbResult<BotList> {
+        self.select_bot_list(SQL_SELECT_BOT)
+    }
+
+    fn select_bot_list(&self, sql: &str) -> AtbDbResult<BotList> {
+        let conn = self.get_conn()?;
+
+        // bot情報一覧を取得する
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map(rusqlite::params![], Bot::from_row)?;
+
+        Ok(BotList {
+            bot: rows.collect::<Result<Vec<Bot>, _>>()?,
+        })
+    }
+}
+
+// トークンが指定したbotのものか確認する
+// idが存在しない場合・アーカイブした場合・トークンが一致しない場合はNotFoundを返す
+pub(crate) fn verify_bot_token(
+    conn: &rusqlite::Connection,
+    id: i64,
+    token: &str,
+) -> AtbDbResult<()> {
+    use rusqlite::OptionalExtension;
+
+    // トークンの先頭部分でbotを検索する
+    let prefix = token_prefix(token).ok_or(AtbDbError::NotFound)?;
+    let found = conn
+        .query_row(
+            "select id, token_salt, token_hash from bot where token_prefix = ?1 and archived_at is null",
+            rusqlite::params![prefix],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    // ハッシュ値を比較し、一致したbotが指定したidか確認する
+    match &found {
+        Some((found_id, salt, hash)) if verify_token(token, salt, hash) && *found_id == id => {
+            Ok(())
+        }
+        _ => Err(AtbDbError::NotFound),
+    }
+}
+
+// botに新しいトークンを発行して保存し、平文を返す
+fn issue_token(conn: &rusqlite::Connection, id: i64) -> AtbDbResult<String> {
+    for _ in 0..TOKEN_RETRY {
+        let (token, hashed) = generate_token();
+        let HashedToken { prefix, salt, hash } = hashed;
+        let result = conn.execute(
+            "update bot set token_prefix = ?2, token_salt = ?3, token_hash = ?4 where id = ?1",
+            rusqlite::params![id, prefix, salt, hash],
+        );
+        match result {
+            Ok(0) => return Err(AtbDbError::NotFound),
+            Ok(_) => return Ok(token),
+            // 先頭部分が他のbotと重複した場合は発行し直す
+            Err(rusqlite::Error::SqliteFailure(err, _))
+                if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                continue
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err(AtbDbError::Validation(
+        "トークンを発行できませんでした".to_string(),
+    ))
+}
+
+// 指定したIDのbotを取得する
+pub(crate) fn select_bot(conn: &rusqlite::Connection, id: i64) -> AtbDbResult<Bot> {
+    let bot = conn.query_row(
+        &format!("{} where id = ?1 limit 1", SQL_SELECT_BOT),
+        rusqlite::params![id],
+        Bot::from_row,
+    )?;
+    Ok(bot)
+}
+
+// 指定したIDのアーカイブしていないbotを取得する
+pub(crate) fn select_active_bot(conn: &rusqlite::Connection, id: i64) -> AtbDbResult<Bot> {
+    let bot = select_bot(conn, id)?;
+    if bot.is_archived() {
+        return Err(AtbDbError::Validation(format!(
+            "bot id: {} はアーカイブされています",
+            id
+        )));
+    }
+    Ok(bot)
+}
+
+// botの運用段階を変更し、botの変更履歴に記録する
+pub(crate) fn update_operate_type(
+    conn: &rusqlite::Connection,
+    old: &Bot,
+    operate_type: OperateType,
+    actor: &Actor,
+) -> AtbDbResult<Bot> {
+    conn.execute(
+        "update bot set operate_type = ?2 where id = ?1",
+        rusqlite::params![old.id, operate_type.as_str()],
+    )?;
+    let bot = select_bot(conn, old.id)?;
+    record_bot_history(
+        conn,
+        old.id,
+        BotAction::Update,
+        Some(old),
+        Some(&bot),
+        actor,
+    )?;
+    Ok(bot)
+}
+
+// 指定したIDのアーカイブしたbotを取得する
+fn select_archived_bot(conn: &rusqlite::Connection, id: i64) -> AtbDbResult<Bot> {
+    let bot = select_bot(conn, id)?;
+    if !bot.is_archived() {
+        return Err(AtbDbError::Validation(format!(
+            "bot id: {} はアーカイブされていません",
+            id
+        )));
+    }
+    Ok(bot)
+}
+
+impl Bot {
+    #[allow(dead_code)]
+    pub fn get_id(&self) -> i64 {
+        self.id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    #[allow(dead_code)]
+    pub fn get_description(&self) -> &str {
+        &self.description
+    }
+
+    #[allow(dead_code)]
+    pub fn is_enable(&self) -> bool {
+        self.enable
+    }
+
+    #[allow(dead_code)]
+    pub fn get_registered(&self) -> i64 {
+        self.registered
+    }
+
+    #[allow(dead_code)]
+    pub fn get_token_prefix(&self) -> Option<&str> {
+        self.token_prefix.as_deref()
+    }
+
+    // 追加・再発行した時のトークンの平文
+    #[allow(dead_code)]
+    pub fn get_token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_long_order(&self) -> bool {
+        self.long_order
+    }
+
+    #[allow(dead_code)]
+    pub fn is_short_order(&self) -> bool {
+        self.short_order
+    }
+
+    #[allow(dead_code)]
+    pub fn get_operate_type(&self) -> OperateType {
+        self.operate_type
+    }
+
+    #[allow(dead_code)]
+    pub fn get_archived_at(&self) -> Option<i64> {
+        self.archived_at
+    }
+
+    #[allow(dead_code)]
+    pub fn is_archived(&self) -> bool {
+        self.archived_at.is_some()
+    }
+
+    // 取得したレコードをBotに変換する
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Bot> {
+        let operate_type = row.get::<_, String>(8)?;
+        Ok(Bot {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            description: row.get(2)?,
+            enable: row.get(3)?,
+            registered: row.get(4)?,
+            token_prefix: row.get(5)?,
+            token: None,
+            long_order: row.get(6)?,
+            short_order: row.get(7)?,
+            operate_type: operate_type.parse().map_err(|_| {
+                rusqlite::Error::InvalidColumnType(
+                    8,
+                    "operate_type".to_string(),
+                    rusqlite::types::Type::Text,
+                )
+            })?,
+            archived_at: row.get(9)?,
+        })
+    }
+}
+
+impl BotList {
+    #[allow(dead_code)]
+    pub fn get_list_len(&self) -> usize {
+        self.bot.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_list(&self) -> &[Bot] {
+        &self.bot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn operate_type() {
+        use super::*;
+        assert_eq!(
+            "forwardtest".parse::<OperateType>().unwrap(),
+            OperateType::Forwardtest
+        );
+        assert!("operation".parse::<OperateType>().is_err());
+        assert!(OperateType::Backtest < OperateType::Forwardtest);
+        assert_eq!(OperateType::Forwardtest.next(), Some(OperateType::Product));
+        assert_eq!(OperateType::Product.next(), None);
+        assert_eq!(
+            serde_json::to_string(&OperateType::Product).unwrap(),
+            r#""product""#
+        );
+    }
+
+    #[test]
+    fn bot_create_and_patch_from_json() {
+        use super::*;
+        let bot: BotCreate = serde_json::from_str(r#"{"name":"a","description":"b"}"#).unwrap();
+        assert_eq!(bot, BotCreate::new("a", "b"));
+
+        let bot: BotCreate =
+            serde_json::from_str(r#"{"name":"a","description":"b","enable":false}"#).unwrap();
+        assert_eq!(bot, BotCreate::new("a", "b").enable(false));
+
+        // テーブルにない項目・運用段階は受け付けない
+        assert!(serde_json::from_str::<BotCreate>(
+            r#"{"name":"a","description":"b","id":"1; drop table bot"}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<BotCreate>(
+            r#"{"name":"a","description":"b","operate_type":"product"}"#
+        )
+        .is_err());
+        assert!(serde_json::from_str::<BotPatch>(r#"{"token":"x"}"#).is_err());
+        assert!(serde_json::from_str::<BotPatch>(r#"{"operate_type":"product"}"#).is_err());
+
+        assert!(BotCreate::new(" ", "b").validate().is_err());
+        assert!(BotPatch::new().validate().is_err());
+        assert!(BotPatch::new().description("").validate().is_err());
+    }
+
+    #[test]
+    fn bot_crud() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let actor = Actor::cli("test");
+        let bot = atbdb
+            .insert_bot(&BotCreate::new("test", "test bot"), &actor)
+            .unwrap();
+        assert_eq!(bot.get_id(), 1);
+        assert_eq!(bot.get_operate_type(), OperateType::Backtest);
+        assert!(bot.is_enable() && bot.is_long_order() && !bot.is_short_order());
+        let token = bot.get_token().unwrap().to_string();
+        assert!(token.starts_with(bot.get_token_prefix().unwrap()));
+        assert!(atbdb.get_bot_for_api(1, &token).is_ok());
+        assert!(matches!(
+            atbdb.get_bot_for_api(1, &"x".repeat(36)),
+            Err(AtbDbError::NotFound)
+        ));
+
+        // 取得したbotにはトークンの平文が含まれない
+        let bot = atbdb.get_bot(1).unwrap();
+        assert_eq!(bot.get_token(), None);
+        assert!(!serde_json::to_string(&bot).unwrap().contains(&token));
+
+        let patch = BotPatch::new().name("renamed").short_order(true);
+        let bot = atbdb.update_bot(1, &patch, &actor).unwrap();
+        assert_eq!(bot.get_name(), "renamed");
+        assert_eq!(bot.get_description(), "test bot");
+        assert!(bot.is_short_order());
+        assert!(matches!(
+            atbdb.update_bot(2, &patch, &actor),
+            Err(AtbDbError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn archive_restore_purge_bot() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let actor = Actor::cli("test");
+        let bot = atbdb
+            .insert_bot(&BotCreate::new("test", "test bot"), &actor)
+            .unwrap();
+        let token = bot.get_token().unwrap().to_string();
+        atbdb.insert_bot(&BotCreate::new("b", "b"), &actor).unwrap();
+
+        // アーカイブしたbotは一覧・APIから除外され、更新できない
+        let bot = atbdb.archive_bot(1, &actor).unwrap();
+        assert!(bot.is_archived());
+        assert_eq!(atbdb.get_bot_list().unwrap().get_list_len(), 1);
+        assert_eq!(atbdb.get_all_bot_list().unwrap().get_list_len(), 2);
+        assert!(matches!(
+            atbdb.get_bot_for_api(1, &token),
+            Err(AtbDbError::NotFound)
+        ));
+        assert!(matches!(
+            atbdb.update_bot(1, &BotPatch::new().enable(false), &actor),
+            Err(AtbDbError::Validation(_))
+        ));
+        assert!(matches!(
+            atbdb.archive_bot(1, &actor),
+            Err(AtbDbError::Validation(_))
+        ));
+
+        // 復元すると同じトークンで取得できる
+        let bot = atbdb.restore_bot(1, &actor).unwrap();
+        assert!(!bot.is_archived());
+        assert!(atbdb.get_bot_for_api(1, &token).is_ok());
+
+        // アーカイブしていないbotは完全削除できない
+        assert!(matches!(
+            atbdb.purge_bot(1, &actor),
+            Err(AtbDbError::Validation(_))
+        ));
+        atbdb.archive_bot(1, &actor).unwrap();
+        let deleted = atbdb.purge_bot(1, &actor).unwrap();
+        assert_eq!(
+            deleted,
+            vec![("bot_evaluation".to_string(), 0), ("bot".to_string(), 1)]
+        );
+        assert!(matches!(atbdb.get_bot(1), Err(AtbDbError::NotFound)));
+        assert!(matches!(
+            atbdb.purge_bot(1, &actor),
+            Err(AtbDbError::NotFound)
+        ));
+
+        // 変更履歴は残り、完全削除したことも記録する
+        let history = atbdb.get_bot_history(1, None).unwrap();
+        assert_eq!(history.len(), 5);
+        assert_eq!(history[0].get_action(), BotAction::Purge);
+        assert_eq!(atbdb.get_bot_history(2, None).unwrap().len(), 1);
+
+        // 完全削除したbotのIDは使わない
+        atbdb.archive_bot(2, &actor).unwrap();
+        atbdb.purge_bot(2, &actor).unwrap();
+        let bot = atbdb.insert_bot(&BotCreate::new("c", "c"), &actor).unwrap();
+        assert_eq!(bot.get_id(), 3);
+        assert_eq!(atbdb.get_bot_history(3, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rotate_bot_token() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let actor = Actor::cli("test");
+        let first = atbdb.insert_bot(&BotCreate::new("a", "a"), &actor).unwrap();
+        let second = atbdb.insert_bot(&BotCreate::new("b", "b"), &actor).unwrap();
+        let first_token = first.get_token().unwrap();
+        let second_token = second.get_token().unwrap();
+
+        // 他のbotのトークンでは取得できない
+        assert!(matches!(
+            atbdb.get_bot_for_api(1, second_token),
+            Err(AtbDbError::NotFound)
+        ));
+
+        // 再発行すると以前のトークンは使えなくなる
+        let rotated = atbdb.rotate_bot_token(1, &actor).unwrap();
+        let rotated_token = rotated.get_token().unwrap();
+        assert_ne!(rotated_token, first_token);
+        assert!(matches!(
+            atbdb.get_bot_for_api(1, first_token),
+            Err(AtbDbError::NotFound)
+        ));
+        assert!(atbdb.get_bot_for_api(1, rotated_token).is_ok());
+        assert!(atbdb.get_bot_for_api(2, second_token).is_ok());
+
+        assert!(matches!(
+            atbdb.rotate_bot_token(3, &actor),
+            Err(AtbDbError::NotFound)
+        ));
+    }
+}
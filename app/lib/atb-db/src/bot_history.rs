@@ -0,0 +1,337 @@
+use crate::bot::verify_bot_token;
+use crate::{AtbDB, AtbDbError, AtbDbResult, Bot};
+
+// botを操作した人・プログラム
+#[derive(Clone, Debug, PartialEq)]
+pub struct Actor(String);
+
+impl Actor {
+    // コマンドラインから操作したユーザー
+    pub fn cli(user: &str) -> Actor {
+        Actor(format!("cli:{}", user))
+    }
+
+    // APIから操作した接続元(APIキー・接続元アドレスなど)
+    pub fn api(key: &str) -> Actor {
+        Actor(format!("api:{}", key))
+    }
+
+    // 環境変数USER(なければLOGNAME)のユーザーをコマンドラインの操作者とする
+    pub fn from_env() -> Actor {
+        let user = std::env::var("USER")
+            .or_else(|_| std::env::var("LOGNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        Actor::cli(&user)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Actor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// botに対する操作
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BotAction {
+    Insert,
+    Update,
+    // 旧バージョンのdelete_botで削除した
+    Delete,
+    RotateToken,
+    Archive,
+    Restore,
+    Purge,
+}
+
+impl BotAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BotAction::Insert => "insert",
+            BotAction::Update => "update",
+            BotAction::Delete => "delete",
+            BotAction::RotateToken => "rotate_token",
+            BotAction::Archive => "archive",
+            BotAction::Restore => "restore",
+            BotAction::Purge => "purge",
+        }
+    }
+}
+
+impl std::str::FromStr for BotAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<BotAction, String> {
+        match s {
+            "insert" => Ok(BotAction::Insert),
+            "update" => Ok(BotAction::Update),
+            "delete" => Ok(BotAction::Delete),
+            "rotate_token" => Ok(BotAction::RotateToken),
+            "archive" => Ok(BotAction::Archive),
+            "restore" => Ok(BotAction::Restore),
+            "purge" => Ok(BotAction::Purge),
+            _ => Err(format!("不明な操作です: {}", s)),
+        }
+    }
+}
+
+// bot_historyテーブルのレコード
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct BotHistory {
+    id: i64,
+    bot_id: i64,
+    action: BotAction,
+    old_value: Option<serde_json::Value>,
+    new_value: Option<serde_json::Value>,
+    actor: String,
+    registered: i64,
+}
+
+impl BotHistory {
+    #[allow(dead_code)]
+    pub fn get_bot_id(&self) -> i64 {
+        self.bot_id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_action(&self) -> BotAction {
+        self.action
+    }
+
+    #[allow(dead_code)]
+    pub fn get_old_value(&self) -> Option<&serde_json::Value> {
+        self.old_value.as_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_new_value(&self) -> Option<&serde_json::Value> {
+        self.new_value.as_ref()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_actor(&self) -> &str {
+        &self.actor
+    }
+
+    #[allow(dead_code)]
+    pub fn get_registered(&self) -> i64 {
+        self.registered
+    }
+
+    // 取得したレコードをBotHistoryに変換する
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<BotHistory> {
+        let action = row.get::<_, String>(2)?;
+        Ok(BotHistory {
+            id: row.get(0)?,
+            bot_id: row.get(1)?,
+            action: action.parse().map_err(|_| {
+                rusqlite::Error::InvalidColumnType(
+                    2,
+                    "action".to_string(),
+                    rusqlite::types::Type::Text,
+                )
+            })?,
+            old_value: parse_json(row, 3)?,
+            new_value: parse_json(row, 4)?,
+            actor: row.get(5)?,
+            registered: row.get(6)?,
+        })
+    }
+}
+
+fn parse_json(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<Option<serde_json::Value>> {
+    row.get::<_, Option<String>>(idx)?
+        .map(|value| {
+            serde_json::from_str(&value).map_err(|err| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    idx,
+                    rusqlite::types::Type::Text,
+                    Box::new(err),
+                )
+            })
+        })
+        .transpose()
+}
+
+impl AtbDB {
+    // botの変更履歴を新しい順に取得する
+    pub fn get_bot_history(
+        &self,
+        bot_id: i64,
+        limit: Option<usize>,
+    ) -> AtbDbResult<Vec<BotHistory>> {
+        let conn = self.get_conn()?;
+        select_bot_history(&conn, bot_id, limit)
+    }
+
+    // (API用)トークンを確認してbotの変更履歴を新しい順に取得する
+    pub fn get_bot_history_for_api(
+        &self,
+        bot_id: i64,
+        token: &str,
+        limit: Option<usize>,
+    ) -> AtbDbResult<Vec<BotHistory>> {
+        let conn = self.get_conn()?;
+        verify_bot_token(&conn, bot_id, token)?;
+        select_bot_history(&conn, bot_id, limit)
+    }
+}
+
+fn select_bot_history(
+    conn: &rusqlite::Connection,
+    bot_id: i64,
+    limit: Option<usize>,
+) -> AtbDbResult<Vec<BotHistory>> {
+    let mut stmt = conn.prepare(
+        "select id, bot_id, action, old_value, new_value, actor, registered from bot_history where bot_id = ?1 order by id desc limit ?2",
+    )?;
+    let limit = limit.map(|limit| limit as i64).unwrap_or(-1);
+    let rows = stmt.query_map(rusqlite::params![bot_id, limit], BotHistory::from_row)?;
+    Ok(rows.collect::<Result<Vec<BotHistory>, _>>()?)
+}
+
+// botの変更履歴を記録する(botの変更と同じトランザクションで実行する)
+// 変更前後のbotから、値が変わった項目だけを記録する(変わった項目がなければ記録しない)
+pub(crate) fn record_bot_history(
+    conn: &rusqlite::Connection,
+    bot_id: i64,
+    action: BotAction,
+    old: Option<&Bot>,
+    new: Option<&Bot>,
+    actor: &Actor,
+) -> AtbDbResult<()> {
+    let mut old_value = old.map(bot_values).transpose()?;
+    let mut new_value = new.map(bot_values).transpose()?;
+
+    // 変更前後の両方がある場合は、値が同じ項目を除く
+    if let (Some(old_value), Some(new_value)) = (old_value.as_mut(), new_value.as_mut()) {
+        let unchanged: Vec<String> = old_value
+            .iter()
+            .filter(|(key, value)| new_value.get(*key) == Some(*value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in unchanged {
+            old_value.remove(&key);
+            new_value.remove(&key);
+        }
+        if old_value.is_empty() && new_value.is_empty() {
+            return Ok(());
+        }
+    }
+
+    let to_json = |value: Option<serde_json::Map<String, serde_json::Value>>| {
+        value.map(|value| serde_json::Value::Object(value).to_string())
+    };
+    conn.execute(
+        "insert into bot_history (bot_id, action, old_value, new_value, actor) values (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            bot_id,
+            action.as_str(),
+            to_json(old_value),
+            to_json(new_value),
+            actor.as_str()
+        ],
+    )?;
+    Ok(())
+}
+
+// 履歴に記録するbotの項目(id・登録日時・トークンの平文は含めない)
+fn bot_values(bot: &Bot) -> AtbDbResult<serde_json::Map<String, serde_json::Value>> {
+    match serde_json::to_value(bot) {
+        Ok(serde_json::Value::Object(mut values)) => {
+            for key in &["id", "registered", "token"] {
+                values.remove(*key);
+            }
+            Ok(values)
+        }
+        Ok(_) => Err(AtbDbError::Decode("botを変換できません".to_string())),
+        Err(err) => Err(AtbDbError::Decode(err.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn record_bot_history() {
+        use super::*;
+        use crate::{BotCreate, BotPatch};
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let cli = Actor::cli("alice");
+        let api = Actor::api("127.0.0.1");
+
+        let bot = atbdb
+            .insert_bot(&BotCreate::new("test", "test bot"), &api)
+            .unwrap();
+        let token = bot.get_token().unwrap().to_string();
+        let patch = BotPatch::new().enable(false).short_order(false);
+        atbdb.update_bot(1, &patch, &cli).unwrap();
+        // 値が変わらない更新は記録しない
+        atbdb.update_bot(1, &patch, &cli).unwrap();
+        atbdb.rotate_bot_token(1, &cli).unwrap();
+        atbdb.archive_bot(1, &cli).unwrap();
+
+        // 履歴を新しい順に取得できる
+        let history = atbdb.get_bot_history(1, None).unwrap();
+        let actions: Vec<BotAction> = history.iter().map(|h| h.get_action()).collect();
+        assert_eq!(
+            actions,
+            vec![
+                BotAction::Archive,
+                BotAction::RotateToken,
+                BotAction::Update,
+                BotAction::Insert
+            ]
+        );
+        assert_eq!(history[3].get_actor(), "api:127.0.0.1");
+        assert_eq!(history[2].get_actor(), "cli:alice");
+
+        // 更新は値が変わった項目だけを記録する
+        assert_eq!(
+            history[2].get_old_value().unwrap(),
+            &serde_json::json!({"enable": true})
+        );
+        assert_eq!(
+            history[2].get_new_value().unwrap(),
+            &serde_json::json!({"enable": false})
+        );
+        assert!(history[3].get_old_value().is_none());
+        assert_eq!(history[3].get_new_value().unwrap()["name"], "test");
+        assert!(history[0].get_old_value().unwrap()["archived_at"].is_null());
+        assert!(history[0].get_new_value().unwrap()["archived_at"].is_i64());
+
+        // トークンの平文は記録しない
+        let rotated = history[1].get_new_value().unwrap();
+        assert!(rotated.get("token_prefix").is_some());
+        for h in &history {
+            assert!(!serde_json::to_string(h).unwrap().contains(&token));
+        }
+
+        assert_eq!(atbdb.get_bot_history(1, Some(1)).unwrap().len(), 1);
+        assert!(atbdb.get_bot_history(2, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn get_bot_history_for_api() {
+        use super::*;
+        use crate::BotCreate;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let bot = atbdb
+            .insert_bot(&BotCreate::new("test", "test bot"), &Actor::cli("alice"))
+            .unwrap();
+        let token = bot.get_token().unwrap();
+
+        assert_eq!(
+            atbdb.get_bot_history_for_api(1, token, None).unwrap().len(),
+            1
+        );
+        assert!(matches!(
+            atbdb.get_bot_history_for_api(1, &"0".repeat(40), None),
+            Err(AtbDbError::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,333 @@
+// 価格・出来高を誤差なく扱うための固定小数点数(mantissa × 10^-scale)
+#[derive(Clone, Copy, Debug)]
+pub struct Decimal {
+    mantissa: i64,
+    scale: u32,
+}
+
+// 扱える小数点以下の最大桁数
+pub const MAX_DECIMAL_SCALE: u32 = 18;
+
+impl Decimal {
+    pub fn new(mantissa: i64, scale: u32) -> Decimal {
+        assert!(
+            scale <= MAX_DECIMAL_SCALE,
+            "scale must be {} or less",
+            MAX_DECIMAL_SCALE
+        );
+        Decimal { mantissa, scale }
+    }
+
+    pub fn zero(scale: u32) -> Decimal {
+        Decimal::new(0, scale)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_mantissa(&self) -> i64 {
+        self.mantissa
+    }
+
+    #[allow(dead_code)]
+    pub fn get_scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa == 0
+    }
+
+    // 値を変えずに小数点以下の桁数を変更する(桁が落ちる場合やオーバーフローする場合はNone)
+    pub fn rescale(&self, scale: u32) -> Option<Decimal> {
+        if MAX_DECIMAL_SCALE < scale {
+            return None;
+        }
+        if self.scale <= scale {
+            let mantissa = self
+                .mantissa
+                .checked_mul(10i64.checked_pow(scale - self.scale)?)?;
+            return Some(Decimal { mantissa, scale });
+        }
+        let factor = 10i64.pow(self.scale - scale);
+        if self.mantissa % factor != 0 {
+            return None;
+        }
+        Some(Decimal {
+            mantissa: self.mantissa / factor,
+            scale,
+        })
+    }
+
+    // 指定した桁数に四捨五入する(オーバーフローする場合はNone)
+    pub fn round_to(&self, scale: u32) -> Option<Decimal> {
+        if self.scale <= scale {
+            return self.rescale(scale);
+        }
+        let factor = 10i64.pow(self.scale - scale);
+        let quotient = self.mantissa / factor;
+        let remainder = self.mantissa % factor;
+        let mantissa = if factor <= remainder.abs() * 2 {
+            quotient + self.mantissa.signum()
+        } else {
+            quotient
+        };
+        Some(Decimal { mantissa, scale })
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        let lhs = self.rescale(scale)?;
+        let rhs = other.rescale(scale)?;
+        Some(Decimal {
+            mantissa: lhs.mantissa.checked_add(rhs.mantissa)?,
+            scale,
+        })
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        let scale = self.scale.max(other.scale);
+        let lhs = self.rescale(scale)?;
+        let rhs = other.rescale(scale)?;
+        Some(Decimal {
+            mantissa: lhs.mantissa.checked_sub(rhs.mantissa)?,
+            scale,
+        })
+    }
+
+    // 浮動小数点数に変換する(指標計算など誤差を許容する用途向け)
+    pub fn to_f64(&self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    // 桁数を揃えた仮数を比較用に返す
+    fn aligned(&self, other: &Decimal) -> (i128, i128) {
+        let scale = self.scale.max(other.scale);
+        (
+            self.mantissa as i128 * 10i128.pow(scale - self.scale),
+            other.mantissa as i128 * 10i128.pow(scale - other.scale),
+        )
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        let (lhs, rhs) = self.aligned(other);
+        lhs == rhs
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> std::cmp::Ordering {
+        let (lhs, rhs) = self.aligned(other);
+        lhs.cmp(&rhs)
+    }
+}
+
+// 整数型と同様に、オーバーフローした場合はpanicする
+impl std::ops::Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: Decimal) -> Decimal {
+        self.checked_add(&other).expect("Decimal overflow")
+    }
+}
+
+impl std::ops::Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: Decimal) -> Decimal {
+        self.checked_sub(&other).expect("Decimal overflow")
+    }
+}
+
+impl std::ops::Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal::new(-self.mantissa, self.scale)
+    }
+}
+
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let digits = self.mantissa.unsigned_abs().to_string();
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+
+        // 小数点以下の桁数に満たない場合は0で埋める
+        let digits = format!("{:0>width$}", digits, width = self.scale as usize + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - self.scale as usize);
+        write!(f, "{}{}.{}", sign, integer, fraction)
+    }
+}
+
+// 文字列の指数部の上限(仮数部の最大桁数 + 小数点以下の最大桁数)
+const MAX_EXPONENT: i64 = MAX_DECIMAL_SCALE as i64 + 19;
+
+impl std::str::FromStr for Decimal {
+    type Err = String;
+
+    // "123", "-0.5", "1.5e-7" 形式の文字列を変換する
+    fn from_str(s: &str) -> Result<Decimal, String> {
+        use std::convert::TryFrom;
+        let invalid = || format!("数値に変換できません: {}", s);
+
+        let (number, exponent) = match s.find(['e', 'E']) {
+            Some(i) => (&s[..i], s[i + 1..].parse::<i64>().map_err(|_| invalid())?),
+            None => (s, 0),
+        };
+        // 仮数部(i64は19桁)と小数点以下の桁数で表せない指数は、桁を埋める前に拒否する
+        if MAX_EXPONENT < exponent.abs() {
+            return Err(format!(
+                "指数は±{}の範囲で指定してください: {}",
+                MAX_EXPONENT, s
+            ));
+        }
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(number) => (true, number),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (integer, fraction) = match number.find('.') {
+            Some(i) => (&number[..i], &number[i + 1..]),
+            None => (number, ""),
+        };
+        if integer.is_empty() && fraction.is_empty()
+            || !integer
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        // 指数部を小数点以下の桁数に反映する
+        let mut digits = format!("{}{}", integer, fraction);
+        let mut scale = i64::try_from(fraction.len())
+            .ok()
+            .and_then(|len| len.checked_sub(exponent))
+            .ok_or_else(invalid)?;
+        if scale < 0 {
+            digits.push_str(&"0".repeat(-scale as usize));
+            scale = 0;
+        }
+
+        // 末尾の不要な0を取り除く
+        while 0 < scale && digits.ends_with('0') {
+            digits.pop();
+            scale -= 1;
+        }
+        if i64::from(MAX_DECIMAL_SCALE) < scale {
+            return Err(format!(
+                "小数点以下は{}桁までです: {}",
+                MAX_DECIMAL_SCALE, s
+            ));
+        }
+
+        let mantissa = digits.parse::<i64>().map_err(|_| invalid())?;
+        Ok(Decimal::new(
+            if negative { -mantissa } else { mantissa },
+            scale as u32,
+        ))
+    }
+}
+
+impl serde::Serialize for Decimal {
+    // 誤差が出ないよう文字列として出力する
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Decimal {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Decimal, D::Error> {
+        struct DecimalVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for DecimalVisitor {
+            type Value = Decimal;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a decimal string or number")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Decimal, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Decimal, E> {
+                Ok(Decimal::new(v, 0))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Decimal, E> {
+                use std::convert::TryFrom;
+                let v = i64::try_from(v).map_err(E::custom)?;
+                Ok(Decimal::new(v, 0))
+            }
+
+            // f64の最短表記を経由して変換する
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Decimal, E> {
+                v.to_string().parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_and_display() {
+        use super::*;
+        assert_eq!("123".parse::<Decimal>().unwrap(), Decimal::new(123, 0));
+        assert_eq!("-0.05".parse::<Decimal>().unwrap(), Decimal::new(-5, 2));
+        assert_eq!("1.5e-7".parse::<Decimal>().unwrap(), Decimal::new(15, 8));
+        assert_eq!("2E3".parse::<Decimal>().unwrap(), Decimal::new(2000, 0));
+        assert!("1.2.3".parse::<Decimal>().is_err());
+        assert!("".parse::<Decimal>().is_err());
+
+        // 桁を埋めきれない・桁数の計算が溢れる指数は拒否する
+        assert!("1e2000000000".parse::<Decimal>().is_err());
+        assert!("1e-2147483648".parse::<Decimal>().is_err());
+        assert!("1e99999999999999999999".parse::<Decimal>().is_err());
+        assert!("1e20".parse::<Decimal>().is_err());
+        assert_eq!(
+            "0.00000000000000000000000000000001e37"
+                .parse::<Decimal>()
+                .unwrap(),
+            Decimal::new(100000, 0)
+        );
+
+        assert_eq!(Decimal::new(123450, 4).to_string(), "12.3450");
+        assert_eq!(Decimal::new(-5, 3).to_string(), "-0.005");
+        assert_eq!(Decimal::new(1000000, 0).to_string(), "1000000");
+    }
+
+    #[test]
+    fn rescale_and_round() {
+        use super::*;
+        let value = Decimal::new(12345, 3);
+        assert_eq!(value.rescale(5).unwrap().get_mantissa(), 1234500);
+        assert!(value.rescale(2).is_none());
+        assert_eq!(value.round_to(2).unwrap(), Decimal::new(1235, 2));
+        assert_eq!(
+            Decimal::new(-12345, 3).round_to(2).unwrap(),
+            Decimal::new(-1235, 2)
+        );
+        assert_eq!(value.round_to(0).unwrap(), Decimal::new(12, 0));
+        assert!(Decimal::new(i64::MAX, 0).rescale(1).is_none());
+
+        // 0.1 + 0.2 は誤差なく 0.3 になる
+        let sum = Decimal::new(1, 1).checked_add(&Decimal::new(2, 1)).unwrap();
+        assert_eq!(sum, "0.3".parse::<Decimal>().unwrap());
+        assert!(Decimal::new(15, 1) > Decimal::new(149, 2));
+    }
+}
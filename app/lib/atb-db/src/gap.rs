@@ -0,0 +1,503 @@
+use crate::{AtbDB, AtbDbError, AtbDbResult, OhlcvQuery, UnixTime};
+
+// 欠損期間の状態
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GapStatus {
+    // 未解決
+    Missing,
+    // 後の取得で埋まった
+    Repaired,
+    // 取得元にもデータがないことを確認した
+    Unavailable,
+}
+
+impl GapStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GapStatus::Missing => "missing",
+            GapStatus::Repaired => "repaired",
+            GapStatus::Unavailable => "unavailable",
+        }
+    }
+}
+
+impl std::str::FromStr for GapStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GapStatus, String> {
+        match s {
+            "missing" => Ok(GapStatus::Missing),
+            "repaired" => Ok(GapStatus::Repaired),
+            "unavailable" => Ok(GapStatus::Unavailable),
+            _ => Err(format!("不明な状態です: {}", s)),
+        }
+    }
+}
+
+// ローソク足の欠損期間(from〜toの足がすべて欠けている)
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct OhlcvGap {
+    exchange: String,
+    pair: String,
+    period: i64,
+    from: UnixTime,
+    to: UnixTime,
+    status: GapStatus,
+}
+
+impl OhlcvGap {
+    #[allow(dead_code)]
+    pub fn get_exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    #[allow(dead_code)]
+    pub fn get_pair(&self) -> &str {
+        &self.pair
+    }
+
+    #[allow(dead_code)]
+    pub fn get_period(&self) -> i64 {
+        self.period
+    }
+
+    #[allow(dead_code)]
+    pub fn get_from(&self) -> UnixTime {
+        self.from
+    }
+
+    #[allow(dead_code)]
+    pub fn get_to(&self) -> UnixTime {
+        self.to
+    }
+
+    #[allow(dead_code)]
+    pub fn get_status(&self) -> GapStatus {
+        self.status
+    }
+
+    // 欠けている足の本数
+    pub fn get_missing_count(&self) -> i64 {
+        (self.to.get_secs() - self.from.get_secs()) / self.period + 1
+    }
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<OhlcvGap> {
+        let status = row.get::<_, String>(5)?;
+        Ok(OhlcvGap {
+            exchange: row.get(0)?,
+            pair: row.get(1)?,
+            period: row.get(2)?,
+            from: UnixTime::new(row.get(3)?),
+            to: UnixTime::new(row.get(4)?),
+            status: status.parse().map_err(|_| {
+                rusqlite::Error::InvalidColumnType(
+                    5,
+                    "status".to_string(),
+                    rusqlite::types::Type::Text,
+                )
+            })?,
+        })
+    }
+}
+
+impl AtbDB {
+    // 検索条件の範囲で保存済みのローソク足の欠損期間を探す(保存はしない)
+    // from/toを指定した場合は、範囲の端の欠損も含める。queryの並び順・件数・カーソルは使用しない
+    pub fn find_ohlcv_gaps(&self, query: &OhlcvQuery) -> AtbDbResult<Vec<OhlcvGap>> {
+        let conn = self.get_conn()?;
+        let period = query.period;
+        let gap = |from: i64, to: i64| OhlcvGap {
+            exchange: query.exchange.clone(),
+            pair: query.pair.clone(),
+            period,
+            from: UnixTime::new(from),
+            to: UnixTime::new(to),
+            status: GapStatus::Missing,
+        };
+
+        let range_query = OhlcvQuery {
+            limit: None,
+            cursor: None,
+            ..query.clone()
+        };
+        let (sql, params) = range_query.to_sql("unixtime");
+
+        // 前の足との間隔が足の期間より長い箇所を欠損とする
+        let sql_gap = format!(
+            "SELECT prev, unixtime FROM (SELECT unixtime, LAG(unixtime) OVER (ORDER BY unixtime) AS prev FROM ({})) WHERE unixtime - prev > ?",
+            sql
+        );
+        let mut gap_params = params.clone();
+        gap_params.push(rusqlite::types::Value::Integer(period));
+        let mut stmt = conn.prepare(&sql_gap)?;
+        let rows = stmt.query_map(gap_params, |row| {
+            Ok(gap(
+                row.get::<_, i64>(0)? + period,
+                row.get::<_, i64>(1)? - period,
+            ))
+        })?;
+        let mut gaps = rows.collect::<Result<Vec<OhlcvGap>, _>>()?;
+
+        // 範囲の端の欠損を追加する
+        let (first, last) = conn.query_row(
+            &format!("SELECT min(unixtime), max(unixtime) FROM ({})", sql),
+            params,
+            |row| Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<i64>>(1)?)),
+        )?;
+        match (first, last) {
+            (Some(first), Some(last)) => {
+                if let Some(from) = query.from {
+                    let head = first - (first - from) / period * period;
+                    if head < first {
+                        gaps.insert(0, gap(head, first - period));
+                    }
+                }
+                if let Some(to) = query.to {
+                    let tail = last + (to - last) / period * period;
+                    if last < tail {
+                        gaps.push(gap(last + period, tail));
+                    }
+                }
+            }
+            _ => {
+                // 範囲内にローソク足が1本もなければ範囲全体を欠損とする
+                if let (Some(from), Some(to)) = (query.from, query.to) {
+                    let head = (from + period - 1).div_euclid(period) * period;
+                    let tail = to.div_euclid(period) * period;
+                    if head <= tail {
+                        gaps.push(gap(head, tail));
+                    }
+                }
+            }
+        }
+        Ok(gaps)
+    }
+
+    // 欠損期間を探してohlcv_gapテーブルに保存し、範囲内の欠損期間を返す
+    // 保存済みの欠損期間は状態を変更しない(先頭の足が埋まった未解決の欠損期間は縮める)
+    pub fn detect_ohlcv_gaps(&self, query: &OhlcvQuery) -> AtbDbResult<Vec<OhlcvGap>> {
+        let gaps = self.find_ohlcv_gaps(query)?;
+
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        shrink_ohlcv_gaps(&tx, &query.exchange, &query.pair, query.period)?;
+        {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO ohlcv_gap (exchange, pair, period, from_unixtime, to_unixtime) VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(exchange, pair, period, from_unixtime) DO UPDATE SET to_unixtime = excluded.to_unixtime, updated = strftime('%s', 'now')
+                 WHERE status = 'missing' and to_unixtime != excluded.to_unixtime",
+            )?;
+            for gap in &gaps {
+                stmt.execute(rusqlite::params![
+                    gap.exchange,
+                    gap.pair,
+                    gap.period,
+                    gap.from.get_secs(),
+                    gap.to.get_secs()
+                ])?;
+            }
+        }
+        tx.commit()?;
+
+        self.get_ohlcv_gap_list(query, None)
+    }
+
+    // 保存済みの欠損期間のうち、検索条件の範囲に重なるものを取得する
+    pub fn get_ohlcv_gap_list(
+        &self,
+        query: &OhlcvQuery,
+        status: Option<GapStatus>,
+    ) -> AtbDbResult<Vec<OhlcvGap>> {
+        let conn = self.get_conn()?;
+
+        let mut sql = "SELECT exchange, pair, period, from_unixtime, to_unixtime, status FROM ohlcv_gap WHERE exchange = ? and pair = ? and period = ?".to_string();
+        let mut params = vec![
+            rusqlite::types::Value::Text(query.exchange.clone()),
+            rusqlite::types::Value::Text(query.pair.clone()),
+            rusqlite::types::Value::Integer(query.period),
+        ];
+        if let Some(from) = query.from {
+            sql.push_str(" and to_unixtime >= ?");
+            params.push(rusqlite::types::Value::Integer(from));
+        }
+        if let Some(to) = query.to {
+            sql.push_str(" and from_unixtime <= ?");
+            params.push(rusqlite::types::Value::Integer(to));
+        }
+        if let Some(status) = status {
+            sql.push_str(" and status = ?");
+            params.push(rusqlite::types::Value::Text(status.as_str().to_string()));
+        }
+        sql.push_str(" ORDER BY from_unixtime");
+
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params, OhlcvGap::from_row)?;
+        Ok(rows.collect::<Result<Vec<OhlcvGap>, _>>()?)
+    }
+
+    // 欠損期間の状態を変更する
+    pub fn set_ohlcv_gap_status(&self, gap: &OhlcvGap, status: GapStatus) -> AtbDbResult<()> {
+        let conn = self.get_conn()?;
+
+        let count = conn.execute(
+            "UPDATE ohlcv_gap SET status = ?5, updated = strftime('%s', 'now') WHERE exchange = ?1 and pair = ?2 and period = ?3 and from_unixtime = ?4",
+            rusqlite::params![
+                gap.exchange,
+                gap.pair,
+                gap.period,
+                gap.from.get_secs(),
+                status.as_str()
+            ],
+        )?;
+        if count == 0 {
+            return Err(AtbDbError::NotFound);
+        }
+        Ok(())
+    }
+
+    // 未解決の欠損期間のうち、すべての足が保存されたものを取得済みにし、その件数を返す
+    pub fn repair_ohlcv_gaps(&self, exchange: &str, pair: &str, period: i64) -> AtbDbResult<usize> {
+        let mut conn = self.get_conn()?;
+
+        let tx = conn.transaction()?;
+        let mut count = shrink_ohlcv_gaps(&tx, exchange, pair, period)?;
+        count += tx.execute(
+            "UPDATE ohlcv_gap SET status = 'repaired', updated = strftime('%s', 'now')
+             WHERE exchange = ?1 and pair = ?2 and period = ?3 and status = 'missing'
+               and (to_unixtime - from_unixtime) / period + 1 = (
+                 SELECT count(*) FROM ohlcv o
+                 WHERE o.exchange = ohlcv_gap.exchange and o.pair = ohlcv_gap.pair and o.period = ohlcv_gap.period
+                   and o.unixtime BETWEEN ohlcv_gap.from_unixtime and ohlcv_gap.to_unixtime
+               )",
+            rusqlite::params![exchange, pair, period],
+        )?;
+        tx.commit()?;
+        Ok(count)
+    }
+}
+
+// 未解決の欠損期間のうち、先頭の足が保存されたもの(前から一部だけ埋まったもの)を、
+// 最初に欠けている足から始まるように縮める
+// 欠損期間はfrom_unixtimeで識別するため、縮めないと再検出した欠損期間と重複し、取得済みにもならない
+// すべての足が埋まっていれば取得済みにし、その件数を返す
+fn shrink_ohlcv_gaps(
+    tx: &rusqlite::Transaction,
+    exchange: &str,
+    pair: &str,
+    period: i64,
+) -> AtbDbResult<usize> {
+    let mut stmt = tx.prepare(
+        "SELECT from_unixtime, to_unixtime FROM ohlcv_gap g
+         WHERE exchange = ?1 and pair = ?2 and period = ?3 and status = 'missing'
+           and EXISTS (SELECT 1 FROM ohlcv o WHERE o.exchange = g.exchange and o.pair = g.pair and o.period = g.period and o.unixtime = g.from_unixtime)",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![exchange, pair, period], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?))
+    })?;
+    let stale = rows.collect::<Result<Vec<(i64, i64)>, _>>()?;
+
+    let mut repaired = 0;
+    for (from, to) in stale {
+        // 保存済みの足を先頭から順にたどり、最初に欠けている足を探す
+        let mut stmt = tx.prepare_cached(
+            "SELECT unixtime FROM ohlcv WHERE exchange = ?1 and pair = ?2 and period = ?3 and unixtime BETWEEN ?4 and ?5 ORDER BY unixtime",
+        )?;
+        let rows = stmt.query_map(rusqlite::params![exchange, pair, period, from, to], |row| {
+            row.get::<_, i64>(0)
+        })?;
+        let mut next = from;
+        for unixtime in rows {
+            if unixtime? != next {
+                break;
+            }
+            next += period;
+        }
+
+        let key = rusqlite::params![exchange, pair, period, from];
+        if to < next {
+            tx.execute(
+                "UPDATE ohlcv_gap SET status = 'repaired', updated = strftime('%s', 'now') WHERE exchange = ?1 and pair = ?2 and period = ?3 and from_unixtime = ?4",
+                key,
+            )?;
+            repaired += 1;
+            continue;
+        }
+        let exists = tx.query_row(
+            "SELECT count(*) FROM ohlcv_gap WHERE exchange = ?1 and pair = ?2 and period = ?3 and from_unixtime = ?4",
+            rusqlite::params![exchange, pair, period, next],
+            |row| row.get::<_, i64>(0),
+        )?;
+        if exists == 0 {
+            tx.execute(
+                "UPDATE ohlcv_gap SET from_unixtime = ?5, updated = strftime('%s', 'now') WHERE exchange = ?1 and pair = ?2 and period = ?3 and from_unixtime = ?4",
+                rusqlite::params![exchange, pair, period, from, next],
+            )?;
+        } else {
+            // 残りの欠損期間はすでに別に保存されている
+            tx.execute(
+                "DELETE FROM ohlcv_gap WHERE exchange = ?1 and pair = ?2 and period = ?3 and from_unixtime = ?4",
+                key,
+            )?;
+        }
+    }
+    Ok(repaired)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn gap_status() {
+        use super::*;
+        for status in &[
+            GapStatus::Missing,
+            GapStatus::Repaired,
+            GapStatus::Unavailable,
+        ] {
+            assert_eq!(status.as_str().parse::<GapStatus>().unwrap(), *status);
+        }
+        assert!("closed".parse::<GapStatus>().is_err());
+
+        let gap = OhlcvGap {
+            exchange: "bitflyer".to_string(),
+            pair: "btcjpy".to_string(),
+            period: 60,
+            from: UnixTime::new(120),
+            to: UnixTime::new(300),
+            status: GapStatus::Missing,
+        };
+        assert_eq!(gap.get_missing_count(), 4);
+    }
+
+    #[test]
+    fn detect_and_repair_gaps() {
+        use super::*;
+        use crate::{Decimal, OhlcvBar};
+        let bar = |unixtime: i64| {
+            let price = Decimal::new(100, 0);
+            OhlcvBar::new(
+                UnixTime::new(unixtime),
+                price,
+                price,
+                price,
+                price,
+                Decimal::zero(0),
+            )
+            .unwrap()
+        };
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let bars: Vec<OhlcvBar> = [120, 180, 360, 420].iter().map(|&t| bar(t)).collect();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars)
+            .unwrap();
+
+        // 範囲の端と途中の欠損を検出する
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60)
+            .from_time(60)
+            .to_time(480);
+        let gaps = atbdb.find_ohlcv_gaps(&query).unwrap();
+        let ranges: Vec<(i64, i64)> = gaps
+            .iter()
+            .map(|gap| (gap.get_from().get_secs(), gap.get_to().get_secs()))
+            .collect();
+        assert_eq!(ranges, vec![(60, 60), (240, 300), (480, 480)]);
+
+        let gaps = atbdb.detect_ohlcv_gaps(&query).unwrap();
+        assert_eq!(gaps.len(), 3);
+        atbdb
+            .set_ohlcv_gap_status(&gaps[0], GapStatus::Unavailable)
+            .unwrap();
+
+        // 欠損していた足が保存されたら取得済みにする
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &[bar(240), bar(300)])
+            .unwrap();
+        assert_eq!(
+            atbdb.repair_ohlcv_gaps("bitflyer", "btcjpy", 60).unwrap(),
+            1
+        );
+        let missing = atbdb
+            .get_ohlcv_gap_list(&query, Some(GapStatus::Missing))
+            .unwrap();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].get_from().get_secs(), 480);
+        assert_eq!(
+            atbdb
+                .get_ohlcv_gap_list(&query, Some(GapStatus::Unavailable))
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn partial_backfill() {
+        use super::*;
+        use crate::{Decimal, OhlcvBar};
+        let bar = |unixtime: i64| {
+            let price = Decimal::new(100, 0);
+            OhlcvBar::new(
+                UnixTime::new(unixtime),
+                price,
+                price,
+                price,
+                price,
+                Decimal::zero(0),
+            )
+            .unwrap()
+        };
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &[bar(60), bar(360)])
+            .unwrap();
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60);
+        let ranges = |gaps: Vec<OhlcvGap>| -> Vec<(i64, i64, GapStatus)> {
+            gaps.iter()
+                .map(|gap| {
+                    (
+                        gap.get_from().get_secs(),
+                        gap.get_to().get_secs(),
+                        gap.get_status(),
+                    )
+                })
+                .collect()
+        };
+        assert_eq!(
+            ranges(atbdb.detect_ohlcv_gaps(&query).unwrap()),
+            vec![(120, 300, GapStatus::Missing)]
+        );
+
+        // 欠損期間の先頭だけ埋まったら、再検出しても重複せずに縮める
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &[bar(120)])
+            .unwrap();
+        assert_eq!(
+            ranges(atbdb.detect_ohlcv_gaps(&query).unwrap()),
+            vec![(180, 300, GapStatus::Missing)]
+        );
+
+        // 検出せずに埋まった場合も、残りが埋まれば取得済みになる
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &[bar(180)])
+            .unwrap();
+        assert_eq!(
+            atbdb.repair_ohlcv_gaps("bitflyer", "btcjpy", 60).unwrap(),
+            0
+        );
+        assert_eq!(
+            ranges(atbdb.get_ohlcv_gap_list(&query, None).unwrap()),
+            vec![(240, 300, GapStatus::Missing)]
+        );
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &[bar(240), bar(300)])
+            .unwrap();
+        assert_eq!(
+            atbdb.repair_ohlcv_gaps("bitflyer", "btcjpy", 60).unwrap(),
+            1
+        );
+        assert_eq!(
+            ranges(atbdb.get_ohlcv_gap_list(&query, None).unwrap()),
+            vec![(240, 300, GapStatus::Repaired)]
+        );
+    }
+}
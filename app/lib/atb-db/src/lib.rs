@@ -1,360 +1,342 @@
 extern crate r2d2;
 extern crate r2d2_sqlite;
+extern crate rand;
 extern crate read_atb_config;
 extern crate rusqlite;
-
-pub type SqliteResult = rusqlite::Result<()>;
-pub type SqliteError = rusqlite::Error;
-
-#[allow(dead_code)]
-pub struct AtbDB {
-    pool: r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>,
+extern crate serde_json;
+extern crate sha2;
+extern crate subtle;
+
+#[cfg(feature = "async")]
+mod async_db;
+mod bot;
+mod bot_history;
+mod decimal;
+mod gap;
+mod market;
+mod market_fee;
+mod migration;
+mod ohlcv;
+mod ohlcv_bar;
+#[cfg(any(feature = "csv", feature = "parquet"))]
+mod ohlcv_file;
+mod promotion;
+mod resample;
+mod retention;
+mod token;
+
+#[cfg(feature = "async")]
+pub use async_db::AsyncAtbDB;
+pub use bot::{Bot, BotCreate, BotForGet, BotList, BotPatch, OperateType};
+pub use bot_history::{Actor, BotAction, BotHistory};
+pub use decimal::{Decimal, MAX_DECIMAL_SCALE};
+pub use gap::{GapStatus, OhlcvGap};
+pub use market::{ContractType, Market, MarketScale};
+pub use market_fee::MarketFee;
+pub use migration::{DbVersion, LATEST_SCHEMA_VERSION};
+pub use ohlcv::{
+    Ohlcv, OhlcvFormat, OhlcvIter, OhlcvOrder, OhlcvQuery, UpsertSummary, DEFAULT_OHLCV_CHUNK,
+};
+pub use ohlcv_bar::{OhlcvArray, OhlcvBar, UnixTime};
+#[cfg(any(feature = "csv", feature = "parquet"))]
+pub use ohlcv_file::OhlcvFileFormat;
+#[cfg(feature = "parquet")]
+pub use ohlcv_file::PARQUET_ROW_GROUP_SIZE;
+pub use promotion::{
+    BotEvaluation, BotEvaluationCreate, BotPromotion, PromotionGate, PromotionRule,
+};
+pub use resample::{resample, ResampleAnchor, Resampler};
+pub use retention::{
+    RetentionAction, RetentionPolicy, RetentionReport, RetentionRule, RetentionTarget,
+    RetentionYear,
+};
+pub use token::{TOKEN_LENGTH, TOKEN_PREFIX_LENGTH};
+
+// atb-dbの処理で発生するエラー
+#[derive(Debug)]
+pub enum AtbDbError {
+    // 設定ファイルの読み込みに失敗した
+    Config(String),
+    // コネクションプールからコネクションを取得できなかった
+    Pool(r2d2::Error),
+    // SQLの実行に失敗した
+    Sqlite(rusqlite::Error),
+    // データベースのスキーマバージョンが対応していない
+    SchemaVersion { expected: i64, actual: Option<i64> },
+    // テーブル構造がcheck.sqlと一致しない
+    Schema(String),
+    // マイグレーション/ロールバックに失敗した
+    Migration(String),
+    // 取得したレコードの変換に失敗した
+    Decode(String),
+    // 入力値が不正
+    Validation(String),
+    // 対象のレコードが存在しない
+    NotFound,
+    // 処理待ちのクエリが多く受け付けられない
+    Busy,
+    // クエリが指定時間内に終わらなかった
+    Timeout(std::time::Duration),
+    // ワーカーで実行した処理がパニックした
+    Panic(String),
+    // ファイルの読み書きに失敗した
+    Io(std::io::Error),
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct Bot {
-    id: i64,
-    name: String,
-    description: String,
-    enable: bool,
-    registered: i64,
-    token: String,
-    long_order: bool,
-    short_order: bool,
-    operate_type: String,
+pub type AtbDbResult<T> = Result<T, AtbDbError>;
+
+impl std::fmt::Display for AtbDbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AtbDbError::Config(msg) => write!(f, "設定エラー: {}", msg),
+            AtbDbError::Pool(err) => write!(f, "コネクション取得エラー: {}", err),
+            AtbDbError::Sqlite(err) => write!(f, "SQLエラー: {}", err),
+            AtbDbError::SchemaVersion { expected, actual } => match actual {
+                Some(actual) if actual < expected => write!(
+                    f,
+                    "スキーマバージョンエラー: version {} が必要ですが、データベースは version {} です(`atb-db-tool-rs migration`を実行してください)",
+                    expected, actual
+                ),
+                Some(actual) => write!(
+                    f,
+                    "スキーマバージョンエラー: version {} が必要ですが、データベースは version {} です(バイナリを更新してください)",
+                    expected, actual
+                ),
+                None => write!(
+                    f,
+                    "スキーマバージョンエラー: version {} が必要ですが、データベースのバージョンを取得できません",
+                    expected
+                ),
+            },
+            AtbDbError::Schema(msg) => write!(f, "テーブル構造エラー: {}", msg),
+            AtbDbError::Migration(msg) => write!(f, "マイグレーションエラー: {}", msg),
+            AtbDbError::Decode(msg) => write!(f, "データ変換エラー: {}", msg),
+            AtbDbError::Validation(msg) => write!(f, "入力値エラー: {}", msg),
+            AtbDbError::NotFound => write!(f, "対象のデータが存在しません"),
+            AtbDbError::Busy => write!(f, "処理待ちのクエリが多いため受け付けられません"),
+            AtbDbError::Timeout(timeout) => write!(
+                f,
+                "タイムアウトしました: {}ミリ秒以内に処理が終わりませんでした",
+                timeout.as_millis()
+            ),
+            AtbDbError::Panic(msg) => write!(f, "処理が異常終了しました: {}", msg),
+            AtbDbError::Io(err) => write!(f, "ファイル入出力エラー: {}", err),
+        }
+    }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-pub struct BotForGet {
-    name: String,
-    description: String,
-    enable: bool,
-    registered: i64,
-    long_order: bool,
-    short_order: bool,
-    operate_type: String,
+impl std::error::Error for AtbDbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AtbDbError::Pool(err) => Some(err),
+            AtbDbError::Sqlite(err) => Some(err),
+            AtbDbError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
-pub struct BotList {
-    bot: Vec<Bot>,
+impl From<r2d2::Error> for AtbDbError {
+    fn from(err: r2d2::Error) -> AtbDbError {
+        AtbDbError::Pool(err)
+    }
 }
 
-#[derive(serde::Serialize)]
-pub struct Ohlcv {
-    ohlcv: Vec<(f64, f64, f64, f64, f64, i64)>,
+impl From<std::io::Error> for AtbDbError {
+    fn from(err: std::io::Error) -> AtbDbError {
+        AtbDbError::Io(err)
+    }
 }
 
-impl AtbDB {
-    #[allow(dead_code)]
-    pub fn connect(option_atbconf: Option<read_atb_config::AtbConf>) -> Result<AtbDB, String> {
-        let atbconf = if option_atbconf.is_none() {
-            // 環境変数を読み込む
-            let atbconf = read_atb_config::AtbConf::load_conf();
-            if atbconf.is_none() {
-                return Err("環境変数`PATH_ATB_CONFIG`を確認ください".to_string());
+impl From<rusqlite::Error> for AtbDbError {
+    fn from(err: rusqlite::Error) -> AtbDbError {
+        match err {
+            rusqlite::Error::QueryReturnedNoRows => AtbDbError::NotFound,
+            rusqlite::Error::InvalidColumnIndex(_)
+            | rusqlite::Error::InvalidColumnName(_)
+            | rusqlite::Error::InvalidColumnType(_, _, _)
+            | rusqlite::Error::IntegralValueOutOfRange(_, _)
+            | rusqlite::Error::FromSqlConversionFailure(_, _, _) => {
+                AtbDbError::Decode(err.to_string())
             }
-            atbconf
-        } else {
-            option_atbconf
-        };
-
-        // 環境変数から対象データベースのpathを取得する
-        let path_db_file = atbconf.unwrap().get_sqlite3_file();
-        if let None = path_db_file {
-            return Err(
-                "環境変数`PATH_ATB_CONFIG`に指定したファイルを確認してください".to_string(),
-            );
+            _ => AtbDbError::Sqlite(err),
         }
+    }
+}
 
-        // 対象データベースへのコネクションを取得する
-        let manager = r2d2_sqlite::SqliteConnectionManager::file(path_db_file.unwrap());
-        let result_pool = r2d2::Pool::new(manager);
-        if let Err(err) = result_pool {
-            return Err(err.to_string());
-        }
+#[allow(dead_code)]
+pub struct AtbDB {
+    pool: r2d2::Pool<r2d2_sqlite::SqliteConnectionManager>,
+    // open_temp_fileで作成した一時ファイル(破棄時に削除する)
+    temp_file: Option<std::path::PathBuf>,
+}
 
-        Ok(AtbDB {
-            pool: result_pool.unwrap(),
-        })
-    }
+// テスト用データベースの名前が重複しないようにするための連番
+static TEST_DB_SEQ: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
 
-    // ohlcvテーブルから設定条件の最終unixtimeを取得する
-    pub fn get_last_unixtime_from_ohlcv(
-        &self,
-        exchange: &String,
-        pair: &String,
-        period: &String,
-    ) -> rusqlite::Result<i64> {
-        let pool = self.pool.clone();
-        let conn = pool.get().unwrap();
-
-        // 最後のunixtime時刻を取得する
-        conn.query_row(
-            "select max(unixtime) from ohlcv where exchange = ?1 and pair = ?2 and period = ?3",
-            rusqlite::params![&exchange, &pair, &period],
-            |row| row.get(0),
-        )
-    }
+// テスト用データベースの名前
+fn test_db_name() -> String {
+    format!(
+        "atb-db-test-{}-{}",
+        std::process::id(),
+        TEST_DB_SEQ.fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    )
+}
 
-    // 条件に該当するレコードを一つだけ削除する
-    pub fn delete_ohlcv(
-        &self,
-        exchange: &String,
-        pair: &String,
-        period: &String,
-        unixtime: i64,
-    ) -> rusqlite::Result<usize> {
-        let pool = self.pool.clone();
-        let conn = pool.get().unwrap();
-
-        conn.execute(
-            "delete from ohlcv where exchange = ?1 and pair = ?2 and period = ?3 and unixtime = ?4 limit 1",
-            rusqlite::params![&exchange, &pair, &period, unixtime],
-        )
-    }
+impl AtbDB {
+    // データベースに接続し、スキーマバージョンが対応しているか確認する
+    #[allow(dead_code)]
+    pub fn connect(option_atbconf: Option<read_atb_config::AtbConf>) -> AtbDbResult<AtbDB> {
+        let atbconf = AtbDB::load_conf(option_atbconf)?;
+        let atbdb = AtbDB::open(&atbconf)?;
 
-    // 複数のohlcvデータを追加する
-    pub fn insert_ohlcv_list(
-        &self,
-        exchange: &String,
-        pair: &String,
-        period: &String,
-        records: &Vec<(f64, f64, f64, f64, f64, i64)>,
-    ) {
-        let pool = self.pool.clone();
-        let mut conn = pool.get().unwrap();
-
-        // SQLを作成する
-        let sql_key = "exchange, pair, period, open, high, low, close, volume, unixtime";
-        let sql_value = "?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9";
-        let sql_insert = &format!("INSERT INTO ohlcv ({}) VALUES ({})", sql_key, sql_value);
-
-        let tx = conn.transaction().unwrap();
-        for record in records {
-            let _ = tx.execute(
-                sql_insert,
-                rusqlite::params![
-                    exchange, pair, period, record.0, record.1, record.2, record.3, record.4,
-                    record.5
-                ],
-            );
-        }
-        let _ = tx.commit();
+        // 設定ファイルのdatabase.versionとデータベースのバージョンを確認する
+        atbdb.check_schema_version(atbconf.get_database_version())?;
+
+        Ok(atbdb)
     }
 
-    // 複数のohlcvデータを取得する
-    pub fn get_ohlcv_list(
-        &self,
-        exchange: &String,
-        pair: &String,
-        period: &String,
-    ) -> Result<Ohlcv, SqliteError> {
-        let pool = self.pool.clone();
-        let conn = pool.get().unwrap();
-
-        let result_stmt = conn.prepare("SELECT open, high, low, close, volume, unixtime FROM ohlcv WHERE exchange = ?1 and pair = ?2 and period = ?3");
-        if result_stmt.is_err() {
-            return Err(rusqlite::Error::InvalidQuery);
-        }
-        let mut stmt = result_stmt.unwrap();
-
-        let result_rows = stmt.query_map(rusqlite::params![exchange, pair, period], |row| {
-            Ok((
-                row.get::<_, f64>(0)?,
-                row.get::<_, f64>(1)?,
-                row.get::<_, f64>(2)?,
-                row.get::<_, f64>(3)?,
-                row.get::<_, f64>(4)?,
-                row.get::<_, i64>(5)?,
-            ))
-        });
-        if result_rows.is_err() {
-            return Err(rusqlite::Error::QueryReturnedNoRows);
-        }
-        let rows = result_rows.unwrap();
+    // スキーマバージョンを確認せずにデータベースに接続する(マイグレーション用)
+    #[allow(dead_code)]
+    pub fn connect_for_migration(
+        option_atbconf: Option<read_atb_config::AtbConf>,
+    ) -> AtbDbResult<AtbDB> {
+        let atbconf = AtbDB::load_conf(option_atbconf)?;
+        AtbDB::open(&atbconf)
+    }
 
-        let mut ohlcv = Vec::new();
-        for row in rows {
-            if row.is_ok() {
-                ohlcv.push(row.unwrap())
-            }
+    // 設定が指定されていなければ環境変数から読み込む
+    fn load_conf(
+        option_atbconf: Option<read_atb_config::AtbConf>,
+    ) -> AtbDbResult<read_atb_config::AtbConf> {
+        match option_atbconf {
+            Some(atbconf) => Ok(atbconf),
+            // 環境変数を読み込む
+            None => read_atb_config::AtbConf::load_conf().ok_or_else(|| {
+                AtbDbError::Config("環境変数`PATH_ATB_CONFIG`を確認ください".to_string())
+            }),
         }
-        Ok(Ohlcv { ohlcv: ohlcv })
     }
 
-    // botデータを追加する
-    pub fn insert_bot(
-        &self,
-        option: &std::collections::HashMap<String, String>,
-    ) -> rusqlite::Result<usize> {
-        let pool = self.pool.clone();
-        let mut conn = pool.get().unwrap();
-
-        // 取得したデータをデータベースに保存するためのSQL
-        let sql_key = option.keys().map(|s| &**s).collect::<Vec<_>>().join(",");
-        let sql_value = option.keys().map(|_| "?").collect::<Vec<_>>().join(",");
-        let sql_insert = &format!("INSERT INTO bot ({}) VALUES ({})", sql_key, sql_value);
-
-        // SQLを実行する
-        let tx = conn.transaction().unwrap();
-        let result = tx.execute(
-            sql_insert,
-            option.values().map(|s| s.to_string()).collect::<Vec<_>>(),
-        );
-        let _ = tx.commit();
-        result
-    }
+    fn open(atbconf: &read_atb_config::AtbConf) -> AtbDbResult<AtbDB> {
+        // 環境変数から対象データベースのpathを取得する
+        let path_db_file = atbconf.get_sqlite3_file().ok_or_else(|| {
+            AtbDbError::Config(
+                "環境変数`PATH_ATB_CONFIG`に指定したファイルを確認してください".to_string(),
+            )
+        })?;
 
-    // botデータを更新する
-    pub fn update_bot(
-        &self,
-        id: &String,
-        option: &std::collections::HashMap<String, String>,
-    ) -> rusqlite::Result<usize> {
-        let pool = self.pool.clone();
-        let mut conn = pool.get().unwrap();
-
-        // 更新用データをデータベースに上書きするためのSQL
-        let sql_set = option
-            .keys()
-            .enumerate()
-            .map(|key| format!("{} = ?{}", key.1, key.0 + 2))
-            .collect::<Vec<_>>()
-            .join(", ");
-        let mut sql_value: Vec<String> = Vec::new();
-        sql_value.push(id.to_string());
-        for value in option.values() {
-            sql_value.push(value.to_string());
-        }
-        let sql_insert = &format!("UPDATE bot SET {} where id = ?1 limit 1", sql_set);
+        // 対象データベースへのコネクションを取得する
+        let manager = r2d2_sqlite::SqliteConnectionManager::file(path_db_file);
+        let pool = r2d2::Pool::new(manager)?;
 
-        // SQLを実行する
-        let tx = conn.transaction().unwrap();
-        let result = tx.execute(sql_insert, sql_value);
-        let _ = tx.commit();
-        result
+        Ok(AtbDB {
+            pool,
+            temp_file: None,
+        })
     }
 
-    // botデータを削除する
-    pub fn delete_bot(&self, id: &String) -> rusqlite::Result<usize> {
-        let pool = self.pool.clone();
-        let mut conn = pool.get().unwrap();
-
-        // 指定したIDのbotを削除するSQLを実行する
-        let tx = conn.transaction().unwrap();
-        let result = tx.execute(
-            "delete from bot where id = ?1 limit 1",
-            rusqlite::params![&id.to_string()],
+    // (テスト用)最新のスキーマを適用したメモリ上のデータベースを作成する
+    // 設定ファイルは使用せず、破棄するとデータも消える
+    #[allow(dead_code)]
+    pub fn open_in_memory() -> AtbDbResult<AtbDB> {
+        // コネクションプールのすべてのコネクションで同じデータベースを共有する
+        let uri = format!("file:{}?mode=memory&cache=shared", test_db_name());
+        let manager = r2d2_sqlite::SqliteConnectionManager::file(uri).with_flags(
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE
+                | rusqlite::OpenFlags::SQLITE_OPEN_CREATE
+                | rusqlite::OpenFlags::SQLITE_OPEN_URI,
         );
-        let _ = tx.commit();
-        result
-    }
+        let pool = r2d2::Pool::new(manager)?;
 
-    // botデータを取得する
-    pub fn get_bot(&self, id: &String) -> Result<Bot, SqliteError> {
-        let pool = self.pool.clone();
-        let conn = pool.get().unwrap();
-
-        // 指定したIDのbotを取得する
-        conn.query_row(
-            "select id, name, description, enable, registered, token, long_order, short_order, operate_type from bot where id = ?1 limit 1",
-            rusqlite::params![&id.to_string()],
-            |row| Ok(Bot {
-                id: row.get(0).unwrap(),
-                name: row.get(1).unwrap(),
-                description: row.get(2).unwrap(),
-                enable: row.get(3).unwrap(),
-                registered: row.get(4).unwrap(),
-                token: row.get(5).unwrap(),
-                long_order: row.get(6).unwrap(),
-                short_order: row.get(7).unwrap(),
-                operate_type: row.get(8).unwrap(),
-            })
-        )
+        let atbdb = AtbDB {
+            pool,
+            temp_file: None,
+        };
+        atbdb.migrate(LATEST_SCHEMA_VERSION)?;
+        Ok(atbdb)
     }
 
-    // (API用)botデータを取得する
-    pub fn get_bot_for_api(&self, id: &String, token: &String) -> Result<BotForGet, SqliteError> {
-        let pool = self.pool.clone();
-        let conn = pool.get().unwrap();
-
-        conn.query_row(
-            "select name, description, enable, registered, long_order, short_order, operate_type from bot where id = ?1 and token = ?2 limit 1",
-            rusqlite::params![&id.to_string(), &token.to_string()],
-            |row| Ok(BotForGet {
-                name: row.get(0).unwrap(),
-                description: row.get(1).unwrap(),
-                enable: row.get(2).unwrap(),
-                registered: row.get(3).unwrap(),
-                long_order: row.get(4).unwrap(),
-                short_order: row.get(5).unwrap(),
-                operate_type: row.get(6).unwrap(),
-            })
-        )
+    // (テスト用)最新のスキーマを適用した一時ファイルのデータベースを作成する
+    // 設定ファイルは使用せず、破棄するとファイルも削除する
+    #[allow(dead_code)]
+    pub fn open_temp_file() -> AtbDbResult<AtbDB> {
+        let path = std::env::temp_dir().join(format!("{}.sqlite3", test_db_name()));
+        let manager = r2d2_sqlite::SqliteConnectionManager::file(&path);
+        let pool = r2d2::Pool::new(manager)?;
+
+        let atbdb = AtbDB {
+            pool,
+            temp_file: Some(path),
+        };
+        atbdb.migrate(LATEST_SCHEMA_VERSION)?;
+        Ok(atbdb)
     }
 
-    // 複数のbotデータを取得する
-    pub fn get_bot_list(&self) -> Result<BotList, SqliteError> {
-        let pool = self.pool.clone();
-        let conn = pool.get().unwrap();
-
-        // bot情報一覧を取得する
-        let mut stmt = conn.prepare("SELECT * FROM bot").unwrap();
-        let result = stmt.query_map(rusqlite::params![], |row| {
-            Ok(Bot {
-                id: row.get(0).unwrap(),
-                name: row.get(1).unwrap(),
-                description: row.get(2).unwrap(),
-                enable: row.get(3).unwrap(),
-                registered: row.get(4).unwrap(),
-                token: row.get(5).unwrap(),
-                long_order: row.get(6).unwrap(),
-                short_order: row.get(7).unwrap(),
-                operate_type: row.get(8).unwrap(),
-            })
-        });
-        if let Err(err) = result {
-            return Err(err);
-        }
-
-        Ok(BotList {
-            bot: result
-                .unwrap()
-                .map(|bot| bot.unwrap())
-                .collect::<Vec<Bot>>(),
-        })
+    // コネクションプールからコネクションを取得する
+    // 非同期APIのワーカーで実行中の処理なら、タイムアウトした時に中断できるよう登録する
+    fn get_conn(
+        &self,
+    ) -> AtbDbResult<r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>> {
+        let conn = self.pool.get()?;
+        #[cfg(feature = "async")]
+        async_db::register_interrupt(&conn)?;
+        Ok(conn)
     }
-}
 
-impl Bot {
+    // 一時ファイルのデータベースならそのパスを返す
     #[allow(dead_code)]
-    pub fn get_id(&self) -> i64 {
-        self.id
+    pub fn get_temp_file(&self) -> Option<&std::path::Path> {
+        self.temp_file.as_deref()
     }
 }
 
-impl BotList {
-    #[allow(dead_code)]
-    pub fn get_list_len(&self) -> usize {
-        self.bot.len()
+impl Drop for AtbDB {
+    fn drop(&mut self) {
+        if let Some(path) = &self.temp_file {
+            let _ = std::fs::remove_file(path);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     #[test]
-    fn it_works() {
+    fn open_test_database() {
         use super::*;
-        if let Ok(atbdb) = AtbDB::connect(None) {
-            let exchange = "bitflyer".to_string();
-            let pair = "btcjpy".to_string();
-            let period = "60".to_string();
-            println!(
-                "{:?}",
-                atbdb.get_last_unixtime_from_ohlcv(&exchange, &pair, &period)
-            );
-        }
-        assert_eq!(2 + 2, 3);
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        assert_eq!(
+            atbdb.check_schema_version(None).unwrap(),
+            LATEST_SCHEMA_VERSION
+        );
+        atbdb.check_schema().unwrap();
+
+        // メモリ上のデータベースは作成するたびに別のデータベースになる
+        let other = AtbDB::open_in_memory().unwrap();
+        atbdb
+            .insert_bot(&BotCreate::new("test", "test bot"), &Actor::cli("test"))
+            .unwrap();
+        assert_eq!(atbdb.get_bot_list().unwrap().get_list_len(), 1);
+        assert_eq!(other.get_bot_list().unwrap().get_list_len(), 0);
+
+        let atbdb = AtbDB::open_temp_file().unwrap();
+        let path = atbdb.get_temp_file().unwrap().to_path_buf();
+        assert!(path.exists());
+        atbdb.check_schema().unwrap();
+        drop(atbdb);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn convert_sqlite_error() {
+        use super::*;
+        let err: AtbDbError = rusqlite::Error::QueryReturnedNoRows.into();
+        assert!(matches!(err, AtbDbError::NotFound));
+
+        let err: AtbDbError = rusqlite::Error::InvalidColumnIndex(9).into();
+        assert!(matches!(err, AtbDbError::Decode(_)));
+
+        let err: AtbDbError = rusqlite::Error::InvalidQuery.into();
+        assert!(matches!(err, AtbDbError::Sqlite(_)));
     }
 }
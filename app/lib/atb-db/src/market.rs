@@ -0,0 +1,512 @@
+use crate::{AtbDB, AtbDbError, AtbDbResult, Decimal};
+
+// 市場ごとの価格・出来高の小数点以下の桁数
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct MarketScale {
+    price_scale: u32,
+    volume_scale: u32,
+}
+
+impl MarketScale {
+    pub fn new(price_scale: u32, volume_scale: u32) -> MarketScale {
+        MarketScale {
+            price_scale,
+            volume_scale,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_price_scale(&self) -> u32 {
+        self.price_scale
+    }
+
+    #[allow(dead_code)]
+    pub fn get_volume_scale(&self) -> u32 {
+        self.volume_scale
+    }
+
+    // 保存されている整数値を価格に変換する
+    pub fn price(&self, mantissa: i64) -> Decimal {
+        Decimal::new(mantissa, self.price_scale)
+    }
+
+    // 保存されている整数値を出来高に変換する
+    pub fn volume(&self, mantissa: i64) -> Decimal {
+        Decimal::new(mantissa, self.volume_scale)
+    }
+
+    // 価格を保存用の整数値に変換する(桁が落ちる場合はエラー)
+    pub fn encode_price(&self, price: &Decimal) -> AtbDbResult<i64> {
+        encode(price, self.price_scale, "価格")
+    }
+
+    // 出来高を保存用の整数値に変換する(桁が落ちる場合はエラー)
+    pub fn encode_volume(&self, volume: &Decimal) -> AtbDbResult<i64> {
+        encode(volume, self.volume_scale, "出来高")
+    }
+}
+
+fn encode(value: &Decimal, scale: u32, name: &str) -> AtbDbResult<i64> {
+    value
+        .rescale(scale)
+        .map(|value| value.get_mantissa())
+        .ok_or_else(|| {
+            AtbDbError::Validation(format!(
+                "{} {} は小数点以下{}桁で保存できません",
+                name, value, scale
+            ))
+        })
+}
+
+// 取引種別
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContractType {
+    // 現物
+    Spot,
+    // FX(証拠金取引)
+    Fx,
+    // 無期限先物
+    Perpetual,
+}
+
+impl ContractType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContractType::Spot => "spot",
+            ContractType::Fx => "fx",
+            ContractType::Perpetual => "perpetual",
+        }
+    }
+}
+
+impl std::str::FromStr for ContractType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ContractType, String> {
+        match s {
+            "spot" => Ok(ContractType::Spot),
+            "fx" => Ok(ContractType::Fx),
+            "perpetual" => Ok(ContractType::Perpetual),
+            _ => Err(format!("不明な取引種別です: {}", s)),
+        }
+    }
+}
+
+// 市場(取引所・取引通貨)の情報
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct Market {
+    exchange: String,
+    pair: String,
+    base: String,
+    quote: String,
+    contract_type: ContractType,
+    scale: MarketScale,
+    tick_size: Decimal,
+    lot_size: Decimal,
+    periods: Vec<i64>,
+}
+
+impl Market {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        exchange: &str,
+        pair: &str,
+        base: &str,
+        quote: &str,
+        contract_type: ContractType,
+        scale: MarketScale,
+        tick_size: Decimal,
+        lot_size: Decimal,
+    ) -> Market {
+        Market {
+            exchange: exchange.to_string(),
+            pair: pair.to_string(),
+            base: base.to_string(),
+            quote: quote.to_string(),
+            contract_type,
+            scale,
+            tick_size,
+            lot_size,
+            periods: vec![],
+        }
+    }
+
+    // 取得・配信する足の期間
+    pub fn periods(mut self, periods: &[i64]) -> Market {
+        self.periods = periods.to_vec();
+        self.periods.sort_unstable();
+        self.periods.dedup();
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    #[allow(dead_code)]
+    pub fn get_pair(&self) -> &str {
+        &self.pair
+    }
+
+    #[allow(dead_code)]
+    pub fn get_base(&self) -> &str {
+        &self.base
+    }
+
+    #[allow(dead_code)]
+    pub fn get_quote(&self) -> &str {
+        &self.quote
+    }
+
+    #[allow(dead_code)]
+    pub fn get_contract_type(&self) -> ContractType {
+        self.contract_type
+    }
+
+    #[allow(dead_code)]
+    pub fn get_scale(&self) -> MarketScale {
+        self.scale
+    }
+
+    #[allow(dead_code)]
+    pub fn get_tick_size(&self) -> Decimal {
+        self.tick_size
+    }
+
+    #[allow(dead_code)]
+    pub fn get_lot_size(&self) -> Decimal {
+        self.lot_size
+    }
+
+    #[allow(dead_code)]
+    pub fn get_periods(&self) -> &[i64] {
+        &self.periods
+    }
+
+    // 足の期間が有効かどうか
+    pub fn has_period(&self, period: i64) -> bool {
+        self.periods.contains(&period)
+    }
+
+    // 価格が呼値の倍数か確認する
+    pub fn check_price(&self, price: &Decimal) -> AtbDbResult<()> {
+        check_multiple(
+            price,
+            &self.tick_size,
+            self.scale.price_scale,
+            "価格",
+            "呼値",
+        )
+    }
+
+    // 注文数量が最小注文数量の倍数か確認する
+    pub fn check_size(&self, size: &Decimal) -> AtbDbResult<()> {
+        check_multiple(
+            size,
+            &self.lot_size,
+            self.scale.volume_scale,
+            "注文数量",
+            "最小注文数量",
+        )
+    }
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Market> {
+        let scale = MarketScale {
+            price_scale: row.get::<_, i64>(4)? as u32,
+            volume_scale: row.get::<_, i64>(5)? as u32,
+        };
+        let contract_type = row.get::<_, String>(8)?;
+        Ok(Market {
+            exchange: row.get(0)?,
+            pair: row.get(1)?,
+            base: row.get(2)?,
+            quote: row.get(3)?,
+            contract_type: contract_type.parse().map_err(|_| {
+                rusqlite::Error::InvalidColumnType(
+                    8,
+                    "contract_type".to_string(),
+                    rusqlite::types::Type::Text,
+                )
+            })?,
+            scale,
+            tick_size: scale.price(row.get(6)?),
+            lot_size: scale.volume(row.get(7)?),
+            periods: vec![],
+        })
+    }
+}
+
+// 値が単位の正の倍数か確認する
+fn check_multiple(
+    value: &Decimal,
+    unit: &Decimal,
+    scale: u32,
+    name: &str,
+    unit_name: &str,
+) -> AtbDbResult<()> {
+    let value_mantissa = encode(value, scale, name)?;
+    let unit_mantissa = encode(unit, scale, unit_name)?;
+    if value_mantissa <= 0 || value_mantissa % unit_mantissa != 0 {
+        return Err(AtbDbError::Validation(format!(
+            "{} {} は{} {} の倍数ではありません",
+            name, value, unit_name, unit
+        )));
+    }
+    Ok(())
+}
+
+const SQL_SELECT_MARKET: &str = "select exchange, pair, base, quote, price_scale, volume_scale, tick_size, lot_size, contract_type from market";
+
+impl AtbDB {
+    // 市場の価格・出来高の桁数を取得する
+    pub fn get_market_scale(&self, exchange: &str, pair: &str) -> AtbDbResult<MarketScale> {
+        let conn = self.get_conn()?;
+
+        let scale = conn.query_row(
+            "select price_scale, volume_scale from market where exchange = ?1 and pair = ?2",
+            rusqlite::params![exchange, pair],
+            |row| {
+                Ok(MarketScale {
+                    price_scale: row.get::<_, i64>(0)? as u32,
+                    volume_scale: row.get::<_, i64>(1)? as u32,
+                })
+            },
+        )?;
+        Ok(scale)
+    }
+
+    // 市場の情報を取得する
+    pub fn get_market(&self, exchange: &str, pair: &str) -> AtbDbResult<Market> {
+        let conn = self.get_conn()?;
+
+        let market = conn.query_row(
+            &format!("{} where exchange = ?1 and pair = ?2", SQL_SELECT_MARKET),
+            rusqlite::params![exchange, pair],
+            Market::from_row,
+        )?;
+        Ok(Market {
+            periods: get_periods(&conn, exchange, pair)?,
+            ..market
+        })
+    }
+
+    // 登録済みの市場の一覧を取得する
+    pub fn get_market_list(&self) -> AtbDbResult<Vec<Market>> {
+        let conn = self.get_conn()?;
+
+        let mut stmt = conn.prepare(&format!("{} order by exchange, pair", SQL_SELECT_MARKET))?;
+        let rows = stmt.query_map(rusqlite::params![], Market::from_row)?;
+        let mut market_list = rows.collect::<Result<Vec<Market>, _>>()?;
+        for market in &mut market_list {
+            market.periods = get_periods(&conn, &market.exchange, &market.pair)?;
+        }
+        Ok(market_list)
+    }
+
+    // 市場と足の期間が登録されているか確認し、市場の情報を返す
+    pub fn validate_market_period(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+    ) -> AtbDbResult<Market> {
+        let market = match self.get_market(exchange, pair) {
+            Ok(market) => market,
+            Err(AtbDbError::NotFound) => {
+                return Err(AtbDbError::Validation(format!(
+                    "未登録の市場です: {}/{}",
+                    exchange, pair
+                )))
+            }
+            Err(err) => return Err(err),
+        };
+        if !market.has_period(period) {
+            return Err(AtbDbError::Validation(format!(
+                "{}/{} では足の期間 {} は有効ではありません(有効な期間: {:?})",
+                exchange, pair, period, market.periods
+            )));
+        }
+        Ok(market)
+    }
+
+    // 市場を登録する(登録済みの市場は変更できない)
+    pub fn insert_market(&self, market: &Market) -> AtbDbResult<()> {
+        let tick_size = encode(&market.tick_size, market.scale.price_scale, "呼値")?;
+        let lot_size = encode(&market.lot_size, market.scale.volume_scale, "最小注文数量")?;
+        if tick_size <= 0 || lot_size <= 0 {
+            return Err(AtbDbError::Validation(
+                "呼値・最小注文数量は正の値を指定してください".to_string(),
+            ));
+        }
+
+        let mut conn = self.get_conn()?;
+        let tx = conn.transaction()?;
+        tx.execute(
+            "insert into market (exchange, pair, base, quote, price_scale, volume_scale, tick_size, lot_size, contract_type) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            rusqlite::params![
+                market.exchange,
+                market.pair,
+                market.base,
+                market.quote,
+                market.scale.price_scale as i64,
+                market.scale.volume_scale as i64,
+                tick_size,
+                lot_size,
+                market.contract_type.as_str()
+            ],
+        )?;
+        for period in &market.periods {
+            tx.execute(
+                "insert into market_period (exchange, pair, period) values (?1, ?2, ?3)",
+                rusqlite::params![market.exchange, market.pair, period],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // 市場の足の期間を有効にする
+    pub fn enable_market_period(&self, exchange: &str, pair: &str, period: i64) -> AtbDbResult<()> {
+        // 未登録の市場ならNotFoundを返す
+        self.get_market_scale(exchange, pair)?;
+        if period <= 0 {
+            return Err(AtbDbError::Validation(format!(
+                "足の期間は正の値を指定してください: {}",
+                period
+            )));
+        }
+
+        let conn = self.get_conn()?;
+        conn.execute(
+            "insert or ignore into market_period (exchange, pair, period) values (?1, ?2, ?3)",
+            rusqlite::params![exchange, pair, period],
+        )?;
+        Ok(())
+    }
+
+    // 市場の足の期間を無効にする(保存済みのローソク足は削除しない)
+    pub fn disable_market_period(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+    ) -> AtbDbResult<()> {
+        let conn = self.get_conn()?;
+        let count = conn.execute(
+            "delete from market_period where exchange = ?1 and pair = ?2 and period = ?3",
+            rusqlite::params![exchange, pair, period],
+        )?;
+        if count == 0 {
+            return Err(AtbDbError::NotFound);
+        }
+        Ok(())
+    }
+}
+
+// 市場の足の期間を取得する
+fn get_periods(conn: &rusqlite::Connection, exchange: &str, pair: &str) -> AtbDbResult<Vec<i64>> {
+    let mut stmt = conn.prepare_cached(
+        "select period from market_period where exchange = ?1 and pair = ?2 order by period",
+    )?;
+    let rows = stmt.query_map(rusqlite::params![exchange, pair], |row| {
+        row.get::<_, i64>(0)
+    })?;
+    Ok(rows.collect::<Result<Vec<i64>, _>>()?)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn encode_and_decode() {
+        use super::*;
+        let scale = MarketScale::new(0, 8);
+        let volume: Decimal = "0.12345678".parse().unwrap();
+        assert_eq!(scale.encode_volume(&volume).unwrap(), 12345678);
+        assert_eq!(scale.volume(12345678), volume);
+
+        // 円未満の価格は保存できない
+        let price: Decimal = "1000000.5".parse().unwrap();
+        assert!(scale.encode_price(&price).is_err());
+        assert_eq!(
+            scale.encode_price(&price.round_to(0).unwrap()).unwrap(),
+            1000001
+        );
+    }
+
+    #[test]
+    fn market_tick_and_lot() {
+        use super::*;
+        let market = Market::new(
+            "bitflyer",
+            "btcjpy",
+            "btc",
+            "jpy",
+            ContractType::Spot,
+            MarketScale::new(0, 8),
+            Decimal::new(1, 0),
+            Decimal::new(1, 3),
+        )
+        .periods(&[300, 60, 60]);
+        assert_eq!(market.get_periods(), &[60, 300]);
+        assert!(market.has_period(60));
+        assert!(!market.has_period(120));
+
+        assert!(market.check_price(&Decimal::new(1000000, 0)).is_ok());
+        assert!(market.check_price(&Decimal::new(10000005, 1)).is_err());
+        assert!(market.check_size(&Decimal::new(25, 3)).is_ok());
+        assert!(market.check_size(&Decimal::new(25, 4)).is_err());
+        assert!(market.check_size(&Decimal::zero(0)).is_err());
+
+        assert_eq!("fx".parse::<ContractType>().unwrap(), ContractType::Fx);
+        assert!("future".parse::<ContractType>().is_err());
+    }
+
+    #[test]
+    fn insert_and_validate_market() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+
+        // マイグレーションで取得対象の市場が登録されている
+        let market = atbdb
+            .validate_market_period("bitflyer", "btcfxjpy", 60)
+            .unwrap();
+        assert_eq!(market.get_contract_type(), ContractType::Fx);
+        assert_eq!(market.get_lot_size(), Decimal::new(1, 2));
+        assert!(matches!(
+            atbdb.validate_market_period("bitflyer", "btcjyp", 60),
+            Err(AtbDbError::Validation(_))
+        ));
+        assert!(matches!(
+            atbdb.validate_market_period("bitflyer", "btcjpy", 61),
+            Err(AtbDbError::Validation(_))
+        ));
+
+        let market = Market::new(
+            "kraken",
+            "ethusd",
+            "eth",
+            "usd",
+            ContractType::Spot,
+            MarketScale::new(2, 8),
+            Decimal::new(1, 2),
+            Decimal::new(5, 4),
+        )
+        .periods(&[60]);
+        atbdb.insert_market(&market).unwrap();
+        assert_eq!(atbdb.get_market("kraken", "ethusd").unwrap(), market);
+        assert!(atbdb.insert_market(&market).is_err());
+
+        atbdb.enable_market_period("kraken", "ethusd", 300).unwrap();
+        atbdb.disable_market_period("kraken", "ethusd", 60).unwrap();
+        assert_eq!(
+            atbdb.get_market("kraken", "ethusd").unwrap().get_periods(),
+            &[300]
+        );
+        assert!(matches!(
+            atbdb.enable_market_period("kraken", "xbtusd", 60),
+            Err(AtbDbError::NotFound)
+        ));
+    }
+}
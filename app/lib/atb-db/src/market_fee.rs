@@ -0,0 +1,279 @@
+use crate::{AtbDB, AtbDbError, AtbDbResult, Decimal};
+
+// 市場の手数料体系(valid_fromから次の手数料体系までの約定に適用する)
+// 手数料・スプレッドは約定代金に対する比率(0.001 = 0.1%、負の手数料はリベート)
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct MarketFee {
+    exchange: String,
+    pair: String,
+    valid_from: i64,
+    // メイカー手数料(板に並んだ指値注文の約定)
+    maker_fee: Decimal,
+    // テイカー手数料(成行・逆指値注文の約定)
+    taker_fee: Decimal,
+    // 売値と買値の差
+    spread: Decimal,
+}
+
+impl MarketFee {
+    pub fn new(
+        exchange: &str,
+        pair: &str,
+        valid_from: i64,
+        maker_fee: Decimal,
+        taker_fee: Decimal,
+        spread: Decimal,
+    ) -> MarketFee {
+        MarketFee {
+            exchange: exchange.to_string(),
+            pair: pair.to_string(),
+            valid_from,
+            maker_fee,
+            taker_fee,
+            spread,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    #[allow(dead_code)]
+    pub fn get_pair(&self) -> &str {
+        &self.pair
+    }
+
+    #[allow(dead_code)]
+    pub fn get_valid_from(&self) -> i64 {
+        self.valid_from
+    }
+
+    #[allow(dead_code)]
+    pub fn get_maker_fee(&self) -> Decimal {
+        self.maker_fee
+    }
+
+    #[allow(dead_code)]
+    pub fn get_taker_fee(&self) -> Decimal {
+        self.taker_fee
+    }
+
+    #[allow(dead_code)]
+    pub fn get_spread(&self) -> Decimal {
+        self.spread
+    }
+
+    // 入力値を確認する(手数料は-100%〜100%、スプレッドは0〜100%)
+    pub fn validate(&self) -> AtbDbResult<()> {
+        let one = Decimal::new(1, 0);
+        for (name, fee) in &[("maker_fee", self.maker_fee), ("taker_fee", self.taker_fee)] {
+            if *fee <= -one || one <= *fee {
+                return Err(AtbDbError::Validation(format!(
+                    "{}は-1より大きく1より小さい比率で指定してください: {}",
+                    name, fee
+                )));
+            }
+        }
+        if self.spread < Decimal::zero(0) || one <= self.spread {
+            return Err(AtbDbError::Validation(format!(
+                "spreadは0以上1未満の比率で指定してください: {}",
+                self.spread
+            )));
+        }
+        if self.valid_from < 0 {
+            return Err(AtbDbError::Validation(format!(
+                "valid_fromは0以上で指定してください: {}",
+                self.valid_from
+            )));
+        }
+        Ok(())
+    }
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<MarketFee> {
+        Ok(MarketFee {
+            exchange: row.get(0)?,
+            pair: row.get(1)?,
+            valid_from: row.get(2)?,
+            maker_fee: parse_rate(row, 3, "maker_fee")?,
+            taker_fee: parse_rate(row, 4, "taker_fee")?,
+            spread: parse_rate(row, 5, "spread")?,
+        })
+    }
+}
+
+fn parse_rate(row: &rusqlite::Row, index: usize, name: &str) -> rusqlite::Result<Decimal> {
+    row.get::<_, String>(index)?.parse().map_err(|_| {
+        rusqlite::Error::InvalidColumnType(index, name.to_string(), rusqlite::types::Type::Text)
+    })
+}
+
+const SQL_SELECT_MARKET_FEE: &str =
+    "select exchange, pair, valid_from, maker_fee, taker_fee, spread from market_fee";
+
+impl AtbDB {
+    // 市場の手数料体系を適用開始日時の順に取得する
+    pub fn get_market_fee_list(&self, exchange: &str, pair: &str) -> AtbDbResult<Vec<MarketFee>> {
+        let conn = self.get_conn()?;
+
+        let mut stmt = conn.prepare(&format!(
+            "{} where exchange = ?1 and pair = ?2 order by valid_from",
+            SQL_SELECT_MARKET_FEE
+        ))?;
+        let rows = stmt.query_map(rusqlite::params![exchange, pair], MarketFee::from_row)?;
+        Ok(rows.collect::<Result<Vec<MarketFee>, _>>()?)
+    }
+
+    // 指定した日時に適用される手数料体系を取得する(登録がなければNotFound)
+    pub fn get_market_fee(
+        &self,
+        exchange: &str,
+        pair: &str,
+        unixtime: i64,
+    ) -> AtbDbResult<MarketFee> {
+        let conn = self.get_conn()?;
+
+        let fee = conn.query_row(
+            &format!(
+                "{} where exchange = ?1 and pair = ?2 and valid_from <= ?3 order by valid_from desc limit 1",
+                SQL_SELECT_MARKET_FEE
+            ),
+            rusqlite::params![exchange, pair, unixtime],
+            MarketFee::from_row,
+        )?;
+        Ok(fee)
+    }
+
+    // 手数料体系を登録する(同じ適用開始日時の手数料体系は置き換える)
+    pub fn upsert_market_fee(&self, fee: &MarketFee) -> AtbDbResult<()> {
+        fee.validate()?;
+        // 未登録の市場ならNotFoundを返す
+        self.get_market_scale(&fee.exchange, &fee.pair)?;
+
+        let conn = self.get_conn()?;
+        conn.execute(
+            "insert or replace into market_fee (exchange, pair, valid_from, maker_fee, taker_fee, spread) values (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                fee.exchange,
+                fee.pair,
+                fee.valid_from,
+                fee.maker_fee.to_string(),
+                fee.taker_fee.to_string(),
+                fee.spread.to_string()
+            ],
+        )?;
+        Ok(())
+    }
+
+    // 手数料体系を削除する
+    pub fn delete_market_fee(
+        &self,
+        exchange: &str,
+        pair: &str,
+        valid_from: i64,
+    ) -> AtbDbResult<()> {
+        let conn = self.get_conn()?;
+        let count = conn.execute(
+            "delete from market_fee where exchange = ?1 and pair = ?2 and valid_from = ?3",
+            rusqlite::params![exchange, pair, valid_from],
+        )?;
+        if count == 0 {
+            return Err(AtbDbError::NotFound);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn market_fee() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+
+        // 取得対象の市場はマイグレーションで登録される
+        let fee = atbdb
+            .get_market_fee("bitmex", "btcusd-perpetual-futures", 0)
+            .unwrap();
+        assert_eq!(fee.get_maker_fee(), Decimal::new(-25, 5));
+        assert_eq!(fee.get_taker_fee(), Decimal::new(75, 5));
+
+        // 適用開始日時で手数料体系を切り替える
+        let new_fee = MarketFee::new(
+            "bitflyer",
+            "btcjpy",
+            1_600_000_000,
+            Decimal::new(1, 3),
+            Decimal::new(12, 4),
+            Decimal::new(1, 4),
+        );
+        atbdb.upsert_market_fee(&new_fee).unwrap();
+        assert_eq!(
+            atbdb
+                .get_market_fee_list("bitflyer", "btcjpy")
+                .unwrap()
+                .len(),
+            2
+        );
+        let fee = atbdb
+            .get_market_fee("bitflyer", "btcjpy", 1_599_999_999)
+            .unwrap();
+        assert_eq!(fee.get_taker_fee(), Decimal::new(15, 4));
+        assert_eq!(
+            atbdb
+                .get_market_fee("bitflyer", "btcjpy", 1_600_000_000)
+                .unwrap(),
+            new_fee
+        );
+
+        atbdb
+            .delete_market_fee("bitflyer", "btcjpy", 1_600_000_000)
+            .unwrap();
+        assert!(matches!(
+            atbdb.delete_market_fee("bitflyer", "btcjpy", 1_600_000_000),
+            Err(AtbDbError::NotFound)
+        ));
+
+        // 入力値・未登録の市場
+        let invalid = MarketFee::new(
+            "bitflyer",
+            "btcjpy",
+            0,
+            Decimal::new(1, 0),
+            Decimal::zero(0),
+            Decimal::zero(0),
+        );
+        assert!(matches!(
+            atbdb.upsert_market_fee(&invalid),
+            Err(AtbDbError::Validation(_))
+        ));
+        let invalid = MarketFee::new(
+            "bitflyer",
+            "btcjpy",
+            0,
+            Decimal::zero(0),
+            Decimal::zero(0),
+            Decimal::new(-1, 3),
+        );
+        assert!(matches!(
+            atbdb.upsert_market_fee(&invalid),
+            Err(AtbDbError::Validation(_))
+        ));
+        let unknown = MarketFee::new(
+            "bitflyer",
+            "ethjpy",
+            0,
+            Decimal::zero(0),
+            Decimal::zero(0),
+            Decimal::zero(0),
+        );
+        assert!(matches!(
+            atbdb.upsert_market_fee(&unknown),
+            Err(AtbDbError::NotFound)
+        ));
+        assert!(matches!(
+            atbdb.get_market_fee("bitflyer", "ethjpy", 0),
+            Err(AtbDbError::NotFound)
+        ));
+    }
+}
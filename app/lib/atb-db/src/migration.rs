@@ -0,0 +1,417 @@
+use crate::token::hash_existing_token;
+use crate::{AtbDB, AtbDbError, AtbDbResult};
+
+// 1バージョン分のマイグレーション/ロールバック用SQL
+struct Migration {
+    version: i64,
+    up: &'static str,
+    down: Option<&'static str>,
+    // SQLだけでは移行できないデータを変換してマイグレーションする処理(upのSQLの実行を含む)
+    convert: Option<fn(&rusqlite::Transaction, &str) -> rusqlite::Result<()>>,
+}
+
+// app/sql 以下のSQLファイルを埋め込んだマイグレーション一覧(バージョン順)
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 0,
+        up: include_str!("../../../sql/up_000.sql"),
+        down: None,
+        convert: None,
+    },
+    Migration {
+        version: 1,
+        up: include_str!("../../../sql/up_001.sql"),
+        down: Some(include_str!("../../../sql/down_001.sql")),
+        convert: None,
+    },
+    Migration {
+        version: 2,
+        up: include_str!("../../../sql/up_002.sql"),
+        down: Some(include_str!("../../../sql/down_002.sql")),
+        convert: None,
+    },
+    Migration {
+        version: 3,
+        up: include_str!("../../../sql/up_003.sql"),
+        down: Some(include_str!("../../../sql/down_003.sql")),
+        convert: None,
+    },
+    Migration {
+        version: 4,
+        up: include_str!("../../../sql/up_004.sql"),
+        down: Some(include_str!("../../../sql/down_004.sql")),
+        convert: None,
+    },
+    Migration {
+        version: 5,
+        up: include_str!("../../../sql/up_005.sql"),
+        down: Some(include_str!("../../../sql/down_005.sql")),
+        convert: None,
+    },
+    Migration {
+        version: 6,
+        up: include_str!("../../../sql/up_006.sql"),
+        down: Some(include_str!("../../../sql/down_006.sql")),
+        convert: None,
+    },
+    Migration {
+        version: 7,
+        up: include_str!("../../../sql/up_007.sql"),
+        down: Some(include_str!("../../../sql/down_007.sql")),
+        convert: Some(hash_plaintext_tokens),
+    },
+    Migration {
+        version: 8,
+        up: include_str!("../../../sql/up_008.sql"),
+        down: Some(include_str!("../../../sql/down_008.sql")),
+        convert: None,
+    },
+    Migration {
+        version: 9,
+        up: include_str!("../../../sql/up_009.sql"),
+        down: Some(include_str!("../../../sql/down_009.sql")),
+        convert: None,
+    },
+    Migration {
+        version: 10,
+        up: include_str!("../../../sql/up_010.sql"),
+        down: Some(include_str!("../../../sql/down_010.sql")),
+        convert: None,
+    },
+    Migration {
+        version: 11,
+        up: include_str!("../../../sql/up_011.sql"),
+        down: Some(include_str!("../../../sql/down_011.sql")),
+        convert: None,
+    },
+];
+
+// このクレートが対応する最新のスキーマバージョン
+pub const LATEST_SCHEMA_VERSION: i64 = 11;
+
+// 最新バージョンのテーブル構造(sqlite3の`.schema`の出力)
+const CHECK_SQL: &str = include_str!("../../../sql/check.sql");
+
+// versionテーブルのレコード
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct DbVersion {
+    version: i64,
+    registered: i64,
+}
+
+impl DbVersion {
+    #[allow(dead_code)]
+    pub fn get_version(&self) -> i64 {
+        self.version
+    }
+
+    #[allow(dead_code)]
+    pub fn get_registered(&self) -> i64 {
+        self.registered
+    }
+}
+
+impl AtbDB {
+    // versionテーブルの内容を取得する(テーブルがなければ空)
+    pub fn get_version_list(&self) -> AtbDbResult<Vec<DbVersion>> {
+        let conn = self.get_conn()?;
+
+        if !has_version_table(&conn)? {
+            return Ok(vec![]);
+        }
+
+        let mut stmt = conn.prepare("select version, registered from version order by version")?;
+        let rows = stmt.query_map(rusqlite::params![], |row| {
+            Ok(DbVersion {
+                version: row.get(0)?,
+                registered: row.get(1)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<Vec<DbVersion>, _>>()?)
+    }
+
+    // 現在のスキーマバージョンを取得する(未インストールならNone)
+    pub fn get_schema_version(&self) -> AtbDbResult<Option<i64>> {
+        let conn = self.get_conn()?;
+
+        if !has_version_table(&conn)? {
+            return Ok(None);
+        }
+
+        let version = conn.query_row(
+            "select max(version) from version",
+            rusqlite::params![],
+            |row| row.get::<_, Option<i64>>(0),
+        )?;
+        Ok(version)
+    }
+
+    // データベースのバージョンが、このクレートと設定ファイルのdatabase.versionに一致するか確認する
+    pub fn check_schema_version(&self, configured_version: Option<i64>) -> AtbDbResult<i64> {
+        if let Some(configured_version) = configured_version {
+            if configured_version != LATEST_SCHEMA_VERSION {
+                return Err(AtbDbError::Config(format!(
+                    "database.version = v{} ですが、このバイナリは v{} に対応しています",
+                    configured_version, LATEST_SCHEMA_VERSION
+                )));
+            }
+        }
+
+        match self.get_schema_version()? {
+            Some(version) if version == LATEST_SCHEMA_VERSION => Ok(version),
+            actual => Err(AtbDbError::SchemaVersion {
+                expected: LATEST_SCHEMA_VERSION,
+                actual,
+            }),
+        }
+    }
+
+    // テーブル構造がcheck.sqlと一致するか確認する
+    pub fn check_schema(&self) -> AtbDbResult<()> {
+        let conn = self.get_conn()?;
+
+        let mut stmt = conn.prepare(
+            "select sql from sqlite_master where sql is not null and name not like 'sqlite_%'",
+        )?;
+        let rows = stmt.query_map(rusqlite::params![], |row| row.get::<_, String>(0))?;
+        let actual = rows
+            .map(|sql| sql.map(|sql| normalize_sql(&sql)))
+            .collect::<Result<Vec<String>, _>>()?;
+        let expected = CHECK_SQL
+            .split(";\n")
+            .map(normalize_sql)
+            .filter(|sql| !sql.is_empty())
+            .collect::<Vec<String>>();
+
+        // 差分を定義の1行目で表示する
+        let mut diff = vec![];
+        for sql in expected.iter().filter(|sql| !actual.contains(sql)) {
+            diff.push(format!("- {}", sql.lines().next().unwrap_or("")));
+        }
+        for sql in actual.iter().filter(|sql| !expected.contains(sql)) {
+            diff.push(format!("+ {}", sql.lines().next().unwrap_or("")));
+        }
+        if !diff.is_empty() {
+            return Err(AtbDbError::Schema(diff.join(", ")));
+        }
+        Ok(())
+    }
+
+    // 指定バージョンまでマイグレーションし、適用したバージョンを返す
+    pub fn migrate(&self, target_version: i64) -> AtbDbResult<Vec<i64>> {
+        if !(0..=LATEST_SCHEMA_VERSION).contains(&target_version) {
+            return Err(AtbDbError::Migration(format!(
+                "version {} は存在しません(0〜{})",
+                target_version, LATEST_SCHEMA_VERSION
+            )));
+        }
+
+        let current_version = self.get_schema_version()?;
+        if let Some(current_version) = current_version {
+            if target_version < current_version {
+                return Err(AtbDbError::Migration(format!(
+                    "db.version = {} より古い version {} にはマイグレーションできません",
+                    current_version, target_version
+                )));
+            }
+        }
+
+        // 最初に適用するバージョン
+        let first_version = current_version.map_or(0, |version| version + 1);
+
+        let mut conn = self.get_conn()?;
+        let mut applied = vec![];
+        for migration in MIGRATIONS
+            .iter()
+            .filter(|m| first_version <= m.version && m.version <= target_version)
+        {
+            // 1バージョンずつトランザクション内で適用する
+            let tx = conn.transaction()?;
+            match migration.convert {
+                Some(convert) => convert(&tx, migration.up),
+                None => tx.execute_batch(migration.up),
+            }
+            .map_err(|err| migration_error("up", migration.version, err))?;
+            tx.commit()?;
+            applied.push(migration.version);
+        }
+        Ok(applied)
+    }
+
+    // 指定バージョンまでロールバックし、取り消したバージョンを返す
+    pub fn rollback(&self, target_version: i64) -> AtbDbResult<Vec<i64>> {
+        if target_version < 0 {
+            return Err(AtbDbError::Migration(
+                "version には0以上を指定してください".to_string(),
+            ));
+        }
+
+        let current_version = self.get_schema_version()?.ok_or_else(|| {
+            AtbDbError::Migration("データベースがマイグレーションされていません".to_string())
+        })?;
+        if current_version < target_version {
+            return Err(AtbDbError::Migration(format!(
+                "db.version = {} より新しい version {} にはロールバックできません",
+                current_version, target_version
+            )));
+        }
+
+        let mut conn = self.get_conn()?;
+        let mut undone = vec![];
+        for migration in MIGRATIONS
+            .iter()
+            .rev()
+            .filter(|m| target_version < m.version && m.version <= current_version)
+        {
+            let down = migration.down.ok_or_else(|| {
+                AtbDbError::Migration(format!(
+                    "version {} のロールバックファイルがありません",
+                    migration.version
+                ))
+            })?;
+
+            // 1バージョンずつトランザクション内で取り消す
+            let tx = conn.transaction()?;
+            tx.execute_batch(down)
+                .map_err(|err| migration_error("down", migration.version, err))?;
+            tx.commit()?;
+            undone.push(migration.version);
+        }
+        Ok(undone)
+    }
+}
+
+// version 7: 平文で保存していたトークンをソルト付きハッシュに変換する
+// 形式が不正・検索用の先頭部分が他のbotと重複するトークンは移行しない(`bot-admin token rotate`で再発行する)
+fn hash_plaintext_tokens(tx: &rusqlite::Transaction, up: &str) -> rusqlite::Result<()> {
+    // テーブルを作り直す前に平文のトークンを読み込む
+    let tokens = tx
+        .prepare("select id, token from bot")?
+        .query_map(rusqlite::NO_PARAMS, |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    tx.execute_batch(up)?;
+    for (id, token) in tokens {
+        let hashed = match hash_existing_token(&token) {
+            Some(hashed) => hashed,
+            None => continue,
+        };
+        let duplicated = tx.query_row(
+            "select count(*) from bot where token_prefix = ?1",
+            rusqlite::params![hashed.prefix],
+            |row| row.get::<_, i64>(0),
+        )?;
+        if 0 < duplicated {
+            continue;
+        }
+        tx.execute(
+            "update bot set token_prefix = ?2, token_salt = ?3, token_hash = ?4 where id = ?1",
+            rusqlite::params![id, hashed.prefix, hashed.salt, hashed.hash],
+        )?;
+    }
+    Ok(())
+}
+
+// versionテーブルが存在するかどうか
+fn has_version_table(conn: &rusqlite::Connection) -> AtbDbResult<bool> {
+    let count = conn.query_row(
+        "select count(*) from sqlite_master where type = 'table' and name = 'version'",
+        rusqlite::params![],
+        |row| row.get::<_, i64>(0),
+    )?;
+    Ok(count > 0)
+}
+
+// 比較用にSQLの前後の空白と末尾の`;`を取り除く
+fn normalize_sql(sql: &str) -> String {
+    sql.trim().trim_end_matches(';').trim_end().to_string()
+}
+
+// SQLファイルの実行エラーをマイグレーションエラーに変換する
+fn migration_error(direction: &str, version: i64, err: rusqlite::Error) -> AtbDbError {
+    AtbDbError::Migration(format!(
+        "fail - version:{} - {}_{:03}.sql: {}",
+        version, direction, version, err
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn migrations_are_ordered() {
+        use super::*;
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i64);
+        }
+        assert_eq!(MIGRATIONS.last().unwrap().version, LATEST_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn conf_version_type() {
+        use super::*;
+        // 設定ファイルのdatabase.versionで最新のスキーマバージョンを指定できる
+        let conf_type = include_str!("../../../../conf/atb-conf-type.dhall");
+        let line = conf_type
+            .lines()
+            .find(|line| line.trim_start().starts_with("Version"))
+            .unwrap();
+        let latest = format!("v{}", LATEST_SCHEMA_VERSION);
+        assert!(line
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .any(|version| version == latest));
+    }
+
+    #[test]
+    fn migrate_plaintext_tokens() {
+        use super::*;
+        use crate::{Actor, BotCreate};
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        atbdb
+            .insert_bot(&BotCreate::new("new", "new bot"), &Actor::cli("test"))
+            .unwrap();
+        atbdb.rollback(6).unwrap();
+
+        // version 6では平文のUUID形式のトークンを発行していた
+        let conn = atbdb.get_conn().unwrap();
+        conn.execute(
+            "insert into bot(name, description) values('old', 'old bot')",
+            rusqlite::NO_PARAMS,
+        )
+        .unwrap();
+        let token: String = conn
+            .query_row(
+                "select token from bot where name = 'old'",
+                rusqlite::NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        drop(conn);
+        assert_eq!(token.len(), 36);
+
+        // マイグレーション後も同じトークンで認証でき、平文は残らない
+        atbdb.migrate(LATEST_SCHEMA_VERSION).unwrap();
+        atbdb.check_schema().unwrap();
+        assert!(atbdb.get_bot_for_api(2, &token).is_ok());
+        assert_eq!(
+            atbdb.get_bot(2).unwrap().get_token_prefix(),
+            Some(&token[..8])
+        );
+        assert!(atbdb.get_bot_for_api(1, &token).is_err());
+    }
+
+    #[test]
+    fn normalize_check_sql() {
+        use super::*;
+        assert_eq!(
+            normalize_sql("  CREATE INDEX idx_exchange ON ohlcv(exchange, pair, period);\n"),
+            "CREATE INDEX idx_exchange ON ohlcv(exchange, pair, period)"
+        );
+        assert!(CHECK_SQL
+            .split(";\n")
+            .map(normalize_sql)
+            .any(|sql| sql.starts_with("CREATE TABLE version(")));
+    }
+}
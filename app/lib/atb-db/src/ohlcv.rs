@@ -0,0 +1,771 @@
+use crate::{AtbDB, AtbDbError, AtbDbResult, MarketScale, OhlcvBar, UnixTime};
+
+// iter_ohlcvで1回のSELECTで読み込むローソク足の件数(デフォルト)
+pub const DEFAULT_OHLCV_CHUNK: usize = 10000;
+
+pub struct Ohlcv {
+    ohlcv: Vec<OhlcvBar>,
+    next_cursor: Option<i64>,
+    format: OhlcvFormat,
+}
+
+// Ohlcvをシリアライズする際のローソク足の形式
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OhlcvFormat {
+    // [open, high, low, close, volume, unixtime]
+    Array,
+    // {"unixtime", "open", "high", "low", "close", "volume"}
+    Object,
+}
+
+// ohlcvデータの並び順
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OhlcvOrder {
+    // 古い順
+    Asc,
+    // 新しい順
+    Desc,
+}
+
+// ohlcvデータの検索条件
+#[derive(Clone, Debug)]
+pub struct OhlcvQuery {
+    pub(crate) exchange: String,
+    pub(crate) pair: String,
+    pub(crate) period: i64,
+    pub(crate) from: Option<i64>,
+    pub(crate) to: Option<i64>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) order: OhlcvOrder,
+    pub(crate) cursor: Option<i64>,
+}
+
+impl OhlcvQuery {
+    pub fn new(exchange: &str, pair: &str, period: i64) -> OhlcvQuery {
+        OhlcvQuery {
+            exchange: exchange.to_string(),
+            pair: pair.to_string(),
+            period,
+            from: None,
+            to: None,
+            limit: None,
+            order: OhlcvOrder::Asc,
+            cursor: None,
+        }
+    }
+
+    // 指定したunixtime以降(指定時刻を含む)に絞り込む
+    pub fn from_time(mut self, unixtime: i64) -> OhlcvQuery {
+        self.from = Some(unixtime);
+        self
+    }
+
+    // 指定したunixtime以前(指定時刻を含む)に絞り込む
+    pub fn to_time(mut self, unixtime: i64) -> OhlcvQuery {
+        self.to = Some(unixtime);
+        self
+    }
+
+    // 取得件数の上限
+    pub fn limit(mut self, limit: usize) -> OhlcvQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn order(mut self, order: OhlcvOrder) -> OhlcvQuery {
+        self.order = order;
+        self
+    }
+
+    // 前回取得したOhlcvのnext_cursorを指定し、その続きから取得する
+    pub fn cursor(mut self, cursor: i64) -> OhlcvQuery {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    #[allow(dead_code)]
+    pub fn get_pair(&self) -> &str {
+        &self.pair
+    }
+
+    #[allow(dead_code)]
+    pub fn get_period(&self) -> i64 {
+        self.period
+    }
+
+    // 検索条件からSELECT文とパラメータを作成する
+    pub(crate) fn to_sql(&self, columns: &str) -> (String, Vec<rusqlite::types::Value>) {
+        let mut sql = format!(
+            "SELECT {} FROM ohlcv WHERE exchange = ? and pair = ? and period = ?",
+            columns
+        );
+        let mut params = vec![
+            rusqlite::types::Value::Text(self.exchange.clone()),
+            rusqlite::types::Value::Text(self.pair.clone()),
+            rusqlite::types::Value::Integer(self.period),
+        ];
+
+        if let Some(from) = self.from {
+            sql.push_str(" and unixtime >= ?");
+            params.push(rusqlite::types::Value::Integer(from));
+        }
+        if let Some(to) = self.to {
+            sql.push_str(" and unixtime <= ?");
+            params.push(rusqlite::types::Value::Integer(to));
+        }
+
+        // カーソルの時刻は前回取得済みなので含めない
+        if let Some(cursor) = self.cursor {
+            sql.push_str(match self.order {
+                OhlcvOrder::Asc => " and unixtime > ?",
+                OhlcvOrder::Desc => " and unixtime < ?",
+            });
+            params.push(rusqlite::types::Value::Integer(cursor));
+        }
+
+        sql.push_str(match self.order {
+            OhlcvOrder::Asc => " ORDER BY unixtime ASC",
+            OhlcvOrder::Desc => " ORDER BY unixtime DESC",
+        });
+
+        if let Some(limit) = self.limit {
+            sql.push_str(" LIMIT ?");
+            params.push(rusqlite::types::Value::Integer(limit as i64));
+        }
+
+        (sql, params)
+    }
+}
+
+// upsert_ohlcv_listの処理件数
+#[derive(serde::Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct UpsertSummary {
+    inserted: usize,
+    updated: usize,
+    unchanged: usize,
+}
+
+impl UpsertSummary {
+    #[allow(dead_code)]
+    pub fn get_inserted(&self) -> usize {
+        self.inserted
+    }
+
+    #[allow(dead_code)]
+    pub fn get_updated(&self) -> usize {
+        self.updated
+    }
+
+    #[allow(dead_code)]
+    pub fn get_unchanged(&self) -> usize {
+        self.unchanged
+    }
+
+    // 処理件数を合算する
+    pub fn merge(&mut self, other: &UpsertSummary) {
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.unchanged += other.unchanged;
+    }
+}
+
+impl Ohlcv {
+    // シリアライズする際のローソク足の形式(デフォルトは配列形式)
+    pub fn format(mut self, format: OhlcvFormat) -> Ohlcv {
+        self.format = format;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_list(&self) -> &[OhlcvBar] {
+        &self.ohlcv
+    }
+
+    #[allow(dead_code)]
+    pub fn into_list(self) -> Vec<OhlcvBar> {
+        self.ohlcv
+    }
+
+    #[allow(dead_code)]
+    pub fn get_list_len(&self) -> usize {
+        self.ohlcv.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn get_next_cursor(&self) -> Option<i64> {
+        self.next_cursor
+    }
+}
+
+// ohlcvデータを一定件数ずつ読み込みながら1本ずつ返すイテレータ
+// 読み込みに失敗した場合はエラーを返して終了する
+pub struct OhlcvIter<'a> {
+    atbdb: &'a AtbDB,
+    query: OhlcvQuery,
+    scale: MarketScale,
+    chunk_size: usize,
+    remaining: Option<usize>,
+    chunk: std::vec::IntoIter<OhlcvBar>,
+    finished: bool,
+}
+
+impl<'a> OhlcvIter<'a> {
+    // 1回のSELECTで読み込む件数(メモリに保持する最大件数)
+    pub fn chunk_size(mut self, chunk_size: usize) -> OhlcvIter<'a> {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    // 次の一定件数を読み込む
+    fn next_chunk(&mut self) -> AtbDbResult<Vec<OhlcvBar>> {
+        let limit = match self.remaining {
+            Some(remaining) => remaining.min(self.chunk_size),
+            None => self.chunk_size,
+        };
+        if limit == 0 {
+            self.finished = true;
+            return Ok(vec![]);
+        }
+
+        let query = OhlcvQuery {
+            limit: Some(limit),
+            ..self.query.clone()
+        };
+        let chunk = self.atbdb.select_ohlcv(&query, &self.scale)?;
+
+        // 読み込んだ件数が上限に満たなければ最後まで読み込んだ
+        if chunk.len() < limit {
+            self.finished = true;
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= chunk.len();
+        }
+        if let Some(bar) = chunk.last() {
+            self.query.cursor = Some(bar.get_unixtime().get_secs());
+        }
+        Ok(chunk)
+    }
+}
+
+impl<'a> Iterator for OhlcvIter<'a> {
+    type Item = AtbDbResult<OhlcvBar>;
+
+    fn next(&mut self) -> Option<AtbDbResult<OhlcvBar>> {
+        loop {
+            if let Some(bar) = self.chunk.next() {
+                return Some(Ok(bar));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.next_chunk() {
+                Ok(chunk) => self.chunk = chunk.into_iter(),
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl<'a> std::iter::FusedIterator for OhlcvIter<'a> {}
+
+impl serde::Serialize for Ohlcv {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let len = if self.next_cursor.is_some() { 2 } else { 1 };
+        let mut state = serializer.serialize_struct("Ohlcv", len)?;
+        match self.format {
+            OhlcvFormat::Array => {
+                let ohlcv: Vec<_> = self.ohlcv.iter().map(OhlcvBar::to_array).collect();
+                state.serialize_field("ohlcv", &ohlcv)?;
+            }
+            OhlcvFormat::Object => state.serialize_field("ohlcv", &self.ohlcv)?,
+        }
+        if let Some(next_cursor) = self.next_cursor {
+            state.serialize_field("next_cursor", &next_cursor)?;
+        }
+        state.end()
+    }
+}
+
+impl AtbDB {
+    // ohlcvテーブルから設定条件の最終unixtimeを取得する
+    pub fn get_last_unixtime_from_ohlcv(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+    ) -> AtbDbResult<i64> {
+        let conn = self.get_conn()?;
+
+        // 最後のunixtime時刻を取得する
+        let unixtime = conn.query_row(
+            "select max(unixtime) from ohlcv where exchange = ?1 and pair = ?2 and period = ?3",
+            rusqlite::params![exchange, pair, period],
+            |row| row.get::<_, Option<i64>>(0),
+        )?;
+
+        // 該当データがなければNotFoundを返す
+        unixtime.ok_or(AtbDbError::NotFound)
+    }
+
+    // 条件に該当するレコードを一つだけ削除する
+    pub fn delete_ohlcv(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+        unixtime: i64,
+    ) -> AtbDbResult<usize> {
+        let conn = self.get_conn()?;
+
+        let count = conn.execute(
+            "delete from ohlcv where exchange = ?1 and pair = ?2 and period = ?3 and unixtime = ?4 limit 1",
+            rusqlite::params![exchange, pair, period, unixtime],
+        )?;
+        Ok(count)
+    }
+
+    // 複数のohlcvデータを追加する
+    pub fn insert_ohlcv_list(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+        records: &[OhlcvBar],
+    ) -> AtbDbResult<usize> {
+        let scale = self.get_market_scale(exchange, pair)?;
+        let mut conn = self.get_conn()?;
+
+        // SQLを作成する
+        let sql_key = "exchange, pair, period, open, high, low, close, volume, unixtime";
+        let sql_value = "?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9";
+        let sql_insert = &format!("INSERT INTO ohlcv ({}) VALUES ({})", sql_key, sql_value);
+
+        let tx = conn.transaction()?;
+        let mut count = 0;
+        for record in records {
+            let values = encode_record(&scale, record)?;
+            count += tx.execute(
+                sql_insert,
+                rusqlite::params![
+                    exchange,
+                    pair,
+                    period,
+                    values[0],
+                    values[1],
+                    values[2],
+                    values[3],
+                    values[4],
+                    record.get_unixtime().get_secs()
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    // 複数のohlcvデータを追加し、既存のローソク足は値が変わっていれば更新する
+    pub fn upsert_ohlcv_list(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+        records: &[OhlcvBar],
+    ) -> AtbDbResult<UpsertSummary> {
+        use rusqlite::OptionalExtension;
+
+        let scale = self.get_market_scale(exchange, pair)?;
+        let mut conn = self.get_conn()?;
+        let mut summary = UpsertSummary::default();
+
+        let tx = conn.transaction()?;
+        {
+            let mut stmt_select = tx.prepare_cached(
+                "SELECT open, high, low, close, volume FROM ohlcv WHERE exchange = ?1 and pair = ?2 and period = ?3 and unixtime = ?4",
+            )?;
+            let mut stmt_insert = tx.prepare_cached(
+                "INSERT INTO ohlcv (exchange, pair, period, open, high, low, close, volume, unixtime) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            let mut stmt_update = tx.prepare_cached(
+                "UPDATE ohlcv SET open = ?4, high = ?5, low = ?6, close = ?7, volume = ?8 WHERE exchange = ?1 and pair = ?2 and period = ?3 and unixtime = ?9",
+            )?;
+
+            for record in records {
+                let values = encode_record(&scale, record)?;
+
+                // 保存済みのローソク足を取得する
+                let stored = stmt_select
+                    .query_row(
+                        rusqlite::params![exchange, pair, period, record.get_unixtime().get_secs()],
+                        |row| {
+                            Ok([
+                                row.get::<_, i64>(0)?,
+                                row.get::<_, i64>(1)?,
+                                row.get::<_, i64>(2)?,
+                                row.get::<_, i64>(3)?,
+                                row.get::<_, i64>(4)?,
+                            ])
+                        },
+                    )
+                    .optional()?;
+
+                let params = rusqlite::params![
+                    exchange,
+                    pair,
+                    period,
+                    values[0],
+                    values[1],
+                    values[2],
+                    values[3],
+                    values[4],
+                    record.get_unixtime().get_secs()
+                ];
+                match stored {
+                    None => {
+                        stmt_insert.execute(params)?;
+                        summary.inserted += 1;
+                    }
+                    Some(stored) if stored != values => {
+                        stmt_update.execute(params)?;
+                        summary.updated += 1;
+                    }
+                    Some(_) => summary.unchanged += 1,
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(summary)
+    }
+
+    // 検索条件に該当するohlcvデータを取得する
+    // 変換できないレコードがあればDecodeエラーを返す
+    pub fn get_ohlcv_list(&self, query: &OhlcvQuery) -> AtbDbResult<Ohlcv> {
+        let scale = self.get_market_scale(&query.exchange, &query.pair)?;
+        let ohlcv = self.select_ohlcv(query, &scale)?;
+
+        // 取得件数が上限に達していれば、続きを取得するためのカーソルを返す
+        let next_cursor = match query.limit {
+            Some(limit) if ohlcv.len() == limit => {
+                ohlcv.last().map(|bar| bar.get_unixtime().get_secs())
+            }
+            _ => None,
+        };
+
+        Ok(Ohlcv {
+            ohlcv,
+            next_cursor,
+            format: OhlcvFormat::Array,
+        })
+    }
+
+    // 検索条件に該当するohlcvデータを一定件数ずつ読み込むイテレータを作成する
+    // limitを指定した場合は合計でlimit件まで返す
+    pub fn iter_ohlcv(&self, query: &OhlcvQuery) -> AtbDbResult<OhlcvIter<'_>> {
+        let scale = self.get_market_scale(&query.exchange, &query.pair)?;
+        Ok(OhlcvIter {
+            atbdb: self,
+            query: query.clone(),
+            scale,
+            chunk_size: DEFAULT_OHLCV_CHUNK,
+            remaining: query.limit,
+            chunk: vec![].into_iter(),
+            finished: false,
+        })
+    }
+
+    // 検索条件に該当するohlcvデータをchunk_size件ずつ読み込んで関数に渡す
+    // 関数がエラーを返した場合はそこで中断する。渡した件数の合計を返す
+    pub fn for_each_ohlcv_chunk<F>(
+        &self,
+        query: &OhlcvQuery,
+        chunk_size: usize,
+        mut f: F,
+    ) -> AtbDbResult<usize>
+    where
+        F: FnMut(&[OhlcvBar]) -> AtbDbResult<()>,
+    {
+        let mut iter = self.iter_ohlcv(query)?.chunk_size(chunk_size);
+        let mut count = 0;
+        loop {
+            let chunk = iter.next_chunk()?;
+            if !chunk.is_empty() {
+                f(&chunk)?;
+                count += chunk.len();
+            }
+            if iter.finished {
+                return Ok(count);
+            }
+        }
+    }
+
+    // 検索条件に該当するohlcvデータを市場の桁数で変換して取得する
+    fn select_ohlcv(&self, query: &OhlcvQuery, scale: &MarketScale) -> AtbDbResult<Vec<OhlcvBar>> {
+        let conn = self.get_conn()?;
+
+        let (sql, params) = query.to_sql("open, high, low, close, volume, unixtime");
+        let mut stmt = conn.prepare(&sql)?;
+        let mut rows = stmt.query(params)?;
+
+        let mut ohlcv = vec![];
+        while let Some(row) = rows.next()? {
+            ohlcv.push(decode_row(scale, row)?);
+        }
+        Ok(ohlcv)
+    }
+}
+
+// 保存されているレコードをローソク足に変換する
+fn decode_row(scale: &MarketScale, row: &rusqlite::Row) -> AtbDbResult<OhlcvBar> {
+    let unixtime = row.get::<_, i64>(5)?;
+    let values = [
+        row.get::<_, i64>(0),
+        row.get::<_, i64>(1),
+        row.get::<_, i64>(2),
+        row.get::<_, i64>(3),
+        row.get::<_, i64>(4),
+    ];
+    let mut mantissa = [0; 5];
+    for (i, value) in values.iter().enumerate() {
+        mantissa[i] = *value.as_ref().map_err(|err| {
+            AtbDbError::Decode(format!(
+                "unixtime:{} のローソク足を変換できません: {}",
+                unixtime, err
+            ))
+        })?;
+    }
+
+    // 保存されている値の整合性も確認する
+    OhlcvBar::new(
+        UnixTime::new(unixtime),
+        scale.price(mantissa[0]),
+        scale.price(mantissa[1]),
+        scale.price(mantissa[2]),
+        scale.price(mantissa[3]),
+        scale.volume(mantissa[4]),
+    )
+    .map_err(|err| match err {
+        AtbDbError::Validation(msg) => AtbDbError::Decode(msg),
+        err => err,
+    })
+}
+
+// ローソク足の価格・出来高を保存用の整数値に変換する
+pub(crate) fn encode_record(scale: &MarketScale, record: &OhlcvBar) -> AtbDbResult<[i64; 5]> {
+    Ok([
+        scale.encode_price(&record.get_open())?,
+        scale.encode_price(&record.get_high())?,
+        scale.encode_price(&record.get_low())?,
+        scale.encode_price(&record.get_close())?,
+        scale.encode_volume(&record.get_volume())?,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn query_to_sql() {
+        use super::*;
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60);
+        let (sql, params) = query.to_sql("unixtime");
+        assert_eq!(
+            sql,
+            "SELECT unixtime FROM ohlcv WHERE exchange = ? and pair = ? and period = ? ORDER BY unixtime ASC"
+        );
+        assert_eq!(params.len(), 3);
+
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60)
+            .from_time(1514764800)
+            .to_time(1514851200)
+            .order(OhlcvOrder::Desc)
+            .cursor(1514800000)
+            .limit(100);
+        let (sql, params) = query.to_sql("unixtime");
+        assert_eq!(
+            sql,
+            "SELECT unixtime FROM ohlcv WHERE exchange = ? and pair = ? and period = ? and unixtime >= ? and unixtime <= ? and unixtime < ? ORDER BY unixtime DESC LIMIT ?"
+        );
+        assert_eq!(params.len(), 7);
+    }
+
+    fn bar(unixtime: i64, close: i64) -> super::OhlcvBar {
+        use crate::{Decimal, UnixTime};
+        super::OhlcvBar::new(
+            UnixTime::new(unixtime),
+            Decimal::new(close, 0),
+            Decimal::new(close, 0),
+            Decimal::new(close, 0),
+            Decimal::new(close, 0),
+            Decimal::new(1, 3),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn upsert_and_get_ohlcv_list() {
+        use super::*;
+        use crate::{Decimal, UnixTime};
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let bars = vec![bar(60, 100), bar(120, 101), bar(180, 102)];
+        let summary = atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars)
+            .unwrap();
+        assert_eq!(summary.get_inserted(), 3);
+
+        // 変更された足だけを更新する
+        let bars = vec![bar(180, 103), bar(240, 104)];
+        let summary = atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars)
+            .unwrap();
+        assert_eq!(
+            (
+                summary.get_inserted(),
+                summary.get_updated(),
+                summary.get_unchanged()
+            ),
+            (1, 1, 0)
+        );
+        assert_eq!(
+            atbdb
+                .get_last_unixtime_from_ohlcv("bitflyer", "btcjpy", 60)
+                .unwrap(),
+            240
+        );
+
+        // カーソルで続きを取得する
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60).limit(2);
+        let ohlcv = atbdb.get_ohlcv_list(&query).unwrap();
+        assert_eq!(ohlcv.get_list_len(), 2);
+        assert_eq!(ohlcv.get_next_cursor(), Some(120));
+        let ohlcv = atbdb
+            .get_ohlcv_list(&query.cursor(ohlcv.get_next_cursor().unwrap()))
+            .unwrap();
+        assert_eq!(ohlcv.get_list(), &[bar(180, 103), bar(240, 104)][..]);
+
+        // 市場の桁数で保存できない値はエラー
+        let price = Decimal::new(1005, 1);
+        let fraction = OhlcvBar::new(
+            UnixTime::new(300),
+            price,
+            price,
+            price,
+            price,
+            Decimal::zero(0),
+        )
+        .unwrap();
+        assert!(matches!(
+            atbdb.upsert_ohlcv_list("bitflyer", "btcjpy", 60, &[fraction]),
+            Err(AtbDbError::Validation(_))
+        ));
+        assert!(matches!(
+            atbdb.get_last_unixtime_from_ohlcv("bitflyer", "btcjpy", 300),
+            Err(AtbDbError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn iter_ohlcv() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let bars: Vec<OhlcvBar> = (1..=25).map(|i| bar(i * 60, 100 + i)).collect();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars)
+            .unwrap();
+
+        // 一定件数ずつ読み込んでも全件を時刻順に返す
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60);
+        let iter = atbdb.iter_ohlcv(&query).unwrap().chunk_size(10);
+        let all: Vec<OhlcvBar> = iter.collect::<AtbDbResult<_>>().unwrap();
+        assert_eq!(all, bars);
+
+        // limit・並び順・範囲の指定は全体に対して適用する
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60)
+            .to_time(20 * 60)
+            .order(OhlcvOrder::Desc)
+            .limit(12);
+        let iter = atbdb.iter_ohlcv(&query).unwrap().chunk_size(5);
+        let unixtimes: Vec<i64> = iter
+            .map(|bar| bar.unwrap().get_unixtime().get_secs())
+            .collect();
+        assert_eq!(
+            unixtimes,
+            (9..=20).rev().map(|i| i * 60).collect::<Vec<_>>()
+        );
+
+        // チャンクごとに関数に渡す
+        let mut sizes = vec![];
+        let count = atbdb
+            .for_each_ohlcv_chunk(&OhlcvQuery::new("bitflyer", "btcjpy", 60), 10, |chunk| {
+                sizes.push(chunk.len());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!((count, sizes), (25, vec![10, 10, 5]));
+
+        // 関数がエラーを返せば中断する
+        let mut calls = 0;
+        let result = atbdb.for_each_ohlcv_chunk(&query, 5, |_| {
+            calls += 1;
+            Err(AtbDbError::Validation("中断".to_string()))
+        });
+        assert!(matches!(result, Err(AtbDbError::Validation(_))));
+        assert_eq!(calls, 1);
+
+        // 登録されていない市場はNotFound
+        assert!(matches!(
+            atbdb.iter_ohlcv(&OhlcvQuery::new("unknown", "btcjpy", 60)),
+            Err(AtbDbError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn decode_error() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let bars: Vec<OhlcvBar> = (1..=3).map(|i| bar(i * 60, 100 + i)).collect();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars)
+            .unwrap();
+
+        // 高値が安値より低い足を直接書き込む
+        let conn = atbdb.pool.get().unwrap();
+        conn.execute(
+            "UPDATE ohlcv SET high = 1 WHERE unixtime = 120",
+            rusqlite::NO_PARAMS,
+        )
+        .unwrap();
+
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60);
+        assert!(matches!(
+            atbdb.get_ohlcv_list(&query),
+            Err(AtbDbError::Decode(_))
+        ));
+
+        // イテレータは変換できた足を返した後にエラーを返して終了する
+        let mut iter = atbdb.iter_ohlcv(&query).unwrap().chunk_size(1);
+        assert_eq!(iter.next().unwrap().unwrap(), bars[0]);
+        assert!(matches!(iter.next(), Some(Err(AtbDbError::Decode(_)))));
+        assert!(iter.next().is_none());
+
+        // 数値でない値もDecodeエラー
+        conn.execute(
+            "UPDATE ohlcv SET high = 'x' WHERE unixtime = 120",
+            rusqlite::NO_PARAMS,
+        )
+        .unwrap();
+        assert!(matches!(
+            atbdb.get_ohlcv_list(&query),
+            Err(AtbDbError::Decode(_))
+        ));
+    }
+}
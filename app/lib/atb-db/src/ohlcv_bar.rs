@@ -0,0 +1,288 @@
+use crate::{AtbDbError, AtbDbResult, Decimal};
+
+// UNIX時間(秒)
+#[derive(
+    serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+pub struct UnixTime(i64);
+
+impl UnixTime {
+    pub fn new(secs: i64) -> UnixTime {
+        UnixTime(secs)
+    }
+
+    #[allow(dead_code)]
+    pub fn get_secs(&self) -> i64 {
+        self.0
+    }
+
+    // 指定秒数後の時刻
+    pub fn add_secs(&self, secs: i64) -> UnixTime {
+        UnixTime(self.0 + secs)
+    }
+
+    // UTCの日時に変換する(範囲外の場合はNone)
+    pub fn to_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        use chrono::TimeZone;
+        chrono::Utc.timestamp_opt(self.0, 0).single()
+    }
+}
+
+impl From<i64> for UnixTime {
+    fn from(secs: i64) -> UnixTime {
+        UnixTime(secs)
+    }
+}
+
+impl std::fmt::Display for UnixTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// 配列形式のローソク足 [open, high, low, close, volume, unixtime]
+pub type OhlcvArray = (Decimal, Decimal, Decimal, Decimal, Decimal, i64);
+
+// ローソク足1本分のデータ
+#[derive(serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OhlcvBar {
+    unixtime: UnixTime,
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: Decimal,
+}
+
+impl OhlcvBar {
+    // 高値・安値・出来高の整合性を確認してローソク足を作成する
+    pub fn new(
+        unixtime: UnixTime,
+        open: Decimal,
+        high: Decimal,
+        low: Decimal,
+        close: Decimal,
+        volume: Decimal,
+    ) -> AtbDbResult<OhlcvBar> {
+        if high < open.max(close).max(low) || open.min(close) < low {
+            return Err(AtbDbError::Validation(format!(
+                "unixtime:{} の高値・安値が不正です(open:{}, high:{}, low:{}, close:{})",
+                unixtime, open, high, low, close
+            )));
+        }
+        if volume < Decimal::zero(0) {
+            return Err(AtbDbError::Validation(format!(
+                "unixtime:{} の出来高が負の値です: {}",
+                unixtime, volume
+            )));
+        }
+        Ok(OhlcvBar {
+            unixtime,
+            open,
+            high,
+            low,
+            close,
+            volume,
+        })
+    }
+
+    // データベースから読み込んだ値など、確認済みの値から作成する
+    pub(crate) fn new_unchecked(
+        unixtime: UnixTime,
+        open: Decimal,
+        high: Decimal,
+        low: Decimal,
+        close: Decimal,
+        volume: Decimal,
+    ) -> OhlcvBar {
+        OhlcvBar {
+            unixtime,
+            open,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+
+    // 配列形式から作成する
+    pub fn from_array(array: OhlcvArray) -> AtbDbResult<OhlcvBar> {
+        let (open, high, low, close, volume, unixtime) = array;
+        OhlcvBar::new(UnixTime::new(unixtime), open, high, low, close, volume)
+    }
+
+    // 配列形式に変換する
+    pub fn to_array(&self) -> OhlcvArray {
+        (
+            self.open,
+            self.high,
+            self.low,
+            self.close,
+            self.volume,
+            self.unixtime.get_secs(),
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn get_unixtime(&self) -> UnixTime {
+        self.unixtime
+    }
+
+    #[allow(dead_code)]
+    pub fn get_open(&self) -> Decimal {
+        self.open
+    }
+
+    #[allow(dead_code)]
+    pub fn get_high(&self) -> Decimal {
+        self.high
+    }
+
+    #[allow(dead_code)]
+    pub fn get_low(&self) -> Decimal {
+        self.low
+    }
+
+    #[allow(dead_code)]
+    pub fn get_close(&self) -> Decimal {
+        self.close
+    }
+
+    #[allow(dead_code)]
+    pub fn get_volume(&self) -> Decimal {
+        self.volume
+    }
+
+    // 値幅(高値 - 安値)
+    pub fn range(&self) -> Decimal {
+        self.high - self.low
+    }
+
+    // 実体(終値 - 始値、陰線は負の値)
+    pub fn body(&self) -> Decimal {
+        self.close - self.open
+    }
+
+    // 陽線かどうか
+    pub fn is_bullish(&self) -> bool {
+        self.open < self.close
+    }
+
+    // 陰線かどうか
+    pub fn is_bearish(&self) -> bool {
+        self.close < self.open
+    }
+
+    // 典型価格((高値 + 安値 + 終値) / 3)
+    pub fn typical_price(&self) -> f64 {
+        (self.high.to_f64() + self.low.to_f64() + self.close.to_f64()) / 3.0
+    }
+
+    // 足の中での騰落率(終値 / 始値 - 1)
+    pub fn change(&self) -> f64 {
+        self.close.to_f64() / self.open.to_f64() - 1.0
+    }
+
+    // 前の足の終値からの騰落率(終値 / 前の足の終値 - 1)
+    pub fn return_since(&self, prev: &OhlcvBar) -> f64 {
+        self.close.to_f64() / prev.close.to_f64() - 1.0
+    }
+
+    // 前の足の終値からの対数収益率
+    pub fn log_return_since(&self, prev: &OhlcvBar) -> f64 {
+        (self.close.to_f64() / prev.close.to_f64()).ln()
+    }
+}
+
+// 配列形式・オブジェクト形式のどちらからでも読み込めるようにする
+impl<'de> serde::Deserialize<'de> for OhlcvBar {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<OhlcvBar, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum OhlcvBarRepr {
+            Array(Decimal, Decimal, Decimal, Decimal, Decimal, i64),
+            Object {
+                unixtime: UnixTime,
+                open: Decimal,
+                high: Decimal,
+                low: Decimal,
+                close: Decimal,
+                volume: Decimal,
+            },
+        }
+
+        let bar = match OhlcvBarRepr::deserialize(deserializer)? {
+            OhlcvBarRepr::Array(open, high, low, close, volume, unixtime) => {
+                OhlcvBar::from_array((open, high, low, close, volume, unixtime))
+            }
+            OhlcvBarRepr::Object {
+                unixtime,
+                open,
+                high,
+                low,
+                close,
+                volume,
+            } => OhlcvBar::new(unixtime, open, high, low, close, volume),
+        };
+        bar.map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn bar(open: &str, high: &str, low: &str, close: &str) -> super::OhlcvBar {
+        use super::*;
+        OhlcvBar::new(
+            UnixTime::new(1514764800),
+            open.parse().unwrap(),
+            high.parse().unwrap(),
+            low.parse().unwrap(),
+            close.parse().unwrap(),
+            "1.5".parse().unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn bar_helpers() {
+        use super::*;
+        let prev = bar("100", "110", "90", "100");
+        let bar = bar("100", "120", "95", "105.5");
+        assert_eq!(bar.range(), "25".parse::<Decimal>().unwrap());
+        assert_eq!(bar.body(), "5.5".parse::<Decimal>().unwrap());
+        assert!(bar.is_bullish());
+        assert!(!bar.is_bearish());
+        assert!((bar.typical_price() - 106.833333).abs() < 1e-6);
+        assert!((bar.change() - 0.055).abs() < 1e-12);
+        assert!((bar.return_since(&prev) - 0.055).abs() < 1e-12);
+
+        // 高値が始値より低いローソク足は作成できない
+        assert!(OhlcvBar::new(
+            UnixTime::new(0),
+            Decimal::new(100, 0),
+            Decimal::new(99, 0),
+            Decimal::new(90, 0),
+            Decimal::new(95, 0),
+            Decimal::zero(0),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn bar_serde() {
+        use super::*;
+        let bar = bar("100", "120", "95", "105.5");
+        let json = serde_json::to_string(&bar).unwrap();
+        assert_eq!(
+            json,
+            r#"{"unixtime":1514764800,"open":"100","high":"120","low":"95","close":"105.5","volume":"1.5"}"#
+        );
+        assert_eq!(serde_json::from_str::<OhlcvBar>(&json).unwrap(), bar);
+
+        let array = serde_json::to_string(&bar.to_array()).unwrap();
+        assert_eq!(array, r#"["100","120","95","105.5","1.5",1514764800]"#);
+        assert_eq!(serde_json::from_str::<OhlcvBar>(&array).unwrap(), bar);
+        assert!(serde_json::from_str::<OhlcvBar>("[100, 90, 95, 105, 1, 0]").is_err());
+    }
+}
@@ -0,0 +1,725 @@
+// ohlcvデータのCSV・Parquetファイルへの書き出し・読み込み
+// featureの`csv`・`parquet`で有効になる
+//
+// 列は unixtime, open, high, low, close, volume の順に書き出す
+// - CSV: 1行目がヘッダー、価格・出来高は誤差が出ないよう10進数の文字列
+// - Parquet: unixtimeはINT64(秒)、価格・出来高は市場の桁数のDECIMAL(INT64)
+//
+// 読み込みは列名で判断するため、列の順番は問わず余分な列は無視する
+// - unixtime列は unixtime, timestamp, time, datetime, date のいずれかの列名
+// - 時刻は整数(秒・ミリ秒・マイクロ秒・ナノ秒を桁数で判断する)、RFC3339、
+//   "YYYY-MM-DD HH:MM:SS"(UTC)のいずれか
+// - 時刻は足の終了時刻で、足の区切り(UTCまたは日本時間の0時、週足は月曜日0時が基準)に揃っていること
+// - ファイル全体を確認してから1つのトランザクションで保存する(upsert)
+
+use crate::{
+    AtbDB, AtbDbError, AtbDbResult, Decimal, OhlcvBar, OhlcvQuery, ResampleAnchor, UnixTime,
+    UpsertSummary,
+};
+
+// Parquetの1つの行グループに書き込むローソク足の件数
+#[cfg(feature = "parquet")]
+pub const PARQUET_ROW_GROUP_SIZE: usize = 100000;
+
+// 書き出す列名
+const COLUMNS: [&str; 6] = ["unixtime", "open", "high", "low", "close", "volume"];
+
+// unixtime列として読み込む列名(先に見つかったものを使う)
+const TIME_COLUMNS: [&str; 5] = ["unixtime", "timestamp", "time", "datetime", "date"];
+
+// ohlcvデータのファイル形式
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OhlcvFileFormat {
+    Csv,
+    Parquet,
+}
+
+impl OhlcvFileFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OhlcvFileFormat::Csv => "csv",
+            OhlcvFileFormat::Parquet => "parquet",
+        }
+    }
+
+    // ファイルの拡張子(.csv, .parquet)から形式を判断する
+    pub fn from_path(path: &std::path::Path) -> Option<OhlcvFileFormat> {
+        path.extension()?.to_str()?.to_lowercase().parse().ok()
+    }
+}
+
+impl std::str::FromStr for OhlcvFileFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OhlcvFileFormat, String> {
+        match s {
+            "csv" => Ok(OhlcvFileFormat::Csv),
+            "parquet" | "pq" => Ok(OhlcvFileFormat::Parquet),
+            _ => Err(format!("不明なファイル形式です: {}", s)),
+        }
+    }
+}
+
+impl AtbDB {
+    // 検索条件に該当するohlcvデータをファイルに書き出し、書き出した件数を返す
+    pub fn export_ohlcv_file(
+        &self,
+        query: &OhlcvQuery,
+        format: OhlcvFileFormat,
+        path: &std::path::Path,
+    ) -> AtbDbResult<usize> {
+        // 書き出す前に市場を確認し、存在しなければファイルを作成しない
+        self.get_market_scale(&query.exchange, &query.pair)?;
+        match format {
+            #[cfg(feature = "csv")]
+            OhlcvFileFormat::Csv => {
+                let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+                self.export_ohlcv_csv(query, file)
+            }
+            #[cfg(feature = "parquet")]
+            OhlcvFileFormat::Parquet => {
+                let file = std::fs::File::create(path)?;
+                self.export_ohlcv_parquet(query, file)
+            }
+            #[cfg(not(all(feature = "csv", feature = "parquet")))]
+            _ => Err(disabled_format(format)),
+        }
+    }
+
+    // ファイルのohlcvデータを確認して保存する(既存のローソク足は値が変わっていれば更新する)
+    pub fn import_ohlcv_file(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+        format: OhlcvFileFormat,
+        path: &std::path::Path,
+    ) -> AtbDbResult<UpsertSummary> {
+        match format {
+            #[cfg(feature = "csv")]
+            OhlcvFileFormat::Csv => {
+                let file = std::fs::File::open(path)?;
+                self.import_ohlcv_csv(exchange, pair, period, file)
+            }
+            #[cfg(feature = "parquet")]
+            OhlcvFileFormat::Parquet => {
+                let file = std::fs::File::open(path)?;
+                self.import_ohlcv_parquet(exchange, pair, period, file)
+            }
+            #[cfg(not(all(feature = "csv", feature = "parquet")))]
+            _ => Err(disabled_format(format)),
+        }
+    }
+
+    // 検索条件に該当するohlcvデータをCSVで書き出し、書き出した件数を返す
+    #[cfg(feature = "csv")]
+    pub fn export_ohlcv_csv<W: std::io::Write>(
+        &self,
+        query: &OhlcvQuery,
+        writer: W,
+    ) -> AtbDbResult<usize> {
+        let mut writer = csv::Writer::from_writer(writer);
+        writer.write_record(COLUMNS).map_err(csv_error)?;
+
+        let count = self.for_each_ohlcv_chunk(query, crate::DEFAULT_OHLCV_CHUNK, |chunk| {
+            for bar in chunk {
+                writer
+                    .write_record(&[
+                        bar.get_unixtime().to_string(),
+                        bar.get_open().to_string(),
+                        bar.get_high().to_string(),
+                        bar.get_low().to_string(),
+                        bar.get_close().to_string(),
+                        bar.get_volume().to_string(),
+                    ])
+                    .map_err(csv_error)?;
+            }
+            Ok(())
+        })?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    // CSVのohlcvデータを確認して保存する
+    #[cfg(feature = "csv")]
+    pub fn import_ohlcv_csv<R: std::io::Read>(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+        reader: R,
+    ) -> AtbDbResult<UpsertSummary> {
+        self.validate_market_period(exchange, pair, period)?;
+
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let columns = find_columns(reader.headers().map_err(csv_error)?.iter())?;
+
+        let mut records = vec![];
+        for record in reader.records() {
+            let record = record.map_err(csv_error)?;
+            let line = record.position().map(|pos| pos.line()).unwrap_or(0);
+            let value = |i: usize| record.get(columns[i]).unwrap_or("");
+            let bar = parse_unixtime(value(0))
+                .and_then(|unixtime| {
+                    let mut values = [Decimal::zero(0); 5];
+                    for (i, v) in values.iter_mut().enumerate() {
+                        *v = parse_decimal(COLUMNS[i + 1], value(i + 1))?;
+                    }
+                    Ok((unixtime, values))
+                })
+                .map_err(AtbDbError::Validation)
+                .and_then(|(unixtime, v)| OhlcvBar::new(unixtime, v[0], v[1], v[2], v[3], v[4]))
+                .map_err(|err| at_row(line as usize, err))?;
+            records.push((line as usize, bar));
+        }
+
+        self.upsert_imported_ohlcv(exchange, pair, period, records)
+    }
+
+    // 検索条件に該当するohlcvデータをParquetで書き出し、書き出した件数を返す
+    #[cfg(feature = "parquet")]
+    pub fn export_ohlcv_parquet<W: std::io::Write + Send>(
+        &self,
+        query: &OhlcvQuery,
+        writer: W,
+    ) -> AtbDbResult<usize> {
+        use parquet::data_type::Int64Type;
+        use parquet::file::properties::WriterProperties;
+
+        let scale = self.get_market_scale(&query.exchange, &query.pair)?;
+        let schema = format!(
+            "message ohlcv {{
+                required int64 unixtime;
+                required int64 open (DECIMAL(18, {price}));
+                required int64 high (DECIMAL(18, {price}));
+                required int64 low (DECIMAL(18, {price}));
+                required int64 close (DECIMAL(18, {price}));
+                required int64 volume (DECIMAL(18, {volume}));
+            }}",
+            price = scale.get_price_scale(),
+            volume = scale.get_volume_scale()
+        );
+        let schema = parquet::schema::parser::parse_message_type(&schema).map_err(parquet_error)?;
+
+        // 市場・足の期間をファイルのメタデータに残す
+        let metadata = vec![
+            ("exchange", query.exchange.clone()),
+            ("pair", query.pair.clone()),
+            ("period", query.period.to_string()),
+        ]
+        .into_iter()
+        .map(|(key, value)| parquet::file::metadata::KeyValue::new(key.to_string(), value))
+        .collect();
+        let properties = WriterProperties::builder()
+            .set_compression(parquet::basic::Compression::SNAPPY)
+            .set_key_value_metadata(Some(metadata))
+            .build();
+
+        let mut writer = parquet::file::writer::SerializedFileWriter::new(
+            writer,
+            std::sync::Arc::new(schema),
+            std::sync::Arc::new(properties),
+        )
+        .map_err(parquet_error)?;
+
+        let count = self.for_each_ohlcv_chunk(query, PARQUET_ROW_GROUP_SIZE, |chunk| {
+            // 列ごとの値(価格・出来高は市場の桁数の整数値)
+            let mut columns = vec![Vec::with_capacity(chunk.len()); COLUMNS.len()];
+            for bar in chunk {
+                columns[0].push(bar.get_unixtime().get_secs());
+                for (i, value) in crate::ohlcv::encode_record(&scale, bar)?.iter().enumerate() {
+                    columns[i + 1].push(*value);
+                }
+            }
+
+            let mut row_group = writer.next_row_group().map_err(parquet_error)?;
+            for values in &columns {
+                let mut column = row_group
+                    .next_column()
+                    .map_err(parquet_error)?
+                    .ok_or_else(|| AtbDbError::Decode("Parquetの列が足りません".to_string()))?;
+                column
+                    .typed::<Int64Type>()
+                    .write_batch(values, None, None)
+                    .map_err(parquet_error)?;
+                column.close().map_err(parquet_error)?;
+            }
+            row_group.close().map_err(parquet_error)?;
+            Ok(())
+        })?;
+        writer.close().map_err(parquet_error)?;
+        Ok(count)
+    }
+
+    // Parquetのohlcvデータを確認して保存する
+    #[cfg(feature = "parquet")]
+    pub fn import_ohlcv_parquet<R: parquet::file::reader::ChunkReader + 'static>(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+        reader: R,
+    ) -> AtbDbResult<UpsertSummary> {
+        use parquet::file::reader::FileReader;
+
+        self.validate_market_period(exchange, pair, period)?;
+
+        let reader = parquet::file::serialized_reader::SerializedFileReader::new(reader)
+            .map_err(parquet_error)?;
+        let names: Vec<String> = reader
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .root_schema()
+            .get_fields()
+            .iter()
+            .map(|field| field.name().to_string())
+            .collect();
+        let columns = find_columns(names.iter().map(String::as_str))?;
+
+        let mut records = vec![];
+        for (i, row) in reader
+            .get_row_iter(None)
+            .map_err(parquet_error)?
+            .enumerate()
+        {
+            let row = row.map_err(parquet_error)?;
+            let fields: Vec<&parquet::record::Field> =
+                row.get_column_iter().map(|(_, field)| field).collect();
+            let bar = field_to_unixtime(fields[columns[0]])
+                .and_then(|unixtime| {
+                    let mut values = [Decimal::zero(0); 5];
+                    for (j, v) in values.iter_mut().enumerate() {
+                        *v = field_to_decimal(COLUMNS[j + 1], fields[columns[j + 1]])?;
+                    }
+                    Ok((unixtime, values))
+                })
+                .map_err(AtbDbError::Validation)
+                .and_then(|(unixtime, v)| OhlcvBar::new(unixtime, v[0], v[1], v[2], v[3], v[4]))
+                .map_err(|err| at_row(i + 1, err))?;
+            records.push((i + 1, bar));
+        }
+
+        self.upsert_imported_ohlcv(exchange, pair, period, records)
+    }
+
+    // 読み込んだローソク足(行番号, 足)の時刻・重複を確認し、1つのトランザクションで保存する
+    fn upsert_imported_ohlcv(
+        &self,
+        exchange: &str,
+        pair: &str,
+        period: i64,
+        mut records: Vec<(usize, OhlcvBar)>,
+    ) -> AtbDbResult<UpsertSummary> {
+        // 最初の足で基準時刻を判断し、すべての足が同じ基準の区切りに揃っているか確認する
+        let anchor = records.first().and_then(|(_, bar)| {
+            [ResampleAnchor::Utc, ResampleAnchor::Jst]
+                .iter()
+                .copied()
+                .find(|anchor| anchor.is_aligned(bar.get_unixtime().get_secs(), period))
+        });
+        for (row, bar) in &records {
+            let unixtime = bar.get_unixtime().get_secs();
+            if !anchor.is_some_and(|anchor| anchor.is_aligned(unixtime, period)) {
+                return Err(at_row(
+                    *row,
+                    AtbDbError::Validation(format!(
+                        "unixtime:{} は足の期間 {} の区切りの時刻ではありません",
+                        unixtime, period
+                    )),
+                ));
+            }
+        }
+
+        records.sort_by_key(|(_, bar)| bar.get_unixtime());
+        if let Some(pair) = records
+            .windows(2)
+            .find(|records| records[0].1.get_unixtime() == records[1].1.get_unixtime())
+        {
+            return Err(at_row(
+                pair[1].0.max(pair[0].0),
+                AtbDbError::Validation(format!(
+                    "unixtime:{} のローソク足が重複しています",
+                    pair[0].1.get_unixtime()
+                )),
+            ));
+        }
+        let records: Vec<OhlcvBar> = records.into_iter().map(|(_, bar)| bar).collect();
+        self.upsert_ohlcv_list(exchange, pair, period, &records)
+    }
+}
+
+// featureが無効なファイル形式のエラー
+#[cfg(not(all(feature = "csv", feature = "parquet")))]
+fn disabled_format(format: OhlcvFileFormat) -> AtbDbError {
+    AtbDbError::Config(format!(
+        "{}形式を扱うにはatb-dbのfeature `{}` を有効にしてください",
+        format.as_str(),
+        format.as_str()
+    ))
+}
+
+// 読み込みエラーに行番号を付ける
+fn at_row(row: usize, err: AtbDbError) -> AtbDbError {
+    match err {
+        AtbDbError::Validation(msg) => AtbDbError::Validation(format!("{}行目: {}", row, msg)),
+        err => err,
+    }
+}
+
+// 列名からunixtime, open, high, low, close, volumeの列番号を探す
+fn find_columns<'a, I: Iterator<Item = &'a str>>(names: I) -> AtbDbResult<[usize; 6]> {
+    let names: Vec<String> = names.map(|name| name.trim().to_lowercase()).collect();
+    let position = |name: &str| names.iter().position(|n| n == name);
+
+    let mut columns = [0; 6];
+    columns[0] = TIME_COLUMNS
+        .iter()
+        .find_map(|name| position(name))
+        .ok_or_else(|| {
+            AtbDbError::Validation(format!(
+                "時刻の列がありません(列名: {})",
+                TIME_COLUMNS.join(", ")
+            ))
+        })?;
+    for (i, name) in COLUMNS.iter().enumerate().skip(1) {
+        columns[i] = position(name)
+            .ok_or_else(|| AtbDbError::Validation(format!("{}の列がありません", name)))?;
+    }
+    Ok(columns)
+}
+
+// 時刻を変換する
+// 整数は桁数で単位を判断する(11桁まで秒、14桁までミリ秒、17桁までマイクロ秒、それ以上はナノ秒)
+fn parse_unixtime(value: &str) -> Result<UnixTime, String> {
+    if let Ok(number) = value.parse::<Decimal>() {
+        if number.get_scale() != 0 {
+            return Err(format!("時刻が整数ではありません: {}", value));
+        }
+        return integer_to_unixtime(number.get_mantissa());
+    }
+
+    let datetime = chrono::DateTime::parse_from_rfc3339(value)
+        .map(|datetime| datetime.timestamp())
+        .or_else(|_| {
+            chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+                .or_else(|_| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+                .map(|datetime| {
+                    use chrono::TimeZone;
+                    chrono::Utc.from_utc_datetime(&datetime).timestamp()
+                })
+        })
+        .map_err(|_| format!("時刻に変換できません: {}", value))?;
+    Ok(UnixTime::new(datetime))
+}
+
+// 整数の時刻を秒に変換する(秒未満の端数がある場合はエラー)
+fn integer_to_unixtime(value: i64) -> Result<UnixTime, String> {
+    let unit = match value.abs() {
+        v if v < 100_000_000_000 => 1,
+        v if v < 100_000_000_000_000 => 1_000,
+        v if v < 100_000_000_000_000_000 => 1_000_000,
+        _ => 1_000_000_000,
+    };
+    if value % unit != 0 {
+        return Err(format!("時刻に秒未満の端数があります: {}", value));
+    }
+    Ok(UnixTime::new(value / unit))
+}
+
+// 価格・出来高を変換する
+fn parse_decimal(name: &str, value: &str) -> Result<Decimal, String> {
+    value
+        .parse::<Decimal>()
+        .map_err(|err| format!("{}: {}", name, err))
+}
+
+// Parquetの値を時刻に変換する
+#[cfg(feature = "parquet")]
+fn field_to_unixtime(field: &parquet::record::Field) -> Result<UnixTime, String> {
+    use parquet::record::Field;
+    match field {
+        Field::Int(value) => integer_to_unixtime(*value as i64),
+        Field::Long(value) => integer_to_unixtime(*value),
+        Field::TimestampMillis(value) if value % 1_000 == 0 => Ok(UnixTime::new(value / 1_000)),
+        Field::TimestampMicros(value) if value % 1_000_000 == 0 => {
+            Ok(UnixTime::new(value / 1_000_000))
+        }
+        Field::Double(value) => parse_unixtime(&value.to_string()),
+        Field::Str(value) => parse_unixtime(value),
+        _ => Err(format!("時刻に変換できません: {}", field)),
+    }
+}
+
+// Parquetの値を価格・出来高に変換する
+#[cfg(feature = "parquet")]
+fn field_to_decimal(name: &str, field: &parquet::record::Field) -> Result<Decimal, String> {
+    use parquet::record::Field;
+    match field {
+        Field::Int(value) => Ok(Decimal::new(*value as i64, 0)),
+        Field::Long(value) => Ok(Decimal::new(*value, 0)),
+        Field::Decimal(value) => {
+            // ビッグエンディアンの2の補数からi64に変換する
+            let data = value.data();
+            if data.is_empty() || 8 < data.len() {
+                return Err(format!("{}: 扱えないDECIMALです: {}", name, field));
+            }
+            // 桁数の範囲外の値はDisplayできないので桁数のみ表示する
+            if value.scale() < 0 || crate::MAX_DECIMAL_SCALE < value.scale() as u32 {
+                return Err(format!(
+                    "{}: 小数点以下{}桁を超えるDECIMALは扱えません: 小数点以下{}桁",
+                    name,
+                    crate::MAX_DECIMAL_SCALE,
+                    value.scale()
+                ));
+            }
+            let mut bytes = if data[0] & 0x80 == 0 {
+                [0; 8]
+            } else {
+                [0xff; 8]
+            };
+            bytes[8 - data.len()..].copy_from_slice(data);
+            Ok(Decimal::new(
+                i64::from_be_bytes(bytes),
+                value.scale() as u32,
+            ))
+        }
+        Field::Float(value) => parse_decimal(name, &value.to_string()),
+        Field::Double(value) => parse_decimal(name, &value.to_string()),
+        Field::Str(value) => parse_decimal(name, value),
+        _ => Err(format!("{}: 数値に変換できません: {}", name, field)),
+    }
+}
+
+// CSVの読み書きのエラーを変換する
+#[cfg(feature = "csv")]
+fn csv_error(err: csv::Error) -> AtbDbError {
+    let msg = err.to_string();
+    match err.into_kind() {
+        csv::ErrorKind::Io(err) => AtbDbError::Io(err),
+        _ => AtbDbError::Validation(format!("CSVを読み込めません: {}", msg)),
+    }
+}
+
+// Parquetの読み書きのエラーを変換する
+#[cfg(feature = "parquet")]
+fn parquet_error(err: parquet::errors::ParquetError) -> AtbDbError {
+    AtbDbError::Validation(format!("Parquetを読み書きできません: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    fn bars() -> Vec<crate::OhlcvBar> {
+        use crate::{Decimal, OhlcvBar, UnixTime};
+        (1..=3)
+            .map(|i| {
+                OhlcvBar::new(
+                    UnixTime::new(1514764800 + i * 60),
+                    Decimal::new(1000000 + i, 0),
+                    Decimal::new(1000010 + i, 0),
+                    Decimal::new(999990 + i, 0),
+                    Decimal::new(1000005 + i, 0),
+                    Decimal::new(12345678 * i, 8),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn file_format() {
+        use super::*;
+        let path = std::path::Path::new("/tmp/btcjpy.CSV");
+        assert_eq!(OhlcvFileFormat::from_path(path), Some(OhlcvFileFormat::Csv));
+        let path = std::path::Path::new("btcjpy.parquet");
+        assert_eq!(
+            OhlcvFileFormat::from_path(path),
+            Some(OhlcvFileFormat::Parquet)
+        );
+        assert_eq!(
+            OhlcvFileFormat::from_path(std::path::Path::new("btcjpy")),
+            None
+        );
+        assert!("json".parse::<OhlcvFileFormat>().is_err());
+    }
+
+    #[test]
+    fn parse_unixtime() {
+        use super::*;
+        let expected = Ok(UnixTime::new(1514764800));
+        assert_eq!(super::parse_unixtime("1514764800"), expected);
+        assert_eq!(super::parse_unixtime("1514764800.0"), expected);
+        assert_eq!(super::parse_unixtime("1514764800000"), expected);
+        assert_eq!(super::parse_unixtime("1514764800000000"), expected);
+        assert_eq!(super::parse_unixtime("1514764800000000000"), expected);
+        assert_eq!(super::parse_unixtime("2018-01-01T09:00:00+09:00"), expected);
+        assert_eq!(super::parse_unixtime("2018-01-01 00:00:00"), expected);
+        assert!(super::parse_unixtime("1514764800500").is_err());
+        assert!(super::parse_unixtime("2018/01/01").is_err());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn csv_roundtrip() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars())
+            .unwrap();
+
+        let mut buf = vec![];
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60);
+        assert_eq!(atbdb.export_ohlcv_csv(&query, &mut buf).unwrap(), 3);
+        let csv = String::from_utf8(buf.clone()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "unixtime,open,high,low,close,volume");
+        assert_eq!(
+            lines[1],
+            "1514764860,1000001,1000011,999991,1000006,0.12345678"
+        );
+
+        // 書き出したファイルを別のデータベースに読み込む
+        let other = AtbDB::open_in_memory().unwrap();
+        let summary = other
+            .import_ohlcv_csv("bitflyer", "btcjpy", 60, buf.as_slice())
+            .unwrap();
+        assert_eq!(summary.get_inserted(), 3);
+        assert_eq!(other.get_ohlcv_list(&query).unwrap().into_list(), bars());
+
+        // もう一度読み込んでも変わらない
+        let summary = other
+            .import_ohlcv_csv("bitflyer", "btcjpy", 60, buf.as_slice())
+            .unwrap();
+        assert_eq!(summary.get_unchanged(), 3);
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn import_vendor_csv() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60);
+
+        // 列の順番・列名の大文字小文字・時刻の形式は問わず、余分な列は無視する
+        let csv = "Timestamp,Volume,Open,High,Low,Close,Trades\n\
+                   1514764920000, 0.5,100,110,90,105,12\n\
+                   2018-01-01T00:01:00Z,1,100,101,99,100,3\n";
+        let summary = atbdb
+            .import_ohlcv_csv("bitflyer", "btcjpy", 60, csv.as_bytes())
+            .unwrap();
+        assert_eq!(summary.get_inserted(), 2);
+        let list = atbdb.get_ohlcv_list(&query).unwrap().into_list();
+        assert_eq!(list[0].get_unixtime(), UnixTime::new(1514764860));
+        assert_eq!(list[1].get_volume(), "0.5".parse::<Decimal>().unwrap());
+
+        // 不正な行があれば何も保存しない
+        let invalid =
+            |csv: &str| match atbdb.import_ohlcv_csv("bitflyer", "btcjpy", 60, csv.as_bytes()) {
+                Err(AtbDbError::Validation(msg)) => msg,
+                result => panic!("{:?}", result.map(|_| ())),
+            };
+        let msg = invalid("unixtime,open,high,low,close,volume\n1514764980,1,1,1,1,1\n1514765040,100,90,110,100,1\n");
+        assert!(msg.starts_with("3行目"), "{}", msg);
+        let msg = invalid("unixtime,open,high,low,close,volume\n1514764980,1.5,2,1,1,1\n");
+        assert!(msg.contains("小数点以下0桁"), "{}", msg);
+        let msg = invalid(
+            "unixtime,open,high,low,close,volume\n1514764980,1,1,1,1,1\n1514764980,2,2,2,2,1\n",
+        );
+        assert!(msg.contains("重複"), "{}", msg);
+        let msg = invalid("unixtime,open,high,low,close\n");
+        assert!(msg.contains("volume"), "{}", msg);
+        let msg = invalid("unixtime,open,high,low,close,volume\nabc,1,1,1,1,1\n");
+        assert!(msg.contains("時刻"), "{}", msg);
+        let msg = invalid(
+            "unixtime,open,high,low,close,volume\n1514764980,1,1,1,1,1\n1514765010,1,1,1,1,1\n",
+        );
+        assert!(msg.starts_with("3行目"), "{}", msg);
+        assert!(msg.contains("区切り"), "{}", msg);
+        assert_eq!(atbdb.get_ohlcv_list(&query).unwrap().get_list_len(), 2);
+
+        // 登録されていない足の期間には保存しない
+        assert!(matches!(
+            atbdb.import_ohlcv_csv("bitflyer", "btcjpy", 7, csv.as_bytes()),
+            Err(AtbDbError::Validation(_))
+        ));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_roundtrip() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars())
+            .unwrap();
+
+        let mut buf = vec![];
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60).from_time(1514764920);
+        assert_eq!(atbdb.export_ohlcv_parquet(&query, &mut buf).unwrap(), 2);
+
+        let other = AtbDB::open_in_memory().unwrap();
+        let summary = other
+            .import_ohlcv_parquet("bitflyer", "btcjpy", 60, bytes::Bytes::from(buf))
+            .unwrap();
+        assert_eq!(summary.get_inserted(), 2);
+        assert_eq!(
+            other.get_ohlcv_list(&query).unwrap().into_list(),
+            bars()[1..].to_vec()
+        );
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_decimal() {
+        use super::*;
+        use parquet::record::Field;
+        let field = |value: i64, scale: i32| {
+            Field::Decimal(parquet::data_type::Decimal::from_i64(value, 38, scale))
+        };
+        assert_eq!(
+            field_to_decimal("open", &field(12345, 2)),
+            Ok(Decimal::new(12345, 2))
+        );
+        // 扱える桁数を超えるDECIMALはエラー
+        assert!(field_to_decimal("open", &field(12345, 19)).is_err());
+    }
+
+    #[cfg(all(feature = "csv", feature = "parquet"))]
+    #[test]
+    fn export_and_import_file() {
+        use super::*;
+        let atbdb = AtbDB::open_temp_file().unwrap();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars())
+            .unwrap();
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60);
+        let dir = atbdb.get_temp_file().unwrap().with_extension("export");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for format in &[OhlcvFileFormat::Csv, OhlcvFileFormat::Parquet] {
+            let path = dir.join(format!("btcjpy.{}", format.as_str()));
+            assert_eq!(atbdb.export_ohlcv_file(&query, *format, &path).unwrap(), 3);
+
+            let other = AtbDB::open_in_memory().unwrap();
+            let summary = other
+                .import_ohlcv_file("bitflyer", "btcjpy", 60, *format, &path)
+                .unwrap();
+            assert_eq!(summary.get_inserted(), 3);
+        }
+
+        // 存在しない市場はファイルを作成しない
+        let path = dir.join("unknown.csv");
+        let query = OhlcvQuery::new("unknown", "btcjpy", 60);
+        assert!(atbdb
+            .export_ohlcv_file(&query, OhlcvFileFormat::Csv, &path)
+            .is_err());
+        assert!(!path.exists());
+        assert!(matches!(
+            atbdb.import_ohlcv_file("bitflyer", "btcjpy", 60, OhlcvFileFormat::Csv, &path),
+            Err(AtbDbError::Io(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
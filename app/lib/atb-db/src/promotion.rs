@@ -0,0 +1,798 @@
+use crate::bot::{select_active_bot, select_bot, update_operate_type};
+use crate::bot_history::Actor;
+use crate::{AtbDB, AtbDbError, AtbDbResult, Decimal, OperateType};
+
+// 1日の秒数
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// 運用段階を上げるための条件
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct PromotionGate {
+    // 最小の取引回数
+    min_trades: i64,
+    // 最小の評価期間(日数)
+    min_days: i64,
+    // 許容する最大ドローダウン(比率、0.2 = 20%)
+    max_drawdown: Decimal,
+}
+
+impl PromotionGate {
+    pub fn new(min_trades: i64, min_days: i64, max_drawdown: Decimal) -> PromotionGate {
+        PromotionGate {
+            min_trades,
+            min_days,
+            max_drawdown,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn get_min_trades(&self) -> i64 {
+        self.min_trades
+    }
+
+    #[allow(dead_code)]
+    pub fn get_min_days(&self) -> i64 {
+        self.min_days
+    }
+
+    #[allow(dead_code)]
+    pub fn get_max_drawdown(&self) -> Decimal {
+        self.max_drawdown
+    }
+
+    // 評価結果が満たしていない条件の一覧を返す(空なら昇格できる)
+    pub fn check(&self, evaluation: &BotEvaluation) -> Vec<String> {
+        let mut unmet = vec![];
+        if evaluation.trade_count < self.min_trades {
+            unmet.push(format!(
+                "取引回数が{}回未満です({}回)",
+                self.min_trades, evaluation.trade_count
+            ));
+        }
+        let days = evaluation.get_duration() / SECONDS_PER_DAY;
+        if days < self.min_days {
+            unmet.push(format!("評価期間が{}日未満です({}日)", self.min_days, days));
+        }
+        if self.max_drawdown < evaluation.max_drawdown {
+            unmet.push(format!(
+                "最大ドローダウンが{}を超えています({})",
+                self.max_drawdown, evaluation.max_drawdown
+            ));
+        }
+        unmet
+    }
+}
+
+// 運用段階ごとの昇格条件
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct PromotionRule {
+    // バックテストからフォワードテストに上げる条件
+    forwardtest: PromotionGate,
+    // フォワードテストから実運用に上げる条件
+    product: PromotionGate,
+}
+
+impl Default for PromotionRule {
+    fn default() -> PromotionRule {
+        PromotionRule {
+            forwardtest: PromotionGate::new(100, 0, Decimal::new(3, 1)),
+            product: PromotionGate::new(30, 14, Decimal::new(2, 1)),
+        }
+    }
+}
+
+impl PromotionRule {
+    pub fn forwardtest(mut self, gate: PromotionGate) -> PromotionRule {
+        self.forwardtest = gate;
+        self
+    }
+
+    pub fn product(mut self, gate: PromotionGate) -> PromotionRule {
+        self.product = gate;
+        self
+    }
+
+    // 設定ファイルのpromotionから昇格条件を作成する(指定のない項目はデフォルト値)
+    pub fn from_conf(atbconf: &read_atb_config::AtbConf) -> AtbDbResult<PromotionRule> {
+        let default = PromotionRule::default();
+        Ok(PromotionRule {
+            forwardtest: gate_from_conf(atbconf, OperateType::Forwardtest, default.forwardtest)?,
+            product: gate_from_conf(atbconf, OperateType::Product, default.product)?,
+        })
+    }
+
+    // 指定した運用段階に上げる条件(バックテストに上げる条件はない)
+    pub fn get_gate(&self, operate_type: OperateType) -> Option<&PromotionGate> {
+        match operate_type {
+            OperateType::Backtest => None,
+            OperateType::Forwardtest => Some(&self.forwardtest),
+            OperateType::Product => Some(&self.product),
+        }
+    }
+}
+
+fn gate_from_conf(
+    atbconf: &read_atb_config::AtbConf,
+    operate_type: OperateType,
+    default: PromotionGate,
+) -> AtbDbResult<PromotionGate> {
+    let key = operate_type.as_str();
+    let max_drawdown = match atbconf.get_promotion_max_drawdown(key) {
+        Some(value) => value.parse().map_err(|err| {
+            AtbDbError::Config(format!("promotion.{}.max_drawdown: {}", key, err))
+        })?,
+        None => default.max_drawdown,
+    };
+    Ok(PromotionGate {
+        min_trades: atbconf
+            .get_promotion_min_trades(key)
+            .unwrap_or(default.min_trades),
+        min_days: atbconf
+            .get_promotion_min_days(key)
+            .unwrap_or(default.min_days),
+        max_drawdown,
+    })
+}
+
+// 登録するbotの評価結果
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BotEvaluationCreate {
+    operate_type: OperateType,
+    trade_count: i64,
+    max_drawdown: Decimal,
+    started: i64,
+    ended: i64,
+}
+
+impl BotEvaluationCreate {
+    pub fn new(
+        operate_type: OperateType,
+        trade_count: i64,
+        max_drawdown: Decimal,
+        started: i64,
+        ended: i64,
+    ) -> BotEvaluationCreate {
+        BotEvaluationCreate {
+            operate_type,
+            trade_count,
+            max_drawdown,
+            started,
+            ended,
+        }
+    }
+
+    // 入力値を確認する
+    pub fn validate(&self) -> AtbDbResult<()> {
+        if self.trade_count < 0 {
+            return Err(AtbDbError::Validation(format!(
+                "取引回数が負の値です: {}",
+                self.trade_count
+            )));
+        }
+        if self.max_drawdown < Decimal::zero(0) {
+            return Err(AtbDbError::Validation(format!(
+                "最大ドローダウンが負の値です: {}",
+                self.max_drawdown
+            )));
+        }
+        if self.ended < self.started {
+            return Err(AtbDbError::Validation(format!(
+                "評価期間の終了日時({})が開始日時({})より前です",
+                self.ended, self.started
+            )));
+        }
+        Ok(())
+    }
+}
+
+// bot_evaluationテーブルのレコード
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct BotEvaluation {
+    id: i64,
+    bot_id: i64,
+    operate_type: OperateType,
+    trade_count: i64,
+    max_drawdown: Decimal,
+    started: i64,
+    ended: i64,
+    registered: i64,
+}
+
+impl BotEvaluation {
+    #[allow(dead_code)]
+    pub fn get_id(&self) -> i64 {
+        self.id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_bot_id(&self) -> i64 {
+        self.bot_id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_operate_type(&self) -> OperateType {
+        self.operate_type
+    }
+
+    #[allow(dead_code)]
+    pub fn get_trade_count(&self) -> i64 {
+        self.trade_count
+    }
+
+    #[allow(dead_code)]
+    pub fn get_max_drawdown(&self) -> Decimal {
+        self.max_drawdown
+    }
+
+    #[allow(dead_code)]
+    pub fn get_started(&self) -> i64 {
+        self.started
+    }
+
+    #[allow(dead_code)]
+    pub fn get_ended(&self) -> i64 {
+        self.ended
+    }
+
+    // 評価期間(秒)
+    #[allow(dead_code)]
+    pub fn get_duration(&self) -> i64 {
+        self.ended - self.started
+    }
+
+    #[allow(dead_code)]
+    pub fn get_registered(&self) -> i64 {
+        self.registered
+    }
+
+    // 取得したレコードをBotEvaluationに変換する
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<BotEvaluation> {
+        Ok(BotEvaluation {
+            id: row.get(0)?,
+            bot_id: row.get(1)?,
+            operate_type: parse_operate_type(row, 2)?,
+            trade_count: row.get(3)?,
+            max_drawdown: row.get::<_, String>(4)?.parse().map_err(|_| {
+                rusqlite::Error::InvalidColumnType(
+                    4,
+                    "max_drawdown".to_string(),
+                    rusqlite::types::Type::Text,
+                )
+            })?,
+            started: row.get(5)?,
+            ended: row.get(6)?,
+            registered: row.get(7)?,
+        })
+    }
+}
+
+// bot_promotionテーブルのレコード
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
+pub struct BotPromotion {
+    id: i64,
+    bot_id: i64,
+    from_type: OperateType,
+    to_type: OperateType,
+    // 昇格の根拠にした評価結果ID(降格はNone)
+    evaluation_id: Option<i64>,
+    actor: String,
+    registered: i64,
+}
+
+impl BotPromotion {
+    #[allow(dead_code)]
+    pub fn get_bot_id(&self) -> i64 {
+        self.bot_id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_from_type(&self) -> OperateType {
+        self.from_type
+    }
+
+    #[allow(dead_code)]
+    pub fn get_to_type(&self) -> OperateType {
+        self.to_type
+    }
+
+    #[allow(dead_code)]
+    pub fn get_evaluation_id(&self) -> Option<i64> {
+        self.evaluation_id
+    }
+
+    #[allow(dead_code)]
+    pub fn get_actor(&self) -> &str {
+        &self.actor
+    }
+
+    #[allow(dead_code)]
+    pub fn get_registered(&self) -> i64 {
+        self.registered
+    }
+
+    // 昇格ならtrue
+    #[allow(dead_code)]
+    pub fn is_promotion(&self) -> bool {
+        self.from_type < self.to_type
+    }
+
+    // 取得したレコードをBotPromotionに変換する
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<BotPromotion> {
+        Ok(BotPromotion {
+            id: row.get(0)?,
+            bot_id: row.get(1)?,
+            from_type: parse_operate_type(row, 2)?,
+            to_type: parse_operate_type(row, 3)?,
+            evaluation_id: row.get(4)?,
+            actor: row.get(5)?,
+            registered: row.get(6)?,
+        })
+    }
+}
+
+fn parse_operate_type(row: &rusqlite::Row, idx: usize) -> rusqlite::Result<OperateType> {
+    row.get::<_, String>(idx)?.parse().map_err(|_| {
+        rusqlite::Error::InvalidColumnType(
+            idx,
+            "operate_type".to_string(),
+            rusqlite::types::Type::Text,
+        )
+    })
+}
+
+const SQL_SELECT_BOT_EVALUATION: &str = "select id, bot_id, operate_type, trade_count, max_drawdown, started, ended, registered from bot_evaluation";
+
+const SQL_SELECT_BOT_PROMOTION: &str =
+    "select id, bot_id, from_type, to_type, evaluation_id, actor, registered from bot_promotion";
+
+impl AtbDB {
+    // botの評価結果(バックテスト・フォワードテストの成績)を登録する
+    pub fn insert_bot_evaluation(
+        &self,
+        bot_id: i64,
+        evaluation: &BotEvaluationCreate,
+    ) -> AtbDbResult<BotEvaluation> {
+        evaluation.validate()?;
+        let mut conn = self.get_conn()?;
+
+        let tx = conn.transaction()?;
+        select_bot(&tx, bot_id)?;
+        tx.execute(
+            "insert into bot_evaluation (bot_id, operate_type, trade_count, max_drawdown, started, ended) values (?1, ?2, ?3, ?4, ?5, ?6)",
+            rusqlite::params![
+                bot_id,
+                evaluation.operate_type.as_str(),
+                evaluation.trade_count,
+                evaluation.max_drawdown.to_string(),
+                evaluation.started,
+                evaluation.ended
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        let evaluation = tx.query_row(
+            &format!("{} where id = ?1", SQL_SELECT_BOT_EVALUATION),
+            rusqlite::params![id],
+            BotEvaluation::from_row,
+        )?;
+        tx.commit()?;
+        Ok(evaluation)
+    }
+
+    // botの評価結果を新しい順に取得する
+    pub fn get_bot_evaluation_list(&self, bot_id: i64) -> AtbDbResult<Vec<BotEvaluation>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "{} where bot_id = ?1 order by id desc",
+            SQL_SELECT_BOT_EVALUATION
+        ))?;
+        let rows = stmt.query_map(rusqlite::params![bot_id], BotEvaluation::from_row)?;
+        Ok(rows.collect::<Result<Vec<BotEvaluation>, _>>()?)
+    }
+
+    // botの運用段階の変更履歴を新しい順に取得する
+    pub fn get_bot_promotion_list(&self, bot_id: i64) -> AtbDbResult<Vec<BotPromotion>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(&format!(
+            "{} where bot_id = ?1 order by id desc",
+            SQL_SELECT_BOT_PROMOTION
+        ))?;
+        let rows = stmt.query_map(rusqlite::params![bot_id], BotPromotion::from_row)?;
+        Ok(rows.collect::<Result<Vec<BotPromotion>, _>>()?)
+    }
+
+    // botの運用段階を1つ上げる(バックテスト→フォワードテスト→実運用)
+    // 現在の運用段階の最新の評価結果が昇格条件を満たす場合のみ上げられる
+    // 評価結果は最後に運用段階を変更した後に登録したもので、他の昇格の根拠にしていないものに限る
+    // フォワードテストの評価結果は、評価期間もフォワードテストに上げた後から始まっている必要がある
+    // 日時は秒単位のため、運用段階の変更と同じ秒のものは前後が分からないので認めない
+    pub fn promote_bot(
+        &self,
+        id: i64,
+        rule: &PromotionRule,
+        actor: &Actor,
+    ) -> AtbDbResult<BotPromotion> {
+        let mut conn = self.get_conn()?;
+
+        let tx = conn.transaction()?;
+        let bot = select_active_bot(&tx, id)?;
+        let from_type = bot.get_operate_type();
+        let to_type = from_type.next().ok_or_else(|| {
+            AtbDbError::Validation(format!(
+                "bot id: {} は{}のため、これ以上昇格できません",
+                id, from_type
+            ))
+        })?;
+        let gate = rule.get_gate(to_type).ok_or_else(|| {
+            AtbDbError::Validation(format!("{}への昇格条件がありません", to_type))
+        })?;
+
+        // 現在の運用段階の最新の評価結果を根拠にする
+        let evaluation = select_latest_evaluation(&tx, id, from_type)?.ok_or_else(|| {
+            AtbDbError::Validation(format!(
+                "bot id: {} の{}の評価結果が登録されていません",
+                id, from_type
+            ))
+        })?;
+        if select_promotion_by_evaluation(&tx, id, evaluation.id)?.is_some() {
+            return Err(AtbDbError::Validation(format!(
+                "bot id: {} の評価結果 id: {} は以前の昇格の根拠にしています",
+                id, evaluation.id
+            )));
+        }
+        if let Some(changed) = select_latest_promotion(&tx, id)? {
+            if evaluation.registered <= changed.registered {
+                return Err(AtbDbError::Validation(format!(
+                    "bot id: {} の評価結果 id: {} は運用段階の変更({})以前に登録されています",
+                    id, evaluation.id, changed.registered
+                )));
+            }
+            if from_type == OperateType::Forwardtest && evaluation.started <= changed.registered {
+                return Err(AtbDbError::Validation(format!(
+                    "bot id: {} の評価期間の開始日時({})が{}に上げた日時({})以前です",
+                    id, evaluation.started, from_type, changed.registered
+                )));
+            }
+        }
+        let unmet = gate.check(&evaluation);
+        if !unmet.is_empty() {
+            return Err(AtbDbError::Validation(format!(
+                "bot id: {} は{}への昇格条件を満たしていません: {}",
+                id,
+                to_type,
+                unmet.join(", ")
+            )));
+        }
+
+        update_operate_type(&tx, &bot, to_type, actor)?;
+        let promotion = insert_promotion(&tx, id, from_type, to_type, Some(evaluation.id), actor)?;
+        tx.commit()?;
+        Ok(promotion)
+    }
+
+    // botの運用段階を下げる(条件なしでいつでも下げられる)
+    pub fn demote_bot(
+        &self,
+        id: i64,
+        to_type: OperateType,
+        actor: &Actor,
+    ) -> AtbDbResult<BotPromotion> {
+        let mut conn = self.get_conn()?;
+
+        let tx = conn.transaction()?;
+        let bot = select_active_bot(&tx, id)?;
+        let from_type = bot.get_operate_type();
+        if from_type <= to_type {
+            return Err(AtbDbError::Validation(format!(
+                "bot id: {} は{}のため、{}には降格できません",
+                id, from_type, to_type
+            )));
+        }
+
+        update_operate_type(&tx, &bot, to_type, actor)?;
+        let promotion = insert_promotion(&tx, id, from_type, to_type, None, actor)?;
+        tx.commit()?;
+        Ok(promotion)
+    }
+}
+
+// 指定した運用段階の最新の評価結果を取得する
+fn select_latest_evaluation(
+    conn: &rusqlite::Connection,
+    bot_id: i64,
+    operate_type: OperateType,
+) -> AtbDbResult<Option<BotEvaluation>> {
+    use rusqlite::OptionalExtension;
+    let evaluation = conn
+        .query_row(
+            &format!(
+                "{} where bot_id = ?1 and operate_type = ?2 order by id desc limit 1",
+                SQL_SELECT_BOT_EVALUATION
+            ),
+            rusqlite::params![bot_id, operate_type.as_str()],
+            BotEvaluation::from_row,
+        )
+        .optional()?;
+    Ok(evaluation)
+}
+
+// 最後の運用段階の変更を取得する
+fn select_latest_promotion(
+    conn: &rusqlite::Connection,
+    bot_id: i64,
+) -> AtbDbResult<Option<BotPromotion>> {
+    use rusqlite::OptionalExtension;
+    let promotion = conn
+        .query_row(
+            &format!(
+                "{} where bot_id = ?1 order by id desc limit 1",
+                SQL_SELECT_BOT_PROMOTION
+            ),
+            rusqlite::params![bot_id],
+            BotPromotion::from_row,
+        )
+        .optional()?;
+    Ok(promotion)
+}
+
+// 評価結果を根拠にした昇格を取得する
+fn select_promotion_by_evaluation(
+    conn: &rusqlite::Connection,
+    bot_id: i64,
+    evaluation_id: i64,
+) -> AtbDbResult<Option<BotPromotion>> {
+    use rusqlite::OptionalExtension;
+    let promotion = conn
+        .query_row(
+            &format!(
+                "{} where bot_id = ?1 and evaluation_id = ?2 limit 1",
+                SQL_SELECT_BOT_PROMOTION
+            ),
+            rusqlite::params![bot_id, evaluation_id],
+            BotPromotion::from_row,
+        )
+        .optional()?;
+    Ok(promotion)
+}
+
+// 運用段階の変更を記録する
+fn insert_promotion(
+    conn: &rusqlite::Connection,
+    bot_id: i64,
+    from_type: OperateType,
+    to_type: OperateType,
+    evaluation_id: Option<i64>,
+    actor: &Actor,
+) -> AtbDbResult<BotPromotion> {
+    conn.execute(
+        "insert into bot_promotion (bot_id, from_type, to_type, evaluation_id, actor) values (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            bot_id,
+            from_type.as_str(),
+            to_type.as_str(),
+            evaluation_id,
+            actor.as_str()
+        ],
+    )?;
+    let promotion = conn.query_row(
+        &format!("{} where id = ?1", SQL_SELECT_BOT_PROMOTION),
+        rusqlite::params![conn.last_insert_rowid()],
+        BotPromotion::from_row,
+    )?;
+    Ok(promotion)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn promotion_rule_from_conf() {
+        use super::*;
+        let atbconf = read_atb_config::AtbConf::load_from_str(
+            "promotion:\n  product:\n    min_trades: 10\n    max_drawdown: 0.15\n",
+        )
+        .unwrap();
+        let rule = PromotionRule::from_conf(&atbconf).unwrap();
+        let default = PromotionRule::default();
+        assert_eq!(
+            rule.get_gate(OperateType::Forwardtest),
+            default.get_gate(OperateType::Forwardtest)
+        );
+        let gate = rule.get_gate(OperateType::Product).unwrap();
+        assert_eq!(gate.get_min_trades(), 10);
+        assert_eq!(gate.get_min_days(), 14);
+        assert_eq!(gate.get_max_drawdown(), Decimal::new(15, 2));
+        assert!(rule.get_gate(OperateType::Backtest).is_none());
+
+        let atbconf = read_atb_config::AtbConf::load_from_str(
+            "promotion:\n  product:\n    max_drawdown: abc\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            PromotionRule::from_conf(&atbconf),
+            Err(AtbDbError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn promote_and_demote_bot() {
+        use super::*;
+        use crate::{BotAction, BotCreate};
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let actor = Actor::cli("test");
+        atbdb
+            .insert_bot(&BotCreate::new("test", "test bot"), &actor)
+            .unwrap();
+        let rule = PromotionRule::default()
+            .forwardtest(PromotionGate::new(100, 0, Decimal::new(3, 1)))
+            .product(PromotionGate::new(30, 14, Decimal::new(2, 1)));
+        let day = SECONDS_PER_DAY;
+
+        // 評価結果がなければ昇格できない
+        assert!(matches!(
+            atbdb.promote_bot(1, &rule, &actor),
+            Err(AtbDbError::Validation(_))
+        ));
+
+        // 最新の評価結果が条件を満たさなければ昇格できない
+        let evaluation =
+            BotEvaluationCreate::new(OperateType::Backtest, 99, Decimal::new(1, 1), 0, 365 * day);
+        atbdb.insert_bot_evaluation(1, &evaluation).unwrap();
+        match atbdb.promote_bot(1, &rule, &actor) {
+            Err(AtbDbError::Validation(msg)) => assert!(msg.contains("取引回数")),
+            other => panic!("{:?}", other),
+        }
+
+        let evaluation = BotEvaluationCreate::new(
+            OperateType::Backtest,
+            150,
+            Decimal::new(25, 2),
+            0,
+            365 * day,
+        );
+        let evaluation = atbdb.insert_bot_evaluation(1, &evaluation).unwrap();
+        let promotion = atbdb.promote_bot(1, &rule, &actor).unwrap();
+        assert!(promotion.is_promotion());
+        assert_eq!(promotion.get_to_type(), OperateType::Forwardtest);
+        assert_eq!(promotion.get_evaluation_id(), Some(evaluation.get_id()));
+        assert_eq!(
+            atbdb.get_bot(1).unwrap().get_operate_type(),
+            OperateType::Forwardtest
+        );
+
+        // バックテストの評価結果ではフォワードテストから昇格できない
+        match atbdb.promote_bot(1, &rule, &actor) {
+            Err(AtbDbError::Validation(msg)) => assert!(msg.contains("forwardtest")),
+            other => panic!("{:?}", other),
+        }
+
+        // フォワードテストに上げる前から始まった評価期間は認めない
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let started = promotion.get_registered();
+        assert!(started <= now);
+
+        // 運用段階の変更と同じ秒に登録した評価結果は認めない
+        let evaluation = BotEvaluationCreate::new(
+            OperateType::Forwardtest,
+            30,
+            Decimal::new(2, 1),
+            started + 1,
+            started + 15 * day,
+        );
+        let evaluation = atbdb.insert_bot_evaluation(1, &evaluation).unwrap();
+        let conn = atbdb.get_conn().unwrap();
+        conn.execute(
+            "update bot_evaluation set registered = ?1 where id = ?2",
+            rusqlite::params![started, evaluation.get_id()],
+        )
+        .unwrap();
+        match atbdb.promote_bot(1, &rule, &actor) {
+            Err(AtbDbError::Validation(msg)) => assert!(msg.contains("運用段階の変更")),
+            other => panic!("{:?}", other),
+        }
+
+        // 以降は運用段階の変更から1秒経過したものとする
+        conn.execute(
+            "update bot_promotion set registered = ?1 where id = ?2",
+            rusqlite::params![started - 1, promotion.id],
+        )
+        .unwrap();
+        drop(conn);
+        let started = started - 1;
+        let evaluation = BotEvaluationCreate::new(
+            OperateType::Forwardtest,
+            30,
+            Decimal::new(2, 1),
+            started,
+            started + 14 * day,
+        );
+        atbdb.insert_bot_evaluation(1, &evaluation).unwrap();
+        match atbdb.promote_bot(1, &rule, &actor) {
+            Err(AtbDbError::Validation(msg)) => assert!(msg.contains("開始日時")),
+            other => panic!("{:?}", other),
+        }
+
+        // 期間・ドローダウンを満たさない
+        let evaluation = BotEvaluationCreate::new(
+            OperateType::Forwardtest,
+            30,
+            Decimal::new(21, 2),
+            started + 1,
+            started + 1 + 13 * day,
+        );
+        atbdb.insert_bot_evaluation(1, &evaluation).unwrap();
+        match atbdb.promote_bot(1, &rule, &actor) {
+            Err(AtbDbError::Validation(msg)) => {
+                assert!(msg.contains("評価期間") && msg.contains("最大ドローダウン"))
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let evaluation = BotEvaluationCreate::new(
+            OperateType::Forwardtest,
+            30,
+            Decimal::new(2, 1),
+            started + 1,
+            started + 1 + 14 * day,
+        );
+        atbdb.insert_bot_evaluation(1, &evaluation).unwrap();
+        atbdb.promote_bot(1, &rule, &actor).unwrap();
+        assert!(matches!(
+            atbdb.promote_bot(1, &rule, &actor),
+            Err(AtbDbError::Validation(_))
+        ));
+
+        // 降格は条件なしでできるが、同じ段階・上の段階は指定できない
+        assert!(matches!(
+            atbdb.demote_bot(1, OperateType::Product, &actor),
+            Err(AtbDbError::Validation(_))
+        ));
+        let promotion = atbdb.demote_bot(1, OperateType::Backtest, &actor).unwrap();
+        assert!(!promotion.is_promotion());
+        assert_eq!(promotion.get_evaluation_id(), None);
+
+        // 降格後は以前の昇格の根拠にした評価結果では上げられない
+        match atbdb.promote_bot(1, &rule, &actor) {
+            Err(AtbDbError::Validation(msg)) => assert!(msg.contains("以前の昇格")),
+            other => panic!("{:?}", other),
+        }
+
+        // 変更は運用段階の変更履歴とbotの変更履歴に記録される
+        let promotions = atbdb.get_bot_promotion_list(1).unwrap();
+        let stages: Vec<(OperateType, OperateType)> = promotions
+            .iter()
+            .map(|p| (p.get_from_type(), p.get_to_type()))
+            .collect();
+        assert_eq!(
+            stages,
+            vec![
+                (OperateType::Product, OperateType::Backtest),
+                (OperateType::Forwardtest, OperateType::Product),
+                (OperateType::Backtest, OperateType::Forwardtest),
+            ]
+        );
+        let history = atbdb.get_bot_history(1, Some(1)).unwrap();
+        assert_eq!(history[0].get_action(), BotAction::Update);
+        assert_eq!(
+            history[0].get_new_value().unwrap()["operate_type"],
+            "backtest"
+        );
+        assert_eq!(atbdb.get_bot_evaluation_list(1).unwrap().len(), 6);
+
+        // 存在しないbot・不正な評価結果は登録できない
+        let evaluation =
+            BotEvaluationCreate::new(OperateType::Backtest, 1, Decimal::new(1, 1), 0, 0);
+        assert!(matches!(
+            atbdb.insert_bot_evaluation(2, &evaluation),
+            Err(AtbDbError::NotFound)
+        ));
+        let evaluation =
+            BotEvaluationCreate::new(OperateType::Backtest, -1, Decimal::new(1, 1), 0, 0);
+        assert!(matches!(
+            atbdb.insert_bot_evaluation(1, &evaluation),
+            Err(AtbDbError::Validation(_))
+        ));
+    }
+}
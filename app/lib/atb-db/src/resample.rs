@@ -0,0 +1,427 @@
+use crate::{AtbDB, AtbDbError, AtbDbResult, OhlcvBar, OhlcvOrder, OhlcvQuery, UpsertSummary};
+
+// 1週間の秒数
+const WEEK: i64 = 604800;
+
+// UNIX時間0(木曜日)から月曜日0時までのずれ
+const WEEK_START_MONDAY: i64 = 259200;
+
+// 集約時にデータベースから1回に読み込むローソク足の件数
+const RESAMPLE_CHUNK: usize = 50000;
+
+// 集約後の足の区切りの基準時刻
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResampleAnchor {
+    // UTCの0時で区切る
+    Utc,
+    // 日本時間(UTC+9)の0時で区切る
+    Jst,
+}
+
+impl ResampleAnchor {
+    // UTCからのずれ(秒)
+    pub fn get_utc_offset(&self) -> i64 {
+        match self {
+            ResampleAnchor::Utc => 0,
+            ResampleAnchor::Jst => 32400,
+        }
+    }
+
+    // 指定した期間の足を区切る時刻のずれ(週足以上は月曜日0時で区切る)
+    pub fn get_period_offset(&self, period: i64) -> i64 {
+        let mut offset = self.get_utc_offset();
+        if period % WEEK == 0 {
+            offset += WEEK_START_MONDAY;
+        }
+        offset
+    }
+
+    // 指定した時刻が足の区切り(足の終了時刻)か
+    pub fn is_aligned(&self, unixtime: i64, period: i64) -> bool {
+        (unixtime + self.get_period_offset(period)).rem_euclid(period) == 0
+    }
+}
+
+// 集約中のローソク足
+struct Bucket {
+    close_time: i64,
+    first_time: i64,
+    last_time: i64,
+    // 集約した短い足の本数
+    count: i64,
+    bar: OhlcvBar,
+}
+
+// 短い足のローソク足を順に受け取り、長い足のローソク足に集約する
+//
+// unixtimeは足の終了時刻(cryptowatと同じ)として扱う。
+// 集約後の足は終了時刻が (unixtime + ずれ) % target_period == 0 になる時刻で区切り、
+// 短い足がすべて揃っていない(取得範囲の端で途切れた・途中が欠けた)足は出力しない。
+// 週足以上は月曜日0時で区切る。
+pub struct Resampler {
+    base_period: i64,
+    target_period: i64,
+    offset: i64,
+    current: Option<Bucket>,
+}
+
+impl Resampler {
+    pub fn new(
+        base_period: i64,
+        target_period: i64,
+        anchor: ResampleAnchor,
+    ) -> AtbDbResult<Resampler> {
+        if base_period <= 0 || target_period < base_period || target_period % base_period != 0 {
+            return Err(AtbDbError::Validation(format!(
+                "足の期間 {} は {} の倍数ではありません",
+                target_period, base_period
+            )));
+        }
+
+        let offset = anchor.get_period_offset(target_period);
+        if offset % base_period != 0 {
+            return Err(AtbDbError::Validation(format!(
+                "足の期間 {} では {:?} 基準の {} の足に集約できません",
+                base_period, anchor, target_period
+            )));
+        }
+
+        Ok(Resampler {
+            base_period,
+            target_period,
+            offset,
+            current: None,
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn get_target_period(&self) -> i64 {
+        self.target_period
+    }
+
+    // 指定時刻に終了する短い足が含まれる、集約後の足の終了時刻
+    pub fn bucket_close_time(&self, unixtime: i64) -> i64 {
+        let shifted = unixtime + self.offset;
+        let close = shifted
+            + (self.target_period - shifted.rem_euclid(self.target_period)) % self.target_period;
+        close - self.offset
+    }
+
+    // ローソク足を追加し、集約が完了した足があれば返す(古い順に追加すること)
+    pub fn push(&mut self, bar: &OhlcvBar) -> AtbDbResult<Option<OhlcvBar>> {
+        let unixtime = bar.get_unixtime().get_secs();
+        if let Some(current) = &self.current {
+            if unixtime <= current.last_time {
+                return Err(AtbDbError::Validation(format!(
+                    "unixtime:{} が古い順に並んでいません",
+                    unixtime
+                )));
+            }
+        }
+
+        let close_time = self.bucket_close_time(unixtime);
+        if let Some(current) = &mut self.current {
+            if current.close_time == close_time {
+                current.last_time = unixtime;
+                current.count += 1;
+                current.bar = OhlcvBar::new_unchecked(
+                    current.bar.get_unixtime(),
+                    current.bar.get_open(),
+                    current.bar.get_high().max(bar.get_high()),
+                    current.bar.get_low().min(bar.get_low()),
+                    bar.get_close(),
+                    current.bar.get_volume() + bar.get_volume(),
+                );
+                return Ok(None);
+            }
+        }
+
+        let done = self.current.take();
+        self.current = Some(Bucket {
+            close_time,
+            first_time: unixtime,
+            last_time: unixtime,
+            count: 1,
+            bar: OhlcvBar::new_unchecked(
+                close_time.into(),
+                bar.get_open(),
+                bar.get_high(),
+                bar.get_low(),
+                bar.get_close(),
+                bar.get_volume(),
+            ),
+        });
+        Ok(done.and_then(|bucket| self.completed(bucket)))
+    }
+
+    // 集約中の足を返して終了する(途中までの足は返さない)
+    pub fn finish(mut self) -> Option<OhlcvBar> {
+        self.current
+            .take()
+            .and_then(|bucket| self.completed(bucket))
+    }
+
+    // 短い足がすべて揃っている場合のみ集約後の足を返す
+    // (古い順で重複がないので、最初と最後の足と本数が揃っていれば途中も欠けていない)
+    fn completed(&self, bucket: Bucket) -> Option<OhlcvBar> {
+        let first_close = bucket.close_time - self.target_period + self.base_period;
+        if bucket.first_time == first_close
+            && bucket.last_time == bucket.close_time
+            && bucket.count == self.target_period / self.base_period
+        {
+            Some(bucket.bar)
+        } else {
+            None
+        }
+    }
+}
+
+// 古い順に並んだローソク足を長い足に集約する
+pub fn resample(
+    bars: &[OhlcvBar],
+    base_period: i64,
+    target_period: i64,
+    anchor: ResampleAnchor,
+) -> AtbDbResult<Vec<OhlcvBar>> {
+    let mut resampler = Resampler::new(base_period, target_period, anchor)?;
+    let mut resampled = vec![];
+    for bar in bars {
+        if let Some(bar) = resampler.push(bar)? {
+            resampled.push(bar);
+        }
+    }
+    resampled.extend(resampler.finish());
+    Ok(resampled)
+}
+
+impl AtbDB {
+    // 保存済みのローソク足(queryの足の期間)を集約して取得する
+    // queryの並び順・件数・カーソルは使用しない
+    pub fn get_resampled_ohlcv_list(
+        &self,
+        query: &OhlcvQuery,
+        target_period: i64,
+        anchor: ResampleAnchor,
+    ) -> AtbDbResult<Vec<OhlcvBar>> {
+        let mut resampled = vec![];
+        self.resample_ohlcv(query, target_period, anchor, |bars| {
+            resampled.extend_from_slice(bars);
+            Ok(())
+        })?;
+        Ok(resampled)
+    }
+
+    // 保存済みのローソク足(queryの足の期間)を集約し、target_periodの足として保存する
+    // queryの並び順・件数・カーソルは使用しない
+    pub fn store_resampled_ohlcv(
+        &self,
+        query: &OhlcvQuery,
+        target_period: i64,
+        anchor: ResampleAnchor,
+    ) -> AtbDbResult<UpsertSummary> {
+        let resampler = Resampler::new(query.period, target_period, anchor)?;
+
+        // 保存済みの足と区切りの基準時刻が異なる場合は保存しない
+        let conn = self.get_conn()?;
+        let misaligned = conn.query_row(
+            "select count(*) from ohlcv where exchange = ?1 and pair = ?2 and period = ?3 and (unixtime + ?4) % ?3 != 0",
+            rusqlite::params![query.exchange, query.pair, target_period, resampler.offset],
+            |row| row.get::<_, i64>(0),
+        )?;
+        drop(conn);
+        if 0 < misaligned {
+            return Err(AtbDbError::Validation(format!(
+                "保存済みの足の期間 {} のローソク足は {:?} 基準ではありません",
+                target_period, anchor
+            )));
+        }
+
+        let mut summary = UpsertSummary::default();
+        self.resample_ohlcv(query, target_period, anchor, |bars| {
+            let stored =
+                self.upsert_ohlcv_list(&query.exchange, &query.pair, target_period, bars)?;
+            summary.merge(&stored);
+            Ok(())
+        })?;
+        Ok(summary)
+    }
+
+    // 保存済みのローソク足を分割して読み込みながら集約し、集約後の足を順に渡す
+    fn resample_ohlcv<F>(
+        &self,
+        query: &OhlcvQuery,
+        target_period: i64,
+        anchor: ResampleAnchor,
+        mut f: F,
+    ) -> AtbDbResult<()>
+    where
+        F: FnMut(&[OhlcvBar]) -> AtbDbResult<()>,
+    {
+        let mut resampler = Resampler::new(query.period, target_period, anchor)?;
+
+        let query = OhlcvQuery {
+            order: OhlcvOrder::Asc,
+            ..query.clone()
+        };
+        self.for_each_ohlcv_chunk(&query, RESAMPLE_CHUNK, |chunk| {
+            let mut resampled = vec![];
+            for bar in chunk {
+                if let Some(bar) = resampler.push(bar)? {
+                    resampled.push(bar);
+                }
+            }
+            if resampled.is_empty() {
+                return Ok(());
+            }
+            f(&resampled)
+        })?;
+
+        if let Some(bar) = resampler.finish() {
+            f(&[bar])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn bars(period: i64, start: i64, prices: &[(i64, i64, i64, i64)]) -> Vec<super::OhlcvBar> {
+        use super::*;
+        use crate::{Decimal, UnixTime};
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, &(open, high, low, close))| {
+                OhlcvBar::new(
+                    UnixTime::new(start + period * i as i64),
+                    Decimal::new(open, 0),
+                    Decimal::new(high, 0),
+                    Decimal::new(low, 0),
+                    Decimal::new(close, 0),
+                    Decimal::new(1, 1),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resample_ohlcv() {
+        use super::*;
+        use crate::Decimal;
+        // 00:01〜00:07に終了する1分足を5分足に集約する(00:05の足のみ揃っている)
+        let bars = bars(
+            60,
+            60,
+            &[
+                (10, 12, 9, 11),
+                (11, 15, 10, 14),
+                (14, 14, 8, 9),
+                (9, 10, 9, 10),
+                (10, 11, 10, 11),
+                (11, 11, 11, 11),
+                (11, 11, 11, 11),
+            ],
+        );
+        let resampled = resample(&bars, 60, 300, ResampleAnchor::Utc).unwrap();
+        assert_eq!(resampled.len(), 1);
+        let bar = resampled[0];
+        assert_eq!(bar.get_unixtime().get_secs(), 300);
+        assert_eq!(bar.get_open(), Decimal::new(10, 0));
+        assert_eq!(bar.get_high(), Decimal::new(15, 0));
+        assert_eq!(bar.get_low(), Decimal::new(8, 0));
+        assert_eq!(bar.get_close(), Decimal::new(11, 0));
+        assert_eq!(bar.get_volume(), Decimal::new(5, 1));
+
+        // 途中の足が欠けている足は出力しない(00:03の足がない)
+        let mut gapped = bars.clone();
+        gapped.remove(2);
+        assert!(resample(&gapped, 60, 300, ResampleAnchor::Utc)
+            .unwrap()
+            .is_empty());
+
+        // 古い順でなければエラー
+        let mut reversed = bars.clone();
+        reversed.reverse();
+        assert!(resample(&reversed, 60, 300, ResampleAnchor::Utc).is_err());
+        assert!(resample(&bars, 60, 90, ResampleAnchor::Utc).is_err());
+    }
+
+    #[test]
+    fn resample_alignment() {
+        use super::*;
+        let utc = Resampler::new(3600, 86400, ResampleAnchor::Utc).unwrap();
+        assert_eq!(utc.bucket_close_time(86400), 86400);
+        assert_eq!(utc.bucket_close_time(3600), 86400);
+
+        // 日本時間の0時はUTCの15時
+        let jst = Resampler::new(3600, 86400, ResampleAnchor::Jst).unwrap();
+        assert_eq!(jst.bucket_close_time(3600), 54000);
+        assert_eq!(jst.bucket_close_time(57600), 140400);
+
+        // 週足は月曜日0時(1970-01-05)で区切る
+        let week = Resampler::new(86400, 604800, ResampleAnchor::Utc).unwrap();
+        assert_eq!(week.bucket_close_time(86400), 345600);
+        assert_eq!(week.bucket_close_time(432000), 950400);
+
+        // 日足の区切りは基準時刻ごとに異なる
+        assert!(ResampleAnchor::Utc.is_aligned(86400, 86400));
+        assert!(!ResampleAnchor::Jst.is_aligned(86400, 86400));
+        assert!(ResampleAnchor::Jst.is_aligned(54000, 86400));
+        assert!(ResampleAnchor::Utc.is_aligned(345600, 604800));
+        assert!(!ResampleAnchor::Utc.is_aligned(604800, 604800));
+
+        // 2時間足は日本時間の0時で区切れない
+        assert!(Resampler::new(7200, 86400, ResampleAnchor::Jst).is_err());
+    }
+
+    #[test]
+    fn store_resampled_ohlcv() {
+        use super::*;
+        let atbdb = AtbDB::open_in_memory().unwrap();
+        let prices: Vec<(i64, i64, i64, i64)> = (0..12).map(|i| (i, i + 2, i - 1, i + 1)).collect();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars(60, 60, &prices))
+            .unwrap();
+
+        // 00:00〜00:12の1分足から00:05と00:10の5分足を作成する(00:15の足は途中なので作成しない)
+        let query = OhlcvQuery::new("bitflyer", "btcjpy", 60);
+        let summary = atbdb
+            .store_resampled_ohlcv(&query, 300, ResampleAnchor::Utc)
+            .unwrap();
+        assert_eq!(summary.get_inserted(), 2);
+        let stored = atbdb
+            .get_ohlcv_list(&OhlcvQuery::new("bitflyer", "btcjpy", 300))
+            .unwrap();
+        assert_eq!(
+            stored.get_list(),
+            &atbdb
+                .get_resampled_ohlcv_list(&query, 300, ResampleAnchor::Utc)
+                .unwrap()[..]
+        );
+
+        // 2回目は変更なし
+        let summary = atbdb
+            .store_resampled_ohlcv(&query, 300, ResampleAnchor::Utc)
+            .unwrap();
+        assert_eq!(summary.get_unchanged(), 2);
+
+        // 基準時刻の異なる日足は同じ期間に保存できない
+        atbdb
+            .store_resampled_ohlcv(&query, 86400, ResampleAnchor::Utc)
+            .unwrap();
+        atbdb
+            .upsert_ohlcv_list(
+                "bitflyer",
+                "btcjpy",
+                86400,
+                &bars(86400, 86400, &[(1, 1, 1, 1)]),
+            )
+            .unwrap();
+        assert!(matches!(
+            atbdb.store_resampled_ohlcv(&query, 86400, ResampleAnchor::Jst),
+            Err(AtbDbError::Validation(_))
+        ));
+    }
+}
@@ -0,0 +1,731 @@
+use crate::{AtbDB, AtbDbError, AtbDbResult, UnixTime};
+
+// 1日の秒数
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// 保持する日数の上限(秒に換算してもi64の範囲に収まるようにする)
+const MAX_KEEP_DAYS: i64 = 365 * 1000;
+
+// アーカイブに作成するテーブル・インデックス(元のデータベースと同じ定義を使う)
+const ARCHIVE_SCHEMA: [&str; 3] = ["market", "ohlcv", "idx_ohlcv_unique"];
+
+// 保持期間を過ぎたローソク足の扱い
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RetentionAction {
+    // 削除する
+    Delete,
+    // 年ごとのデータベースファイルに移してから削除する
+    Archive,
+}
+
+impl RetentionAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetentionAction::Delete => "delete",
+            RetentionAction::Archive => "archive",
+        }
+    }
+}
+
+impl std::str::FromStr for RetentionAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RetentionAction, String> {
+        match s {
+            "delete" => Ok(RetentionAction::Delete),
+            "archive" => Ok(RetentionAction::Archive),
+            _ => Err(format!("不明な保持期間後の処理です: {}", s)),
+        }
+    }
+}
+
+// 市場・足の期間ごとの保持期間
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub struct RetentionRule {
+    // 対象の取引所(Noneならすべて)
+    exchange: Option<String>,
+    // 対象の通貨(Noneならすべて)
+    pair: Option<String>,
+    period: i64,
+    // 保持する日数
+    keep_days: i64,
+    action: RetentionAction,
+}
+
+impl RetentionRule {
+    pub fn new(period: i64, keep_days: i64, action: RetentionAction) -> RetentionRule {
+        RetentionRule {
+            exchange: None,
+            pair: None,
+            period,
+            keep_days,
+            action,
+        }
+    }
+
+    pub fn exchange(mut self, exchange: &str) -> RetentionRule {
+        self.exchange = Some(exchange.to_string());
+        self
+    }
+
+    pub fn pair(mut self, pair: &str) -> RetentionRule {
+        self.pair = Some(pair.to_string());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_period(&self) -> i64 {
+        self.period
+    }
+
+    #[allow(dead_code)]
+    pub fn get_keep_days(&self) -> i64 {
+        self.keep_days
+    }
+
+    #[allow(dead_code)]
+    pub fn get_action(&self) -> RetentionAction {
+        self.action
+    }
+
+    // 対象の市場・足の期間かどうか
+    pub fn matches(&self, exchange: &str, pair: &str, period: i64) -> bool {
+        self.period == period
+            && self.exchange.iter().all(|e| e == exchange)
+            && self.pair.iter().all(|p| p == pair)
+    }
+
+    // 基準時刻から見て保持期間を過ぎた境界(この時刻より前の足が対象)
+    pub fn get_cutoff(&self, now: UnixTime) -> UnixTime {
+        now.add_secs(-self.keep_days * SECONDS_PER_DAY)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.period <= 0 {
+            return Err(format!(
+                "足の期間には1以上を指定してください: {}",
+                self.period
+            ));
+        }
+        if self.keep_days <= 0 || MAX_KEEP_DAYS < self.keep_days {
+            return Err(format!(
+                "保持する日数には1以上{}以下を指定してください: {}",
+                MAX_KEEP_DAYS, self.keep_days
+            ));
+        }
+        Ok(())
+    }
+}
+
+// ローソク足の保持ルール(どのルールにも該当しない足はずっと保持する)
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RetentionPolicy {
+    // 上から順に確認し、最初に該当したルールを使う
+    rules: Vec<RetentionRule>,
+    // アーカイブしたローソク足を保存するディレクトリ
+    archive_dir: Option<std::path::PathBuf>,
+}
+
+impl RetentionPolicy {
+    pub fn rule(mut self, rule: RetentionRule) -> RetentionPolicy {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn archive_dir<P: AsRef<std::path::Path>>(mut self, dir: P) -> RetentionPolicy {
+        self.archive_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn get_rules(&self) -> &[RetentionRule] {
+        &self.rules
+    }
+
+    #[allow(dead_code)]
+    pub fn get_archive_dir(&self) -> Option<&std::path::Path> {
+        self.archive_dir.as_deref()
+    }
+
+    // 市場・足の期間に該当する最初のルール
+    pub fn find_rule(&self, exchange: &str, pair: &str, period: i64) -> Option<&RetentionRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(exchange, pair, period))
+    }
+
+    // 設定ファイルのretentionから保持ルールを作成する
+    pub fn from_conf(atbconf: &read_atb_config::AtbConf) -> AtbDbResult<RetentionPolicy> {
+        let mut policy = RetentionPolicy::default();
+        if let Some(dir) = atbconf.get_retention_archive_dir() {
+            policy = policy.archive_dir(dir);
+        }
+
+        for i in 0..atbconf.get_retention_rules_len() {
+            let missing = |key: &str| {
+                AtbDbError::Config(format!("retention.rules[{}].{} がありません", i, key))
+            };
+            let action = atbconf
+                .get_retention_rule_action(i)
+                .ok_or_else(|| missing("action"))?
+                .parse()
+                .map_err(|err| {
+                    AtbDbError::Config(format!("retention.rules[{}].action: {}", i, err))
+                })?;
+            let mut rule = RetentionRule::new(
+                atbconf
+                    .get_retention_rule_period(i)
+                    .ok_or_else(|| missing("period"))?,
+                atbconf
+                    .get_retention_rule_keep_days(i)
+                    .ok_or_else(|| missing("keep_days"))?,
+                action,
+            );
+            if let Some(exchange) = atbconf.get_retention_rule_exchange(i) {
+                rule = rule.exchange(exchange);
+            }
+            if let Some(pair) = atbconf.get_retention_rule_pair(i) {
+                rule = rule.pair(pair);
+            }
+            policy = policy.rule(rule);
+        }
+
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    // ルールの値と、アーカイブする場合の保存先を確認する
+    pub fn validate(&self) -> AtbDbResult<()> {
+        for (i, rule) in self.rules.iter().enumerate() {
+            rule.validate()
+                .map_err(|err| AtbDbError::Config(format!("retention.rules[{}]: {}", i, err)))?;
+        }
+        let archive = self
+            .rules
+            .iter()
+            .any(|rule| rule.action == RetentionAction::Archive);
+        if archive && self.archive_dir.is_none() {
+            return Err(AtbDbError::Config(
+                "アーカイブするにはretention.archive_dirを指定してください".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// 1年分の対象のローソク足
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct RetentionYear {
+    // 年(UTC)
+    year: i32,
+    count: usize,
+    // 対象の最初・最後の足の時刻
+    from: UnixTime,
+    to: UnixTime,
+    // アーカイブ先のファイル
+    archive_file: Option<std::path::PathBuf>,
+}
+
+impl RetentionYear {
+    #[allow(dead_code)]
+    pub fn get_year(&self) -> i32 {
+        self.year
+    }
+
+    #[allow(dead_code)]
+    pub fn get_count(&self) -> usize {
+        self.count
+    }
+
+    #[allow(dead_code)]
+    pub fn get_from(&self) -> UnixTime {
+        self.from
+    }
+
+    #[allow(dead_code)]
+    pub fn get_to(&self) -> UnixTime {
+        self.to
+    }
+
+    #[allow(dead_code)]
+    pub fn get_archive_file(&self) -> Option<&std::path::Path> {
+        self.archive_file.as_deref()
+    }
+}
+
+// 市場・足の期間ごとの対象のローソク足
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct RetentionTarget {
+    exchange: String,
+    pair: String,
+    period: i64,
+    action: RetentionAction,
+    // この時刻より前の足が対象
+    cutoff: UnixTime,
+    years: Vec<RetentionYear>,
+    // 合わせて削除する欠損期間の記録
+    gap_count: usize,
+}
+
+impl RetentionTarget {
+    #[allow(dead_code)]
+    pub fn get_exchange(&self) -> &str {
+        &self.exchange
+    }
+
+    #[allow(dead_code)]
+    pub fn get_pair(&self) -> &str {
+        &self.pair
+    }
+
+    #[allow(dead_code)]
+    pub fn get_period(&self) -> i64 {
+        self.period
+    }
+
+    #[allow(dead_code)]
+    pub fn get_action(&self) -> RetentionAction {
+        self.action
+    }
+
+    #[allow(dead_code)]
+    pub fn get_cutoff(&self) -> UnixTime {
+        self.cutoff
+    }
+
+    #[allow(dead_code)]
+    pub fn get_years(&self) -> &[RetentionYear] {
+        &self.years
+    }
+
+    #[allow(dead_code)]
+    pub fn get_gap_count(&self) -> usize {
+        self.gap_count
+    }
+
+    // 対象のローソク足の件数
+    pub fn get_count(&self) -> usize {
+        self.years.iter().map(|year| year.count).sum()
+    }
+}
+
+// 保持ルールの適用結果(dry_runなら適用した場合の予定)
+#[derive(serde::Serialize, Clone, Debug, PartialEq)]
+pub struct RetentionReport {
+    dry_run: bool,
+    targets: Vec<RetentionTarget>,
+    // VACUUM・ANALYZEを実行したかどうか
+    vacuumed: bool,
+}
+
+impl RetentionReport {
+    #[allow(dead_code)]
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    #[allow(dead_code)]
+    pub fn get_targets(&self) -> &[RetentionTarget] {
+        &self.targets
+    }
+
+    #[allow(dead_code)]
+    pub fn is_vacuumed(&self) -> bool {
+        self.vacuumed
+    }
+
+    // 対象のローソク足の件数
+    pub fn get_count(&self) -> usize {
+        self.targets.iter().map(RetentionTarget::get_count).sum()
+    }
+}
+
+impl AtbDB {
+    // 保持ルールに従って、基準時刻から見て保持期間を過ぎたローソク足を削除・アーカイブする
+    // 削除した場合はVACUUM・ANALYZEを実行する。dry_runなら対象を数えるだけで変更しない
+    pub fn apply_retention(
+        &self,
+        policy: &RetentionPolicy,
+        now: UnixTime,
+        dry_run: bool,
+    ) -> AtbDbResult<RetentionReport> {
+        policy.validate()?;
+        let targets = self.find_retention_targets(policy, now)?;
+
+        if !dry_run {
+            if let Some(dir) = &policy.archive_dir {
+                if targets.iter().any(|t| t.action == RetentionAction::Archive) {
+                    std::fs::create_dir_all(dir)?;
+                }
+            }
+            for target in &targets {
+                self.remove_retention_target(target)?;
+            }
+        }
+
+        // 削除した領域を解放し、統計情報を更新する
+        let vacuumed = !dry_run && targets.iter().any(|t| 0 < t.get_count() + t.gap_count);
+        if vacuumed {
+            let conn = self.get_conn()?;
+            conn.execute_batch("VACUUM; ANALYZE;")?;
+        }
+
+        Ok(RetentionReport {
+            dry_run,
+            targets,
+            vacuumed,
+        })
+    }
+
+    // 保存済みの市場・足の期間ごとに、保持期間を過ぎたローソク足を年ごとに数える
+    fn find_retention_targets(
+        &self,
+        policy: &RetentionPolicy,
+        now: UnixTime,
+    ) -> AtbDbResult<Vec<RetentionTarget>> {
+        let conn = self.get_conn()?;
+        let mut stmt = conn.prepare(
+            "select distinct exchange, pair, period from ohlcv order by exchange, pair, period",
+        )?;
+        let markets = stmt
+            .query_map(rusqlite::NO_PARAMS, |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<(String, String, i64)>, _>>()?;
+
+        let mut stmt_years = conn.prepare(
+            "select cast(strftime('%Y', unixtime, 'unixepoch') as integer) as year, count(*), min(unixtime), max(unixtime) from ohlcv where exchange = ?1 and pair = ?2 and period = ?3 and unixtime < ?4 group by year order by year",
+        )?;
+        let mut stmt_gaps = conn.prepare(
+            "select count(*) from ohlcv_gap where exchange = ?1 and pair = ?2 and period = ?3 and to_unixtime < ?4",
+        )?;
+
+        let mut targets = vec![];
+        for (exchange, pair, period) in markets {
+            let rule = match policy.find_rule(&exchange, &pair, period) {
+                Some(rule) => rule,
+                None => continue,
+            };
+            let cutoff = rule.get_cutoff(now);
+            let params = rusqlite::params![exchange, pair, period, cutoff.get_secs()];
+
+            let years = stmt_years
+                .query_map(params, |row| {
+                    let year: i32 = row.get(0)?;
+                    Ok(RetentionYear {
+                        year,
+                        count: row.get::<_, i64>(1)? as usize,
+                        from: UnixTime::new(row.get(2)?),
+                        to: UnixTime::new(row.get(3)?),
+                        archive_file: match (rule.action, &policy.archive_dir) {
+                            (RetentionAction::Archive, Some(dir)) => Some(archive_file(dir, year)),
+                            _ => None,
+                        },
+                    })
+                })?
+                .collect::<Result<Vec<RetentionYear>, _>>()?;
+            let gap_count = stmt_gaps.query_row(params, |row| row.get::<_, i64>(0))? as usize;
+
+            targets.push(RetentionTarget {
+                exchange,
+                pair,
+                period,
+                action: rule.action,
+                cutoff,
+                years,
+                gap_count,
+            });
+        }
+        Ok(targets)
+    }
+
+    // 対象のローソク足を年ごとに(アーカイブしてから)削除する
+    fn remove_retention_target(&self, target: &RetentionTarget) -> AtbDbResult<()> {
+        let mut conn = self.get_conn()?;
+        for year in &target.years {
+            let params = rusqlite::params![
+                target.exchange,
+                target.pair,
+                target.period,
+                year.from.get_secs(),
+                year.to.get_secs()
+            ];
+
+            if let Some(path) = &year.archive_file {
+                attach_archive(&conn, path)?;
+                let result = archive_ohlcv(&mut conn, params);
+                conn.execute_batch("DETACH DATABASE archive")?;
+                result?;
+            } else {
+                conn.execute(
+                    "delete from ohlcv where exchange = ?1 and pair = ?2 and period = ?3 and unixtime between ?4 and ?5",
+                    params,
+                )?;
+            }
+        }
+
+        // 削除した期間の欠損期間の記録も削除する
+        conn.execute(
+            "delete from ohlcv_gap where exchange = ?1 and pair = ?2 and period = ?3 and to_unixtime < ?4",
+            rusqlite::params![
+                target.exchange,
+                target.pair,
+                target.period,
+                target.cutoff.get_secs()
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+// 年ごとのアーカイブファイルのパス
+fn archive_file(dir: &std::path::Path, year: i32) -> std::path::PathBuf {
+    dir.join(format!("ohlcv-{}.sqlite3", year))
+}
+
+// アーカイブファイルを接続し、なければテーブルを作成する
+fn attach_archive(conn: &rusqlite::Connection, path: &std::path::Path) -> AtbDbResult<()> {
+    let path = path.to_str().ok_or_else(|| {
+        AtbDbError::Config(format!("アーカイブのパスが不正です: {}", path.display()))
+    })?;
+    conn.execute("ATTACH DATABASE ?1 AS archive", rusqlite::params![path])?;
+
+    let result = (|| {
+        for name in &ARCHIVE_SCHEMA {
+            let sql: String = conn.query_row(
+                "select sql from main.sqlite_master where name = ?1",
+                rusqlite::params![name],
+                |row| row.get(0),
+            )?;
+            // "CREATE TABLE ohlcv(" を "CREATE TABLE IF NOT EXISTS archive.ohlcv(" にする
+            let sql = sql.replacen(
+                &format!(" {}", name),
+                &format!(" IF NOT EXISTS archive.{}", name),
+                1,
+            );
+            conn.execute_batch(&sql)?;
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        conn.execute_batch("DETACH DATABASE archive")?;
+    }
+    result
+}
+
+// 1年分のローソク足と市場の情報をアーカイブに移す(1つのトランザクションで行う)
+fn archive_ohlcv(
+    conn: &mut rusqlite::Connection,
+    params: &[&dyn rusqlite::ToSql],
+) -> AtbDbResult<()> {
+    let tx = conn.transaction()?;
+    // 列の順番が異なるアーカイブ(列を追加した後に作成したものなど)でも列名で対応させる
+    tx.execute(
+        "insert or replace into archive.market (exchange, pair, base, quote, price_scale, volume_scale, tick_size, lot_size, contract_type) \
+         select exchange, pair, base, quote, price_scale, volume_scale, tick_size, lot_size, contract_type from main.market where exchange = ?1 and pair = ?2",
+        &params[..2],
+    )?;
+    tx.execute(
+        "insert or replace into archive.ohlcv (exchange, pair, period, open, high, low, close, volume, unixtime) \
+         select exchange, pair, period, open, high, low, close, volume, unixtime from main.ohlcv where exchange = ?1 and pair = ?2 and period = ?3 and unixtime between ?4 and ?5",
+        params,
+    )?;
+    tx.execute(
+        "delete from main.ohlcv where exchange = ?1 and pair = ?2 and period = ?3 and unixtime between ?4 and ?5",
+        params,
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    fn bar(unixtime: i64) -> crate::OhlcvBar {
+        use crate::{Decimal, OhlcvBar, UnixTime};
+        let price = Decimal::new(100, 0);
+        OhlcvBar::new(
+            UnixTime::new(unixtime),
+            price,
+            price,
+            price,
+            price,
+            Decimal::new(1, 0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn policy_from_conf() {
+        use super::*;
+        let conf = |yaml: &str| read_atb_config::AtbConf::load_from_str(yaml).unwrap();
+
+        let policy = RetentionPolicy::from_conf(&conf(
+            "retention:\n  archive_dir: /tmp/archive\n  rules:\n    - exchange: bitflyer\n      period: 60\n      keep_days: 730\n      action: archive\n    - period: 60\n      keep_days: 30\n      action: delete\n",
+        ))
+        .unwrap();
+        assert_eq!(policy.get_rules().len(), 2);
+        assert_eq!(
+            policy.get_archive_dir(),
+            Some(std::path::Path::new("/tmp/archive"))
+        );
+
+        // 最初に該当したルールを使い、該当しなければずっと保持する
+        let rule = policy.find_rule("bitflyer", "btcjpy", 60).unwrap();
+        assert_eq!(rule.get_action(), RetentionAction::Archive);
+        let rule = policy.find_rule("liquid", "btcjpy", 60).unwrap();
+        assert_eq!(rule.get_keep_days(), 30);
+        assert!(policy.find_rule("bitflyer", "btcjpy", 3600).is_none());
+
+        assert_eq!(
+            RetentionPolicy::from_conf(&conf("api:\n  port: 8080\n")).unwrap(),
+            RetentionPolicy::default()
+        );
+        for yaml in &[
+            "retention:\n  rules:\n    - period: 60\n      keep_days: 30\n      action: archive\n",
+            "retention:\n  rules:\n    - period: 60\n      keep_days: 0\n      action: delete\n",
+            "retention:\n  rules:\n    - period: 60\n      keep_days: 365001\n      action: delete\n",
+            "retention:\n  rules:\n    - period: 60\n      keep_days: 9223372036854775807\n      action: delete\n",
+            "retention:\n  rules:\n    - period: 60\n      keep_days: 30\n      action: drop\n",
+            "retention:\n  rules:\n    - period: 60\n      action: delete\n",
+        ] {
+            assert!(matches!(
+                RetentionPolicy::from_conf(&conf(yaml)),
+                Err(AtbDbError::Config(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn apply_retention() {
+        use super::*;
+        let atbdb = AtbDB::open_temp_file().unwrap();
+        let archive_dir = atbdb.get_temp_file().unwrap().with_extension("archive");
+
+        // 2017年末・2018年初・直近の1分足と、古い5分足
+        let bars = vec![
+            bar(1514764740),
+            bar(1514764800),
+            bar(1514764860),
+            bar(1559260800),
+        ];
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars)
+            .unwrap();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 300, &[bar(1514764800)])
+            .unwrap();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 3600, &[bar(1514764800)])
+            .unwrap();
+        atbdb
+            .pool
+            .get()
+            .unwrap()
+            .execute(
+                "insert into ohlcv_gap (exchange, pair, period, from_unixtime, to_unixtime) values ('bitflyer', 'btcjpy', 60, 1514764680, 1514764680)",
+                rusqlite::NO_PARAMS,
+            )
+            .unwrap();
+
+        let policy = RetentionPolicy::default()
+            .rule(RetentionRule::new(60, 365, RetentionAction::Archive).exchange("bitflyer"))
+            .rule(RetentionRule::new(300, 30, RetentionAction::Delete))
+            .archive_dir(&archive_dir);
+        let now = UnixTime::new(1559347200);
+        let count = |period| {
+            let query = crate::OhlcvQuery::new("bitflyer", "btcjpy", period);
+            atbdb.get_ohlcv_list(&query).unwrap().get_list_len()
+        };
+
+        // dry_runは対象を数えるだけ
+        let report = atbdb.apply_retention(&policy, now, true).unwrap();
+        assert!(report.is_dry_run());
+        assert!(!report.is_vacuumed());
+        assert_eq!(report.get_count(), 4);
+        let target = &report.get_targets()[0];
+        assert_eq!(target.get_period(), 60);
+        assert_eq!(target.get_cutoff(), UnixTime::new(1559347200 - 365 * 86400));
+        assert_eq!(target.get_gap_count(), 1);
+        let years: Vec<(i32, usize)> = target
+            .get_years()
+            .iter()
+            .map(|year| (year.get_year(), year.get_count()))
+            .collect();
+        assert_eq!(years, vec![(2017, 1), (2018, 2)]);
+        assert_eq!(
+            target.get_years()[0].get_archive_file(),
+            Some(archive_dir.join("ohlcv-2017.sqlite3").as_path())
+        );
+        assert_eq!(
+            report.get_targets()[1].get_action(),
+            RetentionAction::Delete
+        );
+        assert_eq!(report.get_targets().len(), 2);
+        assert_eq!(count(60), 4);
+        assert!(!archive_dir.exists());
+
+        // 既存のアーカイブは列の順番が異なっても列名で対応させる
+        std::fs::create_dir_all(&archive_dir).unwrap();
+        rusqlite::Connection::open(archive_dir.join("ohlcv-2017.sqlite3"))
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE ohlcv(unixtime TIMESTAMP NOT NULL, volume INTEGER NOT NULL, exchange TEXT NOT NULL, pair TEXT NOT NULL, period INTEGER NOT NULL, open INTEGER NOT NULL, high INTEGER NOT NULL, low INTEGER NOT NULL, close INTEGER NOT NULL);",
+            )
+            .unwrap();
+
+        // 保持期間を過ぎた足を年ごとのファイルに移して削除する
+        let report = atbdb.apply_retention(&policy, now, false).unwrap();
+        assert_eq!(report.get_count(), 4);
+        assert!(report.is_vacuumed());
+        assert_eq!((count(60), count(300), count(3600)), (1, 0, 1));
+        let gaps = atbdb
+            .get_ohlcv_gap_list(&crate::OhlcvQuery::new("bitflyer", "btcjpy", 60), None)
+            .unwrap();
+        assert!(gaps.is_empty());
+
+        let archive = rusqlite::Connection::open(archive_dir.join("ohlcv-2018.sqlite3")).unwrap();
+        let archived: i64 = archive
+            .query_row("select count(*) from ohlcv", rusqlite::NO_PARAMS, |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(archived, 2);
+        let price_scale: i64 = archive
+            .query_row(
+                "select price_scale from market where exchange = 'bitflyer' and pair = 'btcjpy'",
+                rusqlite::NO_PARAMS,
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(price_scale, 0);
+        let archive = rusqlite::Connection::open(archive_dir.join("ohlcv-2017.sqlite3")).unwrap();
+        let archived: (i64, i64, String) = archive
+            .query_row(
+                "select unixtime, volume, exchange from ohlcv",
+                rusqlite::NO_PARAMS,
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        // 出来高は × 10^volume_scale(8)で保存する
+        assert_eq!(archived, (1514764740, 100000000, "bitflyer".to_string()));
+
+        // もう一度適用しても対象はない
+        let report = atbdb.apply_retention(&policy, now, false).unwrap();
+        assert_eq!(report.get_count(), 0);
+        assert!(!report.is_vacuumed());
+
+        // アーカイブ先がなければ何もしない
+        let policy =
+            RetentionPolicy::default().rule(RetentionRule::new(3600, 1, RetentionAction::Archive));
+        assert!(matches!(
+            atbdb.apply_retention(&policy, now, true),
+            Err(AtbDbError::Config(_))
+        ));
+        std::fs::remove_dir_all(&archive_dir).unwrap();
+    }
+}
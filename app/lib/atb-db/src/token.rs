@@ -0,0 +1,130 @@
+// botのアクセス用トークン
+// トークンは20バイトの乱数の16進数(40文字)で、先頭8文字を検索用に平文で保存する
+// 全体はソルト付きのSHA-256ハッシュで保存し、平文は発行時にしか取得できない
+// version 7より前に発行したUUID形式(36文字)のトークンも、マイグレーションでハッシュに変換して使い続けられる
+
+// トークンの長さ(16進数の文字数)
+pub const TOKEN_LENGTH: usize = 40;
+
+// 検索用に平文で保存するトークンの先頭の文字数
+pub const TOKEN_PREFIX_LENGTH: usize = 8;
+
+// UUID形式のトークンの長さ
+const UUID_TOKEN_LENGTH: usize = 36;
+
+// UUID形式のトークンの区切り文字の位置
+const UUID_HYPHENS: [usize; 4] = [8, 13, 18, 23];
+
+// ソルトのバイト数
+const SALT_BYTES: usize = 16;
+
+// 保存用のトークン情報
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HashedToken {
+    pub(crate) prefix: String,
+    pub(crate) salt: String,
+    pub(crate) hash: String,
+}
+
+// 新しいトークンを発行し、平文と保存用の情報を返す
+pub(crate) fn generate_token() -> (String, HashedToken) {
+    let token = to_hex(&random_bytes(TOKEN_LENGTH / 2));
+    let hashed = hash_new_salt(&token);
+    (token, hashed)
+}
+
+// 発行済みの平文のトークンから保存用の情報を作る(トークンの形式でなければNone)
+pub(crate) fn hash_existing_token(token: &str) -> Option<HashedToken> {
+    token_prefix(token)?;
+    Some(hash_new_salt(token))
+}
+
+// 新しいソルトでトークンのハッシュ値を計算する
+fn hash_new_salt(token: &str) -> HashedToken {
+    let salt = to_hex(&random_bytes(SALT_BYTES));
+    HashedToken {
+        prefix: token[..TOKEN_PREFIX_LENGTH].to_string(),
+        hash: hash_token(&salt, token),
+        salt,
+    }
+}
+
+// トークンの検索用の先頭部分(トークンの形式でなければNone)
+pub(crate) fn token_prefix(token: &str) -> Option<&str> {
+    let valid = match token.len() {
+        TOKEN_LENGTH => token.bytes().all(|b| b.is_ascii_hexdigit()),
+        UUID_TOKEN_LENGTH => token.bytes().enumerate().all(|(i, b)| {
+            if UUID_HYPHENS.contains(&i) {
+                b == b'-'
+            } else {
+                b.is_ascii_hexdigit()
+            }
+        }),
+        _ => false,
+    };
+    if !valid {
+        return None;
+    }
+    Some(&token[..TOKEN_PREFIX_LENGTH])
+}
+
+// トークンが保存されているハッシュ値と一致するか確認する(比較は一定時間で行う)
+pub(crate) fn verify_token(token: &str, salt: &str, hash: &str) -> bool {
+    use subtle::ConstantTimeEq;
+    let actual = hash_token(salt, token);
+    actual.as_bytes().ct_eq(hash.as_bytes()).into()
+}
+
+// ソルトとトークンからハッシュ値を計算する
+fn hash_token(salt: &str, token: &str) -> String {
+    use sha2::Digest;
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(token.as_bytes());
+    to_hex(&hasher.finalize())
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    use rand::RngCore;
+    let mut bytes = vec![0; len];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn generate_and_verify_token() {
+        use super::*;
+        let (token, hashed) = generate_token();
+        assert_eq!(token.len(), TOKEN_LENGTH);
+        assert_eq!(token_prefix(&token), Some(hashed.prefix.as_str()));
+        assert_eq!(hashed.salt.len(), SALT_BYTES * 2);
+        assert_eq!(hashed.hash.len(), 64);
+        assert!(!hashed.hash.contains(&token));
+
+        assert!(verify_token(&token, &hashed.salt, &hashed.hash));
+        let mut other = token.clone();
+        other.replace_range(39.., if token.ends_with('0') { "1" } else { "0" });
+        assert!(!verify_token(&other, &hashed.salt, &hashed.hash));
+
+        // 同じトークンでもソルトが違えばハッシュ値も違う
+        let (_, again) = generate_token();
+        assert_ne!(hash_token(&again.salt, &token), hashed.hash);
+
+        // UUID形式のトークン
+        let uuid = "0a1b2c3d-4e5f-6a7b-8c9d-0e1f2a3b4c5d";
+        assert_eq!(token_prefix(uuid), Some("0a1b2c3d"));
+        let hashed = hash_existing_token(uuid).unwrap();
+        assert!(verify_token(uuid, &hashed.salt, &hashed.hash));
+        assert_eq!(token_prefix(&uuid.replace('-', "0")), None);
+        assert_eq!(hash_existing_token("short"), None);
+
+        assert_eq!(token_prefix("short"), None);
+        assert_eq!(token_prefix(&"z".repeat(TOKEN_LENGTH)), None);
+    }
+}
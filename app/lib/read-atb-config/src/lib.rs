@@ -13,62 +13,136 @@ impl AtbConf {
     #[allow(dead_code)]
     pub fn load_conf() -> Option<AtbConf> {
         // 環境変数PATH_ATB_CONFIGの値を読み込む
-        let result_env_atb_config = std::env::var("PATH_ATB_CONFIG");
-        if let Err(_) = result_env_atb_config {
-            return None;
-        }
-        let env_atb_config = result_env_atb_config.unwrap();
+        let env_atb_config = std::env::var("PATH_ATB_CONFIG").ok()?;
 
         // Yamlファイルを開く
-        let file = std::fs::File::open(env_atb_config);
-        if let Err(_) = file {
-            return None;
-        }
+        let mut file = std::fs::File::open(env_atb_config).ok()?;
 
         // Yamlファイルを読み込む
         let mut contents = String::new();
-        if let Err(_) = file.unwrap().read_to_string(&mut contents) {
-            return None
-        }
+        file.read_to_string(&mut contents).ok()?;
 
         // 読み込んだYamlファイルをオブジェクトに変換する
-        let result_atb_config = yaml_rust::YamlLoader::load_from_str(&contents);
-        if let Err(_) = result_atb_config {
-            return None
-        }
+        AtbConf::load_from_str(&contents)
+    }
+
+    #[allow(dead_code)]
+    pub fn load_from_str(contents: &str) -> Option<AtbConf> {
+        let result_atb_config = yaml_rust::YamlLoader::load_from_str(contents).ok()?;
 
-        let atb_config = &result_atb_config.unwrap()[0];
+        let atb_config = result_atb_config.first()?;
         // println!("{:?}", atb_config);
         Some(AtbConf { yaml: atb_config.clone()})
     }
 
     #[allow(dead_code)]
     pub fn get_sqlite3_file(&self) -> Option<String> {
-        return if let Some(file) = self.yaml["database"]["sqlite3"]["db_file"].as_str() {
-            Some(file.to_string())
-        } else {
-            None
-        };
+        self.yaml["database"]["sqlite3"]["db_file"]
+            .as_str()
+            .map(|file| file.to_string())
+    }
+
+    // database.version(v1, v2, ...)を数値で取得する
+    #[allow(dead_code)]
+    pub fn get_database_version(&self) -> Option<i64> {
+        let version = self.yaml["database"]["version"].as_str()?;
+        version.strip_prefix('v')?.parse::<i64>().ok()
     }
 
     #[allow(dead_code)]
     pub fn has_api_host(&self) -> bool {
-        return self.yaml["api"]["host"].as_str().is_some();
+        self.yaml["api"]["host"].as_str().is_some()
     }
 
     #[allow(dead_code)]
     pub fn has_api_port(&self) -> bool {
-        return self.yaml["api"]["port"].as_i64().is_some();
+        self.yaml["api"]["port"].as_i64().is_some()
     }
 
     #[allow(dead_code)]
     pub fn get_api_host(&self) -> Option<&str> {
-        return self.yaml["api"]["host"].as_str();
+        self.yaml["api"]["host"].as_str()
     }
 
     #[allow(dead_code)]
     pub fn get_api_port(&self) -> Option<i64> {
-        return self.yaml["api"]["port"].as_i64();
+        self.yaml["api"]["port"].as_i64()
+    }
+
+    // api.db_workers(データベースに同時に問い合わせる数)を取得する
+    #[allow(dead_code)]
+    pub fn get_api_db_workers(&self) -> Option<i64> {
+        self.yaml["api"]["db_workers"].as_i64()
+    }
+
+    // api.timeout_ms(1リクエストあたりのデータベース処理のタイムアウト)を取得する
+    #[allow(dead_code)]
+    pub fn get_api_timeout_ms(&self) -> Option<i64> {
+        self.yaml["api"]["timeout_ms"].as_i64()
+    }
+
+    // promotion.<昇格先の運用段階>.min_tradesを取得する
+    #[allow(dead_code)]
+    pub fn get_promotion_min_trades(&self, operate_type: &str) -> Option<i64> {
+        self.yaml["promotion"][operate_type]["min_trades"].as_i64()
+    }
+
+    // promotion.<昇格先の運用段階>.min_daysを取得する
+    #[allow(dead_code)]
+    pub fn get_promotion_min_days(&self, operate_type: &str) -> Option<i64> {
+        self.yaml["promotion"][operate_type]["min_days"].as_i64()
+    }
+
+    // promotion.<昇格先の運用段階>.max_drawdownを誤差が出ないよう文字列で取得する
+    #[allow(dead_code)]
+    pub fn get_promotion_max_drawdown(&self, operate_type: &str) -> Option<String> {
+        match &self.yaml["promotion"][operate_type]["max_drawdown"] {
+            yaml_rust::Yaml::Real(value) | yaml_rust::Yaml::String(value) => Some(value.clone()),
+            yaml_rust::Yaml::Integer(value) => Some(value.to_string()),
+            _ => None,
+        }
+    }
+
+    // retention.archive_dir(アーカイブしたローソク足を保存するディレクトリ)を取得する
+    #[allow(dead_code)]
+    pub fn get_retention_archive_dir(&self) -> Option<&str> {
+        self.yaml["retention"]["archive_dir"].as_str()
+    }
+
+    // retention.rulesの件数を取得する
+    #[allow(dead_code)]
+    pub fn get_retention_rules_len(&self) -> usize {
+        self.yaml["retention"]["rules"].as_vec().map(|rules| rules.len()).unwrap_or(0)
+    }
+
+    // retention.rules[index].exchange(省略時はすべての取引所)を取得する
+    #[allow(dead_code)]
+    pub fn get_retention_rule_exchange(&self, index: usize) -> Option<&str> {
+        self.yaml["retention"]["rules"][index]["exchange"].as_str()
+    }
+
+    // retention.rules[index].pair(省略時はすべての通貨)を取得する
+    #[allow(dead_code)]
+    pub fn get_retention_rule_pair(&self, index: usize) -> Option<&str> {
+        self.yaml["retention"]["rules"][index]["pair"].as_str()
+    }
+
+    // retention.rules[index].period(足の期間)を取得する
+    #[allow(dead_code)]
+    pub fn get_retention_rule_period(&self, index: usize) -> Option<i64> {
+        self.yaml["retention"]["rules"][index]["period"].as_i64()
+    }
+
+    // retention.rules[index].keep_days(保持する日数)を取得する
+    #[allow(dead_code)]
+    pub fn get_retention_rule_keep_days(&self, index: usize) -> Option<i64> {
+        self.yaml["retention"]["rules"][index]["keep_days"].as_i64()
+    }
+
+    // retention.rules[index].action(delete, archive)を取得する
+    #[allow(dead_code)]
+    pub fn get_retention_rule_action(&self, index: usize) -> Option<&str> {
+        self.yaml["retention"]["rules"][index]["action"].as_str()
     }
 }
 
@@ -85,4 +159,56 @@ mod tests {
         }
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn database_version() {
+        let atb_conf = AtbConf::load_from_str("database:\n  version: v2\n").unwrap();
+        assert_eq!(atb_conf.get_database_version(), Some(2));
+
+        let atb_conf = AtbConf::load_from_str("database:\n  version: 2\n").unwrap();
+        assert_eq!(atb_conf.get_database_version(), None);
+    }
+
+    #[test]
+    fn api() {
+        let atb_conf = AtbConf::load_from_str("api:\n  port: 8080\n  db_workers: 4\n  timeout_ms: 3000\n").unwrap();
+        assert_eq!(atb_conf.get_api_port(), Some(8080));
+        assert_eq!(atb_conf.get_api_db_workers(), Some(4));
+        assert_eq!(atb_conf.get_api_timeout_ms(), Some(3000));
+        let atb_conf = AtbConf::load_from_str("api:\n  port: 8080\n").unwrap();
+        assert_eq!(atb_conf.get_api_db_workers(), None);
+    }
+
+    #[test]
+    fn promotion() {
+        let atb_conf = AtbConf::load_from_str(
+            "promotion:\n  product:\n    min_trades: 30\n    min_days: 14\n    max_drawdown: 0.15\n",
+        )
+        .unwrap();
+        assert_eq!(atb_conf.get_promotion_min_trades("product"), Some(30));
+        assert_eq!(atb_conf.get_promotion_min_days("product"), Some(14));
+        assert_eq!(atb_conf.get_promotion_max_drawdown("product"), Some("0.15".to_string()));
+        assert_eq!(atb_conf.get_promotion_min_trades("forwardtest"), None);
+        assert_eq!(atb_conf.get_promotion_max_drawdown("forwardtest"), None);
+    }
+
+    #[test]
+    fn retention() {
+        let atb_conf = AtbConf::load_from_str(
+            "retention:\n  archive_dir: /tmp/archive\n  rules:\n    - exchange: bitflyer\n      pair: null\n      period: 60\n      keep_days: 730\n      action: archive\n    - period: 300\n      keep_days: 1825\n      action: delete\n",
+        )
+        .unwrap();
+        assert_eq!(atb_conf.get_retention_archive_dir(), Some("/tmp/archive"));
+        assert_eq!(atb_conf.get_retention_rules_len(), 2);
+        assert_eq!(atb_conf.get_retention_rule_exchange(0), Some("bitflyer"));
+        assert_eq!(atb_conf.get_retention_rule_pair(0), None);
+        assert_eq!(atb_conf.get_retention_rule_period(0), Some(60));
+        assert_eq!(atb_conf.get_retention_rule_keep_days(1), Some(1825));
+        assert_eq!(atb_conf.get_retention_rule_action(1), Some("delete"));
+        assert_eq!(atb_conf.get_retention_rule_exchange(1), None);
+        assert_eq!(atb_conf.get_retention_rule_period(2), None);
+
+        let atb_conf = AtbConf::load_from_str("database:\n  version: v2\n").unwrap();
+        assert_eq!(atb_conf.get_retention_rules_len(), 0);
+    }
 }
@@ -7,14 +7,51 @@ CREATE TABLE ohlcv(
   exchange  TEXT      NOT NULL,  -- 取引所
   pair      TEXT      NOT NULL,  -- 取引通貨
   period    INTEGER   NOT NULL,  -- 足の期間
-  open      INTEGER   NOT NULL,  -- 始値
-  high      INTEGER   NOT NULL,  -- 高値
-  low       INTEGER   NOT NULL,  -- 安値
-  close     INTEGER   NOT NULL,  -- 終値
-  volume    INTEGER   NOT NULL,  -- 出来高
+  open      INTEGER   NOT NULL,  -- 始値(× 10^market.price_scale)
+  high      INTEGER   NOT NULL,  -- 高値(× 10^market.price_scale)
+  low       INTEGER   NOT NULL,  -- 安値(× 10^market.price_scale)
+  close     INTEGER   NOT NULL,  -- 終値(× 10^market.price_scale)
+  volume    INTEGER   NOT NULL,  -- 出来高(× 10^market.volume_scale)
   unixtime  TIMESTAMP NOT NULL   -- UNIX時間
 );
-CREATE INDEX idx_exchange ON ohlcv(exchange, pair, period);
+CREATE UNIQUE INDEX idx_ohlcv_unique ON ohlcv(exchange, pair, period, unixtime);
+CREATE TABLE ohlcv_gap(
+  exchange       TEXT      NOT NULL,  -- 取引所
+  pair           TEXT      NOT NULL,  -- 取引通貨
+  period         INTEGER   NOT NULL,  -- 足の期間
+  from_unixtime  INTEGER   NOT NULL,  -- 欠損している最初の足のUNIX時間
+  to_unixtime    INTEGER   NOT NULL,  -- 欠損している最後の足のUNIX時間
+
+  -- 状態(未解決、取得済み、取得元にデータなし)
+  status         TEXT      NOT NULL CHECK(status in ('missing', 'repaired', 'unavailable')) DEFAULT 'missing',
+
+  detected       TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now')),  -- 検出日時
+  updated        TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now')),  -- 状態の更新日時
+  PRIMARY KEY(exchange, pair, period, from_unixtime),
+  CHECK(from_unixtime <= to_unixtime)
+);
+CREATE TABLE market(
+  exchange       TEXT      NOT NULL,  -- 取引所
+  pair           TEXT      NOT NULL,  -- 取引通貨
+  base           TEXT      NOT NULL,  -- 基軸通貨(btcjpyのbtc)
+  quote          TEXT      NOT NULL,  -- 決済通貨(btcjpyのjpy)
+  price_scale    INTEGER   NOT NULL CHECK(price_scale BETWEEN 0 AND 18),   -- 価格の小数点以下の桁数
+  volume_scale   INTEGER   NOT NULL CHECK(volume_scale BETWEEN 0 AND 18),  -- 出来高の小数点以下の桁数
+  tick_size      INTEGER   NOT NULL CHECK(tick_size > 0),  -- 呼値(× 10^price_scale)
+  lot_size       INTEGER   NOT NULL CHECK(lot_size > 0),   -- 最小注文数量(× 10^volume_scale)
+
+  -- 取引種別(現物、FX、無期限先物)
+  contract_type  TEXT      NOT NULL CHECK(contract_type in ('spot', 'fx', 'perpetual')),
+
+  PRIMARY KEY(exchange, pair)
+);
+CREATE TABLE market_period(
+  exchange  TEXT      NOT NULL,  -- 取引所
+  pair      TEXT      NOT NULL,  -- 取引通貨
+  period    INTEGER   NOT NULL CHECK(period > 0),  -- 足の期間(秒)
+  PRIMARY KEY(exchange, pair, period),
+  FOREIGN KEY(exchange, pair) REFERENCES market(exchange, pair) ON DELETE CASCADE
+);
 CREATE TABLE bot(
   -- botID
   id            INTEGER   PRIMARY KEY,
@@ -31,8 +68,14 @@ CREATE TABLE bot(
   -- 登録日時
   registered    TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now')),
 
-   -- アクセス用トークン
-  token         TEXT      NOT NULL CHECK(length(token) = 36) DEFAULT(printf('%s-%s-%s-%s-%s', lower(hex(randomblob(4))), lower(hex(randomblob(2))), lower(hex(randomblob(2))), lower(hex(randomblob(2))), lower(hex(randomblob(6))))),
+  -- アクセス用トークンの先頭8文字(検索用)
+  token_prefix  TEXT      UNIQUE CHECK(length(token_prefix) = 8),
+
+  -- アクセス用トークンのソルト(16バイトの16進数)
+  token_salt    TEXT      CHECK(length(token_salt) = 32),
+
+  -- アクセス用トークンのハッシュ値(SHA-256(ソルト + トークン)の16進数)
+  token_hash    TEXT      CHECK(length(token_hash) = 64),
 
   -- ロング注文可能かどうか
   long_order    BOOLEAN   NOT NULL CHECK(long_order in (0, 1)) DEFAULT 1,
@@ -41,7 +84,55 @@ CREATE TABLE bot(
   short_order   BOOLEAN   NOT NULL CHECK(short_order in (0, 1)) DEFAULT 0,
 
   -- 運用段階(バックテスト、フォワードテスト、実運用)
-  operate_type  TEXT      NOT NULL CHECK(operate_type in ('backtest', 'forwardtest', 'product')) DEFAULT 'backtest',
+  operate_type  TEXT      NOT NULL CHECK(operate_type in ('backtest', 'forwardtest', 'product')) DEFAULT 'backtest', archived_at TIMESTAMP,
+
+  -- トークンは3項目すべて設定されているか、すべて未設定
+  CHECK((token_prefix IS NULL) = (token_salt IS NULL) AND (token_salt IS NULL) = (token_hash IS NULL))
+);
+CREATE TABLE bot_history(
+  id          INTEGER   PRIMARY KEY,
+  bot_id      INTEGER   NOT NULL,  -- 対象botID
+
+  -- 操作(追加、更新、削除、トークン再発行、アーカイブ、復元、完全削除)
+  action      TEXT      NOT NULL CHECK(action in ('insert', 'update', 'delete', 'rotate_token', 'archive', 'restore', 'purge')),
+
+  old_value   TEXT,                -- 変更前の値(JSON、変更した項目のみ)
+  new_value   TEXT,                -- 変更後の値(JSON、変更した項目のみ)
+  actor       TEXT      NOT NULL,  -- 操作者(cli:ユーザー名、api:接続元など)
+  registered  TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now'))  -- 操作日時
+);
+CREATE INDEX idx_bot_history_bot_id ON bot_history(bot_id, id);
+CREATE TABLE bot_evaluation(
+  id            INTEGER   PRIMARY KEY,
+  bot_id        INTEGER   NOT NULL,  -- 対象botID
+
+  -- 評価した運用段階(バックテスト、フォワードテスト、実運用)
+  operate_type  TEXT      NOT NULL CHECK(operate_type in ('backtest', 'forwardtest', 'product')),
 
-  unique(id)
+  trade_count   INTEGER   NOT NULL CHECK(trade_count >= 0),  -- 取引回数
+  max_drawdown  TEXT      NOT NULL,  -- 最大ドローダウン(比率の10進数文字列、0.2 = 20%)
+  started       TIMESTAMP NOT NULL,  -- 評価期間の開始日時
+  ended         TIMESTAMP NOT NULL CHECK(started <= ended),  -- 評価期間の終了日時
+  registered    TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now'))  -- 登録日時
+);
+CREATE INDEX idx_bot_evaluation_bot_id ON bot_evaluation(bot_id, operate_type, id);
+CREATE TABLE bot_promotion(
+  id             INTEGER   PRIMARY KEY,
+  bot_id         INTEGER   NOT NULL,  -- 対象botID
+  from_type      TEXT      NOT NULL CHECK(from_type in ('backtest', 'forwardtest', 'product')),  -- 変更前の運用段階
+  to_type        TEXT      NOT NULL CHECK(to_type in ('backtest', 'forwardtest', 'product')),    -- 変更後の運用段階
+  evaluation_id  INTEGER,             -- 昇格の根拠にした評価結果ID(降格はNULL)
+  actor          TEXT      NOT NULL,  -- 操作者(cli:ユーザー名、api:接続元など)
+  registered     TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now'))  -- 変更日時
+);
+CREATE INDEX idx_bot_promotion_bot_id ON bot_promotion(bot_id, id);
+CREATE TABLE market_fee(
+  exchange    TEXT      NOT NULL,  -- 取引所
+  pair        TEXT      NOT NULL,  -- 取引通貨
+  valid_from  TIMESTAMP NOT NULL,  -- 適用開始日時
+  maker_fee   TEXT      NOT NULL,  -- メイカー手数料(板に並んだ指値注文の約定)
+  taker_fee   TEXT      NOT NULL,  -- テイカー手数料(成行・逆指値注文の約定)
+  spread      TEXT      NOT NULL,  -- 売値と買値の差(テイカーは半分を不利な価格で約定する)
+  PRIMARY KEY(exchange, pair, valid_from),
+  FOREIGN KEY(exchange, pair) REFERENCES market(exchange, pair) ON DELETE CASCADE
 );
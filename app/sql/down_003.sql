@@ -0,0 +1,10 @@
+-----
+-- DBバージョン:3 のロールバックファイル
+
+-----
+-- 一意制約のINDEXを元のINDEXに戻す
+CREATE INDEX IF NOT EXISTS idx_exchange ON ohlcv(exchange, pair, period);
+DROP INDEX idx_ohlcv_unique;
+
+-- バージョン情報を削除する
+DELETE FROM version WHERE version = 3;
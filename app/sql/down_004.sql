@@ -0,0 +1,37 @@
+-----
+-- DBバージョン:4 のロールバックファイル
+
+-----
+-- 価格データを小数に戻す(インデックスは元のテーブルと共に削除される)
+ALTER TABLE ohlcv RENAME TO ohlcv_prev;
+
+CREATE TABLE ohlcv(
+  exchange  TEXT      NOT NULL,  -- 取引所
+  pair      TEXT      NOT NULL,  -- 取引通貨
+  period    INTEGER   NOT NULL,  -- 足の期間
+  open      INTEGER   NOT NULL,  -- 始値
+  high      INTEGER   NOT NULL,  -- 高値
+  low       INTEGER   NOT NULL,  -- 安値
+  close     INTEGER   NOT NULL,  -- 終値
+  volume    INTEGER   NOT NULL,  -- 出来高
+  unixtime  TIMESTAMP NOT NULL   -- UNIX時間
+);
+
+INSERT INTO ohlcv(exchange, pair, period, open, high, low, close, volume, unixtime)
+  SELECT o.exchange, o.pair, o.period,
+         o.open   * 1.0 / CAST(substr('1000000000000000000', 1, m.price_scale + 1) AS INTEGER),
+         o.high   * 1.0 / CAST(substr('1000000000000000000', 1, m.price_scale + 1) AS INTEGER),
+         o.low    * 1.0 / CAST(substr('1000000000000000000', 1, m.price_scale + 1) AS INTEGER),
+         o.close  * 1.0 / CAST(substr('1000000000000000000', 1, m.price_scale + 1) AS INTEGER),
+         o.volume * 1.0 / CAST(substr('1000000000000000000', 1, m.volume_scale + 1) AS INTEGER),
+         o.unixtime
+  FROM ohlcv_prev o INNER JOIN market m ON o.exchange = m.exchange AND o.pair = m.pair;
+
+DROP TABLE ohlcv_prev;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_ohlcv_unique ON ohlcv(exchange, pair, period, unixtime);
+
+-- 市場テーブルを削除する
+DROP TABLE market;
+
+-- バージョン情報を削除する
+DELETE FROM version WHERE version = 4;
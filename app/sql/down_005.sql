@@ -0,0 +1,8 @@
+-----
+-- DBバージョン:5 のロールバックファイル
+
+-- 欠損期間テーブルを削除する
+DROP TABLE ohlcv_gap;
+
+-- バージョン情報を削除する
+DELETE FROM version WHERE version = 5;
@@ -0,0 +1,25 @@
+-----
+-- DBバージョン:6 のロールバックファイル
+
+-- 足の期間テーブルを削除する
+DROP TABLE market_period;
+
+-----
+-- 市場テーブルを価格・出来高の桁数のみに戻す
+ALTER TABLE market RENAME TO market_prev;
+
+CREATE TABLE market(
+  exchange      TEXT      NOT NULL,  -- 取引所
+  pair          TEXT      NOT NULL,  -- 取引通貨
+  price_scale   INTEGER   NOT NULL CHECK(price_scale BETWEEN 0 AND 18),   -- 価格の小数点以下の桁数
+  volume_scale  INTEGER   NOT NULL CHECK(volume_scale BETWEEN 0 AND 18),  -- 出来高の小数点以下の桁数
+  PRIMARY KEY(exchange, pair)
+);
+
+INSERT INTO market(exchange, pair, price_scale, volume_scale)
+  SELECT exchange, pair, price_scale, volume_scale FROM market_prev;
+
+DROP TABLE market_prev;
+
+-- バージョン情報を削除する
+DELETE FROM version WHERE version = 6;
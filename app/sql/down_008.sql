@@ -0,0 +1,9 @@
+-----
+-- DBバージョン:8 のロールバックファイル
+
+-----
+-- botの変更履歴テーブルを削除する
+DROP TABLE bot_history;
+
+-- バージョン情報を削除する
+DELETE FROM version WHERE version = 8;
@@ -0,0 +1,82 @@
+-----
+-- DBバージョン:9 のロールバックファイル
+
+-----
+-- botの変更履歴の操作を元に戻す
+-- アーカイブ・復元は更新、完全削除は削除として残す
+ALTER TABLE bot_history RENAME TO bot_history_prev;
+
+CREATE TABLE bot_history(
+  id          INTEGER   PRIMARY KEY,
+  bot_id      INTEGER   NOT NULL,  -- 対象botID
+
+  -- 操作(追加、更新、削除、トークン再発行)
+  action      TEXT      NOT NULL CHECK(action in ('insert', 'update', 'delete', 'rotate_token')),
+
+  old_value   TEXT,                -- 変更前の値(JSON、変更した項目のみ)
+  new_value   TEXT,                -- 変更後の値(JSON、変更した項目のみ)
+  actor       TEXT      NOT NULL,  -- 操作者(cli:ユーザー名、api:接続元など)
+  registered  TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now'))  -- 操作日時
+);
+
+INSERT INTO bot_history(id, bot_id, action, old_value, new_value, actor, registered)
+  SELECT id, bot_id,
+         CASE action WHEN 'archive' THEN 'update' WHEN 'restore' THEN 'update' WHEN 'purge' THEN 'delete' ELSE action END,
+         old_value, new_value, actor, registered
+  FROM bot_history_prev;
+
+DROP TABLE bot_history_prev;
+
+CREATE INDEX IF NOT EXISTS idx_bot_history_bot_id ON bot_history(bot_id, id);
+
+-----
+-- アーカイブ日時を削除する(アーカイブしていたbotは無効にして残す)
+UPDATE bot SET enable = 0 WHERE archived_at IS NOT NULL;
+
+ALTER TABLE bot RENAME TO bot_prev;
+
+CREATE TABLE bot(
+  -- botID
+  id            INTEGER   PRIMARY KEY,
+
+  -- 名前
+  name          TEXT      NOT NULL,
+
+  -- 説明文
+  description   TEXT      NOT NULL,
+
+  -- 有効かどうか
+  enable        BOOLEAN   NOT NULL CHECK(enable in (0, 1)) DEFAULT 1,
+
+  -- 登録日時
+  registered    TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now')),
+
+  -- アクセス用トークンの先頭8文字(検索用)
+  token_prefix  TEXT      UNIQUE CHECK(length(token_prefix) = 8),
+
+  -- アクセス用トークンのソルト(16バイトの16進数)
+  token_salt    TEXT      CHECK(length(token_salt) = 32),
+
+  -- アクセス用トークンのハッシュ値(SHA-256(ソルト + トークン)の16進数)
+  token_hash    TEXT      CHECK(length(token_hash) = 64),
+
+  -- ロング注文可能かどうか
+  long_order    BOOLEAN   NOT NULL CHECK(long_order in (0, 1)) DEFAULT 1,
+
+  -- ショート注文可能かどうか
+  short_order   BOOLEAN   NOT NULL CHECK(short_order in (0, 1)) DEFAULT 0,
+
+  -- 運用段階(バックテスト、フォワードテスト、実運用)
+  operate_type  TEXT      NOT NULL CHECK(operate_type in ('backtest', 'forwardtest', 'product')) DEFAULT 'backtest',
+
+  -- トークンは3項目すべて設定されているか、すべて未設定
+  CHECK((token_prefix IS NULL) = (token_salt IS NULL) AND (token_salt IS NULL) = (token_hash IS NULL))
+);
+
+INSERT INTO bot(id, name, description, enable, registered, token_prefix, token_salt, token_hash, long_order, short_order, operate_type)
+  SELECT id, name, description, enable, registered, token_prefix, token_salt, token_hash, long_order, short_order, operate_type FROM bot_prev;
+
+DROP TABLE bot_prev;
+
+-- バージョン情報を削除する
+DELETE FROM version WHERE version = 9;
@@ -0,0 +1,13 @@
+-----
+-- DBバージョン:10 のロールバックファイル
+
+-----
+-- botの運用段階の変更履歴テーブルを削除する
+DROP TABLE bot_promotion;
+
+-----
+-- botの評価結果テーブルを削除する
+DROP TABLE bot_evaluation;
+
+-- バージョン情報を削除する
+DELETE FROM version WHERE version = 10;
@@ -0,0 +1,9 @@
+-----
+-- DBバージョン:11 のロールバックファイル
+
+-----
+-- 市場ごとの手数料体系テーブルを削除する
+DROP TABLE market_fee;
+
+-- バージョン情報を削除する
+DELETE FROM version WHERE version = 11;
@@ -0,0 +1,17 @@
+-----
+-- DBバージョン:3 のマイグレーションファイル
+
+-- 現在のバージョンを挿入する
+INSERT INTO version(version) VALUES(3);
+
+-----
+-- 重複したローソク足は最後に追加したレコードだけを残す
+DELETE FROM ohlcv WHERE rowid NOT IN (
+  SELECT max(rowid) FROM ohlcv GROUP BY exchange, pair, period, unixtime
+);
+
+-- 取引所・取引通貨・足の期間・UNIX時間で一意にする
+CREATE UNIQUE INDEX IF NOT EXISTS idx_ohlcv_unique ON ohlcv(exchange, pair, period, unixtime);
+
+-- 一意制約のINDEXで代用できるため削除する
+DROP INDEX IF EXISTS idx_exchange;
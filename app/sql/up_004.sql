@@ -0,0 +1,58 @@
+-----
+-- DBバージョン:4 のマイグレーションファイル
+
+-- 現在のバージョンを挿入する
+INSERT INTO version(version) VALUES(4);
+
+-----
+-- 市場(取引所・取引通貨)ごとの価格・出来高の小数点以下の桁数
+CREATE TABLE IF NOT EXISTS market(
+  exchange      TEXT      NOT NULL,  -- 取引所
+  pair          TEXT      NOT NULL,  -- 取引通貨
+  price_scale   INTEGER   NOT NULL CHECK(price_scale BETWEEN 0 AND 18),   -- 価格の小数点以下の桁数
+  volume_scale  INTEGER   NOT NULL CHECK(volume_scale BETWEEN 0 AND 18),  -- 出来高の小数点以下の桁数
+  PRIMARY KEY(exchange, pair)
+);
+
+-- 取得対象の市場を登録する
+INSERT OR IGNORE INTO market(exchange, pair, price_scale, volume_scale) VALUES
+  ('bitflyer', 'btcjpy', 0, 8),
+  ('bitflyer', 'btcfxjpy', 0, 8),
+  ('liquid', 'btcjpy', 2, 8),
+  ('ftx', 'btcusd', 2, 8),
+  ('bitmex', 'btcusd-perpetual-futures', 2, 4);
+
+-- 保存済みのその他の市場は小数点以下8桁で登録する
+INSERT OR IGNORE INTO market(exchange, pair, price_scale, volume_scale)
+  SELECT DISTINCT exchange, pair, 8, 8 FROM ohlcv;
+
+-----
+-- 価格データを 値 × 10^scale の整数で保存し直す
+ALTER TABLE ohlcv RENAME TO ohlcv_prev;
+
+CREATE TABLE ohlcv(
+  exchange  TEXT      NOT NULL,  -- 取引所
+  pair      TEXT      NOT NULL,  -- 取引通貨
+  period    INTEGER   NOT NULL,  -- 足の期間
+  open      INTEGER   NOT NULL,  -- 始値(× 10^market.price_scale)
+  high      INTEGER   NOT NULL,  -- 高値(× 10^market.price_scale)
+  low       INTEGER   NOT NULL,  -- 安値(× 10^market.price_scale)
+  close     INTEGER   NOT NULL,  -- 終値(× 10^market.price_scale)
+  volume    INTEGER   NOT NULL,  -- 出来高(× 10^market.volume_scale)
+  unixtime  TIMESTAMP NOT NULL   -- UNIX時間
+);
+
+INSERT INTO ohlcv(exchange, pair, period, open, high, low, close, volume, unixtime)
+  SELECT o.exchange, o.pair, o.period,
+         CAST(round(o.open   * CAST(substr('1000000000000000000', 1, m.price_scale + 1) AS INTEGER)) AS INTEGER),
+         CAST(round(o.high   * CAST(substr('1000000000000000000', 1, m.price_scale + 1) AS INTEGER)) AS INTEGER),
+         CAST(round(o.low    * CAST(substr('1000000000000000000', 1, m.price_scale + 1) AS INTEGER)) AS INTEGER),
+         CAST(round(o.close  * CAST(substr('1000000000000000000', 1, m.price_scale + 1) AS INTEGER)) AS INTEGER),
+         CAST(round(o.volume * CAST(substr('1000000000000000000', 1, m.volume_scale + 1) AS INTEGER)) AS INTEGER),
+         o.unixtime
+  FROM ohlcv_prev o INNER JOIN market m ON o.exchange = m.exchange AND o.pair = m.pair;
+
+DROP TABLE ohlcv_prev;
+
+-- 取引所・取引通貨・足の期間・UNIX時間で一意にする
+CREATE UNIQUE INDEX IF NOT EXISTS idx_ohlcv_unique ON ohlcv(exchange, pair, period, unixtime);
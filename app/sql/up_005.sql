@@ -0,0 +1,23 @@
+-----
+-- DBバージョン:5 のマイグレーションファイル
+
+-- 現在のバージョンを挿入する
+INSERT INTO version(version) VALUES(5);
+
+-----
+-- ローソク足の欠損期間
+CREATE TABLE IF NOT EXISTS ohlcv_gap(
+  exchange       TEXT      NOT NULL,  -- 取引所
+  pair           TEXT      NOT NULL,  -- 取引通貨
+  period         INTEGER   NOT NULL,  -- 足の期間
+  from_unixtime  INTEGER   NOT NULL,  -- 欠損している最初の足のUNIX時間
+  to_unixtime    INTEGER   NOT NULL,  -- 欠損している最後の足のUNIX時間
+
+  -- 状態(未解決、取得済み、取得元にデータなし)
+  status         TEXT      NOT NULL CHECK(status in ('missing', 'repaired', 'unavailable')) DEFAULT 'missing',
+
+  detected       TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now')),  -- 検出日時
+  updated        TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now')),  -- 状態の更新日時
+  PRIMARY KEY(exchange, pair, period, from_unixtime),
+  CHECK(from_unixtime <= to_unixtime)
+);
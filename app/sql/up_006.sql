@@ -0,0 +1,71 @@
+-----
+-- DBバージョン:6 のマイグレーションファイル
+
+-- 現在のバージョンを挿入する
+INSERT INTO version(version) VALUES(6);
+
+-----
+-- 市場テーブルに通貨・呼値・最小注文数量・取引種別を追加する
+ALTER TABLE market RENAME TO market_prev;
+
+CREATE TABLE market(
+  exchange       TEXT      NOT NULL,  -- 取引所
+  pair           TEXT      NOT NULL,  -- 取引通貨
+  base           TEXT      NOT NULL,  -- 基軸通貨(btcjpyのbtc)
+  quote          TEXT      NOT NULL,  -- 決済通貨(btcjpyのjpy)
+  price_scale    INTEGER   NOT NULL CHECK(price_scale BETWEEN 0 AND 18),   -- 価格の小数点以下の桁数
+  volume_scale   INTEGER   NOT NULL CHECK(volume_scale BETWEEN 0 AND 18),  -- 出来高の小数点以下の桁数
+  tick_size      INTEGER   NOT NULL CHECK(tick_size > 0),  -- 呼値(× 10^price_scale)
+  lot_size       INTEGER   NOT NULL CHECK(lot_size > 0),   -- 最小注文数量(× 10^volume_scale)
+
+  -- 取引種別(現物、FX、無期限先物)
+  contract_type  TEXT      NOT NULL CHECK(contract_type in ('spot', 'fx', 'perpetual')),
+
+  PRIMARY KEY(exchange, pair)
+);
+
+-- 登録済みの市場は通貨を取引通貨の前後3文字とし、呼値・最小注文数量は最小単位で登録する
+INSERT INTO market(exchange, pair, base, quote, price_scale, volume_scale, tick_size, lot_size, contract_type)
+  SELECT exchange, pair, substr(pair, 1, 3), substr(pair, -3, 3), price_scale, volume_scale, 1, 1, 'spot'
+  FROM market_prev;
+
+DROP TABLE market_prev;
+
+-- 取得対象の市場を更新する
+UPDATE market SET base = 'btc', quote = 'jpy', tick_size = 1, lot_size = 100000, contract_type = 'spot'
+  WHERE exchange = 'bitflyer' AND pair = 'btcjpy';
+UPDATE market SET base = 'btc', quote = 'jpy', tick_size = 1, lot_size = 1000000, contract_type = 'fx'
+  WHERE exchange = 'bitflyer' AND pair = 'btcfxjpy';
+UPDATE market SET base = 'btc', quote = 'jpy', tick_size = 1, lot_size = 100000, contract_type = 'spot'
+  WHERE exchange = 'liquid' AND pair = 'btcjpy';
+UPDATE market SET base = 'btc', quote = 'usd', tick_size = 100, lot_size = 10000, contract_type = 'spot'
+  WHERE exchange = 'ftx' AND pair = 'btcusd';
+UPDATE market SET base = 'btc', quote = 'usd', tick_size = 50, lot_size = 10000, contract_type = 'perpetual'
+  WHERE exchange = 'bitmex' AND pair = 'btcusd-perpetual-futures';
+
+-----
+-- 市場ごとに取得・配信する足の期間
+CREATE TABLE IF NOT EXISTS market_period(
+  exchange  TEXT      NOT NULL,  -- 取引所
+  pair      TEXT      NOT NULL,  -- 取引通貨
+  period    INTEGER   NOT NULL CHECK(period > 0),  -- 足の期間(秒)
+  PRIMARY KEY(exchange, pair, period),
+  FOREIGN KEY(exchange, pair) REFERENCES market(exchange, pair) ON DELETE CASCADE
+);
+
+-- 取得対象の市場はfetch.shの足の期間を登録する
+INSERT OR IGNORE INTO market_period(exchange, pair, period)
+  SELECT m.exchange, m.pair, p.period
+  FROM market m, (
+    SELECT 60 AS period UNION ALL SELECT 300 UNION ALL SELECT 900 UNION ALL SELECT 1800
+    UNION ALL SELECT 3600 UNION ALL SELECT 7200 UNION ALL SELECT 14400 UNION ALL SELECT 21600
+    UNION ALL SELECT 43200 UNION ALL SELECT 86400 UNION ALL SELECT 259200 UNION ALL SELECT 604800
+  ) p
+  WHERE (m.exchange, m.pair) IN (
+    VALUES ('bitflyer', 'btcjpy'), ('bitflyer', 'btcfxjpy'), ('liquid', 'btcjpy'),
+           ('ftx', 'btcusd'), ('bitmex', 'btcusd-perpetual-futures')
+  );
+
+-- 保存済みのローソク足の足の期間を登録する
+INSERT OR IGNORE INTO market_period(exchange, pair, period)
+  SELECT DISTINCT exchange, pair, period FROM ohlcv;
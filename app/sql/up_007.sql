@@ -0,0 +1,54 @@
+-----
+-- DBバージョン:7 のマイグレーションファイル
+
+-- 現在のバージョンを挿入する
+INSERT INTO version(version) VALUES(7);
+
+-----
+-- botのアクセス用トークンを平文ではなくソルト付きハッシュで保存する
+-- 既存botのトークンはatb-dbのマイグレーション(migration.rsのhash_plaintext_tokens)が
+-- このSQLの実行前に読み込み、実行後にハッシュに変換して保存する
+ALTER TABLE bot RENAME TO bot_prev;
+
+CREATE TABLE bot(
+  -- botID
+  id            INTEGER   PRIMARY KEY,
+
+  -- 名前
+  name          TEXT      NOT NULL,
+
+  -- 説明文
+  description   TEXT      NOT NULL,
+
+  -- 有効かどうか
+  enable        BOOLEAN   NOT NULL CHECK(enable in (0, 1)) DEFAULT 1,
+
+  -- 登録日時
+  registered    TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now')),
+
+  -- アクセス用トークンの先頭8文字(検索用)
+  token_prefix  TEXT      UNIQUE CHECK(length(token_prefix) = 8),
+
+  -- アクセス用トークンのソルト(16バイトの16進数)
+  token_salt    TEXT      CHECK(length(token_salt) = 32),
+
+  -- アクセス用トークンのハッシュ値(SHA-256(ソルト + トークン)の16進数)
+  token_hash    TEXT      CHECK(length(token_hash) = 64),
+
+  -- ロング注文可能かどうか
+  long_order    BOOLEAN   NOT NULL CHECK(long_order in (0, 1)) DEFAULT 1,
+
+  -- ショート注文可能かどうか
+  short_order   BOOLEAN   NOT NULL CHECK(short_order in (0, 1)) DEFAULT 0,
+
+  -- 運用段階(バックテスト、フォワードテスト、実運用)
+  operate_type  TEXT      NOT NULL CHECK(operate_type in ('backtest', 'forwardtest', 'product')) DEFAULT 'backtest',
+
+  -- トークンは3項目すべて設定されているか、すべて未設定
+  CHECK((token_prefix IS NULL) = (token_salt IS NULL) AND (token_salt IS NULL) = (token_hash IS NULL))
+);
+
+INSERT INTO bot(id, name, description, enable, registered, long_order, short_order, operate_type)
+  SELECT id, name, description, enable, registered, long_order, short_order, operate_type FROM bot_prev;
+
+DROP TABLE bot_prev;
@@ -0,0 +1,22 @@
+-----
+-- DBバージョン:8 のマイグレーションファイル
+
+-- 現在のバージョンを挿入する
+INSERT INTO version(version) VALUES(8);
+
+-----
+-- botの変更履歴テーブル(botを削除しても履歴は残す)
+CREATE TABLE IF NOT EXISTS bot_history(
+  id          INTEGER   PRIMARY KEY,
+  bot_id      INTEGER   NOT NULL,  -- 対象botID
+
+  -- 操作(追加、更新、削除、トークン再発行)
+  action      TEXT      NOT NULL CHECK(action in ('insert', 'update', 'delete', 'rotate_token')),
+
+  old_value   TEXT,                -- 変更前の値(JSON、変更した項目のみ)
+  new_value   TEXT,                -- 変更後の値(JSON、変更した項目のみ)
+  actor       TEXT      NOT NULL,  -- 操作者(cli:ユーザー名、api:接続元など)
+  registered  TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now'))  -- 操作日時
+);
+
+CREATE INDEX IF NOT EXISTS idx_bot_history_bot_id ON bot_history(bot_id, id);
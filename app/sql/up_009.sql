@@ -0,0 +1,34 @@
+-----
+-- DBバージョン:9 のマイグレーションファイル
+
+-- 現在のバージョンを挿入する
+INSERT INTO version(version) VALUES(9);
+
+-----
+-- botをアーカイブした日時(アーカイブしていなければNULL)
+-- アーカイブしたbotは一覧・APIの認証から除外し、復元できる
+ALTER TABLE bot ADD COLUMN archived_at TIMESTAMP;
+
+-----
+-- botの変更履歴の操作にアーカイブ・復元・完全削除を追加する
+ALTER TABLE bot_history RENAME TO bot_history_prev;
+
+CREATE TABLE bot_history(
+  id          INTEGER   PRIMARY KEY,
+  bot_id      INTEGER   NOT NULL,  -- 対象botID
+
+  -- 操作(追加、更新、削除、トークン再発行、アーカイブ、復元、完全削除)
+  action      TEXT      NOT NULL CHECK(action in ('insert', 'update', 'delete', 'rotate_token', 'archive', 'restore', 'purge')),
+
+  old_value   TEXT,                -- 変更前の値(JSON、変更した項目のみ)
+  new_value   TEXT,                -- 変更後の値(JSON、変更した項目のみ)
+  actor       TEXT      NOT NULL,  -- 操作者(cli:ユーザー名、api:接続元など)
+  registered  TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now'))  -- 操作日時
+);
+
+INSERT INTO bot_history(id, bot_id, action, old_value, new_value, actor, registered)
+  SELECT id, bot_id, action, old_value, new_value, actor, registered FROM bot_history_prev;
+
+DROP TABLE bot_history_prev;
+
+CREATE INDEX IF NOT EXISTS idx_bot_history_bot_id ON bot_history(bot_id, id);
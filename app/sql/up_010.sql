@@ -0,0 +1,37 @@
+-----
+-- DBバージョン:10 のマイグレーションファイル
+
+-- 現在のバージョンを挿入する
+INSERT INTO version(version) VALUES(10);
+
+-----
+-- botの評価結果テーブル(運用段階を上げる時の根拠)
+CREATE TABLE IF NOT EXISTS bot_evaluation(
+  id            INTEGER   PRIMARY KEY,
+  bot_id        INTEGER   NOT NULL,  -- 対象botID
+
+  -- 評価した運用段階(バックテスト、フォワードテスト、実運用)
+  operate_type  TEXT      NOT NULL CHECK(operate_type in ('backtest', 'forwardtest', 'product')),
+
+  trade_count   INTEGER   NOT NULL CHECK(trade_count >= 0),  -- 取引回数
+  max_drawdown  TEXT      NOT NULL,  -- 最大ドローダウン(比率の10進数文字列、0.2 = 20%)
+  started       TIMESTAMP NOT NULL,  -- 評価期間の開始日時
+  ended         TIMESTAMP NOT NULL CHECK(started <= ended),  -- 評価期間の終了日時
+  registered    TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now'))  -- 登録日時
+);
+
+CREATE INDEX IF NOT EXISTS idx_bot_evaluation_bot_id ON bot_evaluation(bot_id, operate_type, id);
+
+-----
+-- botの運用段階の変更履歴テーブル(昇格・降格)
+CREATE TABLE IF NOT EXISTS bot_promotion(
+  id             INTEGER   PRIMARY KEY,
+  bot_id         INTEGER   NOT NULL,  -- 対象botID
+  from_type      TEXT      NOT NULL CHECK(from_type in ('backtest', 'forwardtest', 'product')),  -- 変更前の運用段階
+  to_type        TEXT      NOT NULL CHECK(to_type in ('backtest', 'forwardtest', 'product')),    -- 変更後の運用段階
+  evaluation_id  INTEGER,             -- 昇格の根拠にした評価結果ID(降格はNULL)
+  actor          TEXT      NOT NULL,  -- 操作者(cli:ユーザー名、api:接続元など)
+  registered     TIMESTAMP NOT NULL DEFAULT (strftime('%s', 'now'))  -- 変更日時
+);
+
+CREATE INDEX IF NOT EXISTS idx_bot_promotion_bot_id ON bot_promotion(bot_id, id);
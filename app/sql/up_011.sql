@@ -0,0 +1,31 @@
+-----
+-- DBバージョン:11 のマイグレーションファイル
+
+-- 現在のバージョンを挿入する
+INSERT INTO version(version) VALUES(11);
+
+-----
+-- 市場ごとの手数料体系(適用開始日時ごとに登録する)
+-- 手数料・スプレッドは約定代金に対する比率の10進数文字列(0.001 = 0.1%、負の手数料はリベート)
+CREATE TABLE IF NOT EXISTS market_fee(
+  exchange    TEXT      NOT NULL,  -- 取引所
+  pair        TEXT      NOT NULL,  -- 取引通貨
+  valid_from  TIMESTAMP NOT NULL,  -- 適用開始日時
+  maker_fee   TEXT      NOT NULL,  -- メイカー手数料(板に並んだ指値注文の約定)
+  taker_fee   TEXT      NOT NULL,  -- テイカー手数料(成行・逆指値注文の約定)
+  spread      TEXT      NOT NULL,  -- 売値と買値の差(テイカーは半分を不利な価格で約定する)
+  PRIMARY KEY(exchange, pair, valid_from),
+  FOREIGN KEY(exchange, pair) REFERENCES market(exchange, pair) ON DELETE CASCADE
+);
+
+-- 取得対象の市場の手数料体系を登録する(最新の手数料体系は`market fee set`で追加する)
+INSERT OR IGNORE INTO market_fee(exchange, pair, valid_from, maker_fee, taker_fee, spread)
+  SELECT exchange, pair, 0, maker_fee, taker_fee, spread
+  FROM (
+    SELECT 'bitflyer' AS exchange, 'btcjpy' AS pair, '0.0015' AS maker_fee, '0.0015' AS taker_fee, '0.0002' AS spread
+    UNION ALL SELECT 'bitflyer', 'btcfxjpy', '0', '0', '0.0002'
+    UNION ALL SELECT 'liquid', 'btcjpy', '0', '0', '0.0005'
+    UNION ALL SELECT 'ftx', 'btcusd', '0.0002', '0.0007', '0.0001'
+    UNION ALL SELECT 'bitmex', 'btcusd-perpetual-futures', '-0.00025', '0.00075', '0.0001'
+  ) f
+  WHERE (exchange, pair) IN (SELECT exchange, pair FROM market);
@@ -0,0 +1,674 @@
+extern crate atb_db;
+extern crate clap;
+extern crate read_atb_config;
+
+fn main() {
+    // 環境変数から設定ファイルを読み込む
+    let atbconf = match read_atb_config::AtbConf::load_conf() {
+        Some(atbconf) => atbconf,
+        None => {
+            error_msg("環境変数`PATH_ATB_CONFIG`を確認ください");
+            std::process::exit(1);
+        }
+    };
+
+    // 対象データベースに接続する(バージョン確認はコマンド側で行う)
+    let atbdb = match atb_db::AtbDB::connect_for_migration(Some(atbconf.clone())) {
+        Ok(atbdb) => atbdb,
+        Err(err) => {
+            error_msg(&err.to_string());
+            std::process::exit(1);
+        }
+    };
+
+    // コマンドライン引数を取得する
+    let args_matches = get_args_matches();
+
+    // コマンドを実行する
+    let result = actual_main(&atbdb, &atbconf, &args_matches);
+
+    // 終了する
+    std::process::exit(result);
+}
+
+fn _clap_version() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("version")
+        .help("対象バージョン")
+        .long("version")
+        .takes_value(true)
+}
+
+fn _clap_market() -> Vec<clap::Arg<'static, 'static>> {
+    vec![
+        clap::Arg::with_name("exchange")
+            .help("対象取引所")
+            .required(true),
+        clap::Arg::with_name("pair").help("対象通貨").required(true),
+    ]
+}
+
+// ohlcvのexport/importで共通の引数
+fn _clap_ohlcv_file() -> Vec<clap::Arg<'static, 'static>> {
+    let mut args = _clap_market();
+    args.push(
+        clap::Arg::with_name("period")
+            .help("足の期間(秒指定)")
+            .required(true),
+    );
+    args.push(
+        clap::Arg::with_name("file")
+            .help("ファイルのパス")
+            .required(true),
+    );
+    args.push(
+        clap::Arg::with_name("format")
+            .help("ファイル形式(省略時は拡張子から判断する)")
+            .long("format")
+            .takes_value(true)
+            .possible_values(&["csv", "parquet"]),
+    );
+    args
+}
+
+fn _clap_required(name: &'static str, help: &'static str) -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name(name)
+        .help(help)
+        .long(name)
+        .takes_value(true)
+        .required(true)
+}
+
+// コマンドライン引数を取得する
+fn get_args_matches() -> clap::ArgMatches<'static> {
+    clap::App::new("atb-db-tool-rs")
+        .version("0.0.1")
+        .author("Didy KUPANHY")
+        .about("データベース管理コマンド")
+        .setting(clap::AppSettings::ArgRequiredElseHelp)
+        .setting(clap::AppSettings::DeriveDisplayOrder)
+        .subcommand(
+            clap::SubCommand::with_name("migration")
+                .about("指定バージョン(省略時は設定ファイルのdatabase.version)までマイグレーションする")
+                .arg(_clap_version()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("rollback")
+                .about("指定バージョンまでロールバックする")
+                .arg(_clap_version().required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("check")
+                .about("スキーマバージョンとテーブル構造(check.sql)を確認する"),
+        )
+        .subcommand(clap::SubCommand::with_name("version").about("versionテーブルの内容を表示する"))
+        .subcommand(
+            clap::SubCommand::with_name("market")
+                .about("市場の一覧表示・登録を行う")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(clap::SubCommand::with_name("list").about("登録済みの市場を表示する"))
+                .subcommand(
+                    clap::SubCommand::with_name("add")
+                        .about("市場を登録する")
+                        .args(&_clap_market())
+                        .arg(_clap_required("base", "基軸通貨(btcjpyのbtc)"))
+                        .arg(_clap_required("quote", "決済通貨(btcjpyのjpy)"))
+                        .arg(
+                            _clap_required("contract-type", "取引種別")
+                                .possible_values(&["spot", "fx", "perpetual"]),
+                        )
+                        .arg(_clap_required("price-scale", "価格の小数点以下の桁数"))
+                        .arg(_clap_required("volume-scale", "出来高の小数点以下の桁数"))
+                        .arg(_clap_required("tick-size", "呼値"))
+                        .arg(_clap_required("lot-size", "最小注文数量"))
+                        .arg(
+                            clap::Arg::with_name("periods")
+                                .help("取得・配信する足の期間(秒指定、カンマ区切り)")
+                                .long("periods")
+                                .takes_value(true)
+                                .use_delimiter(true),
+                        ),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("enable")
+                        .about("足の期間を有効にする")
+                        .args(&_clap_market())
+                        .arg(clap::Arg::with_name("period").help("足の期間(秒指定)").required(true)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("disable")
+                        .about("足の期間を無効にする")
+                        .args(&_clap_market())
+                        .arg(clap::Arg::with_name("period").help("足の期間(秒指定)").required(true)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("fee")
+                        .about("市場の手数料体系の表示・登録を行う")
+                        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(
+                            clap::SubCommand::with_name("list")
+                                .about("手数料体系を適用開始日時の順に表示する")
+                                .args(&_clap_market()),
+                        )
+                        .subcommand(
+                            clap::SubCommand::with_name("set")
+                                .about("手数料体系を登録する(同じ適用開始日時なら置き換える)")
+                                .args(&_clap_market())
+                                .arg(_clap_required("maker-fee", "メイカー手数料の比率(負ならリベート)"))
+                                .arg(_clap_required("taker-fee", "テイカー手数料の比率"))
+                                .arg(_clap_required("spread", "売値と買値の差の比率"))
+                                .arg(
+                                    clap::Arg::with_name("valid-from")
+                                        .help("適用開始日時(UnixTime秒指定、省略時は0)")
+                                        .long("valid-from")
+                                        .takes_value(true),
+                                ),
+                        )
+                        .subcommand(
+                            clap::SubCommand::with_name("delete")
+                                .about("手数料体系を削除する")
+                                .args(&_clap_market())
+                                .arg(
+                                    clap::Arg::with_name("valid-from")
+                                        .help("適用開始日時(UnixTime秒指定)")
+                                        .required(true),
+                                ),
+                        ),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("gap")
+                .about("ローソク足の欠損期間を表示する")
+                .arg(
+                    clap::Arg::with_name("exchange")
+                        .help("対象取引所")
+                        .required(true),
+                )
+                .arg(clap::Arg::with_name("pair").help("対象通貨").required(true))
+                .arg(
+                    clap::Arg::with_name("period")
+                        .help("足の期間(秒指定)")
+                        .required(true),
+                )
+                .arg(
+                    clap::Arg::with_name("from")
+                        .help("対象期間の開始unixtime")
+                        .long("from")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("to")
+                        .help("対象期間の終了unixtime")
+                        .long("to")
+                        .takes_value(true),
+                )
+                .arg(
+                    clap::Arg::with_name("status")
+                        .help("表示する状態")
+                        .long("status")
+                        .takes_value(true)
+                        .possible_values(&["missing", "repaired", "unavailable"]),
+                )
+                .arg(
+                    clap::Arg::with_name("detect")
+                        .help("保存済みのローソク足から欠損期間を探して記録する")
+                        .long("detect"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("retention")
+                .about("設定ファイルのretentionに従って古いローソク足を削除・アーカイブする")
+                .arg(
+                    clap::Arg::with_name("dry-run")
+                        .help("対象のローソク足を表示するだけで変更しない")
+                        .long("dry-run"),
+                )
+                .arg(
+                    clap::Arg::with_name("now")
+                        .help("保持期間の基準にするunixtime(省略時は現在時刻)")
+                        .long("now")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("ohlcv")
+                .about("ローソク足をCSV・Parquetファイルに書き出す・読み込む")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("export")
+                        .about("ローソク足をファイルに書き出す")
+                        .args(&_clap_ohlcv_file())
+                        .arg(
+                            clap::Arg::with_name("from")
+                                .help("対象期間の開始unixtime")
+                                .long("from")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            clap::Arg::with_name("to")
+                                .help("対象期間の終了unixtime")
+                                .long("to")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("import")
+                        .about("ファイルのローソク足を確認して保存する(既存の足は更新する)")
+                        .args(&_clap_ohlcv_file()),
+                ),
+        )
+        .get_matches()
+}
+
+// コマンドを実行する
+fn actual_main(
+    atbdb: &atb_db::AtbDB,
+    atbconf: &read_atb_config::AtbConf,
+    args_matches: &clap::ArgMatches<'static>,
+) -> i32 {
+    let result = match args_matches.subcommand() {
+        ("migration", Some(args_matches)) => _migration(atbdb, atbconf, args_matches),
+        ("rollback", Some(args_matches)) => _rollback(atbdb, args_matches),
+        ("check", Some(_)) => _check(atbdb, atbconf),
+        ("version", Some(_)) => _version(atbdb),
+        ("market", Some(args_matches)) => _market(atbdb, args_matches),
+        ("gap", Some(args_matches)) => _gap(atbdb, args_matches),
+        ("ohlcv", Some(args_matches)) => _ohlcv(atbdb, args_matches),
+        ("retention", Some(args_matches)) => _retention(atbdb, atbconf, args_matches),
+        _ => return 1,
+    };
+
+    if let Err(err) = result {
+        error_msg(&err);
+        1
+    } else {
+        0
+    }
+}
+
+fn error_msg(msg: &str) {
+    eprintln!("[ERROR] {}", msg);
+}
+
+fn info_msg(msg: &str) {
+    println!("[INFO] {}", msg);
+}
+
+// --versionオプションを数値で取得する
+fn _get_version(args_matches: &clap::ArgMatches<'static>) -> Result<Option<i64>, String> {
+    match args_matches.value_of("version") {
+        Some(version) => version
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|_| format!("version must be integer: {}", version)),
+        None => Ok(None),
+    }
+}
+
+// Migrationコマンドを実行する
+fn _migration(
+    atbdb: &atb_db::AtbDB,
+    atbconf: &read_atb_config::AtbConf,
+    args_matches: &clap::ArgMatches<'static>,
+) -> Result<(), String> {
+    // バージョン指定がなければ設定ファイルのバージョンを対象にする
+    let target_version = match _get_version(args_matches)? {
+        Some(version) => version,
+        None => atbconf
+            .get_database_version()
+            .ok_or("database.version does not exists")?,
+    };
+
+    let applied = atbdb
+        .migrate(target_version)
+        .map_err(|err| err.to_string())?;
+    for version in applied {
+        info_msg(&format!("done - version:{}", version));
+    }
+    Ok(())
+}
+
+// Rollbackコマンドを実行する
+fn _rollback(
+    atbdb: &atb_db::AtbDB,
+    args_matches: &clap::ArgMatches<'static>,
+) -> Result<(), String> {
+    let target_version = _get_version(args_matches)?.ok_or("version is not exists")?;
+
+    let undone = atbdb
+        .rollback(target_version)
+        .map_err(|err| err.to_string())?;
+    for version in undone {
+        info_msg(&format!("done - version:{}", version));
+    }
+    Ok(())
+}
+
+// Checkコマンドを実行する
+fn _check(atbdb: &atb_db::AtbDB, atbconf: &read_atb_config::AtbConf) -> Result<(), String> {
+    atbdb
+        .check_schema_version(atbconf.get_database_version())
+        .map_err(|err| err.to_string())?;
+    atbdb.check_schema().map_err(|err| err.to_string())?;
+    info_msg("ok");
+    Ok(())
+}
+
+// Versionコマンドを実行する
+fn _version(atbdb: &atb_db::AtbDB) -> Result<(), String> {
+    let version_list = atbdb.get_version_list().map_err(|err| err.to_string())?;
+    for version in version_list {
+        info_msg(&format!(
+            "installed - version:{} - registered:{}",
+            version.get_version(),
+            version.get_registered()
+        ));
+    }
+    Ok(())
+}
+
+// 数値の引数を取得する
+fn _get_i64(args_matches: &clap::ArgMatches<'static>, name: &str) -> Result<Option<i64>, String> {
+    match args_matches.value_of(name) {
+        Some(value) => value
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|_| format!("{} must be integer: {}", name, value)),
+        None => Ok(None),
+    }
+}
+
+// 数値の引数を取得する(必須)
+fn _get_required<T: std::str::FromStr>(
+    args_matches: &clap::ArgMatches<'static>,
+    name: &str,
+) -> Result<T, String> {
+    let value = args_matches.value_of(name).unwrap();
+    value
+        .parse::<T>()
+        .map_err(|_| format!("{} is invalid: {}", name, value))
+}
+
+// Marketコマンドを実行する
+fn _market(atbdb: &atb_db::AtbDB, args_matches: &clap::ArgMatches<'static>) -> Result<(), String> {
+    match args_matches.subcommand() {
+        ("list", Some(_)) => {
+            let market_list = atbdb.get_market_list().map_err(|err| err.to_string())?;
+            for market in market_list {
+                info_msg(&format!(
+                    "market - {}/{} - {}/{} - {} - tick:{} - lot:{} - periods:{:?}",
+                    market.get_exchange(),
+                    market.get_pair(),
+                    market.get_base(),
+                    market.get_quote(),
+                    market.get_contract_type().as_str(),
+                    market.get_tick_size(),
+                    market.get_lot_size(),
+                    market.get_periods()
+                ));
+            }
+        }
+        ("add", Some(args_matches)) => {
+            let periods = match args_matches.values_of("periods") {
+                Some(values) => values
+                    .map(|value| {
+                        value
+                            .parse::<i64>()
+                            .map_err(|_| format!("period must be integer: {}", value))
+                    })
+                    .collect::<Result<Vec<i64>, String>>()?,
+                None => vec![],
+            };
+            let market = atb_db::Market::new(
+                args_matches.value_of("exchange").unwrap(),
+                args_matches.value_of("pair").unwrap(),
+                args_matches.value_of("base").unwrap(),
+                args_matches.value_of("quote").unwrap(),
+                _get_required(args_matches, "contract-type")?,
+                atb_db::MarketScale::new(
+                    _get_required(args_matches, "price-scale")?,
+                    _get_required(args_matches, "volume-scale")?,
+                ),
+                _get_required(args_matches, "tick-size")?,
+                _get_required(args_matches, "lot-size")?,
+            )
+            .periods(&periods);
+            atbdb
+                .insert_market(&market)
+                .map_err(|err| err.to_string())?;
+            info_msg("done");
+        }
+        ("enable", Some(args_matches)) => {
+            atbdb
+                .enable_market_period(
+                    args_matches.value_of("exchange").unwrap(),
+                    args_matches.value_of("pair").unwrap(),
+                    _get_required(args_matches, "period")?,
+                )
+                .map_err(|err| err.to_string())?;
+            info_msg("done");
+        }
+        ("disable", Some(args_matches)) => {
+            atbdb
+                .disable_market_period(
+                    args_matches.value_of("exchange").unwrap(),
+                    args_matches.value_of("pair").unwrap(),
+                    _get_required(args_matches, "period")?,
+                )
+                .map_err(|err| err.to_string())?;
+            info_msg("done");
+        }
+        ("fee", Some(args_matches)) => _market_fee(atbdb, args_matches)?,
+        _ => return Err("subcommand is not exists".to_string()),
+    }
+    Ok(())
+}
+
+// Market Feeコマンドを実行する
+fn _market_fee(
+    atbdb: &atb_db::AtbDB,
+    args_matches: &clap::ArgMatches<'static>,
+) -> Result<(), String> {
+    match args_matches.subcommand() {
+        ("list", Some(args_matches)) => {
+            let fee_list = atbdb
+                .get_market_fee_list(
+                    args_matches.value_of("exchange").unwrap(),
+                    args_matches.value_of("pair").unwrap(),
+                )
+                .map_err(|err| err.to_string())?;
+            for fee in fee_list {
+                info_msg(&format!(
+                    "fee - {}/{} - from:{} - maker:{} - taker:{} - spread:{}",
+                    fee.get_exchange(),
+                    fee.get_pair(),
+                    fee.get_valid_from(),
+                    fee.get_maker_fee(),
+                    fee.get_taker_fee(),
+                    fee.get_spread()
+                ));
+            }
+        }
+        ("set", Some(args_matches)) => {
+            let fee = atb_db::MarketFee::new(
+                args_matches.value_of("exchange").unwrap(),
+                args_matches.value_of("pair").unwrap(),
+                _get_i64(args_matches, "valid-from")?.unwrap_or(0),
+                _get_required(args_matches, "maker-fee")?,
+                _get_required(args_matches, "taker-fee")?,
+                _get_required(args_matches, "spread")?,
+            );
+            atbdb
+                .upsert_market_fee(&fee)
+                .map_err(|err| err.to_string())?;
+            info_msg("done");
+        }
+        ("delete", Some(args_matches)) => {
+            atbdb
+                .delete_market_fee(
+                    args_matches.value_of("exchange").unwrap(),
+                    args_matches.value_of("pair").unwrap(),
+                    _get_required(args_matches, "valid-from")?,
+                )
+                .map_err(|err| err.to_string())?;
+            info_msg("done");
+        }
+        _ => return Err("subcommand is not exists".to_string()),
+    }
+    Ok(())
+}
+
+// Gapコマンドを実行する
+fn _gap(atbdb: &atb_db::AtbDB, args_matches: &clap::ArgMatches<'static>) -> Result<(), String> {
+    let exchange = args_matches.value_of("exchange").unwrap();
+    let pair = args_matches.value_of("pair").unwrap();
+    let period = _get_i64(args_matches, "period")?.unwrap();
+
+    let mut query = atb_db::OhlcvQuery::new(exchange, pair, period);
+    if let Some(from) = _get_i64(args_matches, "from")? {
+        query = query.from_time(from);
+    }
+    if let Some(to) = _get_i64(args_matches, "to")? {
+        query = query.to_time(to);
+    }
+    let status = match args_matches.value_of("status") {
+        Some(status) => Some(status.parse::<atb_db::GapStatus>()?),
+        None => None,
+    };
+
+    if args_matches.is_present("detect") {
+        atbdb
+            .repair_ohlcv_gaps(exchange, pair, period)
+            .map_err(|err| err.to_string())?;
+        atbdb
+            .detect_ohlcv_gaps(&query)
+            .map_err(|err| err.to_string())?;
+    }
+
+    let gap_list = atbdb
+        .get_ohlcv_gap_list(&query, status)
+        .map_err(|err| err.to_string())?;
+    for gap in &gap_list {
+        info_msg(&format!(
+            "gap - from:{} - to:{} - count:{} - status:{}",
+            gap.get_from(),
+            gap.get_to(),
+            gap.get_missing_count(),
+            gap.get_status().as_str()
+        ));
+    }
+    info_msg(&format!("{} gaps", gap_list.len()));
+    Ok(())
+}
+
+// Ohlcvコマンドを実行する
+fn _ohlcv(atbdb: &atb_db::AtbDB, args_matches: &clap::ArgMatches<'static>) -> Result<(), String> {
+    let (name, args_matches) = match args_matches.subcommand() {
+        (name, Some(args_matches)) => (name, args_matches),
+        _ => return Err("subcommand is not exists".to_string()),
+    };
+    let exchange = args_matches.value_of("exchange").unwrap();
+    let pair = args_matches.value_of("pair").unwrap();
+    let period = _get_i64(args_matches, "period")?.unwrap();
+    let path = std::path::Path::new(args_matches.value_of("file").unwrap());
+
+    // ファイル形式の指定がなければ拡張子から判断する
+    let format = match args_matches.value_of("format") {
+        Some(format) => format.parse::<atb_db::OhlcvFileFormat>()?,
+        None => atb_db::OhlcvFileFormat::from_path(path)
+            .ok_or_else(|| format!("file format is unknown (use --format): {}", path.display()))?,
+    };
+
+    match name {
+        "export" => {
+            let mut query = atb_db::OhlcvQuery::new(exchange, pair, period);
+            if let Some(from) = _get_i64(args_matches, "from")? {
+                query = query.from_time(from);
+            }
+            if let Some(to) = _get_i64(args_matches, "to")? {
+                query = query.to_time(to);
+            }
+            let count = atbdb
+                .export_ohlcv_file(&query, format, path)
+                .map_err(|err| err.to_string())?;
+            info_msg(&format!("exported - {} - count:{}", path.display(), count));
+        }
+        "import" => {
+            let summary = atbdb
+                .import_ohlcv_file(exchange, pair, period, format, path)
+                .map_err(|err| err.to_string())?;
+            info_msg(&format!(
+                "imported - {} - inserted:{} - updated:{} - unchanged:{}",
+                path.display(),
+                summary.get_inserted(),
+                summary.get_updated(),
+                summary.get_unchanged()
+            ));
+        }
+        _ => return Err("subcommand is not exists".to_string()),
+    }
+    Ok(())
+}
+
+// Retentionコマンドを実行する
+fn _retention(
+    atbdb: &atb_db::AtbDB,
+    atbconf: &read_atb_config::AtbConf,
+    args_matches: &clap::ArgMatches<'static>,
+) -> Result<(), String> {
+    let policy = atb_db::RetentionPolicy::from_conf(atbconf).map_err(|err| err.to_string())?;
+    if policy.get_rules().is_empty() {
+        info_msg("retention.rules is empty");
+        return Ok(());
+    }
+    let now = match _get_i64(args_matches, "now")? {
+        Some(now) => now,
+        None => std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(|err| err.to_string())?
+            .as_secs() as i64,
+    };
+    let dry_run = args_matches.is_present("dry-run");
+
+    let report = atbdb
+        .apply_retention(&policy, atb_db::UnixTime::new(now), dry_run)
+        .map_err(|err| err.to_string())?;
+    let label = if dry_run { "dry-run" } else { "done" };
+    for target in report.get_targets() {
+        for year in target.get_years() {
+            let archive = year
+                .get_archive_file()
+                .map(|path| format!(" - archive:{}", path.display()))
+                .unwrap_or_default();
+            info_msg(&format!(
+                "{} - {}/{}/{} - {} - year:{} - from:{} - to:{} - count:{}{}",
+                label,
+                target.get_exchange(),
+                target.get_pair(),
+                target.get_period(),
+                target.get_action().as_str(),
+                year.get_year(),
+                year.get_from(),
+                year.get_to(),
+                year.get_count(),
+                archive
+            ));
+        }
+        if 0 < target.get_gap_count() {
+            info_msg(&format!(
+                "{} - {}/{}/{} - gaps:{}",
+                label,
+                target.get_exchange(),
+                target.get_pair(),
+                target.get_period(),
+                target.get_gap_count()
+            ));
+        }
+    }
+    info_msg(&format!(
+        "{} - total:{} - vacuum:{}",
+        label,
+        report.get_count(),
+        report.is_vacuumed()
+    ));
+    Ok(())
+}
@@ -1,30 +1,42 @@
 extern crate atb_db;
 extern crate clap;
+extern crate read_atb_config;
 
 // コマンドの実行モード
 enum Command {
     Add,
     Update,
-    Remove,
+    Archive,
+    Restore,
+    Purge,
     Get,
     List,
-    NoCommand,
+    TokenRotate,
+    History,
+    Promote,
+    Demote,
+    EvaluationAdd,
+    EvaluationList,
+    Promotions,
+    Nothing,
 }
 
 // コマンドの実行モードとオプションを格納する構造体
 struct Config {
     command: Command,
     option: std::collections::HashMap<String, String>,
+    actor: atb_db::Actor,
 }
 
 fn main() {
     // 対象データベースに接続する
-    let result_atbdb = atb_db::AtbDB::connect(None);
-    if let Err(err) = result_atbdb {
-        eprintln!("{}", err);
-        std::process::exit(1);
-    }
-    let atbdb = result_atbdb.unwrap();
+    let atbdb = match atb_db::AtbDB::connect(None) {
+        Ok(atbdb) => atbdb,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(exit_code(&err));
+        }
+    };
 
     // コマンドライン引数を取得する
     let args_matches = get_args_matches();
@@ -77,26 +89,62 @@ fn _clap_short_order() -> clap::Arg<'static, 'static> {
         .takes_value(true)
 }
 
-fn _clap_operation() -> clap::Arg<'static, 'static> {
-    clap::Arg::with_name("operation")
-        .help("運用")
-        .long("operation")
+fn _clap_operate_type() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("operate_type")
+        .help("運用段階")
+        .long("operate_type")
         .possible_values(&["backtest", "forwardtest", "product"])
         .takes_value(true)
 }
 
+fn _clap_integer(name: &'static str, help: &'static str) -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name(name)
+        .help(help)
+        .long(name)
+        .takes_value(true)
+        .allow_hyphen_values(true)
+        .validator(|v| {
+            v.parse::<i64>()
+                .map(|_| ())
+                .map_err(|_| format!("数値ではありません: {}", v))
+        })
+}
+
+fn _clap_id() -> clap::Arg<'static, 'static> {
+    clap::Arg::with_name("id")
+        .help("対象bot id")
+        .required(true)
+        .validator(|v| {
+            v.parse::<i64>()
+                .map(|_| ())
+                .map_err(|_| format!("bot idが数値ではありません: {}", v))
+        })
+}
+
 fn _clap_output() -> clap::ArgGroup<'static> {
     clap::ArgGroup::with_name("output").args(&["json", "yaml"])
 }
 
 // コマンドライン引数を取得する
 fn get_args_matches() -> clap::ArgMatches<'static> {
+    build_app().get_matches()
+}
+
+// コマンドライン引数の定義
+fn build_app() -> clap::App<'static, 'static> {
     clap::App::new("bot-admin")
         .version("0.0.1")
         .author("Didy KUPANHY")
         .about("bot管理コマンド")
         .setting(clap::AppSettings::ArgRequiredElseHelp)
         .setting(clap::AppSettings::DeriveDisplayOrder)
+        .arg(
+            clap::Arg::with_name("actor")
+                .help("変更履歴に記録する操作者名(デフォルトは環境変数USER)")
+                .long("actor")
+                .takes_value(true)
+                .global(true),
+        )
         .subcommand(
             clap::SubCommand::with_name("add")
                 .about("管理するbotを追加する")
@@ -105,25 +153,41 @@ fn get_args_matches() -> clap::ArgMatches<'static> {
                 .arg(_clap_description().required(true))
                 .arg(_clap_enable())
                 .arg(_clap_long_order())
-                .arg(_clap_short_order())
-                .arg(_clap_operation()),
+                .arg(_clap_short_order()),
         )
         .subcommand(
             clap::SubCommand::with_name("update")
                 .about("対象botの情報を更新する")
                 .setting(clap::AppSettings::DeriveDisplayOrder)
-                .arg(clap::Arg::with_name("id").help("対象bot id").required(true))
+                .arg(_clap_id())
                 .arg(_clap_name())
                 .arg(_clap_description())
                 .arg(_clap_enable())
                 .arg(_clap_long_order())
-                .arg(_clap_short_order())
-                .arg(_clap_operation()),
+                .arg(_clap_short_order()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("archive")
+                .about("対象botをアーカイブする(一覧・APIから除外する)")
+                .alias("remove")
+                .arg(_clap_id()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("restore")
+                .about("アーカイブした対象botを復元する")
+                .arg(_clap_id()),
         )
         .subcommand(
-            clap::SubCommand::with_name("remove")
-                .about("管理から対象botを取り除く")
-                .arg(clap::Arg::with_name("id").help("対象bot id").required(true)),
+            clap::SubCommand::with_name("purge")
+                .about(
+                    "アーカイブした対象botと評価結果を完全に削除する(変更履歴は残す。元に戻せない)",
+                )
+                .arg(_clap_id())
+                .arg(
+                    clap::Arg::with_name("yes")
+                        .help("完全削除を確認する")
+                        .long("yes"),
+                ),
         )
         .subcommand(
             clap::SubCommand::with_name("get")
@@ -132,7 +196,7 @@ fn get_args_matches() -> clap::ArgMatches<'static> {
                     "-j, --json 'json mode: output group'
                                   -y, --yaml 'yaml mode: output group'",
                 )
-                .arg(clap::Arg::with_name("id").help("対象bot id").required(true))
+                .arg(_clap_id())
                 .group(_clap_output()),
         )
         .subcommand(
@@ -142,9 +206,99 @@ fn get_args_matches() -> clap::ArgMatches<'static> {
                     "-j, --json 'json mode: output group'
                                   -y, --yaml 'yaml mode: output group'",
                 )
+                .arg(
+                    clap::Arg::with_name("archived")
+                        .help("アーカイブしたbotも表示する")
+                        .long("archived"),
+                )
+                .group(_clap_output().required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("token")
+                .about("botのアクセス用トークンを管理する")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("rotate")
+                        .about("トークンを再発行する(以前のトークンは使用できなくなる)")
+                        .arg(_clap_id()),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("history")
+                .about("対象botの変更履歴を新しい順に表示する")
+                .args_from_usage(
+                    "-j, --json 'json mode: output group'
+                                  -y, --yaml 'yaml mode: output group'",
+                )
+                .arg(_clap_id())
+                .arg(
+                    clap::Arg::with_name("limit")
+                        .help("表示する件数")
+                        .long("limit")
+                        .takes_value(true)
+                        .validator(|v| {
+                            v.parse::<usize>()
+                                .map(|_| ())
+                                .map_err(|_| format!("件数が数値ではありません: {}", v))
+                        }),
+                )
+                .group(_clap_output().required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("promote")
+                .about("評価結果が昇格条件を満たしていれば、対象botの運用段階を1つ上げる")
+                .arg(_clap_id()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("demote")
+                .about("対象botの運用段階を下げる")
+                .arg(_clap_id())
+                .arg(_clap_operate_type().required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("evaluation")
+                .about("botの評価結果(昇格の根拠)を管理する")
+                .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    clap::SubCommand::with_name("add")
+                        .about("評価結果を登録する")
+                        .setting(clap::AppSettings::DeriveDisplayOrder)
+                        .arg(_clap_id())
+                        .arg(_clap_operate_type().required(true))
+                        .arg(_clap_integer("trades", "取引回数").required(true))
+                        .arg(
+                            clap::Arg::with_name("max_drawdown")
+                                .help("最大ドローダウン(比率、0.2 = 20%)")
+                                .long("max_drawdown")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .arg(
+                            _clap_integer("started", "評価期間の開始日時(unixtime)").required(true),
+                        )
+                        .arg(_clap_integer("ended", "評価期間の終了日時(unixtime)").required(true)),
+                )
+                .subcommand(
+                    clap::SubCommand::with_name("list")
+                        .about("評価結果を新しい順に表示する")
+                        .args_from_usage(
+                            "-j, --json 'json mode: output group'
+                                          -y, --yaml 'yaml mode: output group'",
+                        )
+                        .arg(_clap_id())
+                        .group(_clap_output().required(true)),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("promotions")
+                .about("対象botの運用段階の変更履歴を新しい順に表示する")
+                .args_from_usage(
+                    "-j, --json 'json mode: output group'
+                                  -y, --yaml 'yaml mode: output group'",
+                )
+                .arg(_clap_id())
                 .group(_clap_output().required(true)),
         )
-        .get_matches()
 }
 
 fn _get_option(
@@ -169,8 +323,8 @@ fn _get_option(
         }
     }
 
-    // json/yamlオプションを取得
-    for key in ["json", "yaml"].iter() {
+    // json/yamlなどのフラグを取得
+    for key in ["json", "yaml", "archived", "yes"].iter() {
         if args_matches.is_present(key) {
             option.insert(String::from(*key), "1".to_string());
         }
@@ -181,213 +335,613 @@ fn _get_option(
 
 // 実行コマンドを取得する
 fn get_config(args_matches: clap::ArgMatches<'static>) -> Config {
+    // 変更履歴に記録する操作者
+    let actor = _get_actor(&args_matches);
+
     // Addコマンドのオプション取得
-    if let Some(ref args_matches) = args_matches.subcommand_matches("add") {
+    if let Some(args_matches) = args_matches.subcommand_matches("add") {
         // サブコマンドのオプションのリスト
         let must_keys = vec!["name", "description"];
-        let optional_keys = vec!["enable", "long_order", "short_order", "operation"];
+        let optional_keys = vec!["enable", "long_order", "short_order"];
 
         // サブコマンドのオプションを取得する
-        let option = _get_option(&args_matches, &must_keys, &optional_keys);
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
 
         return Config {
             command: Command::Add,
-            option: option,
+            option,
+            actor,
         };
     }
 
     // Updateコマンドのオプション取得
-    if let Some(ref args_matches) = args_matches.subcommand_matches("update") {
+    if let Some(args_matches) = args_matches.subcommand_matches("update") {
         // サブコマンドのオプションのリスト
         let must_keys = vec!["id"];
-        let optional_keys = vec![
-            "id",
-            "name",
-            "description",
-            "enable",
-            "long_order",
-            "short_order",
-            "operation",
-        ];
+        let optional_keys = vec!["name", "description", "enable", "long_order", "short_order"];
 
         // サブコマンドのオプションを取得する
-        let option = _get_option(&args_matches, &must_keys, &optional_keys);
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
 
         return Config {
             command: Command::Update,
-            option: option,
+            option,
+            actor,
+        };
+    }
+
+    // Archiveコマンドのオプション取得
+    if let Some(args_matches) = args_matches.subcommand_matches("archive") {
+        // サブコマンドのオプションのリスト
+        let must_keys = vec!["id"];
+        let optional_keys = vec![];
+
+        // サブコマンドのオプションを取得する
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
+
+        return Config {
+            command: Command::Archive,
+            option,
+            actor,
+        };
+    }
+
+    // Restoreコマンドのオプション取得
+    if let Some(args_matches) = args_matches.subcommand_matches("restore") {
+        // サブコマンドのオプションのリスト
+        let must_keys = vec!["id"];
+        let optional_keys = vec![];
+
+        // サブコマンドのオプションを取得する
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
+
+        return Config {
+            command: Command::Restore,
+            option,
+            actor,
         };
     }
 
-    // Removeコマンドのオプション取得
-    if let Some(ref args_matches) = args_matches.subcommand_matches("remove") {
+    // Purgeコマンドのオプション取得
+    if let Some(args_matches) = args_matches.subcommand_matches("purge") {
         // サブコマンドのオプションのリスト
         let must_keys = vec!["id"];
         let optional_keys = vec![];
 
         // サブコマンドのオプションを取得する
-        let option = _get_option(&args_matches, &must_keys, &optional_keys);
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
 
         return Config {
-            command: Command::Remove,
-            option: option,
+            command: Command::Purge,
+            option,
+            actor,
         };
     }
 
     // Getコマンドのオプション取得
-    if let Some(ref args_matches) = args_matches.subcommand_matches("get") {
+    if let Some(args_matches) = args_matches.subcommand_matches("get") {
         // サブコマンドのオプションのリスト
         let must_keys = vec!["id"];
         let optional_keys = vec![];
 
         // サブコマンドのオプションを取得する
-        let option = _get_option(&args_matches, &must_keys, &optional_keys);
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
 
         return Config {
             command: Command::Get,
-            option: option,
+            option,
+            actor,
         };
     }
 
     // Listコマンドのオプション取得
-    if let Some(ref args_matches) = args_matches.subcommand_matches("list") {
+    if let Some(args_matches) = args_matches.subcommand_matches("list") {
         // サブコマンドのオプションのリスト
         let must_keys = vec![];
         let optional_keys = vec![];
 
         // サブコマンドのオプションを取得する
-        let option = _get_option(&args_matches, &must_keys, &optional_keys);
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
 
         return Config {
             command: Command::List,
-            option: option,
+            option,
+            actor,
+        };
+    }
+
+    // Token rotateコマンドのオプション取得
+    if let Some(args_matches) = args_matches
+        .subcommand_matches("token")
+        .and_then(|args_matches| args_matches.subcommand_matches("rotate"))
+    {
+        // サブコマンドのオプションのリスト
+        let must_keys = vec!["id"];
+        let optional_keys = vec![];
+
+        // サブコマンドのオプションを取得する
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
+
+        return Config {
+            command: Command::TokenRotate,
+            option,
+            actor,
+        };
+    }
+
+    // Historyコマンドのオプション取得
+    if let Some(args_matches) = args_matches.subcommand_matches("history") {
+        // サブコマンドのオプションのリスト
+        let must_keys = vec!["id"];
+        let optional_keys = vec!["limit"];
+
+        // サブコマンドのオプションを取得する
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
+
+        return Config {
+            command: Command::History,
+            option,
+            actor,
+        };
+    }
+
+    // Promoteコマンドのオプション取得
+    if let Some(args_matches) = args_matches.subcommand_matches("promote") {
+        // サブコマンドのオプションのリスト
+        let must_keys = vec!["id"];
+        let optional_keys = vec![];
+
+        // サブコマンドのオプションを取得する
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
+
+        return Config {
+            command: Command::Promote,
+            option,
+            actor,
+        };
+    }
+
+    // Demoteコマンドのオプション取得
+    if let Some(args_matches) = args_matches.subcommand_matches("demote") {
+        // サブコマンドのオプションのリスト
+        let must_keys = vec!["id", "operate_type"];
+        let optional_keys = vec![];
+
+        // サブコマンドのオプションを取得する
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
+
+        return Config {
+            command: Command::Demote,
+            option,
+            actor,
+        };
+    }
+
+    // Evaluation addコマンドのオプション取得
+    if let Some(args_matches) = args_matches
+        .subcommand_matches("evaluation")
+        .and_then(|args_matches| args_matches.subcommand_matches("add"))
+    {
+        // サブコマンドのオプションのリスト
+        let must_keys = vec![
+            "id",
+            "operate_type",
+            "trades",
+            "max_drawdown",
+            "started",
+            "ended",
+        ];
+        let optional_keys = vec![];
+
+        // サブコマンドのオプションを取得する
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
+
+        return Config {
+            command: Command::EvaluationAdd,
+            option,
+            actor,
+        };
+    }
+
+    // Evaluation listコマンドのオプション取得
+    if let Some(args_matches) = args_matches
+        .subcommand_matches("evaluation")
+        .and_then(|args_matches| args_matches.subcommand_matches("list"))
+    {
+        // サブコマンドのオプションのリスト
+        let must_keys = vec!["id"];
+        let optional_keys = vec![];
+
+        // サブコマンドのオプションを取得する
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
+
+        return Config {
+            command: Command::EvaluationList,
+            option,
+            actor,
+        };
+    }
+
+    // Promotionsコマンドのオプション取得
+    if let Some(args_matches) = args_matches.subcommand_matches("promotions") {
+        // サブコマンドのオプションのリスト
+        let must_keys = vec!["id"];
+        let optional_keys = vec![];
+
+        // サブコマンドのオプションを取得する
+        let option = _get_option(args_matches, &must_keys, &optional_keys);
+
+        return Config {
+            command: Command::Promotions,
+            option,
+            actor,
         };
     }
 
     let option = std::collections::HashMap::new();
     Config {
-        command: Command::NoCommand,
-        option: option,
+        command: Command::Nothing,
+        option,
+        actor,
+    }
+}
+
+// 操作者を取得する(--actorの指定がなければ環境変数のユーザー)
+fn _get_actor(args_matches: &clap::ArgMatches<'static>) -> atb_db::Actor {
+    let actor = args_matches.value_of("actor").or_else(|| {
+        args_matches
+            .subcommand()
+            .1
+            .and_then(|args_matches| args_matches.value_of("actor"))
+    });
+    match actor {
+        Some(actor) => atb_db::Actor::cli(actor),
+        None => atb_db::Actor::from_env(),
     }
 }
 
 // コマンドを実行する
 fn actual_main(atbdb: &atb_db::AtbDB, config: Config) -> i32 {
     let result = match config.command {
-        Command::Add => _add(atbdb, &config.option),
-        Command::Update => _update(atbdb, &config.option),
-        Command::Remove => _remove(atbdb, config.option),
+        Command::Add => _add(atbdb, &config.option, &config.actor).map(|bot| {
+            _print_token(&bot);
+            bot.get_id() as usize
+        }),
+        Command::Update => {
+            _update(atbdb, &config.option, &config.actor).map(|bot| bot.get_id() as usize)
+        }
+        Command::Archive => _archive(atbdb, config.option, &config.actor),
+        Command::Restore => _restore(atbdb, config.option, &config.actor),
+        Command::Purge => _purge(atbdb, config.option, &config.actor),
         Command::Get => _get(atbdb, config.option),
         Command::List => _list(atbdb, config.option),
-        _ => Err(atb_db::SqliteError::ExecuteReturnedResults),
+        Command::TokenRotate => _token_rotate(atbdb, config.option, &config.actor),
+        Command::History => _history(atbdb, config.option),
+        Command::Promote => _promote(atbdb, config.option, &config.actor),
+        Command::Demote => _demote(atbdb, config.option, &config.actor),
+        Command::EvaluationAdd => _evaluation_add(atbdb, config.option),
+        Command::EvaluationList => _evaluation_list(atbdb, config.option),
+        Command::Promotions => _promotions(atbdb, config.option),
+        Command::Nothing => return 1,
     };
 
     if let Err(err) = result {
         eprintln!("{}", err);
-        1
+        exit_code(&err)
     } else {
         0
     }
 }
 
+// エラーの種類に応じた終了コードを返す(sysexits.h準拠)
+fn exit_code(err: &atb_db::AtbDbError) -> i32 {
+    match err {
+        atb_db::AtbDbError::Config(_) => 78,            // EX_CONFIG
+        atb_db::AtbDbError::Pool(_) => 69,              // EX_UNAVAILABLE
+        atb_db::AtbDbError::Sqlite(_) => 74,            // EX_IOERR
+        atb_db::AtbDbError::SchemaVersion { .. } => 78, // EX_CONFIG
+        atb_db::AtbDbError::Schema(_) => 78,            // EX_CONFIG
+        atb_db::AtbDbError::Migration(_) => 78,         // EX_CONFIG
+        atb_db::AtbDbError::Decode(_) => 65,            // EX_DATAERR
+        atb_db::AtbDbError::Validation(_) => 65,        // EX_DATAERR
+        atb_db::AtbDbError::NotFound => 66,             // EX_NOINPUT
+        atb_db::AtbDbError::Busy => 75,                 // EX_TEMPFAIL
+        atb_db::AtbDbError::Timeout(_) => 75,           // EX_TEMPFAIL
+        atb_db::AtbDbError::Panic(_) => 70,             // EX_SOFTWARE
+        atb_db::AtbDbError::Io(_) => 74,                // EX_IOERR
+    }
+}
+
 // Addコマンドを実行する
 fn _add(
     atbdb: &atb_db::AtbDB,
     option: &std::collections::HashMap<String, String>,
-) -> Result<usize, atb_db::SqliteError> {
-    let mut option = option.clone();
+    actor: &atb_db::Actor,
+) -> Result<atb_db::Bot, atb_db::AtbDbError> {
+    let mut bot = atb_db::BotCreate::new(&option["name"], &option["description"]);
 
-    // enableが指定されていれば、enable値を1/0に変換する
-    if let Some(v) = option.get("enable") {
-        let value = if v == "true" { "1" } else { "0" }.to_string();
-        option.insert("enable".to_string(), value);
+    // 指定された項目だけデフォルト値から変更する
+    if let Some(enable) = _get_bool(option, "enable")? {
+        bot = bot.enable(enable);
     }
-
-    // long_orderが指定されていれば、値を1/0に変換する
-    if let Some(v) = option.get("long_order") {
-        let value = if v == "true" { "1" } else { "0" }.to_string();
-        option.insert("long_order".to_string(), value);
+    if let Some(long_order) = _get_bool(option, "long_order")? {
+        bot = bot.long_order(long_order);
     }
-
-    // short_orderが指定されていれば、値を1/0に変換する
-    if let Some(v) = option.get("short_order") {
-        let value = if v == "true" { "1" } else { "0" }.to_string();
-        option.insert("short_order".to_string(), value);
+    if let Some(short_order) = _get_bool(option, "short_order")? {
+        bot = bot.short_order(short_order);
     }
 
     // 新しいbotデータを追加する
-    atbdb.insert_bot(&option)
+    atbdb.insert_bot(&bot, actor)
 }
 
 // Updateコマンドを実行する
 fn _update(
     atbdb: &atb_db::AtbDB,
     option: &std::collections::HashMap<String, String>,
-) -> Result<usize, atb_db::SqliteError> {
-    let mut option = option.clone();
+    actor: &atb_db::Actor,
+) -> Result<atb_db::Bot, atb_db::AtbDbError> {
+    let mut patch = atb_db::BotPatch::new();
+
+    // 指定された項目だけ更新する
+    if let Some(name) = option.get("name") {
+        patch = patch.name(name);
+    }
+    if let Some(description) = option.get("description") {
+        patch = patch.description(description);
+    }
+    if let Some(enable) = _get_bool(option, "enable")? {
+        patch = patch.enable(enable);
+    }
+    if let Some(long_order) = _get_bool(option, "long_order")? {
+        patch = patch.long_order(long_order);
+    }
+    if let Some(short_order) = _get_bool(option, "short_order")? {
+        patch = patch.short_order(short_order);
+    }
+
+    // 対象botデータを更新する
+    atbdb.update_bot(_get_id(option)?, &patch, actor)
+}
 
-    // idを取得する
-    let id_value = option.remove("id");
+// Archiveコマンドを実行する
+fn _archive(
+    atbdb: &atb_db::AtbDB,
+    option: std::collections::HashMap<String, String>,
+    actor: &atb_db::Actor,
+) -> Result<usize, atb_db::AtbDbError> {
+    // 対象botをアーカイブする
+    let bot = atbdb.archive_bot(_get_id(&option)?, actor)?;
+    Ok(bot.get_id() as usize)
+}
 
-    // enableが指定されていれば、値を1/0に変換する
-    if let Some(v) = option.get("enable") {
-        let value = if v == "true" { "1" } else { "0" }.to_string();
-        option.insert("enable".to_string(), value);
+// Restoreコマンドを実行する
+fn _restore(
+    atbdb: &atb_db::AtbDB,
+    option: std::collections::HashMap<String, String>,
+    actor: &atb_db::Actor,
+) -> Result<usize, atb_db::AtbDbError> {
+    // アーカイブした対象botを復元する
+    let bot = atbdb.restore_bot(_get_id(&option)?, actor)?;
+    Ok(bot.get_id() as usize)
+}
+
+// Purgeコマンドを実行する
+fn _purge(
+    atbdb: &atb_db::AtbDB,
+    option: std::collections::HashMap<String, String>,
+    actor: &atb_db::Actor,
+) -> Result<usize, atb_db::AtbDbError> {
+    // 元に戻せないため、--yesの指定がなければ削除しない
+    let id = _get_id(&option)?;
+    if !option.contains_key("yes") {
+        return Err(atb_db::AtbDbError::Validation(format!(
+            "bot id: {} を完全に削除するには--yesを指定してください",
+            id
+        )));
     }
 
-    // long_orderが指定されていれば、値を1/0に変換する
-    if let Some(v) = option.get("long_order") {
-        let value = if v == "true" { "1" } else { "0" }.to_string();
-        option.insert("long_order".to_string(), value);
+    // 対象botと関連データを削除し、テーブルごとの件数を表示する
+    let deleted = atbdb.purge_bot(id, actor)?;
+    for (table, count) in &deleted {
+        println!("{}: {}", table, count);
     }
+    Ok(deleted.iter().map(|(_, count)| count).sum())
+}
+
+// Token rotateコマンドを実行する
+fn _token_rotate(
+    atbdb: &atb_db::AtbDB,
+    option: std::collections::HashMap<String, String>,
+    actor: &atb_db::Actor,
+) -> Result<usize, atb_db::AtbDbError> {
+    // 対象botのトークンを再発行する
+    let bot = atbdb.rotate_bot_token(_get_id(&option)?, actor)?;
+    _print_token(&bot);
+    Ok(bot.get_id() as usize)
+}
 
-    // short_orderが指定されていれば、値を1/0に変換する
-    if let Some(v) = option.get("short_order") {
-        let value = if v == "true" { "1" } else { "0" }.to_string();
-        option.insert("short_order".to_string(), value);
+// 発行したトークンを表示する(平文は保存されないため、この時しか表示できない)
+fn _print_token(bot: &atb_db::Bot) {
+    if let Some(token) = bot.get_token() {
+        println!("bot id: {}", bot.get_id());
+        println!("token: {}", token);
+        eprintln!("トークンは再表示できません。安全な場所に保管してください");
     }
+}
 
-    // 対象botデータを更新する
-    atbdb.update_bot(&id_value.unwrap(), &option)
+// オプションのbot idを取得する
+fn _get_id(option: &std::collections::HashMap<String, String>) -> Result<i64, atb_db::AtbDbError> {
+    let id = &option["id"];
+    id.parse::<i64>()
+        .map_err(|_| atb_db::AtbDbError::Validation(format!("bot idが数値ではありません: {}", id)))
+}
+
+// true/falseのオプションを取得する
+fn _get_bool(
+    option: &std::collections::HashMap<String, String>,
+    key: &str,
+) -> Result<Option<bool>, atb_db::AtbDbError> {
+    option
+        .get(key)
+        .map(|value| {
+            value.parse::<bool>().map_err(|_| {
+                atb_db::AtbDbError::Validation(format!(
+                    "{}はtrue/falseで指定してください: {}",
+                    key, value
+                ))
+            })
+        })
+        .transpose()
 }
 
-// Removeコマンドを実行する
-fn _remove(
+// 運用段階のオプションを取得する
+fn _get_operate_type(
+    option: &std::collections::HashMap<String, String>,
+) -> Result<atb_db::OperateType, atb_db::AtbDbError> {
+    option["operate_type"]
+        .parse()
+        .map_err(atb_db::AtbDbError::Validation)
+}
+
+// 数値のオプションを取得する
+fn _get_i64(
+    option: &std::collections::HashMap<String, String>,
+    key: &str,
+) -> Result<i64, atb_db::AtbDbError> {
+    let value = &option[key];
+    value.parse::<i64>().map_err(|_| {
+        atb_db::AtbDbError::Validation(format!("{}が数値ではありません: {}", key, value))
+    })
+}
+
+// 設定ファイルから昇格条件を取得する(設定ファイルがなければデフォルト値)
+fn _get_promotion_rule() -> Result<atb_db::PromotionRule, atb_db::AtbDbError> {
+    match read_atb_config::AtbConf::load_conf() {
+        Some(atbconf) => atb_db::PromotionRule::from_conf(&atbconf),
+        None => Ok(atb_db::PromotionRule::default()),
+    }
+}
+
+// Promoteコマンドを実行する
+fn _promote(
     atbdb: &atb_db::AtbDB,
     option: std::collections::HashMap<String, String>,
-) -> Result<usize, atb_db::SqliteError> {
-    // idを取得する
-    let id_value = option.get("id");
+    actor: &atb_db::Actor,
+) -> Result<usize, atb_db::AtbDbError> {
+    // 昇格条件を満たしていれば運用段階を上げる
+    let rule = _get_promotion_rule()?;
+    let promotion = atbdb.promote_bot(_get_id(&option)?, &rule, actor)?;
+    println!(
+        "{} -> {}",
+        promotion.get_from_type(),
+        promotion.get_to_type()
+    );
+    Ok(promotion.get_bot_id() as usize)
+}
 
-    // 対象botデータを削除する
-    atbdb.delete_bot(&id_value.unwrap())
+// Demoteコマンドを実行する
+fn _demote(
+    atbdb: &atb_db::AtbDB,
+    option: std::collections::HashMap<String, String>,
+    actor: &atb_db::Actor,
+) -> Result<usize, atb_db::AtbDbError> {
+    // 指定した運用段階に下げる
+    let promotion = atbdb.demote_bot(_get_id(&option)?, _get_operate_type(&option)?, actor)?;
+    println!(
+        "{} -> {}",
+        promotion.get_from_type(),
+        promotion.get_to_type()
+    );
+    Ok(promotion.get_bot_id() as usize)
 }
 
-// Getコマンドを実行する
-fn _get(
+// Evaluation addコマンドを実行する
+fn _evaluation_add(
     atbdb: &atb_db::AtbDB,
     option: std::collections::HashMap<String, String>,
-) -> Result<usize, atb_db::SqliteError> {
-    // idを取得する
-    let id_value = option.get("id");
+) -> Result<usize, atb_db::AtbDbError> {
+    let max_drawdown = option["max_drawdown"]
+        .parse::<atb_db::Decimal>()
+        .map_err(atb_db::AtbDbError::Validation)?;
+    let evaluation = atb_db::BotEvaluationCreate::new(
+        _get_operate_type(&option)?,
+        _get_i64(&option, "trades")?,
+        max_drawdown,
+        _get_i64(&option, "started")?,
+        _get_i64(&option, "ended")?,
+    );
 
-    // 対象botデータを削除する
-    let result = atbdb.get_bot(&id_value.unwrap());
-    if let Err(err) = result {
-        return Err(err);
+    // 評価結果を登録する
+    let evaluation = atbdb.insert_bot_evaluation(_get_id(&option)?, &evaluation)?;
+    Ok(evaluation.get_id() as usize)
+}
+
+// Evaluation listコマンドを実行する
+fn _evaluation_list(
+    atbdb: &atb_db::AtbDB,
+    option: std::collections::HashMap<String, String>,
+) -> Result<usize, atb_db::AtbDbError> {
+    // 対象botの評価結果を取得する
+    let evaluation_list = atbdb.get_bot_evaluation_list(_get_id(&option)?)?;
+    let evaluation_list_len = evaluation_list.len();
+
+    // jsonが指定されていればjson形式で返す
+    if option.contains_key("json") {
+        println!("{}", serde_json::to_string(&evaluation_list).unwrap());
+        return Ok(evaluation_list_len);
+    }
+
+    // yamlが指定されていればyaml形式で返す
+    if option.contains_key("yaml") {
+        println!("{}", serde_yaml::to_string(&evaluation_list).unwrap());
+        return Ok(evaluation_list_len);
+    }
+
+    Ok(evaluation_list_len)
+}
+
+// Promotionsコマンドを実行する
+fn _promotions(
+    atbdb: &atb_db::AtbDB,
+    option: std::collections::HashMap<String, String>,
+) -> Result<usize, atb_db::AtbDbError> {
+    // 対象botの運用段階の変更履歴を取得する
+    let promotion_list = atbdb.get_bot_promotion_list(_get_id(&option)?)?;
+    let promotion_list_len = promotion_list.len();
+
+    // jsonが指定されていればjson形式で返す
+    if option.contains_key("json") {
+        println!("{}", serde_json::to_string(&promotion_list).unwrap());
+        return Ok(promotion_list_len);
+    }
+
+    // yamlが指定されていればyaml形式で返す
+    if option.contains_key("yaml") {
+        println!("{}", serde_yaml::to_string(&promotion_list).unwrap());
+        return Ok(promotion_list_len);
     }
 
-    let bot = result.unwrap();
+    Ok(promotion_list_len)
+}
+
+// Getコマンドを実行する
+fn _get(
+    atbdb: &atb_db::AtbDB,
+    option: std::collections::HashMap<String, String>,
+) -> Result<usize, atb_db::AtbDbError> {
+    // 対象botデータを取得する
+    let bot = atbdb.get_bot(_get_id(&option)?)?;
     let bot_id = bot.get_id() as usize;
 
     // jsonが指定されていればjson形式で返す
-    if option.get("json").is_some() {
+    if option.contains_key("json") {
         println!("{}", serde_json::to_string(&bot).unwrap());
         return Ok(bot_id);
     }
 
     // yamlが指定されていればyaml形式で返す
-    if option.get("yaml").is_some() {
+    if option.contains_key("yaml") {
         println!("{}", serde_yaml::to_string(&bot).unwrap());
         return Ok(bot_id);
     }
@@ -399,27 +953,186 @@ fn _get(
 fn _list(
     atbdb: &atb_db::AtbDB,
     option: std::collections::HashMap<String, String>,
-) -> Result<usize, atb_db::SqliteError> {
-    // botデータの一覧を取得する
-    let result = atbdb.get_bot_list();
-    if let Err(err) = result {
-        return Err(err);
-    }
-
-    let bot_list = result.unwrap();
+) -> Result<usize, atb_db::AtbDbError> {
+    // botデータの一覧を取得する(--archivedの指定があればアーカイブしたbotも含める)
+    let bot_list = if option.contains_key("archived") {
+        atbdb.get_all_bot_list()?
+    } else {
+        atbdb.get_bot_list()?
+    };
     let bot_list_len = bot_list.get_list_len();
 
     // jsonが指定されていればjson形式で返す
-    if option.get("json").is_some() {
+    if option.contains_key("json") {
         println!("{}", serde_json::to_string(&bot_list).unwrap());
         return Ok(bot_list_len);
     }
 
     // yamlが指定されていればyaml形式で返す
-    if option.get("yaml").is_some() {
+    if option.contains_key("yaml") {
         println!("{}", serde_yaml::to_string(&bot_list).unwrap());
         return Ok(bot_list_len);
     }
 
     Ok(bot_list_len)
 }
+
+// Historyコマンドを実行する
+fn _history(
+    atbdb: &atb_db::AtbDB,
+    option: std::collections::HashMap<String, String>,
+) -> Result<usize, atb_db::AtbDbError> {
+    // 件数の指定がなければすべて取得する
+    let limit = match option.get("limit") {
+        Some(limit) => Some(limit.parse::<usize>().map_err(|_| {
+            atb_db::AtbDbError::Validation(format!("件数が数値ではありません: {}", limit))
+        })?),
+        None => None,
+    };
+
+    // 対象botの変更履歴を取得する
+    let history = atbdb.get_bot_history(_get_id(&option)?, limit)?;
+    let history_len = history.len();
+
+    // jsonが指定されていればjson形式で返す
+    if option.contains_key("json") {
+        println!("{}", serde_json::to_string(&history).unwrap());
+        return Ok(history_len);
+    }
+
+    // yamlが指定されていればyaml形式で返す
+    if option.contains_key("yaml") {
+        println!("{}", serde_yaml::to_string(&history).unwrap());
+        return Ok(history_len);
+    }
+
+    Ok(history_len)
+}
+
+#[cfg(test)]
+mod tests {
+    // コマンドライン引数から実行コマンドを作成する
+    fn config(args: &[&str]) -> super::Config {
+        let args = std::iter::once("bot-admin").chain(args.iter().cloned());
+        super::get_config(super::build_app().get_matches_from(args))
+    }
+
+    #[test]
+    fn add_update_remove_bot() {
+        use super::*;
+        let atbdb = atb_db::AtbDB::open_in_memory().unwrap();
+
+        let add = config(&["add", "--name", "test", "--description", "test bot"]);
+        assert_eq!(actual_main(&atbdb, add), 0);
+        let update = config(&["update", "1", "--enable", "false", "--short_order", "true"]);
+        assert_eq!(actual_main(&atbdb, update), 0);
+        assert_eq!(actual_main(&atbdb, config(&["get", "1", "--json"])), 0);
+        assert_eq!(actual_main(&atbdb, config(&["list", "--yaml"])), 0);
+
+        let bot = atbdb.get_bot(1).unwrap();
+        assert!(!bot.is_enable());
+        assert!(bot.is_short_order());
+
+        // 運用段階はupdateでは変更できない
+        for option in &["--operate_type", "--operation"] {
+            let args = ["bot-admin", "update", "1", option, "product"];
+            assert!(super::build_app().get_matches_from_safe(args).is_err());
+        }
+
+        // 評価結果が昇格条件を満たせば運用段階を上げられる
+        assert_eq!(actual_main(&atbdb, config(&["promote", "1"])), 65);
+        let evaluation = config(&[
+            "evaluation",
+            "add",
+            "1",
+            "--operate_type",
+            "backtest",
+            "--trades",
+            "150",
+            "--max_drawdown",
+            "0.1",
+            "--started",
+            "1514764800",
+            "--ended",
+            "1546300800",
+        ]);
+        assert_eq!(actual_main(&atbdb, evaluation), 0);
+        let list = config(&["evaluation", "list", "1", "--json"]);
+        assert_eq!(actual_main(&atbdb, list), 0);
+        assert_eq!(actual_main(&atbdb, config(&["promote", "1"])), 0);
+        assert_eq!(
+            atbdb.get_bot(1).unwrap().get_operate_type(),
+            atb_db::OperateType::Forwardtest
+        );
+        // フォワードテストの評価結果がなければ昇格できない
+        assert_eq!(actual_main(&atbdb, config(&["promote", "1"])), 65);
+
+        // 降格はいつでもできる
+        let demote = config(&["demote", "1", "--operate_type", "backtest"]);
+        assert_eq!(actual_main(&atbdb, demote), 0);
+        let demote = config(&["demote", "1", "--operate_type", "backtest"]);
+        assert_eq!(actual_main(&atbdb, demote), 65);
+        // 以前の昇格の根拠にした評価結果では上げ直せない
+        assert_eq!(actual_main(&atbdb, config(&["promote", "1"])), 65);
+        assert_eq!(
+            actual_main(&atbdb, config(&["promotions", "1", "--yaml"])),
+            0
+        );
+        assert_eq!(atbdb.get_bot_promotion_list(1).unwrap().len(), 2);
+
+        // 更新する項目がなければEX_DATAERR
+        assert_eq!(actual_main(&atbdb, config(&["update", "1"])), 65);
+
+        // アーカイブしたbotは一覧から除外され、復元できる
+        assert_eq!(actual_main(&atbdb, config(&["archive", "1"])), 0);
+        assert_eq!(atbdb.get_bot_list().unwrap().get_list_len(), 0);
+        let list = config(&["list", "--archived", "--json"]);
+        assert_eq!(actual_main(&atbdb, list), 0);
+        assert_eq!(actual_main(&atbdb, config(&["archive", "1"])), 65);
+        assert_eq!(actual_main(&atbdb, config(&["restore", "1"])), 0);
+        assert_eq!(atbdb.get_bot_list().unwrap().get_list_len(), 1);
+
+        // 完全削除はアーカイブしたbotに--yesを指定した場合のみ
+        assert_eq!(actual_main(&atbdb, config(&["purge", "1", "--yes"])), 65);
+        assert_eq!(actual_main(&atbdb, config(&["remove", "1"])), 0);
+        assert_eq!(actual_main(&atbdb, config(&["purge", "1"])), 65);
+        assert_eq!(actual_main(&atbdb, config(&["purge", "1", "--yes"])), 0);
+
+        // 存在しないbotはEX_NOINPUT
+        assert_eq!(actual_main(&atbdb, config(&["get", "1"])), 66);
+        assert_eq!(actual_main(&atbdb, config(&["archive", "1"])), 66);
+        assert_eq!(actual_main(&atbdb, config(&["restore", "1"])), 66);
+
+        // 完全削除したbotのIDは使わず、トークンを再発行する
+        let add = config(&["add", "--name", "test", "--description", "test bot"]);
+        assert_eq!(actual_main(&atbdb, add), 0);
+        let prefix = atbdb
+            .get_bot(2)
+            .unwrap()
+            .get_token_prefix()
+            .unwrap()
+            .to_string();
+        assert_eq!(actual_main(&atbdb, config(&["token", "rotate", "2"])), 0);
+        assert_ne!(
+            atbdb.get_bot(2).unwrap().get_token_prefix().unwrap(),
+            prefix
+        );
+        assert_eq!(actual_main(&atbdb, config(&["token", "rotate", "3"])), 66);
+
+        // 変更履歴に操作者が記録される
+        let update = config(&["--actor", "alice", "update", "2", "--name", "renamed"]);
+        assert_eq!(actual_main(&atbdb, update), 0);
+        let update = config(&["update", "2", "--description", "changed", "--actor", "bob"]);
+        assert_eq!(actual_main(&atbdb, update), 0);
+        let history = atbdb.get_bot_history(2, Some(2)).unwrap();
+        assert_eq!(history[0].get_actor(), "cli:bob");
+        assert_eq!(history[1].get_actor(), "cli:alice");
+        assert_eq!(history[1].get_new_value().unwrap()["name"], "renamed");
+        let history = config(&["history", "2", "--limit", "3", "--yaml"]);
+        assert_eq!(actual_main(&atbdb, history), 0);
+
+        // 数値でないidは受け付けない
+        let args = ["bot-admin", "get", "x"];
+        assert!(super::build_app().get_matches_from_safe(args).is_err());
+    }
+}
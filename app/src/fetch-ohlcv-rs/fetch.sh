@@ -1,7 +1,4 @@
-for i in 60 300 900 1800 3600 7200 14400 21600 43200 86400 259200 604800; do
-  cargo run -- bitflyer btcfxjpy $i;
-  cargo run -- bitflyer btcjpy $i;
-  cargo run -- liquid btcjpy $i;
-  cargo run -- ftx btcusd $i;
-  cargo run -- bitmex btcusd-perpetual-futures $i;
+# 1分足のみ取得し、その他の足は1分足から作成する
+for market in "bitflyer btcfxjpy" "bitflyer btcjpy" "liquid btcjpy" "ftx btcusd" "bitmex btcusd-perpetual-futures"; do
+  cargo run -- $market 60 --resample 300,900,1800,3600,7200,14400,21600,43200,86400,259200,604800;
 done
@@ -5,24 +5,35 @@ extern crate clap;
 struct OhlcvSetting {
     exchange: String,
     pair: String,
-    period: String,
+    period: i64,
+    market: atb_db::Market,
     after: i64,
+    last_stored: Option<i64>,
+    resample: Vec<i64>,
+    anchor: atb_db::ResampleAnchor,
 }
 
 fn main() {
     // 対象データベースに接続する
-    let result_atbdb = atb_db::AtbDB::connect(None);
-    if let Err(err) = result_atbdb {
-        eprintln!("{}", err);
-        std::process::exit(1);
-    }
-    let atbdb = result_atbdb.unwrap();
+    let atbdb = match atb_db::AtbDB::connect(None) {
+        Ok(atbdb) => atbdb,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
     // コマンドライン引数を取得する
     let args_matches = get_args_matches();
 
     // DBから取り出す価格データの設定を取得する
-    let ohlcv_setting = get_ohlcv_setting(&atbdb, args_matches);
+    let ohlcv_setting = match get_ohlcv_setting(&atbdb, args_matches) {
+        Ok(ohlcv_setting) => ohlcv_setting,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
     // 価格データを取得する
     let fetch_result = fetch_ohlcv_from_cryptowat(&ohlcv_setting);
@@ -42,12 +53,23 @@ fn main() {
         std::process::exit(1);
     }
 
+    // 取得した足から長い足を作成して保存する
+    if let Err(err) = store_resampled_ohlcv(&atbdb, &ohlcv_setting) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+
     // 正常終了
     std::process::exit(0);
 }
 
 // コマンドライン引数を取得する
 fn get_args_matches() -> clap::ArgMatches<'static> {
+    build_app().get_matches()
+}
+
+// コマンドライン引数の定義
+fn build_app() -> clap::App<'static, 'static> {
     clap::App::new("fetch-ohlcv-rs")
         .version("0.0.1")
         .author("Didy KUPANHY")
@@ -70,33 +92,79 @@ fn get_args_matches() -> clap::ArgMatches<'static> {
                 .takes_value(true)
                 .required(true),
         )
-        .get_matches()
+        .arg(
+            clap::Arg::with_name("resample")
+                .help("取得した足から作成して保存する足の期間(秒指定、カンマ区切り)")
+                .long("resample")
+                .takes_value(true)
+                .use_delimiter(true),
+        )
+        .arg(
+            clap::Arg::with_name("jst")
+                .help("作成する足を日本時間の0時で区切る")
+                .long("jst"),
+        )
 }
 
 // DBから取り出す価格データの設定を取得する
 fn get_ohlcv_setting(
     atbdb: &atb_db::AtbDB,
     args_matches: clap::ArgMatches<'static>,
-) -> OhlcvSetting {
+) -> atb_db::AtbDbResult<OhlcvSetting> {
     let exchange = args_matches.value_of("exchange").unwrap().to_string();
     let pair = args_matches.value_of("pair").unwrap().to_string();
-    let period = args_matches.value_of("period").unwrap().to_string();
+    let period = args_matches.value_of("period").unwrap();
+    let period = period.parse::<i64>().map_err(|_| {
+        atb_db::AtbDbError::Validation(format!("足の期間が数値ではありません: {}", period))
+    })?;
+
+    // 市場と足の期間が登録されているか確認する
+    let market = atbdb.validate_market_period(&exchange, &pair, period)?;
 
     // データベースから設定条件の最終unixtimeを取得する
-    let after = match atbdb.get_last_unixtime_from_ohlcv(&exchange, &pair, &period) {
-        Ok(after) => after,
-        Err(err) => {
-            eprintln!("{}", err);
-            1514764800
-        }
+    // 保存済みのデータがなければ2018-01-01から取得する
+    let last_stored = match atbdb.get_last_unixtime_from_ohlcv(&exchange, &pair, period) {
+        Ok(last_stored) => Some(last_stored),
+        Err(atb_db::AtbDbError::NotFound) => None,
+        Err(err) => return Err(err),
     };
+    let after = last_stored.unwrap_or(1514764800);
 
-    OhlcvSetting {
-        exchange: exchange,
-        pair: pair,
-        period: period,
-        after: after,
+    // 作成する足の期間を取得する
+    let resample = args_matches
+        .values_of("resample")
+        .map(|values| {
+            values
+                .map(|value| {
+                    value.parse::<i64>().map_err(|_| {
+                        atb_db::AtbDbError::Validation(format!(
+                            "足の期間が数値ではありません: {}",
+                            value
+                        ))
+                    })
+                })
+                .collect::<atb_db::AtbDbResult<Vec<i64>>>()
+        })
+        .unwrap_or_else(|| Ok(vec![]))?;
+    for &target_period in &resample {
+        atbdb.validate_market_period(&exchange, &pair, target_period)?;
     }
+    let anchor = if args_matches.is_present("jst") {
+        atb_db::ResampleAnchor::Jst
+    } else {
+        atb_db::ResampleAnchor::Utc
+    };
+
+    Ok(OhlcvSetting {
+        exchange,
+        pair,
+        period,
+        market,
+        after,
+        last_stored,
+        resample,
+        anchor,
+    })
 }
 
 // 価格データを取得する
@@ -105,7 +173,7 @@ fn fetch_ohlcv_from_cryptowat(
 ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
     let exchange = &ohlcv_setting.exchange;
     let pair = &ohlcv_setting.pair;
-    let period = &ohlcv_setting.period;
+    let period = ohlcv_setting.period;
     let after = &ohlcv_setting.after;
 
     let url = format!(
@@ -122,24 +190,23 @@ fn store_ohlcv_to_database(
     atbdb: &atb_db::AtbDB,
     ohlcv_setting: &OhlcvSetting,
     resp: serde_json::Value,
-) -> atb_db::SqliteResult {
+) -> atb_db::AtbDbResult<()> {
     use chrono::{TimeZone, Utc};
 
     let exchange = &ohlcv_setting.exchange;
     let pair = &ohlcv_setting.pair;
-    let period = &ohlcv_setting.period;
-    let after = ohlcv_setting.after;
+    let period = ohlcv_setting.period;
 
     // 結果がなければ終了
-    if let None = resp.get("result") {
+    if resp.get("result").is_none() {
         println!("取得可能なデータがありませんでした。");
         return Ok(());
     }
-    if let None = resp["result"].get(period.to_string()) {
+    if resp["result"].get(period.to_string()).is_none() {
         println!("取得可能なデータがありませんでした。");
         return Ok(());
     }
-    if resp["result"][period.to_string()].is_array() == false {
+    if !resp["result"][period.to_string()].is_array() {
         println!("取得可能なデータがありませんでした。");
         return Ok(());
     }
@@ -151,79 +218,101 @@ fn store_ohlcv_to_database(
     }
 
     // UNIX時刻をYYYY-MM-DD hh:mm:ss 形式に変換する
-    let head_data: chrono::DateTime<Utc> = Utc.timestamp(
-        resp["result"][period.to_string()][0][0].as_i64().unwrap(),
-        0,
-    );
-    let tail_data: chrono::DateTime<Utc> = Utc.timestamp(
-        resp["result"][period.to_string()][len_ohlcv - 1][0]
-            .as_i64()
-            .unwrap(),
-        0,
-    );
+    let head_data: chrono::DateTime<Utc> = Utc
+        .timestamp_opt(
+            resp["result"][period.to_string()][0][0].as_i64().unwrap(),
+            0,
+        )
+        .unwrap();
+    let tail_data: chrono::DateTime<Utc> = Utc
+        .timestamp_opt(
+            resp["result"][period.to_string()][len_ohlcv - 1][0]
+                .as_i64()
+                .unwrap(),
+            0,
+        )
+        .unwrap();
 
     // 取得したデータの先頭と末尾の日付を出力する
     println!(
         "先頭データ : {}, {}",
         resp["result"][period.to_string()][0][0].as_i64().unwrap(),
-        head_data.to_string()
+        head_data
     );
     println!(
         "末尾データ : {}, {}",
         resp["result"][period.to_string()][len_ohlcv - 1][0]
             .as_i64()
             .unwrap(),
-        tail_data.to_string()
+        tail_data
     );
 
-    // 最終時刻のレコードは再取得するため削除する
-    if after != 1514764800 {
-        let _ = atbdb.delete_ohlcv(&exchange, &pair, &period, after)?;
-    }
+    // 市場の価格・出来高の桁数を取得する
+    let scale = ohlcv_setting.market.get_scale();
 
     let mut records = vec![];
-    let mut lacks = vec![];
 
     for i in 0..len_ohlcv {
-        let record = (
-            resp["result"][period.to_string()][i][1].as_f64().unwrap(),
-            resp["result"][period.to_string()][i][2].as_f64().unwrap(),
-            resp["result"][period.to_string()][i][3].as_f64().unwrap(),
-            resp["result"][period.to_string()][i][4].as_f64().unwrap(),
-            resp["result"][period.to_string()][i][5].as_f64().unwrap(),
-            resp["result"][period.to_string()][i][0].as_i64().unwrap(),
-        );
+        let row = &resp["result"][period.to_string()][i];
+        let record = atb_db::OhlcvBar::new(
+            atb_db::UnixTime::new(row[0].as_i64().unwrap()),
+            to_decimal(&row[1], scale.get_price_scale())?,
+            to_decimal(&row[2], scale.get_price_scale())?,
+            to_decimal(&row[3], scale.get_price_scale())?,
+            to_decimal(&row[4], scale.get_price_scale())?,
+            to_decimal(&row[5], scale.get_volume_scale())?,
+        )?;
 
         records.push(record);
-        lacks.push(record.5);
     }
 
-    // 取得したデータをデータベースに保存する
-    atbdb.insert_ohlcv_list(&exchange, &pair, &period, &records);
+    // 取得したデータをデータベースに保存する(保存済みのローソク足は更新する)
+    let summary = atbdb.upsert_ohlcv_list(exchange, pair, period, &records)?;
+    println!(
+        "追加:{}件, 更新:{}件, 変更なし:{}件",
+        summary.get_inserted(),
+        summary.get_updated(),
+        summary.get_unchanged()
+    );
 
-    lacks.sort();
+    // 今回の取得で埋まった欠損期間を取得済みにする
+    let repaired = atbdb.repair_ohlcv_gaps(exchange, pair, period)?;
+    if 0 < repaired {
+        println!("{}件の欠損期間が埋まりました", repaired);
+    }
 
-    // 欠損データ調査用の変数
-    let i_period = period.parse::<i64>().unwrap();
-    let mut head_unixtime = lacks[0] - i_period;
+    // 前回保存した足から今回取得した最後の足までの欠損期間を記録する
+    let first = records.iter().map(|r| r.get_unixtime()).min().unwrap();
+    let last = records.iter().map(|r| r.get_unixtime()).max().unwrap();
+    let query = atb_db::OhlcvQuery::new(exchange, pair, period)
+        .from_time(
+            ohlcv_setting
+                .last_stored
+                .unwrap_or_else(|| first.get_secs()),
+        )
+        .to_time(last.get_secs());
     let mut lack = 0;
-
-    // 欠損件数をカウントする
-    for i in 0..lacks.len() {
-        // 欠損データがあれば出力する
-        head_unixtime += i_period;
-        if head_unixtime == lacks[i] {
+    for gap in atbdb.detect_ohlcv_gaps(&query)? {
+        if gap.get_status() != atb_db::GapStatus::Missing {
             continue;
         }
-        let h: chrono::DateTime<Utc> = Utc.timestamp(head_unixtime, 0);
-        let t: chrono::DateTime<Utc> = Utc.timestamp(lacks[i], 0);
-        let now_lack = (lacks[i] - head_unixtime) / i_period;
+
+        // 取得した足の間で欠けている足は、取得元にもデータがない
+        if first < gap.get_from() && gap.get_to() < last {
+            atbdb.set_ohlcv_gap_status(&gap, atb_db::GapStatus::Unavailable)?;
+        }
+
+        let h: chrono::DateTime<Utc> = gap.get_from().to_datetime().unwrap();
+        let t: chrono::DateTime<Utc> = gap.get_to().to_datetime().unwrap();
         println!(
             "{}({})から{}({})までの{}件のデータがありません",
-            h, head_unixtime, t, lacks[i], now_lack
+            h,
+            gap.get_from(),
+            t,
+            gap.get_to(),
+            gap.get_missing_count()
         );
-        head_unixtime = lacks[i];
-        lack += now_lack;
+        lack += gap.get_missing_count();
     }
 
     // 保存したデータ数を出力する
@@ -234,3 +323,106 @@ fn store_ohlcv_to_database(
 
     Ok(())
 }
+
+// 取得した足から長い足を作成して保存する
+fn store_resampled_ohlcv(
+    atbdb: &atb_db::AtbDB,
+    ohlcv_setting: &OhlcvSetting,
+) -> atb_db::AtbDbResult<()> {
+    let period = ohlcv_setting.period;
+
+    for &target_period in &ohlcv_setting.resample {
+        // 前回取得済みの足を含む期間から作成し直す
+        let query = atb_db::OhlcvQuery::new(&ohlcv_setting.exchange, &ohlcv_setting.pair, period)
+            .from_time(ohlcv_setting.after - target_period);
+        let summary = atbdb.store_resampled_ohlcv(&query, target_period, ohlcv_setting.anchor)?;
+        println!(
+            "{}秒足 追加:{}件, 更新:{}件, 変更なし:{}件",
+            target_period,
+            summary.get_inserted(),
+            summary.get_updated(),
+            summary.get_unchanged()
+        );
+    }
+    Ok(())
+}
+
+// JSONの数値を誤差なく固定小数点数に変換し、市場の桁数に丸める
+fn to_decimal(value: &serde_json::Value, scale: u32) -> atb_db::AtbDbResult<atb_db::Decimal> {
+    if !value.is_number() {
+        return Err(atb_db::AtbDbError::Validation(format!(
+            "数値ではありません: {}",
+            value
+        )));
+    }
+    value
+        .to_string()
+        .parse::<atb_db::Decimal>()
+        .ok()
+        .and_then(|decimal| decimal.round_to(scale))
+        .ok_or_else(|| atb_db::AtbDbError::Validation(format!("数値を変換できません: {}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    // コマンドライン引数から取得条件を作成する
+    fn setting(atbdb: &atb_db::AtbDB, args: &[&str]) -> atb_db::AtbDbResult<super::OhlcvSetting> {
+        let args = std::iter::once("fetch-ohlcv-rs").chain(args.iter().cloned());
+        super::get_ohlcv_setting(atbdb, super::build_app().get_matches_from(args))
+    }
+
+    // cryptowatの応答形式の1分足 [close_time, open, high, low, close, volume, quote_volume]
+    fn response(unixtimes: &[i64]) -> serde_json::Value {
+        let rows: Vec<serde_json::Value> = unixtimes
+            .iter()
+            .map(|&t| serde_json::json!([t, 1000000, 1000100, 999900, 1000050, 1.23456789, 0]))
+            .collect();
+        serde_json::json!({ "result": { "60": rows } })
+    }
+
+    #[test]
+    fn convert_decimal() {
+        use super::*;
+        let value = serde_json::json!(1.23456789);
+        assert_eq!(to_decimal(&value, 8).unwrap().to_string(), "1.23456789");
+        assert_eq!(to_decimal(&value, 2).unwrap().to_string(), "1.23");
+        assert!(to_decimal(&serde_json::json!("1.0"), 8).is_err());
+    }
+
+    #[test]
+    fn store_ohlcv() {
+        use super::*;
+        let atbdb = atb_db::AtbDB::open_in_memory().unwrap();
+
+        // 登録されていない市場・足の期間は取得しない
+        assert!(setting(&atbdb, &["unknown", "btcjpy", "60"]).is_err());
+        assert!(setting(&atbdb, &["bitflyer", "btcjpy", "61"]).is_err());
+
+        // 1514765100(5本目)が欠けた1時間分の1分足を保存する
+        let setting = setting(&atbdb, &["bitflyer", "btcjpy", "60", "--resample", "300"]).unwrap();
+        assert_eq!(setting.after, 1514764800);
+        let unixtimes: Vec<i64> = (1..=60)
+            .filter(|&i| i != 5)
+            .map(|i| 1514764800 + i * 60)
+            .collect();
+        store_ohlcv_to_database(&atbdb, &setting, response(&unixtimes)).unwrap();
+        store_resampled_ohlcv(&atbdb, &setting).unwrap();
+
+        let query = atb_db::OhlcvQuery::new("bitflyer", "btcjpy", 60);
+        let ohlcv = atbdb.get_ohlcv_list(&query).unwrap();
+        assert_eq!(ohlcv.get_list_len(), 59);
+        assert_eq!(ohlcv.get_list()[0].get_volume().to_string(), "1.23456789");
+
+        // 取得した足の間の欠損は取得元にもデータがない
+        let gaps = atbdb.get_ohlcv_gap_list(&query, None).unwrap();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].get_status(), atb_db::GapStatus::Unavailable);
+
+        // 欠損のある最初の5分足以外の11本が作成される
+        let query = atb_db::OhlcvQuery::new("bitflyer", "btcjpy", 300);
+        assert_eq!(atbdb.get_ohlcv_list(&query).unwrap().get_list_len(), 11);
+
+        // 応答に結果がなければ何もしない
+        store_ohlcv_to_database(&atbdb, &setting, serde_json::json!({})).unwrap();
+    }
+}
@@ -1,7 +1,5 @@
-extern crate read_atb_config;
 extern crate atb_db;
-
-use actix_web;
+extern crate read_atb_config;
 
 use std::sync::Arc;
 
@@ -9,6 +7,10 @@ use std::sync::Arc;
 struct ServerConfig {
     host: String,
     port: String,
+    // データベースに同時に問い合わせる数
+    db_workers: usize,
+    // 1リクエストあたりのデータベース処理のタイムアウト
+    timeout: std::time::Duration,
 }
 
 #[derive(serde::Deserialize)]
@@ -16,31 +18,64 @@ struct GetId {
     id: i64,
 }
 
+// GET /ohlcv のクエリパラメータ
+#[derive(serde::Deserialize)]
+struct GetOhlcvParams {
+    from: Option<i64>,
+    to: Option<i64>,
+    limit: Option<usize>,
+    order: Option<atb_db::OhlcvOrder>,
+    cursor: Option<i64>,
+    format: Option<atb_db::OhlcvFormat>,
+}
+
+// GET /bot/{id}/history のクエリパラメータ
+#[derive(serde::Deserialize)]
+struct GetHistoryParams {
+    limit: Option<usize>,
+}
+
+// GET /ohlcv で1回に返すローソク足の件数(デフォルト・上限)
+const DEFAULT_OHLCV_LIMIT: usize = 1000;
+const MAX_OHLCV_LIMIT: usize = 10000;
+
+// データベースに同時に問い合わせる数・タイムアウトのデフォルト値
+const DEFAULT_DB_WORKERS: usize = 8;
+const DEFAULT_TIMEOUT_MS: u64 = 10000;
+
 fn main() {
     // 環境変数から設定ファイルを読み込む
     let result_atbconf = read_atb_config::AtbConf::load_conf();
-    if let None = result_atbconf {
+    if result_atbconf.is_none() {
         eprintln!("環境変数`PATH_ATB_CONFIG`を確認ください");
         std::process::exit(1);
     }
 
     // 対象データベースに接続する
-    let result_atbdb = atb_db::AtbDB::connect(result_atbconf.clone());
-    if let Err(err) = result_atbdb {
+    let atbdb = match atb_db::AtbDB::connect(result_atbconf.clone()) {
+        Ok(atbdb) => atbdb,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    // テーブル構造を確認する
+    if let Err(err) = atbdb.check_schema() {
         eprintln!("{}", err);
         std::process::exit(1);
     }
 
     let atbconf = result_atbconf.unwrap();
-    let atbdb = result_atbdb.unwrap();
 
     // サーバー設定情報を取得する
-    let result_server_config = get_server_config(atbconf);
-    if let None = result_server_config {
-        eprintln!("環境変数`PATH_ATB_CONFIG`に指定したファイルを確認してください");
-        std::process::exit(1);
-    }
-    let server_config = result_server_config.unwrap();
+    let server_config = match get_server_config(atbconf) {
+        Ok(server_config) => server_config,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
 
     // webサーバーを起動する
     let result = actual_main(atbdb, server_config);
@@ -50,19 +85,50 @@ fn main() {
 }
 
 // サーバー設定情報を取得する
-fn get_server_config(atbconf: read_atb_config::AtbConf) -> Option<ServerConfig> {
-    if atbconf.has_api_host() == false || atbconf.has_api_port() == false {
-        return None;
+fn get_server_config(atbconf: read_atb_config::AtbConf) -> Result<ServerConfig, String> {
+    use std::convert::TryFrom;
+    if !atbconf.has_api_host() || !atbconf.has_api_port() {
+        return Err("環境変数`PATH_ATB_CONFIG`に指定したファイルを確認してください".to_string());
     }
 
-    Some(ServerConfig {
+    let db_workers = match atbconf.get_api_db_workers() {
+        Some(workers) => usize::try_from(workers).map_err(|_| {
+            format!(
+                "設定エラー: api.db_workers には1以上の値を指定してください: {}",
+                workers
+            )
+        })?,
+        None => DEFAULT_DB_WORKERS,
+    };
+    let timeout_ms = match atbconf.get_api_timeout_ms() {
+        Some(timeout) => u64::try_from(timeout).map_err(|_| {
+            format!(
+                "設定エラー: api.timeout_ms には0以上の値を指定してください: {}",
+                timeout
+            )
+        })?,
+        None => DEFAULT_TIMEOUT_MS,
+    };
+
+    Ok(ServerConfig {
         host: atbconf.get_api_host().unwrap().to_string(),
         port: atbconf.get_api_port().unwrap().to_string(),
+        db_workers,
+        timeout: std::time::Duration::from_millis(timeout_ms),
     })
 }
 
 // webサーバーを起動する
 fn actual_main(atbdb: atb_db::AtbDB, server_config: ServerConfig) -> i32 {
+    // 専用のワーカーでデータベースに問い合わせる
+    let atbdb = match atb_db::AsyncAtbDB::new(atbdb, server_config.db_workers) {
+        Ok(atbdb) => atbdb.timeout(server_config.timeout),
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+
     if let Err(err) = run(Arc::new(atbdb), server_config) {
         eprintln!("{}", err);
         1
@@ -73,7 +139,7 @@ fn actual_main(atbdb: atb_db::AtbDB, server_config: ServerConfig) -> i32 {
 
 // APIサーバーを起動する
 #[actix_rt::main]
-async fn run(atbdb: Arc<atb_db::AtbDB>, server_config: ServerConfig) -> std::io::Result<()> {
+async fn run(atbdb: Arc<atb_db::AsyncAtbDB>, server_config: ServerConfig) -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "info,actix_web=info");
     env_logger::init();
 
@@ -84,30 +150,49 @@ async fn run(atbdb: Arc<atb_db::AtbDB>, server_config: ServerConfig) -> std::io:
             .wrap(actix_web::middleware::Logger::default())
             .data(actix_web::web::JsonConfig::default().limit(4096))
             .data(atbdb.clone())
-            .service(actix_web::web::resource("/").route(actix_web::web::get().to(index)))
-            .service(
-                actix_web::web::resource("/ohlcv/{market}/{pair}/{period}")
-                    .route(actix_web::web::get().to(get_ohlcv)),
-            )
-            .service(actix_web::web::resource("/bot").route(actix_web::web::post().to(post_bot)))
-            .service(actix_web::web::resource("/bot/{id}").route(actix_web::web::get().to(get_bot)))
+            .configure(routes)
     })
     .bind(addr)?
     .run()
     .await
 }
 
+// APIのルーティングを設定する
+fn routes(cfg: &mut actix_web::web::ServiceConfig) {
+    cfg.service(actix_web::web::resource("/").route(actix_web::web::get().to(index)))
+        .service(
+            actix_web::web::resource("/ohlcv/{market}/{pair}/{period}")
+                .route(actix_web::web::get().to(get_ohlcv)),
+        )
+        .service(
+            actix_web::web::resource("/market").route(actix_web::web::get().to(get_market_list)),
+        )
+        .service(
+            actix_web::web::resource("/market/{market}/{pair}")
+                .route(actix_web::web::get().to(get_market)),
+        )
+        .service(actix_web::web::resource("/bot").route(actix_web::web::post().to(post_bot)))
+        .service(actix_web::web::resource("/bot/{id}").route(actix_web::web::get().to(get_bot)))
+        .service(
+            actix_web::web::resource("/bot/{id}/history")
+                .route(actix_web::web::get().to(get_bot_history)),
+        );
+}
+
 fn _get_index() -> Result<String, String> {
     Ok(r#"
-        GET /ohlcv/{market}/{pair}/{period}
+        GET /ohlcv/{market}/{pair}/{period}?from={unixtime}&to={unixtime}&limit={n}&order={asc|desc}&cursor={next_cursor}&format={array|object}
+        GET /market
+        GET /market/{market}/{pair}
         GET /bot/{bot-id}
+        GET /bot/{bot-id}/history?limit={n}
         POST /bot
     "#
     .to_string())
 }
 
 async fn index() -> Result<actix_web::HttpResponse, actix_web::Error> {
-    let res = actix_web::web::block(move || _get_index())
+    let res = actix_web::web::block(_get_index)
         .await
         .map(|body| actix_web::HttpResponse::Ok().body(body))
         .map_err(|_| actix_web::HttpResponse::InternalServerError())?;
@@ -116,139 +201,408 @@ async fn index() -> Result<actix_web::HttpResponse, actix_web::Error> {
 
 async fn get_ohlcv(
     path: actix_web::web::Path<(String, String, i64)>,
-    atbdb: actix_web::web::Data<Arc<atb_db::AtbDB>>,
+    params: actix_web::web::Query<GetOhlcvParams>,
+    atbdb: actix_web::web::Data<Arc<atb_db::AsyncAtbDB>>,
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
-    let res = actix_web::web::block(move || {
-        atbdb.get_ohlcv_list(&path.0, &path.1, &path.2.to_string())
-    })
-    .await
-    .map(|ohlcv| actix_web::HttpResponse::Ok().json(ohlcv))
-    .map_err(|_| actix_web::HttpResponse::InternalServerError())?;
+    let query = _get_ohlcv_query(&path, &params);
+    let format = params.format.unwrap_or(atb_db::OhlcvFormat::Array);
+    let res = atbdb
+        .run(move |atbdb| {
+            // 登録されていない市場・足の期間は取得しない
+            atbdb.validate_market_period(
+                query.get_exchange(),
+                query.get_pair(),
+                query.get_period(),
+            )?;
+            atbdb.get_ohlcv_list(&query)
+        })
+        .await
+        .map(|ohlcv| ohlcv.format(format))
+        .map(|ohlcv| actix_web::HttpResponse::Ok().json(ohlcv))
+        .map_err(_error_response)?;
     Ok(res)
 }
 
-fn _get_option(
-    json: serde_json::Value,
-) -> Option<std::collections::HashMap<String, String>> {
-    let mut option: std::collections::HashMap<String, String> = std::collections::HashMap::new();
-
-    // name チェック
-    if json.get("name").is_none()
-        || !json["name"].is_string()
-        || json["name"].as_str().unwrap().len() == 0
-    {
-        return None;
-    }
-    option.insert(
-        "name".to_string(),
-        json["name"].as_str().unwrap().to_string(),
-    );
-
-    // description チェック
-    if json.get("description").is_none()
-        || !json["description"].is_string()
-        || json["description"].as_str().unwrap().len() == 0
-    {
-        return None;
-    }
-    option.insert(
-        "description".to_string(),
-        json["description"].as_str().unwrap().to_string(),
-    );
-
-    // bool チェック
-    if json.get("enable").is_none() || !json["enable"].is_boolean() {
-        return None;
-    }
-    option.insert(
-        "enable".to_string(),
-        if json["enable"].as_bool().unwrap() {
-            "1"
-        } else {
-            "0"
-        }
-        .to_string(),
-    );
-
-    // long_orderが指定されていれば、値を1/0に変換する
-    if json.get("long_order").is_some() && json["long_order"].is_boolean() {
-        let value = if json["long_order"].as_bool().unwrap() {
-            "1"
-        } else {
-            "0"
-        }
-        .to_string();
-        option.insert("long_order".to_string(), value);
+async fn get_market_list(
+    atbdb: actix_web::web::Data<Arc<atb_db::AsyncAtbDB>>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let res = atbdb
+        .run(|atbdb| atbdb.get_market_list())
+        .await
+        .map(|market_list| actix_web::HttpResponse::Ok().json(market_list))
+        .map_err(_error_response)?;
+    Ok(res)
+}
+
+async fn get_market(
+    path: actix_web::web::Path<(String, String)>,
+    atbdb: actix_web::web::Data<Arc<atb_db::AsyncAtbDB>>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let (exchange, pair) = path.into_inner();
+    let res = atbdb
+        .run(move |atbdb| atbdb.get_market(&exchange, &pair))
+        .await
+        .map(|market| actix_web::HttpResponse::Ok().json(market))
+        .map_err(_error_response)?;
+    Ok(res)
+}
+
+// パスとクエリパラメータからohlcvの検索条件を作成する
+fn _get_ohlcv_query(path: &(String, String, i64), params: &GetOhlcvParams) -> atb_db::OhlcvQuery {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_OHLCV_LIMIT)
+        .min(MAX_OHLCV_LIMIT);
+    let mut query = atb_db::OhlcvQuery::new(&path.0, &path.1, path.2)
+        .limit(limit)
+        .order(params.order.unwrap_or(atb_db::OhlcvOrder::Asc));
+
+    if let Some(from) = params.from {
+        query = query.from_time(from);
     }
+    if let Some(to) = params.to {
+        query = query.to_time(to);
+    }
+    if let Some(cursor) = params.cursor {
+        query = query.cursor(cursor);
+    }
+    query
+}
 
-    // short_orderが指定されていれば、値を1/0に変換する
-    if json.get("short_order").is_some() && json["short_order"].is_boolean() {
-        let value = if json["short_order"].as_bool().unwrap() {
-            "1"
-        } else {
-            "0"
+// atb-dbのエラーを対応するHTTPステータスのレスポンスに変換する
+fn _error_response(err: atb_db::AtbDbError) -> actix_web::HttpResponse {
+    log::error!("{}", err);
+    match err {
+        atb_db::AtbDbError::NotFound => actix_web::HttpResponse::NotFound().finish(),
+        // 接続・ワーカーが空いていない
+        atb_db::AtbDbError::Pool(_) | atb_db::AtbDbError::Busy => {
+            actix_web::HttpResponse::ServiceUnavailable().finish()
         }
-        .to_string();
-        option.insert("short_order".to_string(), value);
+        atb_db::AtbDbError::Timeout(_) => actix_web::HttpResponse::GatewayTimeout().finish(),
+        atb_db::AtbDbError::Validation(msg) => actix_web::HttpResponse::BadRequest().body(msg),
+        atb_db::AtbDbError::Config(_)
+        | atb_db::AtbDbError::Sqlite(_)
+        | atb_db::AtbDbError::SchemaVersion { .. }
+        | atb_db::AtbDbError::Schema(_)
+        | atb_db::AtbDbError::Migration(_)
+        | atb_db::AtbDbError::Decode(_)
+        | atb_db::AtbDbError::Panic(_)
+        | atb_db::AtbDbError::Io(_) => actix_web::HttpResponse::InternalServerError().finish(),
     }
-
-    Some(option)
 }
 
 async fn post_bot(
+    req: actix_web::HttpRequest,
     body: actix_web::web::Bytes,
-    atbdb: actix_web::web::Data<Arc<atb_db::AtbDB>>,
+    atbdb: actix_web::web::Data<Arc<atb_db::AsyncAtbDB>>,
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
-    let result = std::str::from_utf8(&body).unwrap();
-    let json: serde_json::Value = serde_json::from_str(result).unwrap();
-
-    let res = actix_web::web::block(move || {
-        let result_option = _get_option(json);
-        if result_option.is_none() {
-            return Err("".to_string());
-        }
-        let option = result_option.unwrap();
-
-        let result_last_id = atbdb.insert_bot(&option);
-        if result_last_id.is_err() {
-            return Err("".to_string());
-        }
-        let last_id = result_last_id.unwrap();
-
-        let result_bot = atbdb.get_bot(&last_id.to_string());
-        if result_bot.is_err() {
-            return Err("".to_string());
-        }
-
-        Ok(result_bot.unwrap())
-    })
-    .await
-    .map(|bot| actix_web::HttpResponse::Ok().json(bot))
-    .map_err(|_| actix_web::HttpResponse::InternalServerError())?;
+    // リクエストボディを検証する(テーブルにない項目は受け付けない)
+    let bot: atb_db::BotCreate = match serde_json::from_slice(&body) {
+        Ok(bot) => bot,
+        Err(err) => return Ok(actix_web::HttpResponse::BadRequest().body(err.to_string())),
+    };
+
+    let actor = _get_actor(&req);
+    let res = atbdb
+        .run(move |atbdb| atbdb.insert_bot(&bot, &actor))
+        .await
+        .map(|bot| actix_web::HttpResponse::Ok().json(bot))
+        .map_err(_error_response)?;
     Ok(res)
 }
 
+// tokenヘッダーを取得する(ない場合・文字列でない場合は空文字列)
 fn _get_token(req: actix_web::HttpRequest) -> String {
-    if req.headers().get("token").is_some() {
-        let token_header = req.headers().get("token");
-        token_header.unwrap().to_str().unwrap()
-    } else {
-        ""
-    }
-    .to_string()
+    req.headers()
+        .get("token")
+        .and_then(|token| token.to_str().ok())
+        .unwrap_or("")
+        .to_string()
+}
+
+// APIの操作者(接続元アドレス)
+fn _get_actor(req: &actix_web::HttpRequest) -> atb_db::Actor {
+    atb_db::Actor::api(req.connection_info().remote().unwrap_or("unknown"))
 }
 
 async fn get_bot(
     req: actix_web::HttpRequest,
     path: actix_web::web::Path<GetId>,
-    atbdb: actix_web::web::Data<Arc<atb_db::AtbDB>>,
+    atbdb: actix_web::web::Data<Arc<atb_db::AsyncAtbDB>>,
 ) -> Result<actix_web::HttpResponse, actix_web::Error> {
     let token = _get_token(req);
-    let res = actix_web::web::block(move || {
-        atbdb.get_bot_for_api(&path.id.to_string(), &token)
-    })
-    .await
-    .map(|bot| actix_web::HttpResponse::Ok().json(bot))
-    .map_err(|_| actix_web::HttpResponse::InternalServerError())?;
+    let id = path.id;
+    let res = atbdb
+        .run(move |atbdb| atbdb.get_bot_for_api(id, &token))
+        .await
+        .map(|bot| actix_web::HttpResponse::Ok().json(bot))
+        .map_err(_error_response)?;
+    Ok(res)
+}
+
+async fn get_bot_history(
+    req: actix_web::HttpRequest,
+    path: actix_web::web::Path<GetId>,
+    params: actix_web::web::Query<GetHistoryParams>,
+    atbdb: actix_web::web::Data<Arc<atb_db::AsyncAtbDB>>,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let token = _get_token(req);
+    let (id, limit) = (path.id, params.limit);
+    let res = atbdb
+        .run(move |atbdb| atbdb.get_bot_history_for_api(id, &token, limit))
+        .await
+        .map(|history| actix_web::HttpResponse::Ok().json(history))
+        .map_err(_error_response)?;
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test;
+
+    // テスト用のデータベースとAPIを作成する
+    macro_rules! init_service {
+        ($atbdb:expr) => {
+            test::init_service(
+                actix_web::App::new()
+                    .data($atbdb.clone())
+                    .configure(super::routes),
+            )
+            .await
+        };
+    }
+
+    #[test]
+    fn server_config() {
+        let load = |yaml: &str| {
+            super::get_server_config(read_atb_config::AtbConf::load_from_str(yaml).unwrap())
+        };
+        let config = load("api:\n  host: 127.0.0.1\n  port: 8080\n").unwrap();
+        assert_eq!(config.db_workers, super::DEFAULT_DB_WORKERS);
+        let config = load("api:\n  host: 127.0.0.1\n  port: 8080\n  db_workers: 2\n").unwrap();
+        assert_eq!(config.db_workers, 2);
+        assert_eq!(
+            config.timeout,
+            std::time::Duration::from_millis(super::DEFAULT_TIMEOUT_MS)
+        );
+
+        // 負の値はusize・u64に変換せずエラーにする
+        assert!(load("api:\n  host: 127.0.0.1\n  port: 8080\n  db_workers: -1\n").is_err());
+        assert!(load("api:\n  host: 127.0.0.1\n  port: 8080\n  timeout_ms: -1\n").is_err());
+        assert!(load("api:\n  port: 8080\n").is_err());
+    }
+
+    // 1分足を5本登録したデータベース
+    fn open_database() -> std::sync::Arc<atb_db::AsyncAtbDB> {
+        let atbdb = atb_db::AtbDB::open_in_memory().unwrap();
+        let bars: Vec<atb_db::OhlcvBar> = (1..=5)
+            .map(|i| {
+                let price = atb_db::Decimal::new(1_000_000 + i, 0);
+                atb_db::OhlcvBar::new(
+                    atb_db::UnixTime::new(1514764800 + i * 60),
+                    price,
+                    price,
+                    price,
+                    price,
+                    atb_db::Decimal::new(15, 1),
+                )
+                .unwrap()
+            })
+            .collect();
+        atbdb
+            .upsert_ohlcv_list("bitflyer", "btcjpy", 60, &bars)
+            .unwrap();
+        std::sync::Arc::new(atb_db::AsyncAtbDB::new(atbdb, 2).unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn get_ohlcv_paging() {
+        let atbdb = open_database();
+        let mut app = init_service!(atbdb);
+
+        let req = test::TestRequest::get()
+            .uri("/ohlcv/bitflyer/btcjpy/60?limit=3")
+            .to_request();
+        let json: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(json["ohlcv"].as_array().unwrap().len(), 3);
+        assert_eq!(json["ohlcv"][0][3], "1000001");
+        assert_eq!(json["next_cursor"], 1514764980);
+
+        let req = test::TestRequest::get()
+            .uri("/ohlcv/bitflyer/btcjpy/60?limit=3&cursor=1514764980&format=object")
+            .to_request();
+        let json: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(json["ohlcv"].as_array().unwrap().len(), 2);
+        assert_eq!(json["ohlcv"][1]["close"], "1000005");
+        assert!(json["next_cursor"].is_null());
+    }
+
+    #[actix_rt::test]
+    async fn get_ohlcv_unknown_market() {
+        let atbdb = open_database();
+        let mut app = init_service!(atbdb);
+
+        for uri in &["/ohlcv/unknown/btcjpy/60", "/ohlcv/bitflyer/btcjpy/61"] {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let res = test::call_service(&mut app, req).await;
+            assert_eq!(res.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn get_market_and_bot() {
+        let atbdb = open_database();
+        let mut app = init_service!(atbdb);
+
+        let req = test::TestRequest::get()
+            .uri("/market/bitflyer/btcjpy")
+            .to_request();
+        let json: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(json["exchange"], "bitflyer");
+
+        let req = test::TestRequest::get()
+            .uri("/market/bitflyer/none")
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let req = test::TestRequest::get()
+            .uri("/bot/1")
+            .header("token", "none")
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn post_bot() {
+        let atbdb = open_database();
+        let mut app = init_service!(atbdb);
+
+        let req = test::TestRequest::post()
+            .uri("/bot")
+            .set_payload(r#"{"name":"test","description":"test bot","enable":true}"#)
+            .to_request();
+        let json: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(json["name"], "test");
+
+        assert_eq!(json["enable"], true);
+        assert_eq!(json["operate_type"], "backtest");
+
+        // 追加時に返したトークンでのみ取得できる
+        let token = json["token"].as_str().unwrap().to_string();
+        let req = test::TestRequest::get()
+            .uri("/bot/1")
+            .header("token", token.as_str())
+            .to_request();
+        let json: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(json["name"], "test");
+        for token in &[&token[..atb_db::TOKEN_LENGTH - 1], "", "\u{3042}"] {
+            let req = test::TestRequest::get()
+                .uri("/bot/1")
+                .header("token", *token)
+                .to_request();
+            let res = test::call_service(&mut app, req).await;
+            assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+        }
+
+        // 必須項目がない・テーブルにない項目・空の名前は受け付けない
+        for payload in &[
+            r#"{"name":"test"}"#,
+            r#"{"name":"test","description":"test bot","token":"x"}"#,
+            r#"{"name":"","description":"test bot"}"#,
+        ] {
+            let req = test::TestRequest::post()
+                .uri("/bot")
+                .set_payload(*payload)
+                .to_request();
+            let res = test::call_service(&mut app, req).await;
+            assert_eq!(res.status(), actix_web::http::StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn get_bot_history() {
+        let atbdb = open_database();
+        let mut app = init_service!(atbdb);
+
+        let req = test::TestRequest::post()
+            .uri("/bot")
+            .set_payload(r#"{"name":"test","description":"test bot"}"#)
+            .to_request();
+        let json: serde_json::Value = test::read_response_json(&mut app, req).await;
+        let token = json["token"].as_str().unwrap().to_string();
+        atbdb
+            .get_ref()
+            .update_bot(
+                1,
+                &atb_db::BotPatch::new().enable(false),
+                &atb_db::Actor::cli("test"),
+            )
+            .unwrap();
+
+        let req = test::TestRequest::get()
+            .uri("/bot/1/history")
+            .header("token", token.as_str())
+            .to_request();
+        let json: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[0]["action"], "update");
+        assert_eq!(json[0]["actor"], "cli:test");
+        assert_eq!(json[1]["action"], "insert");
+        assert!(json[1]["actor"].as_str().unwrap().starts_with("api:"));
+
+        let req = test::TestRequest::get()
+            .uri("/bot/1/history?limit=1")
+            .header("token", token.as_str())
+            .to_request();
+        let json: serde_json::Value = test::read_response_json(&mut app, req).await;
+        assert_eq!(json.as_array().unwrap().len(), 1);
+
+        let req = test::TestRequest::get()
+            .uri("/bot/1/history")
+            .header("token", "0".repeat(atb_db::TOKEN_LENGTH).as_str())
+            .to_request();
+        let res = test::call_service(&mut app, req).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        // アーカイブしたbotはトークンが正しくても取得できない
+        atbdb
+            .get_ref()
+            .archive_bot(1, &atb_db::Actor::cli("test"))
+            .unwrap();
+        for uri in &["/bot/1", "/bot/1/history"] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .header("token", token.as_str())
+                .to_request();
+            let res = test::call_service(&mut app, req).await;
+            assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+        }
+    }
+
+    #[test]
+    fn error_response() {
+        use actix_web::http::StatusCode;
+        let status = |err| super::_error_response(err).status();
+        assert_eq!(status(atb_db::AtbDbError::NotFound), StatusCode::NOT_FOUND);
+        assert_eq!(
+            status(atb_db::AtbDbError::Validation("x".to_string())),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            status(atb_db::AtbDbError::Busy),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            status(atb_db::AtbDbError::Timeout(std::time::Duration::from_secs(
+                1
+            ))),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+        assert_eq!(
+            status(atb_db::AtbDbError::Panic("x".to_string())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}